@@ -0,0 +1,53 @@
+//! Generates the gRPC service from `proto/analyzer.proto` and embeds the git
+//! commit and build time reported by `GET /version`.
+//!
+//! Docker builds have no `.git`, so both can also be passed in: `GIT_COMMIT`
+//! directly and the build time as `SOURCE_DATE_EPOCH`, which also makes
+//! rebuilds of the same commit reproducible.
+
+use std::{
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    tonic_build::compile_protos("proto/analyzer.proto")
+        .unwrap_or_else(|e| panic!("Failed to compile proto/analyzer.proto: {}", e));
+
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // The reflog grows with every commit and checkout
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        let reflog = Path::new(&git_dir).join("logs").join("HEAD");
+        if reflog.is_file() {
+            println!("cargo:rerun-if-changed={}", reflog.display());
+        }
+    }
+
+    let commit = std::env::var("GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.trim().is_empty())
+        .or_else(|| git(&["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit.trim());
+
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0)
+        });
+    println!("cargo:rustc-env=BUILD_UNIX_TIME={}", built_at);
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
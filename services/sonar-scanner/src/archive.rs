@@ -0,0 +1,226 @@
+use crate::config::LimitsConfig;
+use crate::AppError;
+use flate2::read::GzDecoder;
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+use tar::EntryType;
+
+/// Bytes read from the start of an upload to identify its format.
+const MAGIC_LEN: usize = 512;
+
+/// Offset of the `ustar` magic in a tar header.
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Below this much extracted data the compression ratio is not enforced;
+/// small text-heavy archives legitimately compress very well.
+const RATIO_CHECK_FLOOR: u64 = 10 * 1024 * 1024;
+
+/// Caps applied while extracting an upload, so a crafted archive cannot
+/// fill the disk.
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractionLimits {
+    pub max_total_bytes: u64,
+    pub max_file_bytes: u64,
+    pub max_compression_ratio: u64,
+    pub max_entries: usize,
+}
+
+impl ExtractionLimits {
+    pub fn new(config: &LimitsConfig) -> Self {
+        Self {
+            max_total_bytes: config.max_extracted_bytes,
+            max_file_bytes: config.max_extracted_file_bytes,
+            max_compression_ratio: config.max_compression_ratio,
+            max_entries: config.max_archive_entries,
+        }
+    }
+}
+
+/// Running totals for one extraction, checked against [`ExtractionLimits`].
+pub struct ExtractionBudget {
+    limits: ExtractionLimits,
+    archive_len: u64,
+    written: u64,
+    entries: usize,
+}
+
+impl ExtractionBudget {
+    pub fn new(limits: ExtractionLimits, archive_path: &Path) -> Self {
+        Self {
+            limits,
+            archive_len: fs::metadata(archive_path).map(|m| m.len()).unwrap_or(0),
+            written: 0,
+            entries: 0,
+        }
+    }
+
+    /// Counts one archive entry against `max_entries`.
+    pub fn entry(&mut self) -> Result<(), AppError> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(AppError::ArchiveLimitExceeded(format!(
+                "archive has more than {} entries",
+                self.limits.max_entries
+            )));
+        }
+        Ok(())
+    }
+
+    /// Accounts for `size` bytes about to be written for `name`.
+    pub fn reserve(&mut self, name: &str, size: u64) -> Result<(), AppError> {
+        if size > self.limits.max_file_bytes {
+            return Err(AppError::ArchiveLimitExceeded(format!(
+                "'{}' exceeds the per-file limit of {} bytes",
+                name, self.limits.max_file_bytes
+            )));
+        }
+
+        self.written += size;
+        if self.written > self.limits.max_total_bytes {
+            return Err(AppError::ArchiveLimitExceeded(format!(
+                "extracted size exceeds the limit of {} bytes",
+                self.limits.max_total_bytes
+            )));
+        }
+
+        let max_ratio_bytes = self
+            .archive_len
+            .saturating_mul(self.limits.max_compression_ratio);
+        if self.written > RATIO_CHECK_FLOOR && self.written > max_ratio_bytes {
+            return Err(AppError::ArchiveLimitExceeded(format!(
+                "compression ratio exceeds {}:1",
+                self.limits.max_compression_ratio
+            )));
+        }
+        Ok(())
+    }
+
+    /// Copies `reader` into `writer`, never writing past the remaining budget.
+    /// Declared entry sizes are not trusted; the bytes actually produced are counted.
+    pub fn copy(
+        &mut self,
+        name: &str,
+        reader: &mut impl Read,
+        writer: &mut impl io::Write,
+    ) -> Result<(), AppError> {
+        let remaining = self.limits.max_total_bytes.saturating_sub(self.written);
+        let allowed = self.limits.max_file_bytes.min(remaining);
+
+        let copied = io::copy(&mut reader.take(allowed + 1), writer)
+            .map_err(|e| AppError::ZipError(format!("Failed to extract file contents: {}", e)))?;
+        self.reserve(name, copied)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+/// Identifies an archive from its leading bytes rather than its file name,
+/// which multipart clients often leave out or get wrong.
+pub fn detect(path: &Path) -> Result<ArchiveFormat, AppError> {
+    let mut head = Vec::with_capacity(MAGIC_LEN);
+    fs::File::open(path)
+        .and_then(|file| file.take(MAGIC_LEN as u64).read_to_end(&mut head))
+        .map_err(|e| AppError::ZipError(format!("Failed to read upload: {}", e)))?;
+
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        Ok(ArchiveFormat::Zip)
+    } else if head.starts_with(&[0x1f, 0x8b]) {
+        Ok(ArchiveFormat::TarGz)
+    } else if head.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5) == Some(b"ustar") {
+        Ok(ArchiveFormat::Tar)
+    } else {
+        Err(AppError::ZipError(
+            "Unsupported archive format (expected zip, tar or tar.gz)".to_string(),
+        ))
+    }
+}
+
+/// Extracts a tar archive, optionally gzip-compressed, into `extract_path`.
+/// Only regular files and directories are written; links are skipped so an
+/// entry can never point outside the extraction directory.
+pub fn extract_tar(
+    archive_path: &Path,
+    extract_path: &Path,
+    gzipped: bool,
+    budget: &mut ExtractionBudget,
+) -> Result<(), AppError> {
+    let file = fs::File::open(archive_path)
+        .map_err(|e| AppError::ZipError(format!("Failed to open tar archive: {}", e)))?;
+    let reader: Box<dyn Read> = if gzipped {
+        Box::new(GzDecoder::new(io::BufReader::new(file)))
+    } else {
+        Box::new(io::BufReader::new(file))
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    // Keep the archived mtimes so modified_since has something to compare
+    archive.set_preserve_mtime(true);
+    archive.set_preserve_permissions(false);
+
+    let entries = archive
+        .entries()
+        .map_err(|e| AppError::ZipError(format!("Failed to read tar archive: {}", e)))?;
+
+    for entry in entries {
+        let mut entry =
+            entry.map_err(|e| AppError::ZipError(format!("Failed to access tar entry: {}", e)))?;
+
+        budget.entry()?;
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => {
+                // A tar entry's data is exactly its header size
+                let name = entry.path_bytes().into_owned();
+                budget.reserve(&String::from_utf8_lossy(&name), entry.size())?;
+            }
+            EntryType::Directory => {}
+            _ => continue,
+        }
+
+        // unpack_in refuses entries that would land outside extract_path
+        entry
+            .unpack_in(extract_path)
+            .map_err(|e| AppError::ZipError(format!("Failed to extract tar entry: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+/// Copies a local project into `dest` for a scan, so the scanner's work
+/// files and `modified_since` pruning never touch the original. Symbolic
+/// links are skipped, as they are in tarballs, along with any previous
+/// `.scannerwork`; modification times are kept.
+pub fn copy_directory(source: &Path, dest: &Path) -> Result<(), AppError> {
+    let copy_error = |path: &Path, e: io::Error| {
+        AppError::InternalError(format!("Failed to copy {}: {}", path.display(), e))
+    };
+    fs::create_dir_all(dest).map_err(|e| copy_error(dest, e))?;
+
+    for entry in fs::read_dir(source).map_err(|e| copy_error(source, e))? {
+        let entry = entry.map_err(|e| copy_error(source, e))?;
+        let path = entry.path();
+        let file_type = entry.file_type().map_err(|e| copy_error(&path, e))?;
+        let target = dest.join(entry.file_name());
+
+        if file_type.is_dir() {
+            if entry.file_name() != ".scannerwork" {
+                copy_directory(&path, &target)?;
+            }
+        } else if file_type.is_file() {
+            fs::copy(&path, &target).map_err(|e| copy_error(&path, e))?;
+            if let Ok(modified) = entry.metadata().and_then(|meta| meta.modified()) {
+                if let Ok(file) = fs::File::options().write(true).open(&target) {
+                    let _ = file.set_modified(modified);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! `archive_url`: an archive the service downloads itself, e.g. a build
+//! artifact CI has already published, instead of one in the request body.
+
+use crate::config::{LimitsConfig, SourcesConfig};
+use crate::proxy::OutboundProxy;
+use crate::source_hosts::check_source_url;
+use crate::{apply_option, json_option_fields, AnalyzeOptions, AppError, AppState, UploadSource};
+use axum::http::header;
+use std::{path::Path, time::Duration};
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+/// URL schemes accepted for `archive_url`, as for `git_url`.
+const ALLOWED_SCHEMES: &[&str] = &["https", "http"];
+
+/// Redirects followed before a download is given up.
+const MAX_REDIRECTS: usize = 10;
+
+/// Downloads `archive_url` archives under the upload size limit.
+#[derive(Clone)]
+pub struct ArchiveDownloader {
+    http: reqwest::Client,
+    max_bytes: u64,
+    allowed_hosts: Vec<String>,
+}
+
+impl ArchiveDownloader {
+    pub fn new(limits: &LimitsConfig, sources: &SourcesConfig, proxy: &OutboundProxy) -> Self {
+        // Redirects are followed in `download`, which checks each target
+        let http = proxy
+            .client_builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(Duration::from_secs(limits.archive_download_timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            max_bytes: limits.max_upload_bytes,
+            allowed_hosts: sources.allowed_hosts.clone(),
+        }
+    }
+
+    /// Streams `url` to `path`, sending `authorization` as the
+    /// `Authorization` header when given, to `url`'s origin only. Returns
+    /// the number of bytes written. `url` and every redirect target must
+    /// pass [`check_source_url`].
+    async fn download(
+        &self,
+        url: &reqwest::Url,
+        authorization: Option<&str>,
+        path: &Path,
+    ) -> Result<u64, AppError> {
+        let download_error =
+            |e: reqwest::Error| AppError::DownloadError(e.without_url().to_string());
+        let mut target = url.clone();
+        let mut redirects = 0;
+        let mut response = loop {
+            check_source_url("archive_url", &target, &self.allowed_hosts).await?;
+            let mut request = self.http.get(target.clone());
+            if let Some(authorization) = authorization.filter(|_| target.origin() == url.origin()) {
+                request = request.header(header::AUTHORIZATION, authorization);
+            }
+            let response = request.send().await.map_err(download_error)?;
+            if !response.status().is_redirection() {
+                break response;
+            }
+
+            redirects += 1;
+            if redirects > MAX_REDIRECTS {
+                return Err(AppError::DownloadError(format!(
+                    "more than {} redirects",
+                    MAX_REDIRECTS
+                )));
+            }
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| target.join(location).ok())
+                .filter(|location| ALLOWED_SCHEMES.contains(&location.scheme()));
+            target = location.ok_or_else(|| {
+                AppError::DownloadError(format!(
+                    "server responded with {} without an http(s) Location",
+                    response.status()
+                ))
+            })?;
+        };
+        if !response.status().is_success() {
+            return Err(AppError::DownloadError(format!(
+                "server responded with {}",
+                response.status()
+            )));
+        }
+        let too_large =
+            || AppError::ArchiveLimitExceeded("archive exceeds the upload size limit".to_string());
+        if response
+            .content_length()
+            .is_some_and(|len| len > self.max_bytes)
+        {
+            return Err(too_large());
+        }
+
+        let write_error =
+            |e: std::io::Error| AppError::ZipError(format!("Failed to write zip file: {}", e));
+        let mut file = tokio::fs::File::create(path).await.map_err(write_error)?;
+        let mut size = 0u64;
+        while let Some(chunk) = response.chunk().await.map_err(download_error)? {
+            size += chunk.len() as u64;
+            if size > self.max_bytes {
+                return Err(too_large());
+            }
+            file.write_all(&chunk).await.map_err(write_error)?;
+        }
+        file.flush().await.map_err(write_error)?;
+        Ok(size)
+    }
+}
+
+/// Reads a JSON `/analyze` body naming an `archive_url` and an optional
+/// `archive_authorization`, downloads the archive into `temp_path` and
+/// reads the option fields.
+pub async fn read_archive_url_request(
+    state: &AppState,
+    mut body: serde_json::Map<String, serde_json::Value>,
+    temp_path: &Path,
+) -> Result<(UploadSource, AnalyzeOptions), AppError> {
+    let url = match body.remove("archive_url") {
+        Some(serde_json::Value::String(url)) => parse_url(&url)?,
+        _ => {
+            return Err(AppError::InvalidRequest(
+                "Field 'archive_url' must be a string".to_string(),
+            ))
+        }
+    };
+    let authorization = match body.remove("archive_authorization") {
+        Some(serde_json::Value::String(value)) => Some(value),
+        None | Some(serde_json::Value::Null) => None,
+        Some(_) => {
+            return Err(AppError::InvalidRequest(
+                "Field 'archive_authorization' must be a string".to_string(),
+            ))
+        }
+    };
+
+    let mut options = AnalyzeOptions::default();
+    for (name, value) in json_option_fields(body) {
+        apply_option(&mut options, &name, &value)?;
+    }
+
+    let path = temp_path.join("upload.archive");
+    let size = state
+        .archives
+        .download(&url, authorization.as_deref(), &path)
+        .await?;
+    info!(
+        "Downloaded {} byte archive from {} to {:?}",
+        size,
+        redacted(&url),
+        path
+    );
+
+    Ok((UploadSource::Archive(path), options))
+}
+
+fn parse_url(url: &str) -> Result<reqwest::Url, AppError> {
+    let url = reqwest::Url::parse(url.trim())
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid archive_url: {}", e)))?;
+    if !ALLOWED_SCHEMES.contains(&url.scheme()) {
+        return Err(AppError::InvalidRequest(format!(
+            "archive_url scheme '{}' is not supported (expected {})",
+            url.scheme(),
+            ALLOWED_SCHEMES.join(" or ")
+        )));
+    }
+    Ok(url)
+}
+
+/// `url` without credentials or a query string, which often carries a
+/// token, for logs.
+fn redacted(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.set_query(None);
+    url.to_string()
+}
@@ -0,0 +1,228 @@
+//! Azure Blob Storage, as block blobs. Requests are signed with the storage
+//! account key (Shared Key) or carry a SAS token; presigned uploads get a
+//! service SAS of their own, which takes the account key.
+
+use super::{
+    check_response, hmac, query_encode, uri_encode, ObjectStore, PresignedPut,
+    DOWNLOAD_TIMEOUT_SECS,
+};
+use crate::config::ArtifactsConfig;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// Blob service REST API version requests are made against.
+const API_VERSION: &str = "2021-08-06";
+
+enum Credential {
+    /// The decoded account key.
+    SharedKey(Vec<u8>),
+    /// A SAS token, without the leading `?`.
+    Sas(String),
+}
+
+pub struct AzureStore {
+    http: reqwest::Client,
+    account: String,
+    container: String,
+    /// `https://<account>.blob.core.windows.net` unless configured, e.g.
+    /// `http://azurite:10000/devstoreaccount1`.
+    endpoint: String,
+    credential: Credential,
+}
+
+impl AzureStore {
+    pub fn new(
+        http: reqwest::Client,
+        config: &ArtifactsConfig,
+        account: &str,
+        container: &str,
+    ) -> Result<Self> {
+        let credential = match (&config.azure_account_key, &config.azure_sas_token) {
+            (Some(key), _) => Credential::SharedKey(
+                STANDARD
+                    .decode(key.trim())
+                    .context("AZURE_STORAGE_KEY is not valid base64")?,
+            ),
+            (None, Some(token)) => Credential::Sas(token.trim_start_matches('?').to_string()),
+            (None, None) => {
+                bail!(
+                    "Azure artifact storage requires AZURE_STORAGE_KEY or AZURE_STORAGE_SAS_TOKEN"
+                )
+            }
+        };
+        let endpoint = match &config.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => format!("https://{}.blob.core.windows.net", account),
+        };
+        reqwest::Url::parse(&endpoint)
+            .with_context(|| format!("Invalid artifact endpoint {}", endpoint))?;
+        Ok(Self {
+            http,
+            account: account.to_string(),
+            container: container.to_string(),
+            endpoint,
+            credential,
+        })
+    }
+
+    /// A request for `key`, signed or carrying the SAS token. `x-ms-`
+    /// headers beyond the date and version are signed too.
+    fn request(
+        &self,
+        method: Method,
+        key: &str,
+        content_type: &str,
+        content_length: usize,
+        extra_headers: &[(&'static str, String)],
+    ) -> Result<RequestBuilder> {
+        let mut url = reqwest::Url::parse(&self.blob_url(key))
+            .with_context(|| format!("Invalid artifact endpoint {}", self.endpoint))?;
+
+        let mut ms_headers = extra_headers.to_vec();
+        ms_headers.push(("x-ms-date", http_date(OffsetDateTime::now_utc())));
+        ms_headers.push(("x-ms-version", API_VERSION.to_string()));
+        ms_headers.sort();
+
+        let authorization = match &self.credential {
+            Credential::SharedKey(account_key) => {
+                let canonical_headers: String = ms_headers
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+                    .collect();
+                // Content-Encoding, Content-Language, Content-Length,
+                // Content-MD5, Content-Type, Date, If-Modified-Since,
+                // If-Match, If-None-Match, If-Unmodified-Since and Range,
+                // then the `x-ms-` headers and the resource
+                let string_to_sign = format!(
+                    "{}\n\n\n{}\n\n{}\n\n\n\n\n\n\n{}/{}{}",
+                    method,
+                    if content_length == 0 {
+                        String::new()
+                    } else {
+                        content_length.to_string()
+                    },
+                    content_type,
+                    canonical_headers,
+                    self.account,
+                    url.path()
+                );
+                let signature = STANDARD.encode(hmac(account_key, string_to_sign.as_bytes()));
+                Some(format!("SharedKey {}:{}", self.account, signature))
+            }
+            Credential::Sas(token) => {
+                url.set_query(Some(token));
+                None
+            }
+        };
+
+        let mut request = self.http.request(method, url);
+        if !content_type.is_empty() {
+            request = request.header("content-type", content_type);
+        }
+        for (name, value) in &ms_headers {
+            request = request.header(*name, value);
+        }
+        if let Some(authorization) = authorization {
+            request = request.header("authorization", authorization);
+        }
+        Ok(request)
+    }
+
+    fn blob_url(&self, key: &str) -> String {
+        format!("{}/{}/{}", self.endpoint, self.container, uri_encode(key))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for AzureStore {
+    async fn put(&self, key: &str, content_type: &str, body: Vec<u8>) -> Result<String> {
+        let blob_type = [("x-ms-blob-type", "BlockBlob".to_string())];
+        let response = self
+            .request(Method::PUT, key, content_type, body.len(), &blob_type)?
+            .body(body)
+            .send()
+            .await
+            .context("Upload failed")?;
+        check_response(response).await?;
+        Ok(self.blob_url(key))
+    }
+
+    /// A service SAS allowing only the creation of this one blob.
+    async fn presign_put(&self, key: &str, expires_in: Duration) -> Result<PresignedPut> {
+        let Credential::SharedKey(account_key) = &self.credential else {
+            bail!("Presigned uploads to Azure require AZURE_STORAGE_KEY");
+        };
+        let expires_at = OffsetDateTime::now_utc() + expires_in;
+        let expiry = format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            expires_at.year(),
+            u8::from(expires_at.month()),
+            expires_at.day(),
+            expires_at.hour(),
+            expires_at.minute(),
+            expires_at.second()
+        );
+        let permissions = "cw";
+        // Permissions, start, expiry, resource, identifier, IP, protocol,
+        // version, resource type, snapshot time, encryption scope and the
+        // five response header overrides
+        let string_to_sign = format!(
+            "{}\n\n{}\n/blob/{}/{}/{}\n\n\nhttps,http\n{}\nb\n\n\n\n\n\n\n",
+            permissions, expiry, self.account, self.container, key, API_VERSION
+        );
+        let signature = STANDARD.encode(hmac(account_key, string_to_sign.as_bytes()));
+        let url = format!(
+            "{}?sv={}&spr=https,http&se={}&sr=b&sp={}&sig={}",
+            self.blob_url(key),
+            API_VERSION,
+            query_encode(&expiry),
+            permissions,
+            query_encode(&signature)
+        );
+        Ok(PresignedPut {
+            url,
+            headers: vec![("x-ms-blob-type", "BlockBlob".to_string())],
+            expires_at,
+        })
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<reqwest::Response>> {
+        let response = self
+            .request(Method::GET, key, "", 0, &[])?
+            .timeout(Duration::from_secs(DOWNLOAD_TIMEOUT_SECS))
+            .send()
+            .await
+            .context("Download failed")?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        check_response(response).await.map(Some)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, key, "", 0, &[])?
+            .send()
+            .await
+            .context("Delete failed")?;
+        check_response(response).await.map(drop)
+    }
+}
+
+/// RFC 1123 date, as `x-ms-date` expects.
+fn http_date(now: OffsetDateTime) -> String {
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        &now.weekday().to_string()[..3],
+        now.day(),
+        &now.month().to_string()[..3],
+        now.year(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
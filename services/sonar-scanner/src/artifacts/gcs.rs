@@ -0,0 +1,185 @@
+//! Google Cloud Storage, through the JSON API. Requests carry an OAuth
+//! access token, either configured or fetched from the GCE metadata server.
+//! Clients upload to a resumable upload session, whose URI needs no
+//! credentials, since V4 signed URLs would need a service account key.
+
+use super::{
+    check_response, query_encode, uri_encode, ObjectStore, PresignedPut, DOWNLOAD_TIMEOUT_SECS,
+};
+use crate::config::ArtifactsConfig;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{header, StatusCode};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+
+const DEFAULT_ENDPOINT: &str = "https://storage.googleapis.com";
+
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// How long GCS keeps a resumable upload session open.
+const SESSION_LIFETIME: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// How long before its expiry a metadata token is replaced.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+struct MetadataToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+pub struct GcsStore {
+    http: reqwest::Client,
+    bucket: String,
+    endpoint: String,
+    /// The configured token; `None` uses the metadata server.
+    access_token: Option<String>,
+    /// The last metadata token and when it expires.
+    cached_token: Mutex<Option<(String, Instant)>>,
+}
+
+impl GcsStore {
+    pub fn new(http: reqwest::Client, config: &ArtifactsConfig, bucket: &str) -> Result<Self> {
+        let endpoint = config
+            .endpoint
+            .as_deref()
+            .unwrap_or(DEFAULT_ENDPOINT)
+            .trim_end_matches('/')
+            .to_string();
+        reqwest::Url::parse(&endpoint)
+            .with_context(|| format!("Invalid artifact endpoint {}", endpoint))?;
+        Ok(Self {
+            http,
+            bucket: bucket.to_string(),
+            endpoint,
+            access_token: config.gcs_access_token.clone(),
+            cached_token: Mutex::new(None),
+        })
+    }
+
+    /// JSON API URL of `key`'s metadata, or its content with `alt=media`.
+    fn object_url(&self, key: &str) -> String {
+        format!(
+            "{}/storage/v1/b/{}/o/{}",
+            self.endpoint,
+            self.bucket,
+            query_encode(key)
+        )
+    }
+
+    async fn token(&self) -> Result<String> {
+        if let Some(token) = &self.access_token {
+            return Ok(token.clone());
+        }
+        let mut cached = self.cached_token.lock().await;
+        if let Some((token, expires)) = cached.as_ref() {
+            if Instant::now() + TOKEN_REFRESH_MARGIN < *expires {
+                return Ok(token.clone());
+            }
+        }
+
+        let token: MetadataToken = self
+            .http
+            .get(METADATA_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("No GCS_ACCESS_TOKEN and the GCE metadata server is unreachable")?
+            .json()
+            .await
+            .context("Invalid token from the GCE metadata server")?;
+        let expires = Instant::now() + Duration::from_secs(token.expires_in);
+        *cached = Some((token.access_token.clone(), expires));
+        Ok(token.access_token)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for GcsStore {
+    async fn put(&self, key: &str, content_type: &str, body: Vec<u8>) -> Result<String> {
+        let token = self.token().await?;
+        let response = self
+            .http
+            .post(format!(
+                "{}/upload/storage/v1/b/{}/o",
+                self.endpoint, self.bucket
+            ))
+            .query(&[("uploadType", "media"), ("name", key)])
+            .bearer_auth(token)
+            .header("content-type", content_type)
+            .body(body)
+            .send()
+            .await
+            .context("Upload failed")?;
+        check_response(response).await?;
+        Ok(format!(
+            "{}/{}/{}",
+            self.endpoint,
+            self.bucket,
+            uri_encode(key)
+        ))
+    }
+
+    /// Opens a resumable upload session; it stays valid for a week, whatever
+    /// `expires_in` asks for.
+    async fn presign_put(&self, key: &str, _expires_in: Duration) -> Result<PresignedPut> {
+        let token = self.token().await?;
+        let response = self
+            .http
+            .post(format!(
+                "{}/upload/storage/v1/b/{}/o",
+                self.endpoint, self.bucket
+            ))
+            .query(&[("uploadType", "resumable"), ("name", key)])
+            .bearer_auth(token)
+            .header(header::CONTENT_LENGTH, 0)
+            .send()
+            .await
+            .context("Failed to open an upload session")?;
+        let response = check_response(response).await?;
+        let url = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .context("GCS returned no upload session URI")?;
+        Ok(PresignedPut {
+            url: url.to_string(),
+            headers: Vec::new(),
+            expires_at: OffsetDateTime::now_utc() + SESSION_LIFETIME,
+        })
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<reqwest::Response>> {
+        let token = self.token().await?;
+        let response = self
+            .http
+            .get(self.object_url(key))
+            .query(&[("alt", "media")])
+            .bearer_auth(token)
+            .timeout(Duration::from_secs(DOWNLOAD_TIMEOUT_SECS))
+            .send()
+            .await
+            .context("Download failed")?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        check_response(response).await.map(Some)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let token = self.token().await?;
+        let response = self
+            .http
+            .delete(self.object_url(key))
+            .bearer_auth(token)
+            .send()
+            .await
+            .context("Delete failed")?;
+        check_response(response).await.map(drop)
+    }
+}
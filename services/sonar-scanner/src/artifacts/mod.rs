@@ -0,0 +1,352 @@
+//! Archives each finished job's result, reports and scanner log to object
+//! storage, so they outlive the job store and can be linked from CI, and
+//! receives archives clients upload there directly. The provider is chosen
+//! by the scheme of `ARTIFACTS_URL`: S3 or an S3-compatible server, Google
+//! Cloud Storage, or Azure Blob Storage.
+
+use crate::config::ArtifactsConfig;
+use crate::formats::{self, OutputFormat};
+use crate::jobs::{JobRecord, JobStore};
+use crate::proxy::OutboundProxy;
+use crate::AppError;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{path::Path, sync::Arc, time::Duration};
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+mod azure;
+mod gcs;
+mod s3;
+
+use azure::AzureStore;
+use gcs::GcsStore;
+use s3::S3Store;
+
+/// How long a single upload, presign or delete request may take.
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+
+/// How long downloading an archive uploaded by a client may take.
+const DOWNLOAD_TIMEOUT_SECS: u64 = 3600;
+
+/// An object stored for a job, listed in its `artifacts`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    /// File name within the job's prefix, e.g. `report.sarif`.
+    pub name: String,
+    pub key: String,
+    pub url: String,
+    pub content_type: String,
+    pub size_bytes: usize,
+}
+
+/// A bucket or container artifacts are written to.
+#[async_trait]
+trait ObjectStore: Send + Sync {
+    /// Stores `body` under `key`, replacing any existing object, and
+    /// returns the object's URL.
+    async fn put(&self, key: &str, content_type: &str, body: Vec<u8>) -> Result<String>;
+
+    /// A URL a client can PUT `key` to without credentials.
+    async fn presign_put(&self, key: &str, expires_in: Duration) -> Result<PresignedPut>;
+
+    /// The object as a streaming response; `None` when it does not exist.
+    async fn get(&self, key: &str) -> Result<Option<reqwest::Response>>;
+
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Where and how a client uploads an object itself.
+pub struct PresignedPut {
+    pub url: String,
+    /// Headers the PUT must carry.
+    pub headers: Vec<(&'static str, String)>,
+    pub expires_at: OffsetDateTime,
+}
+
+#[derive(Clone)]
+pub struct ArtifactStore {
+    store: Arc<dyn ObjectStore>,
+    /// The configured location, for logs.
+    location: String,
+    prefix: String,
+    reports: Vec<OutputFormat>,
+    public_url: Option<String>,
+    upload_expiry: Duration,
+}
+
+impl ArtifactStore {
+    /// `None` when neither `url` nor `bucket` is configured.
+    pub fn new(config: &ArtifactsConfig, proxy: &OutboundProxy) -> Result<Option<Self>> {
+        let (scheme, path) = match (&config.url, &config.bucket) {
+            (Some(url), _) => url
+                .split_once("://")
+                .with_context(|| format!("Invalid artifact URL {}", url))?,
+            (None, Some(bucket)) => ("s3", bucket.as_str()),
+            (None, None) => return Ok(None),
+        };
+        let reports = config
+            .reports
+            .iter()
+            .map(|report| {
+                OutputFormat::parse(Some(report))
+                    .map_err(|e| anyhow::anyhow!("Invalid artifact report: {}", e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let http = proxy
+            .client_builder()
+            .timeout(std::time::Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .build()
+            .context("Failed to build HTTP client")?;
+
+        let path = path.trim_matches('/');
+        let depth = match scheme {
+            "s3" | "gs" => 1,
+            "az" => 2,
+            _ => bail!(
+                "Unsupported artifact URL scheme {}:// (expected s3://, gs:// or az://)",
+                scheme
+            ),
+        };
+        let mut parts = path.splitn(depth + 1, '/');
+        let names: Vec<String> = parts
+            .by_ref()
+            .take(depth)
+            .filter(|part| !part.is_empty())
+            .map(str::to_string)
+            .collect();
+        let store: Arc<dyn ObjectStore> = match (scheme, names.as_slice()) {
+            ("s3", [bucket]) => Arc::new(S3Store::new(http, config, bucket)?),
+            ("gs", [bucket]) => Arc::new(GcsStore::new(http, config, bucket)?),
+            ("az", [account, container]) => {
+                Arc::new(AzureStore::new(http, config, account, container)?)
+            }
+            _ => bail!(
+                "Artifact URL {}://{} has no {}",
+                scheme,
+                path,
+                if depth == 1 {
+                    "bucket"
+                } else {
+                    "storage account and container"
+                }
+            ),
+        };
+        let prefix = match parts.next().map(|prefix| prefix.trim_matches('/')) {
+            Some(prefix) if !prefix.is_empty() => format!("{}/", prefix),
+            _ => config.prefix.clone(),
+        };
+
+        Ok(Some(Self {
+            store,
+            location: format!("{}://{}", scheme, path),
+            prefix,
+            reports,
+            public_url: config.public_url.clone(),
+            upload_expiry: Duration::from_secs(config.upload_expiry_secs),
+        }))
+    }
+
+    /// Uploads the job's artifacts in the background and records them on the
+    /// job once done.
+    pub fn archive_in_background(&self, tasks: &TaskTracker, jobs: JobStore, job_id: &str) {
+        let store = self.clone();
+        let job_id = job_id.to_string();
+        tasks.spawn(async move {
+            let job = match jobs.get(&job_id).await {
+                Ok(Some(job)) => job,
+                Ok(None) => return,
+                Err(e) => {
+                    warn!("Could not archive artifacts of job {}: {}", job_id, e);
+                    return;
+                }
+            };
+            let artifacts = store.archive(&job).await;
+            if !artifacts.is_empty() {
+                info!(
+                    "Archived {} artifact(s) of job {} to {}",
+                    artifacts.len(),
+                    job_id,
+                    store.location
+                );
+                jobs.add_artifacts(&job_id, artifacts).await;
+            }
+        });
+    }
+
+    /// Uploads the JSON result and reports of a succeeded job, and the
+    /// scanner log of any job. An object that fails to upload is logged
+    /// and left out.
+    async fn archive(&self, job: &JobRecord) -> Vec<Artifact> {
+        let mut files = Vec::new();
+        if let Some(result) = &job.result {
+            let wanted = std::iter::once(OutputFormat::Json).chain(
+                self.reports
+                    .iter()
+                    .copied()
+                    .filter(|&format| format != OutputFormat::Json),
+            );
+            for format in wanted {
+                match formats::render_file(format, result) {
+                    Ok(file) => files.push(file),
+                    Err(e) => warn!("Failed to render {:?} for job {}: {}", format, job.id, e),
+                }
+            }
+        }
+        let logs = job.logs();
+        if !logs.lines.is_empty() {
+            let mut text = String::new();
+            if logs.dropped > 0 {
+                text.push_str(&format!("[{} earlier lines dropped]\n", logs.dropped));
+            }
+            for line in &logs.lines {
+                text.push_str(&line.line);
+                text.push('\n');
+            }
+            files.push((
+                "scanner.log",
+                "text/plain; charset=utf-8",
+                text.into_bytes(),
+            ));
+        }
+
+        let mut artifacts = Vec::with_capacity(files.len());
+        for (name, content_type, body) in files {
+            let key = format!("{}{}/{}", self.prefix, job.id, name);
+            let size_bytes = body.len();
+            match self.store.put(&key, content_type, body).await {
+                Ok(url) => {
+                    let url = match &self.public_url {
+                        Some(base) => format!("{}/{}", base.trim_end_matches('/'), key),
+                        None => url,
+                    };
+                    artifacts.push(Artifact {
+                        name: name.to_string(),
+                        key,
+                        url,
+                        content_type: content_type.to_string(),
+                        size_bytes,
+                    })
+                }
+                Err(e) => warn!("Failed to upload {}: {:#}", key, e),
+            }
+        }
+        artifacts
+    }
+
+    /// A URL the client can upload the archive for `upload_id` to.
+    pub async fn presign_upload(&self, upload_id: &str) -> Result<PresignedPut, AppError> {
+        self.store
+            .presign_put(&self.upload_key(upload_id), self.upload_expiry)
+            .await
+            .map_err(|e| AppError::InternalError(format!("Failed to presign upload: {:#}", e)))
+    }
+
+    /// Streams the archive uploaded for `upload_id` to `path`, failing once
+    /// it exceeds `max_bytes`. Returns the number of bytes written.
+    pub async fn download_upload(
+        &self,
+        upload_id: &str,
+        path: &Path,
+        max_bytes: u64,
+    ) -> Result<u64, AppError> {
+        let download_error = |e: anyhow::Error| {
+            AppError::InternalError(format!("Failed to download upload: {:#}", e))
+        };
+        let Some(mut response) = self
+            .store
+            .get(&self.upload_key(upload_id))
+            .await
+            .map_err(download_error)?
+        else {
+            return Err(AppError::NotFound(format!(
+                "Upload {} (PUT the archive to its URL first)",
+                upload_id
+            )));
+        };
+        let too_large =
+            || AppError::ArchiveLimitExceeded("upload exceeds the request size limit".to_string());
+        if response.content_length().is_some_and(|len| len > max_bytes) {
+            return Err(too_large());
+        }
+
+        let write_error =
+            |e: std::io::Error| AppError::ZipError(format!("Failed to write zip file: {}", e));
+        let mut file = tokio::fs::File::create(path).await.map_err(write_error)?;
+        let mut size = 0u64;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| download_error(e.into()))?
+        {
+            size += chunk.len() as u64;
+            if size > max_bytes {
+                return Err(too_large());
+            }
+            file.write_all(&chunk).await.map_err(write_error)?;
+        }
+        file.flush().await.map_err(write_error)?;
+        Ok(size)
+    }
+
+    /// Deletes the archive uploaded for `upload_id` in the background.
+    pub fn remove_upload_in_background(&self, tasks: &TaskTracker, upload_id: &str) {
+        let store = self.store.clone();
+        let key = self.upload_key(upload_id);
+        tasks.spawn(async move {
+            if let Err(e) = store.delete(&key).await {
+                warn!("Failed to delete {}: {:#}", key, e);
+            }
+        });
+    }
+
+    /// Deletes an archived artifact.
+    pub async fn delete(&self, artifact: &Artifact) -> Result<()> {
+        self.store.delete(&artifact.key).await
+    }
+
+    fn upload_key(&self, upload_id: &str) -> String {
+        format!("{}uploads/{}", self.prefix, upload_id)
+    }
+}
+
+/// Percent-encodes a key for a URL path: everything but unreserved
+/// characters and `/`, as SigV4 canonical URIs require.
+fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Percent-encodes a query parameter value, `/` included.
+fn query_encode(value: &str) -> String {
+    uri_encode(value).replace('/', "%2F")
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Fails with the response body when the request was rejected.
+async fn check_response(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!("Request failed with status {}: {}", status, body);
+    }
+    Ok(response)
+}
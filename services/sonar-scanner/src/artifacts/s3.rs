@@ -0,0 +1,257 @@
+//! Amazon S3 and S3-compatible servers such as MinIO. Requests are signed
+//! with AWS Signature Version 4, in headers or, for presigned uploads, in
+//! the query string.
+
+use super::{
+    check_response, hmac, query_encode, uri_encode, ObjectStore, PresignedPut,
+    DOWNLOAD_TIMEOUT_SECS,
+};
+use crate::config::ArtifactsConfig;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use reqwest::{Method, RequestBuilder, StatusCode};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// Longest validity SigV4 allows for a presigned URL.
+const MAX_PRESIGN_SECS: u64 = 7 * 24 * 3600;
+
+pub struct S3Store {
+    http: reqwest::Client,
+    bucket: String,
+    endpoint: Option<String>,
+    region: String,
+    path_style: bool,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Store {
+    pub fn new(http: reqwest::Client, config: &ArtifactsConfig, bucket: &str) -> Result<Self> {
+        if config.access_key_id.is_empty() || config.secret_access_key.is_empty() {
+            bail!("S3 artifact storage requires AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY");
+        }
+        if config.upload_expiry_secs > MAX_PRESIGN_SECS {
+            bail!(
+                "ARTIFACTS_UPLOAD_EXPIRY_SECS may be at most {} for S3",
+                MAX_PRESIGN_SECS
+            );
+        }
+        let store = Self {
+            http,
+            bucket: bucket.to_string(),
+            endpoint: config.endpoint.clone(),
+            region: config.region.clone(),
+            path_style: config.path_style,
+            access_key_id: config.access_key_id.clone(),
+            secret_access_key: config.secret_access_key.clone(),
+            session_token: config.session_token.clone(),
+        };
+        // Fails on a malformed endpoint now rather than on every upload
+        store.object_url("probe")?;
+        Ok(store)
+    }
+
+    /// Path-style or virtual-hosted URL of `key`.
+    fn object_url(&self, key: &str) -> Result<reqwest::Url> {
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => format!("https://s3.{}.amazonaws.com", self.region),
+        };
+        let mut url = reqwest::Url::parse(&endpoint)
+            .with_context(|| format!("Invalid artifact endpoint {}", endpoint))?;
+        let path = if self.path_style {
+            format!("/{}/{}", self.bucket, uri_encode(key))
+        } else {
+            let host = format!("{}.{}", self.bucket, url.host_str().unwrap_or_default());
+            url.set_host(Some(&host))
+                .with_context(|| format!("Invalid bucket name {}", self.bucket))?;
+            format!("/{}", uri_encode(key))
+        };
+        url.set_path(&path);
+        Ok(url)
+    }
+
+    /// A request for `key` signed in its headers; the caller attaches
+    /// `payload` as the body where there is one.
+    fn request(&self, method: Method, key: &str, payload: &[u8]) -> Result<RequestBuilder> {
+        let url = self.object_url(key)?;
+        let payload_hash = hex_sha256(payload);
+        let amz_date = amz_date(OffsetDateTime::now_utc());
+
+        let mut headers = vec![
+            ("host", host_header(&url)),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\n{}",
+            method,
+            url.path(),
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id,
+            self.scope(&amz_date[..8]),
+            signed_headers,
+            self.signature(&amz_date, &canonical_request)
+        );
+
+        let mut request = self
+            .http
+            .request(method, url)
+            .header("authorization", authorization);
+        // reqwest sets `host` itself, to the same value
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(*name, value);
+        }
+        Ok(request)
+    }
+
+    fn scope(&self, date: &str) -> String {
+        format!("{}/{}/s3/aws4_request", date, self.region)
+    }
+
+    /// Hex SigV4 signature of `canonical_request`.
+    fn signature(&self, amz_date: &str, canonical_request: &str) -> String {
+        let date = &amz_date[..8];
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            self.scope(date),
+            hex_sha256(canonical_request.as_bytes())
+        );
+
+        let key = format!("AWS4{}", self.secret_access_key);
+        let key = hmac(key.as_bytes(), date.as_bytes());
+        let key = hmac(&key, self.region.as_bytes());
+        let key = hmac(&key, b"s3");
+        let key = hmac(&key, b"aws4_request");
+        hmac(&key, string_to_sign.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ObjectStore for S3Store {
+    async fn put(&self, key: &str, content_type: &str, body: Vec<u8>) -> Result<String> {
+        let response = self
+            .request(Method::PUT, key, &body)?
+            .header("content-type", content_type)
+            .body(body)
+            .send()
+            .await
+            .context("Upload failed")?;
+        check_response(response).await?;
+        Ok(self.object_url(key)?.to_string())
+    }
+
+    async fn presign_put(&self, key: &str, expires_in: Duration) -> Result<PresignedPut> {
+        let mut url = self.object_url(key)?;
+        let now = OffsetDateTime::now_utc();
+        let amz_date = amz_date(now);
+
+        // Already sorted by name, as the canonical query string must be
+        let mut query = vec![
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+            (
+                "X-Amz-Credential",
+                format!("{}/{}", self.access_key_id, self.scope(&amz_date[..8])),
+            ),
+            ("X-Amz-Date", amz_date.clone()),
+            ("X-Amz-Expires", expires_in.as_secs().to_string()),
+        ];
+        if let Some(token) = &self.session_token {
+            query.push(("X-Amz-Security-Token", token.clone()));
+        }
+        query.push(("X-Amz-SignedHeaders", "host".to_string()));
+        let canonical_query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, query_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let canonical_request = format!(
+            "PUT\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
+            url.path(),
+            canonical_query,
+            host_header(&url)
+        );
+        let signature = self.signature(&amz_date, &canonical_request);
+        url.set_query(Some(&format!(
+            "{}&X-Amz-Signature={}",
+            canonical_query, signature
+        )));
+
+        Ok(PresignedPut {
+            url: url.to_string(),
+            headers: Vec::new(),
+            expires_at: now + expires_in,
+        })
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<reqwest::Response>> {
+        let response = self
+            .request(Method::GET, key, &[])?
+            .timeout(Duration::from_secs(DOWNLOAD_TIMEOUT_SECS))
+            .send()
+            .await
+            .context("Download failed")?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        check_response(response).await.map(Some)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let response = self
+            .request(Method::DELETE, key, &[])?
+            .send()
+            .await
+            .context("Delete failed")?;
+        check_response(response).await.map(drop)
+    }
+}
+
+/// `host` as signed: the host name, and the port when not the default.
+fn host_header(url: &reqwest::Url) -> String {
+    match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    }
+}
+
+/// `YYYYMMDD'T'HHMMSS'Z'`, the timestamp format SigV4 signs.
+fn amz_date(now: OffsetDateTime) -> String {
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
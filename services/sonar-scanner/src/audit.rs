@@ -0,0 +1,352 @@
+//! Audit trail of scan activity: who scanned what, from where and when, and
+//! how it ended. Entries are appended as JSON lines to their own file, kept
+//! apart from the operational logs so they can be retained and shipped on
+//! their own terms, and are read back through `GET /admin/audit`.
+
+use crate::auth::Tenant;
+use crate::config::AuditConfig;
+use crate::{
+    parse_tenant_field, parse_timestamp_field, project_keys, AnalyzeOptions, AppError, AppState,
+};
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Query, State},
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{net::IpAddr, path::PathBuf, sync::Arc};
+use time::OffsetDateTime;
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+use tokio_util::task::TaskTracker;
+use tracing::{error, warn};
+
+/// Entries per page unless `limit` says otherwise.
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Most entries a single page may hold.
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Hex digits of an API key's SHA-256 kept as its fingerprint.
+const FINGERPRINT_LEN: usize = 16;
+
+/// Who sent a request, added to every authenticated request.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    /// Fingerprint of the API key the request presented; never the key.
+    pub api_key: Option<String>,
+    /// Name of the client certificate that authenticated the request.
+    pub client_certificate: Option<String>,
+    pub source_ip: Option<IpAddr>,
+}
+
+/// Identifies an API key in the audit log without revealing it.
+pub fn key_fingerprint(key: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(key.trim().as_bytes()));
+    digest[..FINGERPRINT_LEN].to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuditOutcome {
+    Succeeded,
+    Failed,
+    Canceled,
+    /// Answered with the cached result of an identical archive.
+    Cached,
+}
+
+impl AuditOutcome {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "SUCCEEDED" => Some(Self::Succeeded),
+            "FAILED" => Some(Self::Failed),
+            "CANCELED" => Some(Self::Canceled),
+            "CACHED" => Some(Self::Cached),
+            _ => None,
+        }
+    }
+}
+
+/// One finished scan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the scan finished.
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub job_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_certificate: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_ip: Option<IpAddr>,
+    pub project_keys: Vec<String>,
+    /// SHA-256 of the uploaded archive; absent for git sources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_sha256: Option<String>,
+    pub outcome: AuditOutcome,
+    /// Error code of a failed or canceled scan.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+}
+
+impl AuditEntry {
+    /// The entry for job `job_id`, run with `options`, ending with `outcome`.
+    pub fn new(
+        job_id: &str,
+        options: &AnalyzeOptions,
+        archive_sha256: Option<&str>,
+        outcome: Result<AuditOutcome, &AppError>,
+    ) -> Self {
+        let (outcome, error_code) = match outcome {
+            Ok(outcome) => (outcome, None),
+            Err(e) if matches!(e, AppError::Canceled(_)) => {
+                (AuditOutcome::Canceled, Some(e.parts().1))
+            }
+            Err(e) => (AuditOutcome::Failed, Some(e.parts().1)),
+        };
+        let caller = &options.caller;
+        Self {
+            timestamp: OffsetDateTime::now_utc(),
+            job_id: job_id.to_string(),
+            request_id: options.request_id.clone(),
+            tenant: options.tenant.clone(),
+            api_key: caller.api_key.clone(),
+            client_certificate: caller.client_certificate.clone(),
+            source_ip: caller.source_ip,
+            project_keys: project_keys(job_id, options),
+            archive_sha256: archive_sha256.map(str::to_string),
+            outcome,
+            error_code: error_code.map(str::to_string),
+        }
+    }
+}
+
+/// Which entries `GET /admin/audit` returns.
+#[derive(Debug, Default)]
+struct AuditFilter {
+    since: Option<OffsetDateTime>,
+    tenant: Option<String>,
+    /// Part of a project key, ignoring case.
+    project: Option<String>,
+    api_key: Option<String>,
+    outcome: Option<AuditOutcome>,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.since.map_or(true, |since| entry.timestamp >= since)
+            && self
+                .tenant
+                .as_ref()
+                .map_or(true, |tenant| entry.tenant.as_ref() == Some(tenant))
+            && self.project.as_ref().map_or(true, |wanted| {
+                let wanted = wanted.to_lowercase();
+                entry
+                    .project_keys
+                    .iter()
+                    .any(|key| key.to_lowercase().contains(&wanted))
+            })
+            && self
+                .api_key
+                .as_ref()
+                .map_or(true, |key| entry.api_key.as_ref() == Some(key))
+            && self
+                .outcome
+                .map_or(true, |outcome| entry.outcome == outcome)
+    }
+}
+
+/// The audit file. Entries are only ever appended; the file is reopened for
+/// each one, so it can be rotated by renaming it.
+#[derive(Clone)]
+pub struct AuditLog {
+    path: Arc<PathBuf>,
+    /// Keeps lines whole while several scans finish at once.
+    lock: Arc<Mutex<()>>,
+}
+
+impl AuditLog {
+    /// `None` when no audit file is configured.
+    pub fn new(config: &AuditConfig) -> Option<Self> {
+        let path = config.path.clone()?;
+        Some(Self {
+            path: Arc::new(path),
+            lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Appends `entry` in the background; failures are logged, since the
+    /// scan they describe has already finished.
+    pub fn record(&self, tasks: &TaskTracker, entry: AuditEntry) {
+        let audit = self.clone();
+        tasks.spawn(async move {
+            if let Err(e) = audit.append(&entry).await {
+                error!(
+                    "Failed to record job {} in the audit log: {:#}",
+                    entry.job_id, e
+                );
+            }
+        });
+    }
+
+    async fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().await;
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.as_path())
+            .await
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    /// Entries matching `filter`, newest first, skipping `offset` and
+    /// returning at most `limit`, with the number matching in all.
+    async fn query(
+        &self,
+        filter: &AuditFilter,
+        limit: usize,
+        offset: usize,
+    ) -> Result<(usize, Vec<AuditEntry>)> {
+        let text = {
+            let _guard = self.lock.lock().await;
+            match tokio::fs::read_to_string(self.path.as_path()).await {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to read {}", self.path.display()))
+                }
+            }
+        };
+
+        let mut total = 0;
+        let mut entries = Vec::new();
+        for (number, line) in text.lines().enumerate().rev() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: AuditEntry = match serde_json::from_str(line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Skipping unreadable audit log line {}: {}", number + 1, e);
+                    continue;
+                }
+            };
+            if !filter.matches(&entry) {
+                continue;
+            }
+            if total >= offset && entries.len() < limit {
+                entries.push(entry);
+            }
+            total += 1;
+        }
+        Ok((total, entries))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    /// Only entries at or after this RFC 3339 timestamp or Unix time.
+    since: Option<String>,
+    tenant: Option<String>,
+    /// Part of a project key, ignoring case.
+    project: Option<String>,
+    /// API key fingerprint, as recorded in `api_key`.
+    api_key: Option<String>,
+    outcome: Option<String>,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditPage {
+    /// Entries matching the filters, across all pages.
+    total: usize,
+    offset: usize,
+    limit: usize,
+    entries: Vec<AuditEntry>,
+}
+
+/// `GET /admin/audit`: the audit trail, newest first. Only admin keys may
+/// read it.
+pub async fn audit_log_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditPage>, AppError> {
+    tenant.require_admin()?;
+    let audit = state
+        .audit
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Audit log (set AUDIT_LOG_PATH)".to_string()))?;
+
+    let filter = AuditFilter {
+        since: query
+            .since
+            .as_deref()
+            .map(|since| parse_timestamp_field("since", since).map(OffsetDateTime::from))
+            .transpose()?,
+        tenant: query
+            .tenant
+            .as_deref()
+            .map(|tenant| parse_tenant_field("tenant", tenant))
+            .transpose()?,
+        project: query
+            .project
+            .map(|project| project.trim().to_string())
+            .filter(|project| !project.is_empty()),
+        api_key: query
+            .api_key
+            .map(|key| key.trim().to_ascii_lowercase())
+            .filter(|key| !key.is_empty()),
+        outcome: query
+            .outcome
+            .as_deref()
+            .map(|outcome| {
+                AuditOutcome::parse(outcome).ok_or_else(|| {
+                    AppError::InvalidRequest(format!(
+                        "Unknown outcome '{}' (expected SUCCEEDED, FAILED, CANCELED or CACHED)",
+                        outcome
+                    ))
+                })
+            })
+            .transpose()?,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(AppError::InvalidRequest(format!(
+            "Parameter 'limit' must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    let (total, entries) = audit
+        .query(&filter, limit, query.offset)
+        .await
+        .map_err(|e| AppError::InternalError(format!("Failed to read the audit log: {:#}", e)))?;
+    Ok(Json(AuditPage {
+        total,
+        offset: query.offset,
+        limit,
+        entries,
+    }))
+}
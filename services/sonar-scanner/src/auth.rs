@@ -0,0 +1,324 @@
+use crate::audit::{self, Caller};
+use crate::config::AuthConfig;
+use crate::tls::ClientCertificate;
+use crate::AppError;
+use anyhow::{bail, Result};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+
+/// Header carrying the client's API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Header carrying a SonarQube token to scan with instead of
+/// `SONARQUBE_TOKEN`.
+pub const SONAR_TOKEN_HEADER: &str = "x-sonar-token";
+
+/// Header carrying a GitHub token to comment on `pr_number` with instead of
+/// `GITHUB_TOKEN`.
+pub const GITHUB_TOKEN_HEADER: &str = "x-github-token";
+
+/// Longest tenant name; it prefixes project keys, which SonarQube caps at
+/// 400 characters.
+const MAX_TENANT_LEN: usize = 64;
+
+/// Accepted API keys, each with the tenant it belongs to, if any, the
+/// client certificate names mapped to tenants, and the admin keys. No keys
+/// disables authentication, though not the admin endpoints, which only
+/// admin keys may call.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    keys: Arc<Vec<(String, Tenant)>>,
+    client_certs: Arc<Vec<(String, Tenant)>>,
+    admin_keys: Arc<Vec<String>>,
+}
+
+impl ApiKeys {
+    pub fn new(config: &AuthConfig) -> Result<Self> {
+        let untenanted = config.api_keys.iter().map(|key| (key, Tenant::default()));
+        let keys = untenanted
+            .chain(tenant_entries(&config.tenants)?)
+            .map(|(key, tenant)| (key.trim().to_string(), tenant))
+            .filter(|(key, _)| !key.is_empty())
+            .collect();
+        let client_certs = tenant_entries(&config.client_certs)?
+            .map(|(name, tenant)| (name.trim().to_string(), tenant))
+            .collect();
+        let admin_keys = config
+            .admin_api_keys
+            .iter()
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .collect();
+        Ok(Self {
+            keys: Arc::new(keys),
+            client_certs: Arc::new(client_certs),
+            admin_keys: Arc::new(admin_keys),
+        })
+    }
+
+    /// Whether requests must authenticate, by an API key or a mapped client
+    /// certificate.
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty() || !self.client_certs.is_empty()
+    }
+
+    /// The tenant a client certificate is mapped to by any of its names,
+    /// with the name that matched.
+    pub fn certificate_tenant<'a>(
+        &self,
+        certificate: &'a ClientCertificate,
+    ) -> Option<(&'a str, Tenant)> {
+        certificate.identities.iter().find_map(|identity| {
+            self.client_certs
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(identity))
+                .map(|(_, tenant)| (identity.as_str(), tenant.clone()))
+        })
+    }
+
+    /// The tenant of a request presenting `candidate`, or `None` if it may
+    /// not proceed. With authentication disabled every request proceeds
+    /// without a tenant; only an admin key makes it an admin.
+    pub fn authenticate(&self, candidate: Option<&str>) -> Option<Tenant> {
+        let candidate = candidate.map(str::trim);
+        if let Some(candidate) = candidate {
+            let is_admin_key = self
+                .admin_keys
+                .iter()
+                .any(|key| constant_time_eq(key.as_bytes(), candidate.as_bytes()));
+            if is_admin_key {
+                return Some(Tenant {
+                    name: None,
+                    admin: true,
+                });
+            }
+        }
+        if !self.is_enabled() {
+            return Some(Tenant::default());
+        }
+        let candidate = candidate?;
+        self.keys
+            .iter()
+            .find(|(key, _)| constant_time_eq(key.as_bytes(), candidate.as_bytes()))
+            .map(|(_, tenant)| tenant.clone())
+    }
+}
+
+/// The tenant a request's API key or client certificate belongs to, added to
+/// every authenticated request. Keys without a tenant, and requests with
+/// authentication disabled, act for every tenant. Admin keys act for every
+/// tenant too and may also call the admin endpoints.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tenant {
+    name: Option<String>,
+    admin: bool,
+}
+
+impl Tenant {
+    fn named(name: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            admin: false,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    /// Whether the caller may see a job submitted by `owner`.
+    pub fn can_see(&self, owner: Option<&str>) -> bool {
+        self.name.is_none() || self.name() == owner
+    }
+
+    /// Refuses callers without an admin key: admin endpoints affect every
+    /// tenant, so no other key, and no request while authentication is
+    /// disabled, may call them.
+    pub fn require_admin(&self) -> Result<(), AppError> {
+        if self.admin {
+            return Ok(());
+        }
+        Err(AppError::Forbidden(
+            "this endpoint requires an admin API key (see ADMIN_API_KEYS)".to_string(),
+        ))
+    }
+
+    /// Tenant a new job is submitted as: the key's own, or `requested` for
+    /// keys without one. A tenanted key may not act for another tenant.
+    pub fn resolve(&self, requested: Option<&str>) -> Result<Option<String>, AppError> {
+        match (&self.name, requested) {
+            (Some(own), Some(requested)) if own != requested => Err(AppError::Forbidden(format!(
+                "this API key may not act for tenant '{}'",
+                requested
+            ))),
+            (Some(own), _) => Ok(Some(own.clone())),
+            (None, requested) => Ok(requested.map(str::to_string)),
+        }
+    }
+}
+
+/// `(value, tenant)` for every value listed under a tenant, once the tenant
+/// names are checked.
+fn tenant_entries(
+    tenants: &BTreeMap<String, Vec<String>>,
+) -> Result<impl Iterator<Item = (&String, Tenant)>> {
+    if let Some(tenant) = tenants.keys().find(|tenant| !is_valid_tenant(tenant)) {
+        bail!(
+            "Invalid tenant name {:?}: use up to {} letters, digits, '-' or '_'",
+            tenant,
+            MAX_TENANT_LEN
+        );
+    }
+    Ok(tenants.iter().flat_map(|(tenant, values)| {
+        values
+            .iter()
+            .map(move |value| (value, Tenant::named(tenant)))
+    }))
+}
+
+/// Whether `tenant` is usable in project keys and container names.
+pub fn is_valid_tenant(tenant: &str) -> bool {
+    !tenant.is_empty()
+        && tenant.len() <= MAX_TENANT_LEN
+        && tenant
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Rejects requests without a valid `X-Api-Key` header with `401` and
+/// records the key's [`Tenant`] and the [`Caller`] on the others. A mutual
+/// TLS client whose certificate is mapped to a tenant needs no key; one
+/// whose certificate is not needs a key like any other client.
+pub async fn require_api_key(
+    State(keys): State<ApiKeys>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let source_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let certificate = request.extensions().get::<ClientCertificate>();
+    let has_certificate = certificate.is_some();
+    let certificate_tenant = certificate
+        .and_then(|certificate| keys.certificate_tenant(certificate))
+        .map(|(name, tenant)| (name.to_string(), tenant));
+    if let Some((name, tenant)) = certificate_tenant {
+        request.extensions_mut().insert(tenant);
+        request.extensions_mut().insert(Caller {
+            api_key: None,
+            client_certificate: Some(name),
+            source_ip,
+        });
+        return next.run(request).await;
+    }
+
+    let candidate = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    let Some(tenant) = keys.authenticate(candidate) else {
+        let message = if has_certificate {
+            "client certificate is not mapped to a tenant and no valid X-Api-Key header was sent"
+        } else {
+            "missing or invalid X-Api-Key header"
+        };
+        return AppError::Unauthorized(message.to_string()).into_response();
+    };
+    let caller = Caller {
+        api_key: candidate
+            .filter(|_| keys.is_enabled() || tenant.is_admin())
+            .map(audit::key_fingerprint),
+        client_certificate: None,
+        source_ip,
+    };
+    request.extensions_mut().insert(tenant);
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
+
+/// Compares without short-circuiting so response timing does not reveal how
+/// much of a key matched.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `candidate` may call the admin endpoints; `None` if it is
+    /// rejected outright.
+    fn admits_admin(config: AuthConfig, candidate: Option<&str>) -> Option<bool> {
+        let tenant = ApiKeys::new(&config).unwrap().authenticate(candidate)?;
+        Some(tenant.require_admin().is_ok())
+    }
+
+    #[test]
+    fn only_admin_keys_pass_require_admin() {
+        let config = AuthConfig {
+            api_keys: vec!["plain".to_string()],
+            tenants: BTreeMap::from([("team-a".to_string(), vec!["team".to_string()])]),
+            admin_api_keys: vec!["root".to_string()],
+            ..AuthConfig::default()
+        };
+
+        assert_eq!(admits_admin(config.clone(), Some(" root ")), Some(true));
+        assert_eq!(admits_admin(config.clone(), Some("plain")), Some(false));
+        assert_eq!(admits_admin(config.clone(), Some("team")), Some(false));
+        assert_eq!(admits_admin(config, None), None);
+    }
+
+    #[test]
+    fn admin_endpoints_stay_closed_with_authentication_disabled() {
+        assert_eq!(admits_admin(AuthConfig::default(), None), Some(false));
+        assert_eq!(
+            admits_admin(AuthConfig::default(), Some("guess")),
+            Some(false)
+        );
+
+        let admin_only = AuthConfig {
+            admin_api_keys: vec!["root".to_string()],
+            ..AuthConfig::default()
+        };
+        assert_eq!(admits_admin(admin_only.clone(), Some("root")), Some(true));
+        assert_eq!(admits_admin(admin_only, None), Some(false));
+    }
+
+    #[test]
+    fn mapped_client_certificates_turn_authentication_on() {
+        let keys = ApiKeys::new(&AuthConfig {
+            client_certs: BTreeMap::from([(
+                "team-a".to_string(),
+                vec!["ci.team-a.example.com".to_string()],
+            )]),
+            ..AuthConfig::default()
+        })
+        .unwrap();
+        assert!(keys.is_enabled());
+        assert_eq!(keys.authenticate(None), None);
+        assert_eq!(keys.authenticate(Some("guess")), None);
+
+        let mapped = ClientCertificate {
+            identities: vec!["CI.team-a.example.com".to_string()],
+        };
+        let (_, tenant) = keys.certificate_tenant(&mapped).unwrap();
+        assert_eq!(tenant.name(), Some("team-a"));
+        let unmapped = ClientCertificate {
+            identities: vec!["ci.team-b.example.com".to_string()],
+        };
+        assert!(keys.certificate_tenant(&unmapped).is_none());
+    }
+}
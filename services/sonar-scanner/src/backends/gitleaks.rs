@@ -0,0 +1,121 @@
+use super::run_process;
+use crate::config::GitleaksConfig;
+use crate::jobs::{JobProgress, JobStage};
+use crate::metrics::{Metrics, Stage};
+use crate::shutdown::ChildProcesses;
+use crate::AppError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::Path,
+    process::Command,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{info, info_span, Instrument};
+
+/// Written next to the project directory so it is not scanned itself.
+const RESULTS_FILE: &str = "gitleaks.json";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GitleaksFinding {
+    #[serde(rename = "RuleID")]
+    rule_id: String,
+    #[serde(default)]
+    description: String,
+    file: String,
+    start_line: u32,
+    /// The matched text, with the secret itself replaced by `REDACTED`.
+    #[serde(rename = "Match", default)]
+    matched: String,
+}
+
+/// A credential found in the project's files. The secret itself is never
+/// returned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectedSecret {
+    /// Gitleaks rule, e.g. `aws-access-token`.
+    pub rule: String,
+    pub description: String,
+    /// Path relative to the project root.
+    pub file: String,
+    pub line: u32,
+    /// The matched text with the secret replaced by `REDACTED`.
+    pub redacted_match: String,
+}
+
+/// Runs `gitleaks dir` over the extracted project to find hard-coded
+/// credentials.
+#[derive(Clone)]
+pub struct GitleaksScanner {
+    config: GitleaksConfig,
+    metrics: Arc<Metrics>,
+    children: ChildProcesses,
+}
+
+impl GitleaksScanner {
+    pub fn new(config: &GitleaksConfig, metrics: Arc<Metrics>, children: ChildProcesses) -> Self {
+        Self {
+            config: config.clone(),
+            metrics,
+            children,
+        }
+    }
+
+    pub async fn scan(
+        &self,
+        project_dir: &Path,
+        timeout: Duration,
+        progress: &JobProgress,
+    ) -> Result<Vec<DetectedSecret>, AppError> {
+        info!("Running gitleaks on {:?}", project_dir);
+        let results_path = project_dir.with_file_name(RESULTS_FILE);
+
+        progress.stage(JobStage::ScannerStarted, Some("gitleaks".to_string()));
+        let started = Instant::now();
+        run_process(
+            self.command(project_dir, &results_path),
+            "gitleaks",
+            timeout,
+            progress,
+            &self.children,
+        )
+        .instrument(info_span!("gitleaks"))
+        .await?;
+        self.metrics.observe_stage(Stage::Scanner, started);
+        progress.stage(JobStage::ScannerFinished, Some("gitleaks".to_string()));
+
+        let text = fs::read_to_string(&results_path).map_err(|e| {
+            AppError::ScannerError(format!("Failed to read gitleaks results: {}", e))
+        })?;
+        let findings: Vec<GitleaksFinding> = serde_json::from_str(&text).map_err(|e| {
+            AppError::ScannerError(format!("Failed to parse gitleaks results: {}", e))
+        })?;
+
+        let secrets: Vec<DetectedSecret> = findings
+            .into_iter()
+            .map(|finding| DetectedSecret {
+                rule: finding.rule_id,
+                description: finding.description,
+                file: finding.file.trim_start_matches("./").to_string(),
+                line: finding.start_line,
+                redacted_match: finding.matched,
+            })
+            .collect();
+        info!("Gitleaks found {} secret(s)", secrets.len());
+        Ok(secrets)
+    }
+
+    /// Findings do not fail the run (`--exit-code 0`); only errors do.
+    fn command(&self, project_dir: &Path, results_path: &Path) -> Command {
+        let mut command = Command::new(&self.config.binary);
+        command
+            .args(["dir", "--no-banner", "--redact", "--exit-code", "0"])
+            .args(["--report-format", "json", "--report-path"])
+            .arg(results_path)
+            .arg(".")
+            .current_dir(project_dir);
+        command
+    }
+}
@@ -0,0 +1,296 @@
+use crate::hotspots::SecurityHotspot;
+use crate::jobs::JobProgress;
+use crate::logs::LogStream;
+use crate::measures::ProjectMetrics;
+use crate::quality_gate::QualityGate;
+use crate::severity::Severity;
+use crate::shutdown::{self, ChildProcesses};
+use crate::sonar_client::{FetchedIssues, IssueFilter, SonarClient};
+use crate::AppError;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::Path,
+    process::{Command, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tracing::error;
+
+mod gitleaks;
+mod semgrep;
+mod sonarqube;
+mod trivy;
+
+pub use gitleaks::{DetectedSecret, GitleaksScanner};
+pub use semgrep::SemgrepBackend;
+pub use sonarqube::{base_scanner_properties, scanner_command, scanner_version, SonarQubeBackend};
+pub use trivy::{DependencyVulnerability, TrivyScanner};
+
+/// Analyzers a request can choose with the `engine` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    SonarQube,
+    /// Runs locally; needs no SonarQube server.
+    Semgrep,
+}
+
+impl Engine {
+    const ALL: &'static [Engine] = &[Engine::SonarQube, Engine::Semgrep];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Engine::SonarQube => "sonarqube",
+            Engine::Semgrep => "semgrep",
+        }
+    }
+
+    /// Parses the requested engines, ignoring case and duplicates.
+    pub fn parse(values: &[String]) -> Result<Vec<Engine>, AppError> {
+        let mut engines = Vec::new();
+        for value in values {
+            let engine = Self::ALL
+                .iter()
+                .copied()
+                .find(|engine| value.trim().eq_ignore_ascii_case(engine.as_str()))
+                .ok_or_else(|| {
+                    AppError::InvalidRequest(format!(
+                        "Unknown engine '{}' (expected one of {})",
+                        value,
+                        Self::ALL
+                            .iter()
+                            .map(|engine| engine.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ))
+                })?;
+            if !engines.contains(&engine) {
+                engines.push(engine);
+            }
+        }
+        Ok(engines)
+    }
+}
+
+/// One backend per [`Engine`], shared by every job.
+#[derive(Clone)]
+pub struct Backends {
+    pub sonarqube: Arc<dyn ScannerBackend>,
+    pub semgrep: Arc<dyn ScannerBackend>,
+}
+
+impl Backends {
+    pub fn get(&self, engine: Engine) -> &dyn ScannerBackend {
+        match engine {
+            Engine::SonarQube => self.sonarqube.as_ref(),
+            Engine::Semgrep => self.semgrep.as_ref(),
+        }
+    }
+}
+
+/// One scan of a job's extracted project.
+#[derive(Clone, Copy)]
+pub struct ScanContext<'a> {
+    pub project_dir: &'a Path,
+    /// Key the results are stored under; a job uses one per quality profile.
+    pub project_key: &'a str,
+    /// Scanner properties beyond the backend's own.
+    pub properties: &'a [(String, String)],
+    pub filter: &'a IssueFilter,
+    /// Quality profile to scan under; `None` keeps the server default.
+    pub profile: Option<&'a str>,
+    /// Languages detected in the project.
+    pub languages: &'a [&'a str],
+    /// How long the analyzer process may run before it is killed.
+    pub timeout: Duration,
+    pub progress: &'a JobProgress,
+    /// SonarQube client acting with the job's credentials.
+    pub sonar: &'a SonarClient,
+}
+
+/// Issues found by one scan.
+pub struct ScanOutput {
+    pub fetched: FetchedIssues,
+    /// `None` when the backend has no gate or its status could not be read.
+    pub quality_gate: Option<QualityGate>,
+    /// Code to review by hand, kept apart from the issues.
+    pub hotspots: Vec<SecurityHotspot>,
+    /// `None` when the backend computes no measures or they could not be read.
+    pub metrics: Option<ProjectMetrics>,
+    /// How long the scan waited for a server to process its report.
+    pub ce_wait: Option<Duration>,
+}
+
+/// An analyzer that scans a project in three steps: `prepare`, `run` and
+/// `collect`.
+#[async_trait]
+pub trait ScannerBackend: Send + Sync {
+    /// Sets up anything the scan needs before it starts, such as the
+    /// project's quality profile.
+    async fn prepare(&self, scan: &ScanContext<'_>) -> Result<(), AppError>;
+
+    /// Analyses the project; returns once the results can be collected,
+    /// with how long it waited for a server to process the report, if any.
+    async fn run(&self, scan: &ScanContext<'_>) -> Result<Option<Duration>, AppError>;
+
+    async fn collect(&self, scan: &ScanContext<'_>) -> Result<ScanOutput, AppError>;
+
+    /// Releases what a job's scans left behind once all of them are done,
+    /// with the job's SonarQube client.
+    async fn clean_up(&self, sonar: &SonarClient, project_keys: &[String], succeeded: bool);
+
+    /// Stops work a canceled job's scans handed to something other than a
+    /// local process; the processes themselves are killed as their scan is
+    /// dropped.
+    async fn cancel(&self, sonar: &SonarClient, project_keys: &[String]) {
+        self.clean_up(sonar, project_keys, false).await;
+    }
+
+    async fn scan(&self, scan: &ScanContext<'_>) -> Result<ScanOutput, AppError> {
+        self.prepare(scan).await?;
+        let ce_wait = self.run(scan).await?;
+        let output = self.collect(scan).await?;
+        Ok(ScanOutput { ce_wait, ..output })
+    }
+}
+
+/// Merges the issues of several engines, tagging each with the engine that
+/// reported it. Engines report different rule keys for the same problem, so
+/// issues from different engines with the same type, file and line are
+/// treated as one finding: the first engine's issue is kept, raised to the
+/// highest severity reported, and the others are listed in
+/// `also_reported_by`. The merged set is capped at `max_issues`.
+pub fn merge(results: Vec<(Engine, FetchedIssues)>, max_issues: Option<usize>) -> FetchedIssues {
+    let mut merged = FetchedIssues::default();
+    let mut seen: HashMap<(String, String, Option<u32>), usize> = HashMap::new();
+
+    for (engine, fetched) in results {
+        merged.truncated |= fetched.truncated;
+        for mut issue in fetched.issues {
+            issue.engine = Some(engine);
+            if let Some(severity) = Severity::parse(&issue.severity) {
+                issue.severity = severity.as_str().to_string();
+            }
+
+            let identity = (
+                issue.issue_type.clone(),
+                issue.path().to_string(),
+                issue.line,
+            );
+            let existing = seen
+                .get(&identity)
+                .map(|&index| &mut merged.issues[index])
+                .filter(|existing| existing.engine != Some(engine));
+            match existing {
+                Some(existing) => {
+                    if Severity::parse(&issue.severity) > Severity::parse(&existing.severity) {
+                        existing.severity = issue.severity;
+                    }
+                    if !existing.also_reported_by.contains(&engine) {
+                        existing.also_reported_by.push(engine);
+                    }
+                }
+                None => {
+                    seen.entry(identity).or_insert(merged.issues.len());
+                    merged.issues.push(issue);
+                }
+            }
+        }
+    }
+
+    merged.total = merged.issues.len();
+    if let Some(max_issues) = max_issues {
+        if merged.issues.len() > max_issues {
+            merged.issues.truncate(max_issues);
+            merged.truncated = true;
+        }
+    }
+    merged
+}
+
+/// Runs an analyzer process in its own process group, so shutdown, the
+/// `timeout` or cancellation can kill it along with anything it started, and forwards each
+/// stdout/stderr line to the job's log stream as it is produced.
+async fn run_process(
+    mut command: Command,
+    name: &str,
+    timeout: Duration,
+    progress: &JobProgress,
+    children: &ChildProcesses,
+) -> Result<(), AppError> {
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+    let mut child = tokio::process::Command::from(command)
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| AppError::ScannerError(format!("Failed to execute {}: {}", name, e)))?;
+    let pid = child.id();
+    let mut registered = pid.map(|pid| children.register(pid));
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let finished = tokio::time::timeout(timeout, async {
+        let (stdout, stderr) = tokio::join!(
+            forward_lines(stdout, LogStream::Stdout, progress),
+            forward_lines(stderr, LogStream::Stderr, progress),
+        );
+        (stdout, stderr, child.wait().await)
+    })
+    .await;
+
+    let Ok((stdout, stderr, status)) = finished else {
+        // The JVM behind sonar-scanner is a grandchild, so kill the group
+        if let Some(pid) = pid {
+            shutdown::kill_group(pid);
+        }
+        let _ = child.wait().await;
+        if let Some(guard) = registered.as_mut() {
+            guard.exited();
+        }
+        error!("{} timed out after {:?}", name, timeout);
+        return Err(AppError::ScannerTimeout(format!(
+            "{} did not finish within {} seconds",
+            name,
+            timeout.as_secs()
+        )));
+    };
+    if let Some(guard) = registered.as_mut() {
+        guard.exited();
+    }
+    let status = status
+        .map_err(|e| AppError::ScannerError(format!("Failed to wait for {}: {}", name, e)))?;
+
+    if !status.success() {
+        error!("{} stderr: {}", name, stderr);
+        error!("{} stdout: {}", name, stdout);
+        return Err(AppError::ScannerError(format!(
+            "{} exited with status: {}. Stderr: {}",
+            name, status, stderr
+        )));
+    }
+    Ok(())
+}
+
+/// Publishes every line read from `reader` and returns the full output.
+async fn forward_lines(
+    reader: Option<impl AsyncRead + Unpin>,
+    stream: LogStream,
+    progress: &JobProgress,
+) -> String {
+    let Some(reader) = reader else {
+        return String::new();
+    };
+
+    let mut output = String::new();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        output.push_str(&line);
+        output.push('\n');
+        progress.log(stream, line);
+    }
+    output
+}
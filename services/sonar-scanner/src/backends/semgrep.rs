@@ -0,0 +1,206 @@
+use super::{run_process, ScanContext, ScanOutput, ScannerBackend};
+use crate::config::SemgrepConfig;
+use crate::jobs::JobStage;
+use crate::metrics::{Metrics, Stage};
+use crate::shutdown::ChildProcesses;
+use crate::sonar_client::{FetchedIssues, SonarClient};
+use crate::{AppError, SonarIssue};
+use async_trait::async_trait;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    process::Command,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{info, info_span, warn, Instrument};
+
+/// Written next to the project directory so it is not scanned itself.
+const RESULTS_FILE: &str = "semgrep.json";
+
+#[derive(Debug, Deserialize)]
+struct SemgrepOutput {
+    #[serde(default)]
+    results: Vec<SemgrepResult>,
+    /// Files or rules Semgrep could not process; the scan still succeeds.
+    #[serde(default)]
+    errors: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SemgrepResult {
+    check_id: String,
+    path: String,
+    start: SemgrepPosition,
+    extra: SemgrepExtra,
+}
+
+#[derive(Debug, Deserialize)]
+struct SemgrepPosition {
+    line: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct SemgrepExtra {
+    message: String,
+    severity: String,
+    #[serde(default)]
+    metadata: SemgrepMetadata,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SemgrepMetadata {
+    category: Option<String>,
+}
+
+/// Runs Semgrep over the project and reports its findings as SonarQube-style
+/// issues. Needs no SonarQube server.
+pub struct SemgrepBackend {
+    config: SemgrepConfig,
+    metrics: Arc<Metrics>,
+    children: ChildProcesses,
+}
+
+impl SemgrepBackend {
+    pub fn new(config: &SemgrepConfig, metrics: Arc<Metrics>, children: ChildProcesses) -> Self {
+        Self {
+            config: config.clone(),
+            metrics,
+            children,
+        }
+    }
+
+    fn command(&self, scan: &ScanContext<'_>) -> Command {
+        let mut command = Command::new(&self.config.binary);
+        command
+            .arg("scan")
+            .arg("--json")
+            .arg("--output")
+            .arg(scan.project_dir.with_file_name(RESULTS_FILE))
+            .args(["--metrics", "off", "--disable-version-check"]);
+        for rules in &self.config.rules {
+            command.arg("--config").arg(rules);
+        }
+        command.arg(".").current_dir(scan.project_dir);
+        command
+    }
+}
+
+#[async_trait]
+impl ScannerBackend for SemgrepBackend {
+    async fn prepare(&self, _scan: &ScanContext<'_>) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn run(&self, scan: &ScanContext<'_>) -> Result<Option<Duration>, AppError> {
+        let project_key = scan.project_key;
+        info!("Running semgrep for job: {}", project_key);
+
+        scan.progress
+            .stage(JobStage::ScannerStarted, Some("semgrep".to_string()));
+        let started = Instant::now();
+        run_process(
+            self.command(scan),
+            "semgrep",
+            scan.timeout,
+            scan.progress,
+            &self.children,
+        )
+        .instrument(info_span!("semgrep", project_key))
+        .await?;
+        self.metrics.observe_stage(Stage::Scanner, started);
+        scan.progress
+            .stage(JobStage::ScannerFinished, Some("semgrep".to_string()));
+        Ok(None)
+    }
+
+    async fn collect(&self, scan: &ScanContext<'_>) -> Result<ScanOutput, AppError> {
+        let path = scan.project_dir.with_file_name(RESULTS_FILE);
+        let text = fs::read_to_string(&path).map_err(|e| {
+            AppError::ScannerError(format!("Failed to read semgrep results: {}", e))
+        })?;
+        let output: SemgrepOutput = serde_json::from_str(&text).map_err(|e| {
+            AppError::ScannerError(format!("Failed to parse semgrep results: {}", e))
+        })?;
+        if !output.errors.is_empty() {
+            warn!(
+                "Semgrep reported {} error(s) for {}",
+                output.errors.len(),
+                scan.project_key
+            );
+        }
+
+        let issues: Vec<SonarIssue> = output
+            .results
+            .into_iter()
+            .map(|result| to_issue(scan.project_key, result))
+            .filter(|issue| scan.filter.types.contains(&issue.issue_type))
+            .filter(|issue| scan.filter.accepts_severity(&issue.severity))
+            .collect();
+        scan.progress.stage(
+            JobStage::IssuesFetched,
+            Some(format!("semgrep: {} issue(s)", issues.len())),
+        );
+
+        Ok(ScanOutput {
+            fetched: FetchedIssues {
+                total: issues.len(),
+                issues,
+                truncated: false,
+            },
+            quality_gate: None,
+            hotspots: Vec::new(),
+            metrics: None,
+            ce_wait: None,
+        })
+    }
+
+    async fn clean_up(&self, _sonar: &SonarClient, _project_keys: &[String], _succeeded: bool) {}
+}
+
+fn to_issue(project_key: &str, result: SemgrepResult) -> SonarIssue {
+    let path = result.path.trim_start_matches("./");
+    let line = result.start.line;
+
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\0{}\0{}", result.check_id, path, line));
+    let digest = format!("{:x}", hasher.finalize());
+
+    SonarIssue {
+        key: format!("semgrep-{}", &digest[..20]),
+        rule: format!("semgrep:{}", result.check_id),
+        severity: severity(&result.extra.severity).to_string(),
+        component: format!("{}:{}", project_key, path),
+        line: Some(line),
+        message: result.extra.message,
+        issue_type: issue_type(result.extra.metadata.category.as_deref()).to_string(),
+        profile: None,
+        engine: None,
+        also_reported_by: Vec::new(),
+        rule_details: None,
+        snippet: None,
+        fingerprint: None,
+        suppressed: false,
+    }
+}
+
+/// Maps Semgrep's `ERROR`/`WARNING`/`INFO` onto SonarQube severities.
+fn severity(value: &str) -> &'static str {
+    match value.to_ascii_uppercase().as_str() {
+        "CRITICAL" => "BLOCKER",
+        "ERROR" | "HIGH" => "CRITICAL",
+        "WARNING" | "MEDIUM" => "MAJOR",
+        _ => "MINOR",
+    }
+}
+
+/// Security rules report vulnerabilities and correctness rules bugs; the
+/// rest are code smells.
+fn issue_type(category: Option<&str>) -> &'static str {
+    match category {
+        Some("security") => "VULNERABILITY",
+        Some("correctness") => "BUG",
+        _ => "CODE_SMELL",
+    }
+}
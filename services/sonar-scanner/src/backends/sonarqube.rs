@@ -0,0 +1,288 @@
+use super::{run_process, ScanContext, ScanOutput, ScannerBackend};
+use crate::config::{SandboxConfig, ScannerIsolation};
+use crate::jobs::JobStage;
+use crate::metrics::{Metrics, Stage};
+use crate::profiles;
+use crate::proxy::OutboundProxy;
+use crate::resource_limits::{Enforcement, ResourceLimits};
+use crate::sandbox;
+use crate::shutdown::ChildProcesses;
+use crate::sonar_client::SonarClient;
+use crate::AppError;
+use async_trait::async_trait;
+use std::{
+    path::Path,
+    process::Command,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+use tracing::{info, info_span, warn, Instrument};
+
+/// Runs sonar-scanner against a SonarQube server and reads the results back
+/// through its Web API.
+pub struct SonarQubeBackend {
+    sandbox: SandboxConfig,
+    limits: ResourceLimits,
+    proxy: OutboundProxy,
+    metrics: Arc<Metrics>,
+    children: ChildProcesses,
+}
+
+impl SonarQubeBackend {
+    pub fn new(
+        sandbox: SandboxConfig,
+        limits: ResourceLimits,
+        proxy: OutboundProxy,
+        metrics: Arc<Metrics>,
+        children: ChildProcesses,
+    ) -> Self {
+        Self {
+            sandbox,
+            limits,
+            proxy,
+            metrics,
+            children,
+        }
+    }
+
+    /// Runs sonar-scanner under the resource limits, forwarding its output
+    /// to the job's log stream and recording what it used.
+    async fn run_sonar_scanner(&self, scan: &ScanContext<'_>) -> Result<(), AppError> {
+        let job_id = scan.project_key;
+        info!("Running sonar-scanner for job: {}", job_id);
+
+        let mut command = scanner_command(
+            scan.project_dir,
+            job_id,
+            scan.sonar,
+            &self.sandbox,
+            &self.limits,
+            &self.proxy,
+            scan.properties,
+        )?;
+        let limited = match self.sandbox.isolation {
+            ScannerIsolation::Host => {
+                Some(self.limits.apply(&mut command, "sonar-scanner", job_id))
+            }
+            ScannerIsolation::Container => None,
+        };
+        let result = run_process(
+            command,
+            "sonar-scanner",
+            scan.timeout,
+            scan.progress,
+            &self.children,
+        )
+        .await;
+        let resources = match limited {
+            Some(limited) => limited.finish(),
+            None => self.limits.report("sonar-scanner", Enforcement::Container),
+        };
+        if resources.oom_killed {
+            warn!("sonar-scanner for {} exceeded its memory limit", job_id);
+        }
+        scan.progress.resources(resources);
+        if result.is_err() && self.sandbox.isolation == ScannerIsolation::Container {
+            sandbox::remove_container(&self.sandbox, job_id).await;
+        }
+        result?;
+
+        info!("Scanner completed successfully");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ScannerBackend for SonarQubeBackend {
+    async fn prepare(&self, scan: &ScanContext<'_>) -> Result<(), AppError> {
+        match scan.profile {
+            Some(profile) if !profiles::is_default(profile) => {
+                profiles::apply_profile(scan.sonar, scan.project_key, profile, scan.languages).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs the scanner under the project key and waits for the CE task.
+    async fn run(&self, scan: &ScanContext<'_>) -> Result<Option<Duration>, AppError> {
+        let project_key = scan.project_key;
+        let progress = scan.progress;
+        let detail = || Some(project_key.to_string());
+
+        // Listen for the webhook before the report is submitted
+        let completion = scan.sonar.notifications().watch(project_key);
+
+        // Run sonar-scanner
+        progress.stage(JobStage::ScannerStarted, detail());
+        let started = Instant::now();
+        self.run_sonar_scanner(scan)
+            .instrument(info_span!("scanner", project_key))
+            .await?;
+        self.metrics.observe_stage(Stage::Scanner, started);
+        progress.stage(JobStage::ScannerFinished, detail());
+
+        // Wait for task completion
+        progress.stage(JobStage::CePending, detail());
+        let started = Instant::now();
+        let waited = scan
+            .sonar
+            .wait_for_completion(project_key, completion)
+            .instrument(info_span!("ce_wait", project_key))
+            .await?;
+        self.metrics.observe_stage(Stage::CeWait, started);
+        progress.stage(JobStage::CeSuccess, detail());
+        Ok(Some(waited))
+    }
+
+    /// Fetches the issues, quality gate status and security hotspots.
+    async fn collect(&self, scan: &ScanContext<'_>) -> Result<ScanOutput, AppError> {
+        let project_key = scan.project_key;
+        let filter = scan.filter;
+
+        // Fetch vulnerabilities, re-checking while the issues index catches up
+        let started = Instant::now();
+        let fetched = scan
+            .sonar
+            .fetch_stable_vulnerabilities(project_key, filter)
+            .instrument(info_span!("fetch_issues", project_key))
+            .await?;
+        self.metrics.observe_stage(Stage::Fetch, started);
+        scan.progress.stage(
+            JobStage::IssuesFetched,
+            Some(format!(
+                "{}: {} issue(s)",
+                project_key,
+                fetched.issues.len()
+            )),
+        );
+
+        // A missing gate status should not cost the caller the issues
+        let quality_gate = match scan
+            .sonar
+            .quality_gate(project_key, filter.analysis_ref.as_ref())
+            .await
+        {
+            Ok(gate) => Some(gate),
+            Err(e) => {
+                warn!("Quality gate status unavailable for {}: {}", project_key, e);
+                None
+            }
+        };
+
+        // Hotspots are reported apart from issues; losing them should not
+        // cost the caller the issues either
+        let hotspots = match scan
+            .sonar
+            .fetch_hotspots(project_key, filter.analysis_ref.as_ref())
+            .await
+        {
+            Ok(hotspots) => hotspots,
+            Err(e) => {
+                warn!("Hotspots unavailable for {}: {}", project_key, e);
+                Vec::new()
+            }
+        };
+
+        // Measures are a summary on top; a failure only loses them
+        let metrics = match scan
+            .sonar
+            .project_metrics(project_key, filter.analysis_ref.as_ref())
+            .await
+        {
+            Ok(metrics) => Some(metrics),
+            Err(e) => {
+                warn!("Measures unavailable for {}: {}", project_key, e);
+                None
+            }
+        };
+        Ok(ScanOutput {
+            fetched,
+            quality_gate,
+            hotspots,
+            metrics,
+            ce_wait: None,
+        })
+    }
+
+    async fn clean_up(&self, sonar: &SonarClient, project_keys: &[String], succeeded: bool) {
+        sonar.clean_up_projects(project_keys, succeeded).await;
+    }
+
+    /// Removes scanner containers, which outlive the killed `docker run`,
+    /// and withdraws reports SonarQube has not started processing.
+    async fn cancel(&self, sonar: &SonarClient, project_keys: &[String]) {
+        for project_key in project_keys {
+            if self.sandbox.isolation == ScannerIsolation::Container {
+                sandbox::remove_container(&self.sandbox, project_key).await;
+            }
+            match sonar.cancel_pending_tasks(project_key).await {
+                Ok(0) => {}
+                Ok(canceled) => info!("Canceled {} pending task(s) of {}", canceled, project_key),
+                Err(e) => warn!("Could not cancel tasks of {}: {}", project_key, e),
+            }
+        }
+        self.clean_up(sonar, project_keys, false).await;
+    }
+}
+
+/// Properties every scan is started with, credentials included. Overridable
+/// defaults such as `sonar.sources` are added by `run_analysis`.
+pub fn base_scanner_properties(job_id: &str, sonar: &SonarClient) -> Vec<(String, String)> {
+    let mut properties = vec![
+        ("sonar.projectKey".to_string(), job_id.to_string()),
+        ("sonar.host.url".to_string(), sonar.base_url.clone()),
+    ];
+
+    if sonar.use_token() {
+        properties.push(("sonar.login".to_string(), sonar.token.clone()));
+    } else {
+        properties.push(("sonar.login".to_string(), "admin".to_string()));
+        properties.push(("sonar.password".to_string(), sonar.password.clone()));
+    }
+
+    properties
+}
+
+/// Builds the sonar-scanner invocation shared by the real scan and the
+/// property validation dry run, in a container when `sandbox` asks for one.
+/// Outside a container `limits` are applied by the caller.
+pub fn scanner_command(
+    project_dir: &Path,
+    job_id: &str,
+    sonar: &SonarClient,
+    sandbox: &SandboxConfig,
+    limits: &ResourceLimits,
+    proxy: &OutboundProxy,
+    extra_properties: &[(String, String)],
+) -> Result<Command, AppError> {
+    let args = base_scanner_properties(job_id, sonar)
+        .into_iter()
+        .chain(extra_properties.iter().cloned())
+        .map(|(key, value)| match key.as_str() {
+            "sonar.host.url" => format!("-D{}={}", key, sandbox::sonar_url(sandbox, &value)),
+            _ => format!("-D{}={}", key, value),
+        })
+        .collect();
+
+    sandbox::scanner_command(sandbox, limits, proxy, project_dir, job_id, args)
+}
+
+/// Version reported by `sonar-scanner --version`, looked up once per process.
+pub fn scanner_version() -> Option<String> {
+    static VERSION: OnceLock<Option<String>> = OnceLock::new();
+
+    VERSION
+        .get_or_init(|| {
+            let output = Command::new("sonar-scanner")
+                .arg("--version")
+                .output()
+                .ok()?;
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            stdout
+                .lines()
+                .find_map(|line| line.split_once("SonarScanner "))
+                .and_then(|(_, rest)| rest.split_whitespace().next())
+                .map(str::to_string)
+        })
+        .clone()
+}
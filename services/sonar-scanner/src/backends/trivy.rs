@@ -0,0 +1,162 @@
+use super::run_process;
+use crate::config::TrivyConfig;
+use crate::jobs::{JobProgress, JobStage};
+use crate::metrics::{Metrics, Stage};
+use crate::shutdown::ChildProcesses;
+use crate::AppError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::Path,
+    process::Command,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{info, info_span, Instrument};
+
+/// Written next to the project directory so it is not scanned itself.
+const RESULTS_FILE: &str = "trivy.json";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TrivyReport {
+    #[serde(default)]
+    results: Vec<TrivyResult>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TrivyResult {
+    target: String,
+    #[serde(rename = "Type", default)]
+    package_type: String,
+    #[serde(default)]
+    vulnerabilities: Vec<TrivyVulnerability>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TrivyVulnerability {
+    #[serde(rename = "VulnerabilityID")]
+    vulnerability_id: String,
+    pkg_name: String,
+    installed_version: String,
+    fixed_version: Option<String>,
+    severity: String,
+    title: Option<String>,
+    #[serde(rename = "PrimaryURL")]
+    primary_url: Option<String>,
+}
+
+/// A known vulnerability in a package the project depends on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependencyVulnerability {
+    /// CVE or advisory ID, e.g. `CVE-2021-44228`.
+    pub id: String,
+    pub package: String,
+    pub installed_version: String,
+    /// First release with a fix; absent when none is available.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_version: Option<String>,
+    /// `CRITICAL`, `HIGH`, `MEDIUM`, `LOW` or `UNKNOWN`.
+    pub severity: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Lock file or manifest the package was found in, relative to the
+    /// project root.
+    pub target: String,
+    /// Ecosystem, e.g. `npm`, `pip` or `debian`.
+    pub package_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Runs `trivy fs` over the extracted project to find vulnerable dependencies
+/// and OS packages.
+#[derive(Clone)]
+pub struct TrivyScanner {
+    config: TrivyConfig,
+    metrics: Arc<Metrics>,
+    children: ChildProcesses,
+}
+
+impl TrivyScanner {
+    pub fn new(config: &TrivyConfig, metrics: Arc<Metrics>, children: ChildProcesses) -> Self {
+        Self {
+            config: config.clone(),
+            metrics,
+            children,
+        }
+    }
+
+    pub async fn scan(
+        &self,
+        project_dir: &Path,
+        timeout: Duration,
+        progress: &JobProgress,
+    ) -> Result<Vec<DependencyVulnerability>, AppError> {
+        info!("Running trivy on {:?}", project_dir);
+        let results_path = project_dir.with_file_name(RESULTS_FILE);
+
+        progress.stage(JobStage::ScannerStarted, Some("trivy".to_string()));
+        let started = Instant::now();
+        run_process(
+            self.command(project_dir, &results_path),
+            "trivy",
+            timeout,
+            progress,
+            &self.children,
+        )
+        .instrument(info_span!("trivy"))
+        .await?;
+        self.metrics.observe_stage(Stage::Scanner, started);
+        progress.stage(JobStage::ScannerFinished, Some("trivy".to_string()));
+
+        let text = fs::read_to_string(&results_path)
+            .map_err(|e| AppError::ScannerError(format!("Failed to read trivy results: {}", e)))?;
+        let report: TrivyReport = serde_json::from_str(&text)
+            .map_err(|e| AppError::ScannerError(format!("Failed to parse trivy results: {}", e)))?;
+
+        let vulnerabilities: Vec<DependencyVulnerability> = report
+            .results
+            .into_iter()
+            .flat_map(|result| {
+                let target = result.target;
+                let package_type = result.package_type;
+                result
+                    .vulnerabilities
+                    .into_iter()
+                    .map(move |vulnerability| DependencyVulnerability {
+                        id: vulnerability.vulnerability_id,
+                        package: vulnerability.pkg_name,
+                        installed_version: vulnerability.installed_version,
+                        fixed_version: vulnerability.fixed_version.filter(|v| !v.is_empty()),
+                        severity: vulnerability.severity,
+                        title: vulnerability.title,
+                        target: target.clone(),
+                        package_type: package_type.clone(),
+                        url: vulnerability.primary_url,
+                    })
+            })
+            .collect();
+        info!(
+            "Trivy found {} dependency vulnerabilities",
+            vulnerabilities.len()
+        );
+        Ok(vulnerabilities)
+    }
+
+    fn command(&self, project_dir: &Path, results_path: &Path) -> Command {
+        let mut command = Command::new(&self.config.binary);
+        command
+            .args(["fs", "--format", "json", "--scanners", "vuln"])
+            .args(["--pkg-types", "os,library"])
+            .arg("--output")
+            .arg(results_path);
+        if self.config.skip_db_update {
+            command.arg("--skip-db-update");
+        }
+        command.arg(".").current_dir(project_dir);
+        command
+    }
+}
@@ -0,0 +1,75 @@
+//! Baselines: the issues of an earlier scan that a team has accepted for
+//! now. Matching issues are still returned, marked `suppressed`, but do not
+//! count towards `total_count` or fail CI reports.
+
+use crate::formats::{fingerprint, CONTENT_FINGERPRINT_KEY, SARIF_FINGERPRINT_KEY};
+use crate::{AppError, SonarIssue};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Fingerprints of the baseline's issues. Ordered so the set's `Debug`
+/// output, part of the result cache key, is stable.
+#[derive(Debug, Default)]
+pub struct Baseline {
+    fingerprints: BTreeSet<String>,
+}
+
+impl Baseline {
+    /// Reads an earlier export of this service: the JSON response, a SARIF
+    /// log or a GitLab Code Quality report.
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        let invalid = |reason: &str| {
+            AppError::InvalidRequest(format!("Field 'baseline' is not a scan export: {}", reason))
+        };
+        let export: Value =
+            serde_json::from_str(value).map_err(|e| invalid(&format!("invalid JSON ({})", e)))?;
+
+        let fingerprints = if let Some(issues) = export.get("vulnerabilities") {
+            let issues: Vec<SonarIssue> = serde_json::from_value(issues.clone())
+                .map_err(|e| invalid(&format!("unreadable issue ({})", e)))?;
+            issues
+                .iter()
+                .flat_map(|issue| [Some(fingerprint(issue)), issue.fingerprint.clone()])
+                .flatten()
+                .collect()
+        } else if let Some(runs) = export.get("runs").and_then(Value::as_array) {
+            runs.iter()
+                .filter_map(|run| run.get("results").and_then(Value::as_array))
+                .flatten()
+                .filter_map(|result| result.get("partialFingerprints"))
+                .flat_map(|fingerprints| {
+                    [SARIF_FINGERPRINT_KEY, CONTENT_FINGERPRINT_KEY]
+                        .map(|key| fingerprints.get(key).and_then(Value::as_str))
+                })
+                .flatten()
+                .map(str::to_string)
+                .collect()
+        } else if let Some(issues) = export.as_array() {
+            issues
+                .iter()
+                .filter_map(|issue| issue.get("fingerprint").and_then(Value::as_str))
+                .map(str::to_string)
+                .collect()
+        } else {
+            return Err(invalid(
+                "expected a JSON result, a SARIF log or a GitLab Code Quality report",
+            ));
+        };
+
+        Ok(Self { fingerprints })
+    }
+
+    /// Marks the issues found in the baseline, by either fingerprint, and
+    /// returns how many there were.
+    pub fn suppress(&self, issues: &mut [SonarIssue]) -> usize {
+        let mut suppressed = 0;
+        for issue in issues {
+            let known = |candidate: &String| self.fingerprints.contains(candidate);
+            if known(&fingerprint(issue)) || issue.fingerprint.as_ref().is_some_and(known) {
+                issue.suppressed = true;
+                suppressed += 1;
+            }
+        }
+        suppressed
+    }
+}
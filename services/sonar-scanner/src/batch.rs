@@ -0,0 +1,378 @@
+//! `POST /analyze/batch`: several archives, or a manifest of repositories,
+//! in one request. Each project becomes its own job, at most
+//! `BATCH_PARALLELISM` of them scan at once, and the response sums up the
+//! outcome per project.
+
+use crate::audit::Caller;
+use crate::auth::Tenant;
+use crate::git_source::GitSource;
+use crate::jobs::{JobError, JobStatus};
+use crate::request_id::RequestId;
+use crate::{
+    auth, github, ids, job_temp_dir, json_option_fields, options_from_fields, parse_sonar_token,
+    prepare_analysis, read_text_field, run_job, save_field, upload_error, Analysis, AnalyzeOptions,
+    AnalyzeResponse, AppError, AppState, PreparedUpload, UploadSource, OPTION_FIELDS,
+};
+use axum::{
+    extract::{FromRequest, Multipart, Query, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::sync::OwnedSemaphorePermit;
+use tracing::{info, info_span, instrument, Instrument};
+
+#[derive(Debug, Deserialize)]
+pub struct BatchQuery {
+    /// Return `202 Accepted` with the job IDs instead of waiting for the scans.
+    #[serde(rename = "async", default)]
+    run_async: bool,
+    /// Issue types to fetch (comma-separated); the body field takes precedence.
+    issue_types: Option<String>,
+    /// Least severe issues to return; the body field takes precedence.
+    min_severity: Option<String>,
+    /// Scan even if an identical archive's result is cached.
+    #[serde(default)]
+    force: bool,
+}
+
+/// One project read from the request, not yet validated.
+struct BatchItem {
+    /// The upload's file name or the repository's redacted URL.
+    name: String,
+    temp_dir: TempDir,
+    source: UploadSource,
+}
+
+/// A registered job waiting for its scan.
+struct PendingScan {
+    index: usize,
+    job_id: String,
+    upload: PreparedUpload,
+    reserved_slot: Option<OwnedSemaphorePermit>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchSummary {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    canceled: usize,
+    /// Jobs still to run; only non-zero for `async=true`.
+    queued: usize,
+    /// Issues found across the succeeded jobs.
+    issue_count: usize,
+    results: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchItemResult {
+    name: String,
+    job_id: String,
+    status: JobStatus,
+    status_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issue_count: Option<usize>,
+    /// Quality gate status of the project, when it could be read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality_gate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JobError>,
+    /// The full result, as `/analyze` returns it in JSON.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Arc<AnalyzeResponse>>,
+}
+
+impl BatchItemResult {
+    fn new(name: String, job_id: String, status: JobStatus) -> Self {
+        Self {
+            status_url: format!("/jobs/{}", job_id),
+            name,
+            job_id,
+            status,
+            issue_count: None,
+            quality_gate: None,
+            error: None,
+            result: None,
+        }
+    }
+
+    fn record(&mut self, outcome: Result<Arc<AnalyzeResponse>, AppError>) {
+        match outcome {
+            Ok(response) => {
+                self.status = JobStatus::Succeeded;
+                self.issue_count = Some(response.total_count);
+                self.quality_gate = response.quality_gate.as_ref().map(|g| g.status.clone());
+                self.result = Some(response);
+            }
+            Err(e) => {
+                self.status = match e {
+                    AppError::Canceled(_) => JobStatus::Canceled,
+                    _ => JobStatus::Failed,
+                };
+                self.error = Some(JobError::from(&e));
+            }
+        }
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn analyze_batch_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    Extension(request_id): Extension<RequestId>,
+    Extension(caller): Extension<Caller>,
+    Query(query): Query<BatchQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
+    info!("Received batch analyze request");
+
+    let sonar_token = parse_sonar_token(
+        request
+            .headers()
+            .get(auth::SONAR_TOKEN_HEADER)
+            .map(|value| value.to_str().unwrap_or_default()),
+    )?;
+    let github_token = github::parse_token(
+        request
+            .headers()
+            .get(auth::GITHUB_TOKEN_HEADER)
+            .map(|value| value.to_str().unwrap_or_default()),
+    )?;
+
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let (items, fields) = if is_json {
+        let Json(body) =
+            Json::<serde_json::Map<String, serde_json::Value>>::from_request(request, &state)
+                .await
+                .map_err(|e| AppError::InvalidRequest(format!("Invalid JSON body: {}", e)))?;
+        read_manifest(body)?
+    } else {
+        let mut multipart = Multipart::from_request(request, &state)
+            .await
+            .map_err(|e| AppError::MissingField(format!("Invalid multipart body: {}", e)))?;
+        read_uploads(&mut multipart)
+            .instrument(info_span!("read_upload"))
+            .await?
+    };
+
+    let max_items = state.config.limits.max_batch_items;
+    if items.is_empty() {
+        return Err(AppError::MissingField(
+            "No archives or repositories in the batch".to_string(),
+        ));
+    }
+    if items.len() > max_items {
+        return Err(AppError::InvalidRequest(format!(
+            "A batch may hold at most {} projects, got {}",
+            max_items,
+            items.len()
+        )));
+    }
+    // Checked once up front so a bad field rejects the batch, not each job
+    batch_options(&fields, &query, &tenant)?;
+
+    let mut results = Vec::with_capacity(items.len());
+    let mut scans = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        let job_id = ids::job_id();
+        let mut options = batch_options(&fields, &query, &tenant)?;
+        options.sonar_token = sonar_token.clone();
+        options.github_token = github_token.clone();
+        options.request_id = Some(request_id.0.clone());
+        options.caller = caller.clone();
+        let job_tenant = options.tenant.clone();
+        let job_project_name = options.project_name.clone();
+        let mut result = BatchItemResult::new(item.name, job_id.clone(), JobStatus::Queued);
+        match prepare_analysis(
+            &state,
+            &job_id,
+            item.temp_dir,
+            item.source,
+            options,
+            query.force,
+        ) {
+            Ok(Analysis::Cached(response)) => result.record(Ok(response)),
+            Ok(Analysis::Scan(upload, reserved_slot)) => scans.push(PendingScan {
+                index,
+                job_id,
+                upload,
+                reserved_slot,
+            }),
+            // Recorded like any failed job so `/jobs/{id}` explains it
+            Err(e) => {
+                state
+                    .jobs
+                    .create(&job_id, job_tenant.as_deref(), job_project_name.as_deref());
+                state.jobs.fail(&job_id, &e);
+                result.record(Err(e));
+            }
+        }
+        results.push(result);
+    }
+    info!(
+        "Batch of {} project(s), {} to scan",
+        results.len(),
+        scans.len()
+    );
+
+    if query.run_async {
+        let tasks = state.tasks.clone();
+        tasks.spawn(async move {
+            scan_all(state, scans).await;
+        });
+        return Ok((StatusCode::ACCEPTED, Json(summarize(results))).into_response());
+    }
+
+    for (index, outcome) in scan_all(state, scans).await {
+        results[index].record(outcome);
+    }
+    Ok(Json(summarize(results)).into_response())
+}
+
+/// Runs the scans, at most `BATCH_PARALLELISM` at a time, and returns each
+/// outcome with the index of its project.
+async fn scan_all(
+    state: AppState,
+    scans: Vec<PendingScan>,
+) -> Vec<(usize, Result<Arc<AnalyzeResponse>, AppError>)> {
+    let parallelism = state.config.scans.batch_parallelism.max(1);
+    stream::iter(scans)
+        .map(|scan| {
+            let state = state.clone();
+            async move {
+                let outcome = run_job(state, scan.job_id, scan.upload, scan.reserved_slot).await;
+                (scan.index, outcome)
+            }
+        })
+        .buffer_unordered(parallelism)
+        .collect()
+        .await
+}
+
+fn summarize(results: Vec<BatchItemResult>) -> BatchSummary {
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    BatchSummary {
+        total: results.len(),
+        succeeded: count(JobStatus::Succeeded),
+        failed: count(JobStatus::Failed),
+        canceled: count(JobStatus::Canceled),
+        queued: count(JobStatus::Queued),
+        issue_count: results.iter().filter_map(|r| r.issue_count).sum(),
+        results,
+    }
+}
+
+/// The shared options of the batch, applied afresh for each job.
+fn batch_options(
+    fields: &[(String, String)],
+    query: &BatchQuery,
+    tenant: &Tenant,
+) -> Result<AnalyzeOptions, AppError> {
+    let mut options = options_from_fields(fields)?;
+    options.apply_query_filters(query.issue_types.as_deref(), query.min_severity.as_deref())?;
+    options.apply_tenant(tenant)?;
+    Ok(options)
+}
+
+/// Reads every `file` (or `zip`) field into its own temp dir, and the option
+/// fields shared by all of them.
+async fn read_uploads(
+    multipart: &mut Multipart,
+) -> Result<(Vec<BatchItem>, Vec<(String, String)>), AppError> {
+    let mut items = Vec::new();
+    let mut fields = Vec::new();
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        upload_error(e, |e| {
+            AppError::MissingField(format!("Failed to read multipart field: {}", e))
+        })
+    })? {
+        let name = field.name().unwrap_or("").to_string();
+
+        match name.as_str() {
+            "file" | "zip" => {
+                let item_name = field
+                    .file_name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("archive-{}", items.len() + 1));
+                let temp_dir = job_temp_dir()?;
+                let path = temp_dir.path().join("upload.archive");
+                let size = save_field(field, &path).await?;
+                info!("Saved {} byte upload {} to {:?}", size, item_name, path);
+                items.push(BatchItem {
+                    name: item_name,
+                    temp_dir,
+                    source: UploadSource::Archive(path),
+                });
+            }
+            name if OPTION_FIELDS.contains(&name) => {
+                let value = read_text_field(field, name).await?;
+                fields.push((name.to_string(), value));
+            }
+            _ => {}
+        }
+    }
+
+    Ok((items, fields))
+}
+
+/// Reads a JSON body: `repositories`, a list of `{"git_url", "ref"}` objects
+/// or plain URLs, and any of the option fields shared by all of them.
+fn read_manifest(
+    mut body: serde_json::Map<String, serde_json::Value>,
+) -> Result<(Vec<BatchItem>, Vec<(String, String)>), AppError> {
+    let repositories = match body.remove("repositories") {
+        Some(serde_json::Value::Array(repositories)) => repositories,
+        None => return Err(AppError::MissingField("repositories".to_string())),
+        Some(_) => {
+            return Err(AppError::InvalidRequest(
+                "Field 'repositories' must be an array".to_string(),
+            ))
+        }
+    };
+
+    let mut items = Vec::with_capacity(repositories.len());
+    for repository in repositories {
+        let source = match repository {
+            serde_json::Value::String(url) => GitSource::parse(&url, None)?,
+            serde_json::Value::Object(repository) => {
+                let url = repository
+                    .get("git_url")
+                    .and_then(|url| url.as_str())
+                    .ok_or_else(|| AppError::MissingField("repositories[].git_url".to_string()))?;
+                let reference = match repository.get("ref") {
+                    Some(serde_json::Value::String(reference)) => Some(reference.as_str()),
+                    None | Some(serde_json::Value::Null) => None,
+                    Some(_) => {
+                        return Err(AppError::InvalidRequest(
+                            "Field 'repositories[].ref' must be a string".to_string(),
+                        ))
+                    }
+                };
+                GitSource::parse(url, reference)?
+            }
+            _ => {
+                return Err(AppError::InvalidRequest(
+                    "Each repository must be a URL or an object with 'git_url'".to_string(),
+                ))
+            }
+        };
+        let temp_dir = job_temp_dir()?;
+        items.push(BatchItem {
+            name: source.redacted_url(),
+            temp_dir,
+            source: UploadSource::Git(source),
+        });
+    }
+
+    Ok((items, json_option_fields(body)))
+}
@@ -0,0 +1,376 @@
+//! Bitbucket Code Insights. A job sent with `bitbucket_repo` and
+//! `bitbucket_commit` publishes its outcome as a report on that commit, which
+//! passes or fails by `BITBUCKET_FAIL_ON`, with one annotation per issue so
+//! findings show inline in pull request diffs. Works with Bitbucket Cloud
+//! and with Bitbucket Server and Data Center, whose APIs differ in shape.
+
+use crate::config::{BitbucketConfig, BitbucketEdition};
+use crate::job_list::severity_counts;
+use crate::proxy::OutboundProxy;
+use crate::review_summary;
+use crate::severity::Severity;
+use crate::{AnalyzeResponse, AppError, SonarIssue};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// Report key, and the prefix of per-project keys.
+const REPORT_KEY: &str = "sonar-backend";
+
+const REPORTER: &str = "sonar-backend";
+
+/// Most annotations a report may hold on either edition.
+const MAX_ANNOTATIONS: usize = 1000;
+
+/// Most annotations Bitbucket Cloud accepts per request.
+const CLOUD_ANNOTATION_BATCH: usize = 100;
+
+/// Parses `bitbucket_repo`: `workspace/repo_slug` on Bitbucket Cloud,
+/// `PROJECT/repo_slug` on Bitbucket Server and Data Center.
+pub fn parse_repo(name: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && part.len() <= 128
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~'))
+    };
+    match value.split_once('/') {
+        Some((owner, slug)) if valid_part(owner) && valid_part(slug) => Ok(value.to_string()),
+        _ => Err(AppError::InvalidRequest(format!(
+            "Field '{}' must be a repository as workspace/repo_slug or PROJECT/repo_slug",
+            name
+        ))),
+    }
+}
+
+/// The commit a job publishes its report on.
+#[derive(Clone)]
+pub struct InsightsTarget {
+    owner: String,
+    slug: String,
+    commit: String,
+    /// Keeps the reports of several projects scanned at one commit apart.
+    project_name: Option<String>,
+}
+
+impl InsightsTarget {
+    /// The commit named by `bitbucket_repo` and `bitbucket_commit`, if any,
+    /// once checked for consistency.
+    pub fn new(
+        repo: Option<&str>,
+        commit: Option<&str>,
+        project_name: Option<&str>,
+        config: &BitbucketConfig,
+    ) -> Result<Option<Self>, AppError> {
+        let (repo, commit) = match (repo, commit) {
+            (None, None) => return Ok(None),
+            (Some(repo), Some(commit)) => (repo, commit),
+            (Some(_), None) => {
+                return Err(AppError::InvalidRequest(
+                    "Field 'bitbucket_commit' is required with 'bitbucket_repo'".to_string(),
+                ))
+            }
+            (None, Some(_)) => {
+                return Err(AppError::InvalidRequest(
+                    "Field 'bitbucket_repo' is required with 'bitbucket_commit'".to_string(),
+                ))
+            }
+        };
+        if config.token.is_none() {
+            return Err(AppError::InvalidRequest(
+                "Field 'bitbucket_repo' needs BITBUCKET_TOKEN to be configured".to_string(),
+            ));
+        }
+        let (owner, slug) = repo.split_once('/').unwrap_or((repo, ""));
+        Ok(Some(Self {
+            owner: owner.to_string(),
+            slug: slug.to_string(),
+            commit: commit.to_string(),
+            project_name: project_name.map(str::to_string),
+        }))
+    }
+
+    /// `REPORT_KEY`, suffixed with the project name's letters and digits
+    /// when there is one.
+    fn report_key(&self) -> String {
+        let Some(name) = &self.project_name else {
+            return REPORT_KEY.to_string();
+        };
+        let suffix: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '-'
+                }
+            })
+            .take(64)
+            .collect();
+        format!("{}-{}", REPORT_KEY, suffix)
+    }
+
+    fn title(&self) -> String {
+        match &self.project_name {
+            Some(name) => format!("Scan results for {}", name),
+            None => "Scan results".to_string(),
+        }
+    }
+}
+
+/// Publishes Code Insights reports.
+#[derive(Clone)]
+pub struct InsightsPublisher {
+    http: reqwest::Client,
+    config: BitbucketConfig,
+}
+
+impl InsightsPublisher {
+    pub fn new(config: &BitbucketConfig, proxy: &OutboundProxy) -> Self {
+        let http = proxy
+            .client_builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    /// Publishes a finished job's report on `target` in a task tracked by
+    /// `tasks`. Failures are logged, never returned: the job has already
+    /// finished and its result stays available from the API.
+    pub fn publish(
+        &self,
+        tasks: &TaskTracker,
+        target: InsightsTarget,
+        job_id: &str,
+        outcome: Result<&AnalyzeResponse, &AppError>,
+    ) {
+        let link = self
+            .config
+            .public_url
+            .as_deref()
+            .map(|url| review_summary::result_url(url, job_id));
+        let report = self.report(&target, outcome, link.as_deref());
+        let annotations: Vec<Value> = match outcome {
+            Ok(response) => {
+                let mut issues: Vec<&SonarIssue> = response
+                    .vulnerabilities
+                    .iter()
+                    .filter(|issue| !issue.suppressed)
+                    .collect();
+                // Stable, so issues of one severity keep SonarQube's order
+                issues.sort_by_key(|issue| std::cmp::Reverse(Severity::parse(&issue.severity)));
+                issues
+                    .into_iter()
+                    .take(MAX_ANNOTATIONS)
+                    .map(|issue| self.annotation(issue, link.as_deref()))
+                    .collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        let publisher = self.clone();
+        let job_id = job_id.to_string();
+        tasks.spawn(async move {
+            let repo = format!("{}/{}", target.owner, target.slug);
+            match publisher.upload(&target, &report, &annotations).await {
+                Ok(()) => info!(
+                    "Published Code Insights report for job {} on {}@{}",
+                    job_id, repo, target.commit
+                ),
+                Err(e) => warn!(
+                    "Failed to publish Code Insights report for job {} on {}@{}: {}",
+                    job_id, repo, target.commit, e
+                ),
+            }
+        });
+    }
+
+    /// Replaces the commit's report and its annotations.
+    async fn upload(
+        &self,
+        target: &InsightsTarget,
+        report: &Value,
+        annotations: &[Value],
+    ) -> Result<(), String> {
+        let report_url = self.report_url(target);
+        // Deleting the report drops the annotations of the previous scan
+        let response = self
+            .authed(self.http.delete(&report_url))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() && status != reqwest::StatusCode::NOT_FOUND {
+            return Err(format!("HTTP {} deleting the previous report", status));
+        }
+
+        self.send(self.http.put(&report_url).json(report)).await?;
+        let annotations_url = format!("{}/annotations", report_url);
+        match self.config.edition {
+            BitbucketEdition::Cloud => {
+                for batch in annotations.chunks(CLOUD_ANNOTATION_BATCH) {
+                    self.send(self.http.post(&annotations_url).json(batch))
+                        .await?;
+                }
+            }
+            BitbucketEdition::Server if !annotations.is_empty() => {
+                let body = json!({ "annotations": annotations });
+                self.send(self.http.post(&annotations_url).json(&body))
+                    .await?;
+            }
+            BitbucketEdition::Server => {}
+        }
+        Ok(())
+    }
+
+    fn report_url(&self, target: &InsightsTarget) -> String {
+        let base = self.config.url.trim_end_matches('/');
+        match self.config.edition {
+            BitbucketEdition::Cloud => format!(
+                "{}/repositories/{}/{}/commit/{}/reports/{}",
+                base,
+                target.owner,
+                target.slug,
+                target.commit,
+                target.report_key()
+            ),
+            BitbucketEdition::Server => format!(
+                "{}/rest/insights/1.0/projects/{}/repos/{}/commits/{}/reports/{}",
+                base,
+                target.owner,
+                target.slug,
+                target.commit,
+                target.report_key()
+            ),
+        }
+    }
+
+    /// The report: pass or fail with the reason, and the issue counts.
+    fn report(
+        &self,
+        target: &InsightsTarget,
+        outcome: Result<&AnalyzeResponse, &AppError>,
+        link: Option<&str>,
+    ) -> Value {
+        let (passed, details, data) = match outcome {
+            Ok(response) => {
+                let (passed, reason) = review_summary::verdict(response, self.config.fail_on);
+                let counts = severity_counts(response);
+                let mut data = vec![
+                    number_data("Blocker issues", counts.blocker),
+                    number_data("Critical issues", counts.critical),
+                    number_data("Major issues", counts.major),
+                    number_data("Minor issues", counts.minor),
+                    number_data("Info issues", counts.info),
+                ];
+                if let Some(gate) = &response.quality_gate {
+                    data.push(
+                        json!({ "title": "Quality gate", "type": "TEXT", "value": gate.status }),
+                    );
+                }
+                (passed, reason, data)
+            }
+            Err(AppError::Canceled(_)) => (false, "Scan canceled".to_string(), Vec::new()),
+            Err(e) => (false, format!("Scan failed: {}", e.parts().1), Vec::new()),
+        };
+        let mut report = json!({
+            "title": target.title(),
+            "details": details,
+            "reporter": REPORTER,
+            "data": data,
+        });
+        match self.config.edition {
+            BitbucketEdition::Cloud => {
+                report["result"] = if passed { "PASSED" } else { "FAILED" }.into();
+                report["report_type"] = "SECURITY".into();
+            }
+            BitbucketEdition::Server => {
+                report["result"] = if passed { "PASS" } else { "FAIL" }.into();
+            }
+        }
+        if let Some(link) = link {
+            report["link"] = link.into();
+        }
+        report
+    }
+
+    fn annotation(&self, issue: &SonarIssue, link: Option<&str>) -> Value {
+        let severity = Severity::parse(&issue.severity).unwrap_or(Severity::Info);
+        let kind = match issue.issue_type.as_str() {
+            "BUG" => "BUG",
+            "CODE_SMELL" => "CODE_SMELL",
+            _ => "VULNERABILITY",
+        };
+        let external_id = issue.fingerprint.as_deref().unwrap_or(&issue.key);
+        let message = format!(
+            "{} ({})",
+            review_summary::one_line(&issue.message),
+            issue.rule
+        );
+        let mut annotation = match self.config.edition {
+            BitbucketEdition::Cloud => json!({
+                "external_id": external_id,
+                "annotation_type": kind,
+                "path": issue.path(),
+                "summary": message,
+                "severity": match severity {
+                    Severity::Blocker | Severity::Critical => "CRITICAL",
+                    Severity::Major => "HIGH",
+                    Severity::Minor => "MEDIUM",
+                    Severity::Info => "LOW",
+                },
+            }),
+            BitbucketEdition::Server => json!({
+                "externalId": external_id,
+                "type": kind,
+                "path": issue.path(),
+                "message": message,
+                "severity": match severity {
+                    Severity::Blocker | Severity::Critical => "HIGH",
+                    Severity::Major => "MEDIUM",
+                    Severity::Minor | Severity::Info => "LOW",
+                },
+            }),
+        };
+        if let Some(line) = issue.line.filter(|&line| line > 0) {
+            annotation["line"] = line.into();
+        }
+        if let Some(link) = link {
+            annotation["link"] = link.into();
+        }
+        annotation
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let response = self
+            .authed(request)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            Err(format!("HTTP {}", status))
+        }
+    }
+
+    fn authed(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let token = self.config.token.as_deref().unwrap_or_default();
+        match &self.config.username {
+            Some(username) => request.basic_auth(username, Some(token)),
+            None => request.bearer_auth(token),
+        }
+    }
+}
+
+fn number_data(title: &str, value: usize) -> Value {
+    json!({ "title": title, "type": "NUMBER", "value": value })
+}
@@ -0,0 +1,150 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// Trips open after `failure_threshold` consecutive failures and rejects calls
+/// until `cooldown` has elapsed. After the cooldown a single probe call is let
+/// through (half-open); its outcome either closes the breaker or re-opens it.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// When the half-open probe was let through. A probe whose caller went
+    /// away without reporting back expires after another cooldown.
+    probe_started_at: Option<Instant>,
+}
+
+impl BreakerState {
+    fn probe_in_flight(&self, cooldown: Duration) -> bool {
+        self.probe_started_at
+            .is_some_and(|started| started.elapsed() < cooldown)
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Returns false while the breaker is open and still cooling down.
+    /// Does not claim the half-open probe slot.
+    pub fn is_available(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        match state.opened_at {
+            Some(opened_at) => {
+                opened_at.elapsed() >= self.cooldown && !state.probe_in_flight(self.cooldown)
+            }
+            None => true,
+        }
+    }
+
+    /// Claims permission for a call. In the half-open state only one caller
+    /// gets through until [`record_success`] or [`record_failure`] is called.
+    ///
+    /// [`record_success`]: CircuitBreaker::record_success
+    /// [`record_failure`]: CircuitBreaker::record_failure
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() < self.cooldown => false,
+            Some(_) if state.probe_in_flight(self.cooldown) => false,
+            Some(_) => {
+                info!("Circuit breaker half-open, probing SonarQube");
+                state.probe_started_at = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.opened_at.is_some() {
+            info!("Circuit breaker closed, SonarQube recovered");
+        }
+        *state = BreakerState::default();
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        state.probe_started_at = None;
+
+        if state.consecutive_failures >= self.failure_threshold {
+            if state.opened_at.is_none() {
+                warn!(
+                    "Circuit breaker opened after {} consecutive failures",
+                    state.consecutive_failures
+                );
+            }
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn opens_after_threshold_failures() {
+        let breaker = CircuitBreaker::new(3, COOLDOWN);
+        for _ in 0..2 {
+            assert!(breaker.try_acquire());
+            breaker.record_failure();
+        }
+        assert!(breaker.is_available());
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+        assert!(!breaker.is_available());
+    }
+
+    #[test]
+    fn rejects_calls_while_open() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        breaker.record_failure();
+        assert!(!breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn closes_after_successful_probe() {
+        let breaker = CircuitBreaker::new(1, COOLDOWN);
+        breaker.record_failure();
+        assert!(!breaker.try_acquire());
+
+        std::thread::sleep(COOLDOWN * 2);
+        assert!(breaker.try_acquire());
+        // Only one probe at a time while half-open
+        assert!(!breaker.try_acquire());
+        breaker.record_success();
+
+        assert!(breaker.is_available());
+        assert!(breaker.try_acquire());
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn reopens_after_failed_probe() {
+        let breaker = CircuitBreaker::new(1, COOLDOWN);
+        breaker.record_failure();
+        std::thread::sleep(COOLDOWN * 2);
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+        assert!(!breaker.try_acquire());
+    }
+}
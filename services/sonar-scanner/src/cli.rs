@@ -0,0 +1,58 @@
+use crate::config::Config;
+use clap::{Args, Parser, Subcommand};
+use std::{net::IpAddr, path::PathBuf};
+
+/// Command-line options. Flags override the matching environment variables
+/// and `config.toml` settings.
+#[derive(Debug, Parser)]
+#[command(name = "sonar-backend", version, about = "SonarQube analysis service")]
+pub struct Cli {
+    /// Configuration file (defaults to `config.toml` if present).
+    #[arg(long, env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on.
+    #[arg(long)]
+    pub bind_addr: Option<IpAddr>,
+
+    /// Port to listen on.
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Runs a one-off task instead of the server.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Scans a local directory or archive, prints the result to stdout and
+    /// exits, without starting the server.
+    Scan(ScanArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ScanArgs {
+    /// Project directory, or a ZIP, `.tar` or `.tar.gz` archive.
+    pub path: PathBuf,
+
+    /// Output format: `json`, `sarif`, `gl-codequality`, `junit`, `csv` or
+    /// `html`.
+    #[arg(long, default_value = "json")]
+    pub format: String,
+
+    /// An `/analyze` option field, e.g. `--option engine=semgrep`. Repeatable.
+    #[arg(long = "option", value_name = "NAME=VALUE")]
+    pub options: Vec<String>,
+}
+
+impl Cli {
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(bind_addr) = self.bind_addr {
+            config.server.bind_addr = bind_addr;
+        }
+        if let Some(port) = self.port {
+            config.server.port = port;
+        }
+    }
+}
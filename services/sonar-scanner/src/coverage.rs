@@ -0,0 +1,313 @@
+use crate::AppError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+use tracing::warn;
+
+/// Directories never searched for coverage reports.
+const SKIPPED_DIRS: &[&str] = &[".git", "node_modules"];
+
+/// Directory of the project an uploaded coverage report is placed in.
+const UPLOAD_DIR: &str = ".coverage-upload";
+
+/// How many bytes of an explicitly named report are sniffed for its format.
+const SNIFF_LEN: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverageFormat {
+    Lcov,
+    Jacoco,
+    Cobertura,
+    Go,
+    Opencover,
+    Simplecov,
+    Clover,
+}
+
+impl CoverageFormat {
+    /// The scanner property SonarQube reads this report format from.
+    pub fn property(self) -> &'static str {
+        match self {
+            CoverageFormat::Lcov => "sonar.javascript.lcov.reportPaths",
+            CoverageFormat::Jacoco => "sonar.coverage.jacoco.xmlReportPaths",
+            CoverageFormat::Cobertura => "sonar.python.coverage.reportPaths",
+            CoverageFormat::Go => "sonar.go.coverage.reportPaths",
+            CoverageFormat::Opencover => "sonar.cs.opencover.reportsPaths",
+            CoverageFormat::Simplecov => "sonar.ruby.coverage.reportPaths",
+            CoverageFormat::Clover => "sonar.php.coverage.reportPaths",
+        }
+    }
+
+    /// Classifies a report by its conventional file name.
+    fn from_file_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        if name == "lcov.info" || name.ends_with(".lcov") {
+            Some(CoverageFormat::Lcov)
+        } else if name.starts_with("jacoco") && name.ends_with(".xml") {
+            Some(CoverageFormat::Jacoco)
+        } else if name.ends_with(".opencover.xml") {
+            Some(CoverageFormat::Opencover)
+        } else if matches!(
+            name.as_str(),
+            "coverage.xml" | "cobertura.xml" | "cobertura-coverage.xml"
+        ) {
+            Some(CoverageFormat::Cobertura)
+        } else if matches!(name.as_str(), "coverage.out" | "cover.out") {
+            Some(CoverageFormat::Go)
+        } else if name == ".resultset.json" {
+            Some(CoverageFormat::Simplecov)
+        } else if name == "clover.xml" {
+            Some(CoverageFormat::Clover)
+        } else {
+            None
+        }
+    }
+
+    /// Classifies a report by the first bytes of its contents.
+    fn from_contents(head: &str) -> Option<Self> {
+        if head.starts_with("TN:") || head.starts_with("SF:") {
+            Some(CoverageFormat::Lcov)
+        } else if head.starts_with("mode: ") {
+            Some(CoverageFormat::Go)
+        } else if head.contains("<!DOCTYPE report") || head.contains("JACOCO") {
+            Some(CoverageFormat::Jacoco)
+        } else if head.contains("<CoverageSession") {
+            Some(CoverageFormat::Opencover)
+        } else if head.contains("<coverage") && head.contains("line-rate") {
+            Some(CoverageFormat::Cobertura)
+        } else if head.contains("<coverage") && head.contains("<project") {
+            Some(CoverageFormat::Clover)
+        } else {
+            None
+        }
+    }
+}
+
+/// A coverage report handed to the scanner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoverageFile {
+    /// Path relative to the project root.
+    pub path: String,
+    pub format: CoverageFormat,
+    pub property: String,
+}
+
+impl CoverageFile {
+    fn new(path: String, format: CoverageFormat) -> Self {
+        Self {
+            path,
+            format,
+            property: format.property().to_string(),
+        }
+    }
+}
+
+/// A coverage report sent next to the archive, kept in the job's temp dir
+/// until the project is extracted.
+#[derive(Debug, Clone)]
+pub struct UploadedReport {
+    pub path: PathBuf,
+    /// Name of the report in the project: the name it was uploaded under.
+    pub file_name: String,
+    pub format: CoverageFormat,
+    pub sha256: String,
+}
+
+impl UploadedReport {
+    /// Checks the report saved at `path`, uploaded as `file_name`. Its format
+    /// is taken from the name or, failing that, sniffed from the contents.
+    pub fn new(path: PathBuf, file_name: Option<&str>, sha256: String) -> Result<Self, AppError> {
+        let file_name = file_name
+            .and_then(|name| Path::new(name).file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("coverage.report")
+            .to_string();
+        let format = CoverageFormat::from_file_name(&file_name)
+            .or_else(|| sniff_format(&path))
+            .ok_or_else(|| {
+                AppError::InvalidRequest(
+                    "Field 'coverage' is not a recognized coverage report (lcov, JaCoCo, \
+                     Cobertura, Go, OpenCover, SimpleCov or Clover)"
+                        .to_string(),
+                )
+            })?;
+        Ok(Self {
+            path,
+            file_name,
+            format,
+            sha256,
+        })
+    }
+
+    /// Copies the report into the extracted project and returns it as
+    /// handed to the scanner.
+    pub fn place(&self, project_dir: &Path) -> Result<CoverageFile, AppError> {
+        let dir = project_dir.join(UPLOAD_DIR);
+        fs::create_dir_all(&dir)
+            .and_then(|()| fs::copy(&self.path, dir.join(&self.file_name)))
+            .map_err(|e| {
+                AppError::InternalError(format!("Failed to place the coverage report: {}", e))
+            })?;
+        Ok(CoverageFile::new(
+            format!("{}/{}", UPLOAD_DIR, self.file_name),
+            self.format,
+        ))
+    }
+}
+
+/// Finds coverage reports in the extracted project. Explicit paths (relative
+/// to the project root) replace auto-detection; their format is taken from the
+/// file name or, failing that, sniffed from the contents.
+pub fn find_coverage_reports(project_dir: &Path, explicit: Option<&[String]>) -> Vec<CoverageFile> {
+    match explicit {
+        Some(paths) => paths
+            .iter()
+            .filter_map(|path| resolve_explicit(project_dir, path))
+            .collect(),
+        None => {
+            let mut found = Vec::new();
+            walk(project_dir, project_dir, &mut found);
+            found.sort_by(|a, b| a.path.cmp(&b.path));
+            found
+        }
+    }
+}
+
+/// Scanner properties for the given reports, one comma-joined list per property.
+pub fn scanner_properties(files: &[CoverageFile]) -> Vec<(String, String)> {
+    let mut grouped: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for file in files {
+        grouped
+            .entry(file.property.as_str())
+            .or_default()
+            .push(file.path.as_str());
+    }
+
+    grouped
+        .into_iter()
+        .map(|(property, paths)| (property.to_string(), paths.join(",")))
+        .collect()
+}
+
+fn resolve_explicit(project_dir: &Path, path: &str) -> Option<CoverageFile> {
+    let relative = Path::new(path.trim());
+    let is_safe = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !is_safe {
+        warn!("Ignoring coverage path outside the project: {}", path);
+        return None;
+    }
+
+    let full_path = project_dir.join(relative);
+    if !full_path.is_file() {
+        warn!("Coverage report not found in archive: {}", path);
+        return None;
+    }
+
+    let format = relative
+        .file_name()
+        .and_then(|name| CoverageFormat::from_file_name(&name.to_string_lossy()))
+        .or_else(|| sniff_format(&full_path));
+
+    match format {
+        Some(format) => Some(CoverageFile::new(to_slash_path(relative), format)),
+        None => {
+            warn!("Unrecognized coverage report format: {}", path);
+            None
+        }
+    }
+}
+
+fn sniff_format(path: &Path) -> Option<CoverageFormat> {
+    let mut head = Vec::new();
+    fs::File::open(path)
+        .ok()?
+        .take(SNIFF_LEN)
+        .read_to_end(&mut head)
+        .ok()?;
+    CoverageFormat::from_contents(String::from_utf8_lossy(&head).trim_start())
+}
+
+fn walk(dir: &Path, root: &Path, found: &mut Vec<CoverageFile>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        if path.is_dir() {
+            if !SKIPPED_DIRS.contains(&name.as_str()) {
+                walk(&path, root, found);
+            }
+        } else if let Some(format) = CoverageFormat::from_file_name(&name) {
+            if let Ok(relative) = path.strip_prefix(root) {
+                found.push(CoverageFile::new(to_slash_path(relative), format));
+            }
+        }
+    }
+}
+
+fn to_slash_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LCOV: &str = "TN:\nSF:src/app.js\nDA:1,1\nend_of_record\n";
+
+    #[test]
+    fn detects_lcov_report() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("coverage")).unwrap();
+        fs::write(dir.path().join("coverage/lcov.info"), LCOV).unwrap();
+        // Reports under skipped directories are not picked up
+        fs::create_dir_all(dir.path().join("node_modules/dep")).unwrap();
+        fs::write(dir.path().join("node_modules/dep/lcov.info"), LCOV).unwrap();
+
+        let files = find_coverage_reports(dir.path(), None);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].format, CoverageFormat::Lcov);
+
+        let properties = scanner_properties(&files);
+        assert_eq!(
+            properties,
+            [(
+                "sonar.javascript.lcov.reportPaths".to_string(),
+                "coverage/lcov.info".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn sniffs_explicit_lcov_report() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("report.txt"), LCOV).unwrap();
+
+        let explicit = ["report.txt".to_string(), "../outside.info".to_string()];
+        let files = find_coverage_reports(dir.path(), Some(&explicit));
+        let properties = scanner_properties(&files);
+        assert_eq!(
+            properties,
+            [(
+                "sonar.javascript.lcov.reportPaths".to_string(),
+                "report.txt".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! `POST /analyze/diff`: scans a `base` and a `head` archive and reports
+//! only what changed between them, so pull request checks show the issues a
+//! change introduces rather than the project's whole backlog.
+
+use crate::audit::Caller;
+use crate::auth::Tenant;
+use crate::quality_gate::QualityGate;
+use crate::request_id::RequestId;
+use crate::{
+    auth, github, ids, job_temp_dir, options_from_fields, parse_sonar_token, prepare_analysis,
+    read_text_field, run_job, save_field, upload_error, Analysis, AnalyzeOptions, AnalyzeResponse,
+    AppError, AppState, SonarIssue, UploadSource, OPTION_FIELDS,
+};
+use axum::{
+    extract::{Multipart, Query, State},
+    http::HeaderMap,
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{info, info_span, instrument, Instrument};
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    /// Issue types to fetch (comma-separated); the multipart field takes precedence.
+    issue_types: Option<String>,
+    /// Least severe issues to return; the multipart field takes precedence.
+    min_severity: Option<String>,
+    /// Scan even if an identical archive's result is cached.
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DiffResponse {
+    base_job_id: String,
+    head_job_id: String,
+    /// Issues in head with no counterpart in base.
+    new_issues: Vec<SonarIssue>,
+    /// Issues in base with no counterpart in head.
+    resolved_issues: Vec<SonarIssue>,
+    new_count: usize,
+    resolved_count: usize,
+    /// Issues found in both.
+    unchanged_count: usize,
+    /// Either scan returned fewer issues than matched, so the delta may be
+    /// incomplete.
+    truncated: bool,
+    /// Quality gate of the head scan.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality_gate: Option<QualityGate>,
+}
+
+#[instrument(skip_all, fields(job_id))]
+pub async fn analyze_diff_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    Extension(request_id): Extension<RequestId>,
+    Extension(caller): Extension<Caller>,
+    Query(query): Query<DiffQuery>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<DiffResponse>, AppError> {
+    info!("Received diff analyze request");
+
+    let sonar_token = parse_sonar_token(
+        headers
+            .get(auth::SONAR_TOKEN_HEADER)
+            .map(|value| value.to_str().unwrap_or_default()),
+    )?;
+    let github_token = github::parse_token(
+        headers
+            .get(auth::GITHUB_TOKEN_HEADER)
+            .map(|value| value.to_str().unwrap_or_default()),
+    )?;
+
+    // Both jobs share the ID's random part, and so do their project keys
+    let diff_id = ids::job_id();
+    tracing::Span::current().record("job_id", &diff_id);
+    let base_job_id = format!("{}_base", diff_id);
+    let head_job_id = format!("{}_head", diff_id);
+
+    let base_dir = job_temp_dir()?;
+    let head_dir = job_temp_dir()?;
+    let (base_path, head_path, fields) =
+        read_uploads(&mut multipart, base_dir.path(), head_dir.path())
+            .instrument(info_span!("read_upload"))
+            .await?;
+
+    // Both scans run with the same options
+    let options = || -> Result<AnalyzeOptions, AppError> {
+        let mut options = options_from_fields(&fields)?;
+        options.apply_query_filters(query.issue_types.as_deref(), query.min_severity.as_deref())?;
+        options.apply_tenant(&tenant)?;
+        options.sonar_token = sonar_token.clone();
+        options.github_token = github_token.clone();
+        options.request_id = Some(request_id.0.clone());
+        options.caller = caller.clone();
+        Ok(options)
+    };
+    let (mut base_options, head_options) = (options()?, options()?);
+    // Only the head scan, the change itself, is reported to the pull or
+    // merge request
+    base_options.github_repo = None;
+    base_options.pr_number = None;
+    base_options.gitlab_project = None;
+    base_options.mr_iid = None;
+    base_options.commit_sha = None;
+    base_options.bitbucket_repo = None;
+    base_options.bitbucket_commit = None;
+
+    let base = prepare_analysis(
+        &state,
+        &base_job_id,
+        base_dir,
+        UploadSource::Archive(base_path),
+        base_options,
+        query.force,
+    )?;
+    let head = match prepare_analysis(
+        &state,
+        &head_job_id,
+        head_dir,
+        UploadSource::Archive(head_path),
+        head_options,
+        query.force,
+    ) {
+        Ok(head) => head,
+        Err(e) => {
+            // The base job is registered already; it must not stay queued
+            if let Analysis::Scan(..) = base {
+                state.jobs.fail(&base_job_id, &e);
+            }
+            return Err(e);
+        }
+    };
+
+    // Both always run to the end so neither job is left RUNNING
+    let (base, head) = futures::join!(
+        analyze(&state, &base_job_id, base),
+        analyze(&state, &head_job_id, head)
+    );
+    let (base, head) = (base?, head?);
+
+    let delta = compare(&base.vulnerabilities, &head.vulnerabilities);
+    info!(
+        "Diff {}: {} new, {} resolved, {} unchanged",
+        diff_id,
+        delta.new.len(),
+        delta.resolved.len(),
+        delta.unchanged
+    );
+
+    Ok(Json(DiffResponse {
+        new_count: delta.new.len(),
+        resolved_count: delta.resolved.len(),
+        unchanged_count: delta.unchanged,
+        new_issues: delta.new,
+        resolved_issues: delta.resolved,
+        truncated: base.truncated || head.truncated,
+        quality_gate: head.quality_gate.clone(),
+        base_job_id,
+        head_job_id,
+    }))
+}
+
+async fn analyze(
+    state: &AppState,
+    job_id: &str,
+    analysis: Analysis,
+) -> Result<Arc<AnalyzeResponse>, AppError> {
+    match analysis {
+        Analysis::Cached(response) => Ok(response),
+        Analysis::Scan(upload, reserved_slot) => {
+            run_job(state.clone(), job_id.to_string(), upload, reserved_slot).await
+        }
+    }
+}
+
+/// Saves the `base` and `head` archives and collects the option fields
+/// shared by both scans.
+async fn read_uploads(
+    multipart: &mut Multipart,
+    base_dir: &Path,
+    head_dir: &Path,
+) -> Result<(PathBuf, PathBuf, Vec<(String, String)>), AppError> {
+    let mut base = None;
+    let mut head = None;
+    let mut fields = Vec::new();
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        upload_error(e, |e| {
+            AppError::MissingField(format!("Failed to read multipart field: {}", e))
+        })
+    })? {
+        let name = field.name().unwrap_or("").to_string();
+
+        match name.as_str() {
+            "base" | "head" => {
+                let dir = if name == "base" { base_dir } else { head_dir };
+                let path = dir.join("upload.archive");
+                let size = save_field(field, &path).await?;
+                info!("Saved {} byte {} upload to {:?}", size, name, path);
+                if name == "base" {
+                    base = Some(path);
+                } else {
+                    head = Some(path);
+                }
+            }
+            name if OPTION_FIELDS.contains(&name) => {
+                let value = read_text_field(field, name).await?;
+                fields.push((name.to_string(), value));
+            }
+            _ => {}
+        }
+    }
+
+    let base = base.ok_or_else(|| AppError::MissingField("base".to_string()))?;
+    let head = head.ok_or_else(|| AppError::MissingField("head".to_string()))?;
+    Ok((base, head, fields))
+}
+
+/// How the head scan's issues differ from the base scan's.
+struct Delta {
+    new: Vec<SonarIssue>,
+    resolved: Vec<SonarIssue>,
+    unchanged: usize,
+}
+
+/// Pairs issues by rule, file and message, ignoring the line since edits
+/// above an issue move it without changing it. Repeated findings are
+/// paired one for one, so a third copy of an existing issue still counts
+/// as new.
+fn compare(base: &[SonarIssue], head: &[SonarIssue]) -> Delta {
+    let identity = |issue: &SonarIssue| {
+        (
+            issue.rule.clone(),
+            issue.path().to_string(),
+            issue.message.clone(),
+        )
+    };
+
+    let mut unmatched: HashMap<_, Vec<usize>> = HashMap::new();
+    for (index, issue) in base.iter().enumerate() {
+        unmatched.entry(identity(issue)).or_default().push(index);
+    }
+
+    let mut new = Vec::new();
+    let mut unchanged = 0;
+    for issue in head {
+        match unmatched
+            .get_mut(&identity(issue))
+            .and_then(|indices| indices.pop())
+        {
+            Some(_) => unchanged += 1,
+            None => new.push(issue.clone()),
+        }
+    }
+
+    // In base order, for a stable response
+    let mut resolved: Vec<usize> = unmatched.into_values().flatten().collect();
+    resolved.sort_unstable();
+
+    Delta {
+        new,
+        resolved: resolved
+            .into_iter()
+            .map(|index| base[index].clone())
+            .collect(),
+        unchanged,
+    }
+}
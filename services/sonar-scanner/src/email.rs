@@ -0,0 +1,185 @@
+//! Email digests of failed scans. With `SMTP_HOST` set, a scan that fails
+//! its quality gate, or has an issue at least as severe as `EMAIL_FAIL_ON`,
+//! is mailed to `EMAIL_TO`: the reason, issue counts per severity, the most
+//! severe findings and a link to the job's HTML report.
+
+use crate::config::{EmailConfig, SmtpTls};
+use crate::job_list::severity_counts;
+use crate::notify::{self, Notice, Notifier};
+use crate::review_summary;
+use crate::severity::Severity;
+use crate::{AnalyzeResponse, SonarIssue};
+use anyhow::{bail, Context, Result};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::fmt::Write;
+use std::time::Duration;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// Sends digests of failed scans over SMTP.
+#[derive(Clone)]
+pub struct EmailNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    config: EmailConfig,
+}
+
+impl EmailNotifier {
+    /// `None` when no SMTP server is configured.
+    pub fn new(config: &EmailConfig) -> Result<Option<Self>> {
+        let Some(host) = &config.smtp_host else {
+            return Ok(None);
+        };
+        let from = config
+            .from
+            .as_deref()
+            .context("EMAIL_FROM is required with SMTP_HOST")?
+            .parse()
+            .context("Invalid EMAIL_FROM")?;
+        if config.to.is_empty() {
+            bail!("EMAIL_TO is required with SMTP_HOST");
+        }
+        let to = config
+            .to
+            .iter()
+            .map(|address| {
+                address
+                    .parse()
+                    .with_context(|| format!("Invalid EMAIL_TO address {:?}", address))
+            })
+            .collect::<Result<Vec<Mailbox>>>()?;
+
+        let builder = match config.smtp_tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        let mut builder = builder
+            .port(config.smtp_port)
+            .timeout(Some(Duration::from_secs(config.timeout_secs)));
+        if let Some(username) = &config.smtp_username {
+            let password = config.smtp_password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+
+        Ok(Some(Self {
+            transport: builder.build(),
+            from,
+            to,
+            config: config.clone(),
+        }))
+    }
+
+    /// The plain-text digest: why the scan failed, issue counts, the most
+    /// severe findings and the report link.
+    fn digest(
+        &self,
+        job_id: &str,
+        project: &str,
+        reason: &str,
+        response: &AnalyzeResponse,
+    ) -> String {
+        let mut body = String::new();
+        let _ = writeln!(body, "The scan of {} failed: {}.\n", project, reason);
+        if let Some(gate) = &response.quality_gate {
+            let _ = writeln!(body, "Quality gate: {}", gate.status);
+        }
+        let counts = severity_counts(response);
+        let _ = writeln!(
+            body,
+            "Issues: {} blocker, {} critical, {} major, {} minor, {} info\n",
+            counts.blocker, counts.critical, counts.major, counts.minor, counts.info
+        );
+
+        let mut findings: Vec<&SonarIssue> = response
+            .vulnerabilities
+            .iter()
+            .filter(|issue| !issue.suppressed)
+            .collect();
+        // Stable, so issues of one severity keep SonarQube's order
+        findings.sort_by_key(|issue| std::cmp::Reverse(Severity::parse(&issue.severity)));
+        if !findings.is_empty() && self.config.top_findings > 0 {
+            body.push_str("Most severe findings:\n\n");
+            for issue in findings.iter().take(self.config.top_findings) {
+                let location = match issue.line {
+                    Some(line) => format!("{}:{}", issue.path(), line),
+                    None => issue.path().to_string(),
+                };
+                let _ = writeln!(
+                    body,
+                    "- {} {} at {}\n  {}",
+                    issue.severity,
+                    issue.rule,
+                    location,
+                    review_summary::one_line(&issue.message)
+                );
+            }
+            let more = findings.len().saturating_sub(self.config.top_findings);
+            if more > 0 {
+                let _ = writeln!(body, "\nand {} more", more);
+            }
+            body.push('\n');
+        }
+        if response.truncated {
+            body.push_str("More issues matched than the scan returned.\n\n");
+        }
+        match &self.config.public_url {
+            Some(url) => {
+                let _ = writeln!(body, "Report: {}", notify::report_url(url, job_id));
+            }
+            None => {
+                let _ = writeln!(body, "Job: {}", job_id);
+            }
+        }
+        body
+    }
+}
+
+impl Notifier for EmailNotifier {
+    /// Mails the digest of a scan that failed; scans that could not run are
+    /// left to the other sinks.
+    fn notify(&self, tasks: &TaskTracker, notice: &Notice<'_>) {
+        let Ok(response) = notice.outcome else {
+            return;
+        };
+        let (passed, reason) = review_summary::verdict(response, self.config.fail_on);
+        if passed {
+            return;
+        }
+        let job_id = notice.job_id;
+        let project = notice.project();
+        let subject = format!(
+            "[sonar-backend] {} failed: {}",
+            review_summary::one_line(project),
+            reason
+        );
+        let body = self.digest(job_id, project, &reason, response);
+
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for recipient in &self.to {
+            message = message.to(recipient.clone());
+        }
+        let message = match message.body(body) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to build the digest of job {}: {}", job_id, e);
+                return;
+            }
+        };
+        let transport = self.transport.clone();
+        let job_id = job_id.to_string();
+        tasks.spawn(async move {
+            match transport.send(message).await {
+                Ok(_) => info!("Mailed the digest of job {}", job_id),
+                Err(e) => warn!("Failed to mail the digest of job {}: {}", job_id, e),
+            }
+        });
+    }
+}
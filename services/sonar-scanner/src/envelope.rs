@@ -0,0 +1,168 @@
+use crate::request_id::RequestId;
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::Request,
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::{json, Value};
+use std::time::Instant;
+use tracing::error;
+
+/// Bumped whenever the envelope layout changes.
+const SCHEMA_VERSION: u32 = 1;
+
+/// Largest body wrapped; larger ones are passed through as they are.
+const MAX_ENVELOPED_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Wraps every response as `{data?, error?, meta: {request_id, duration_ms, schema_version}}`,
+/// keeping the original status code. Successful bodies go under `data`;
+/// error bodies are normalized to `{code?, message}` under `error`.
+/// Streams, upgrades, reports and other non-JSON bodies are left alone, as
+/// is `/metrics`, which Prometheus must be able to scrape.
+pub async fn envelope_responses(request: Request, next: Next) -> Response {
+    // CORS preflights carry no payload worth wrapping
+    if request.method() == Method::OPTIONS || request.uri().path() == "/metrics" {
+        return next.run(request).await;
+    }
+
+    let started = Instant::now();
+    // Set by the request ID middleware, which runs first
+    let request_id = match request.extensions().get::<RequestId>() {
+        Some(RequestId(id)) => id.clone(),
+        None => crate::ids::request_id(),
+    };
+
+    let response = next.run(request).await;
+    if !is_envelopable(&response) {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+
+    let bytes = match to_bytes(body, MAX_ENVELOPED_BYTES as usize).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Failed to buffer response body for envelope: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let payload = serde_json::from_slice::<Value>(&bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+
+    let meta = json!({
+        "request_id": request_id,
+        "duration_ms": started.elapsed().as_millis() as u64,
+        "schema_version": SCHEMA_VERSION,
+    });
+    let envelope = if parts.status.is_success() {
+        json!({ "data": payload, "meta": meta })
+    } else {
+        json!({ "error": error_object(payload), "meta": meta })
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+
+    Response::from_parts(parts, Body::from(envelope.to_string()))
+}
+
+/// Whether `response` is a complete JSON body, or a plain-text error such as
+/// an extractor rejection, small enough to buffer.
+fn is_envelopable(response: &Response) -> bool {
+    if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+        return false;
+    }
+    // Streams, such as server-sent events, have no upper bound
+    match response.body().size_hint().upper() {
+        Some(len) if len <= MAX_ENVELOPED_BYTES => {}
+        _ => return false,
+    }
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase());
+    match content_type.as_deref() {
+        Some(mime) if mime == "application/json" || mime.ends_with("+json") => true,
+        Some("text/plain") | None => !response.status().is_success(),
+        Some(_) => false,
+    }
+}
+
+fn error_object(payload: Value) -> Value {
+    match payload {
+        Value::Object(mut body) => match body.remove("error") {
+            Some(message) => json!({
+                "code": body.remove("code"),
+                "message": message,
+            }),
+            None => Value::Object(body),
+        },
+        other => json!({ "message": other }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppError;
+    use axum::{routing::get, Json, Router};
+    use tower::ServiceExt;
+
+    fn router() -> Router {
+        Router::new()
+            .route("/ok", get(|| async { Json(json!({ "status": "ok" })) }))
+            .route(
+                "/bad",
+                get(|| async { AppError::InvalidRequest("Bad input".to_string()) }),
+            )
+            .route(
+                "/report.html",
+                get(|| async { ([(header::CONTENT_TYPE, "text/html")], "<html></html>") }),
+            )
+            .layer(axum::middleware::from_fn(envelope_responses))
+    }
+
+    async fn get_json(path: &str) -> (StatusCode, Value) {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        let response = router().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn wraps_success_under_data() {
+        let (status, body) = get_json("/ok").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], json!({ "status": "ok" }));
+        assert!(body.get("error").is_none());
+        assert!(body["meta"]["request_id"].is_string());
+        assert!(body["meta"]["duration_ms"].is_u64());
+        assert_eq!(body["meta"]["schema_version"], SCHEMA_VERSION);
+    }
+
+    #[tokio::test]
+    async fn wraps_errors_under_error() {
+        let (status, body) = get_json("/bad").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "INVALID_REQUEST");
+        assert_eq!(body["error"]["message"], "Bad input");
+        assert!(body.get("data").is_none());
+        assert!(body["meta"]["request_id"].is_string());
+    }
+
+    #[tokio::test]
+    async fn passes_other_content_types_through() {
+        let request = Request::get("/report.html").body(Body::empty()).unwrap();
+        let response = router().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html");
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&bytes[..], b"<html></html>");
+    }
+}
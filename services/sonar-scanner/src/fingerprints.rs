@@ -0,0 +1,81 @@
+//! Content-based issue fingerprints. Unlike the SonarQube issue key, which
+//! is new on every scan, and the line-based fingerprint of the SARIF and
+//! GitLab reports, these survive line shifts and repeated scans, so
+//! downstream tools can follow an issue from one scan to the next.
+
+use crate::snippets;
+use crate::SonarIssue;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, path::Path};
+
+/// Lines on each side of the issue's line hashed with it.
+const CONTEXT_LINES: usize = 1;
+
+/// Sets `fingerprint` on every issue: a hash of the rule, the normalized
+/// path and the code around the issue with whitespace removed. Issues
+/// without a readable line fall back to their message. Identical findings
+/// in one file are numbered in line order so each keeps its own fingerprint.
+pub fn assign(project_dir: &Path, issues: &mut [SonarIssue]) {
+    let mut files: HashMap<String, Option<Vec<String>>> = HashMap::new();
+    let mut order: Vec<usize> = (0..issues.len()).collect();
+    order.sort_by_key(|&index| (issues[index].path().to_string(), issues[index].line));
+
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for index in order {
+        let issue = &issues[index];
+        let path = normalize_path(issue.path());
+        let lines = files
+            .entry(issue.path().to_string())
+            .or_insert_with(|| snippets::read_lines(project_dir, issue.path()));
+        let context = issue
+            .line
+            .filter(|line| *line > 0)
+            .zip(lines.as_deref())
+            .and_then(|(line, lines)| code_around(lines, line as usize - 1))
+            .unwrap_or_else(|| normalize_code(&issue.message));
+
+        let base = digest(&[&issue.rule, &path, &context]);
+        let occurrence = occurrences.entry(base.clone()).or_default();
+        *occurrence += 1;
+        let fingerprint = if *occurrence == 1 {
+            base
+        } else {
+            digest(&[&base, &occurrence.to_string()])
+        };
+        issues[index].fingerprint = Some(fingerprint);
+    }
+}
+
+/// The issue's line and its neighbours, or `None` past the end of the file.
+fn code_around(lines: &[String], index: usize) -> Option<String> {
+    if index >= lines.len() {
+        return None;
+    }
+    let start = index.saturating_sub(CONTEXT_LINES);
+    let end = (index + CONTEXT_LINES + 1).min(lines.len());
+    Some(
+        lines[start..end]
+            .iter()
+            .map(|line| normalize_code(line))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+/// Forward slashes, without a leading `./`.
+fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    path.trim_start_matches("./").to_string()
+}
+
+/// Drops all whitespace, so reindenting or reformatting a line keeps its
+/// fingerprint.
+fn normalize_code(text: &str) -> String {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn digest(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(parts.join("\0"));
+    format!("{:x}", hasher.finalize())
+}
@@ -0,0 +1,51 @@
+use crate::SonarIssue;
+
+const HEADER: [&str; 6] = ["rule", "severity", "component", "line", "message", "type"];
+
+/// Renders issues as RFC 4180 CSV with a header row and CRLF line endings.
+pub fn csv_report(issues: &[SonarIssue]) -> String {
+    let mut csv = String::new();
+    write_row(&mut csv, HEADER.iter().copied());
+
+    for issue in issues {
+        let line = issue.line.map(|line| line.to_string()).unwrap_or_default();
+        write_row(
+            &mut csv,
+            [
+                issue.rule.as_str(),
+                issue.severity.as_str(),
+                issue.path(),
+                line.as_str(),
+                issue.message.as_str(),
+                issue.issue_type.as_str(),
+            ],
+        );
+    }
+    csv
+}
+
+fn write_row<'a>(csv: &mut String, fields: impl IntoIterator<Item = &'a str>) {
+    for (index, field) in fields.into_iter().enumerate() {
+        if index > 0 {
+            csv.push(',');
+        }
+        csv.push_str(&escape_field(field));
+    }
+    csv.push_str("\r\n");
+}
+
+/// Quotes fields containing separators, quotes or line breaks. Fields that a
+/// spreadsheet would evaluate as a formula are prefixed with `'`.
+fn escape_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
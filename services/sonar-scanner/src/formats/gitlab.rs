@@ -0,0 +1,111 @@
+use super::fingerprint;
+use crate::SonarIssue;
+use serde::Serialize;
+
+/// One entry of a GitLab Code Quality report.
+#[derive(Debug, Serialize)]
+pub struct CodeQualityIssue {
+    description: String,
+    check_name: String,
+    fingerprint: String,
+    severity: &'static str,
+    location: Location,
+}
+
+#[derive(Debug, Serialize)]
+struct Location {
+    path: String,
+    lines: Lines,
+}
+
+#[derive(Debug, Serialize)]
+struct Lines {
+    begin: u32,
+}
+
+/// One entry per issue; issues in the request's baseline are left out.
+pub fn code_quality_report(issues: &[SonarIssue]) -> Vec<CodeQualityIssue> {
+    issues
+        .iter()
+        .filter(|issue| !issue.suppressed)
+        .map(|issue| CodeQualityIssue {
+            description: issue.message.clone(),
+            check_name: issue.rule.clone(),
+            // Content-based where available, so moved code is not reported as new
+            fingerprint: issue
+                .fingerprint
+                .clone()
+                .unwrap_or_else(|| fingerprint(issue)),
+            severity: severity(&issue.severity),
+            location: Location {
+                path: issue.path().to_string(),
+                lines: Lines {
+                    begin: issue.line.unwrap_or(1),
+                },
+            },
+        })
+        .collect()
+}
+
+/// Maps SonarQube severities onto GitLab's levels.
+fn severity(severity: &str) -> &'static str {
+    match severity.to_ascii_uppercase().as_str() {
+        "BLOCKER" => "blocker",
+        "CRITICAL" => "critical",
+        "MAJOR" => "major",
+        "MINOR" => "minor",
+        _ => "info",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn render(issues: &[SonarIssue]) -> Vec<Value> {
+        let report = serde_json::to_value(code_quality_report(issues)).unwrap();
+        report.as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn renders_required_keys() {
+        let issue = SonarIssue::for_test("AX1", "java:S2076", "CRITICAL", "src/Run.java", 42);
+        let entries = render(&[issue]);
+        assert_eq!(entries.len(), 1);
+
+        let entry = &entries[0];
+        assert_eq!(entry["description"], "java:S2076 violated");
+        assert_eq!(entry["check_name"], "java:S2076");
+        assert_eq!(entry["severity"], "critical");
+        assert_eq!(entry["location"]["path"], "src/Run.java");
+        assert_eq!(entry["location"]["lines"]["begin"], 42);
+        assert!(entry["fingerprint"].as_str().is_some_and(|f| !f.is_empty()));
+    }
+
+    #[test]
+    fn fingerprints_are_stable() {
+        let issue = SonarIssue::for_test("AX1", "java:S2076", "CRITICAL", "src/Run.java", 42);
+        // A rescan reports the same finding under a new issue key
+        let rescanned = SonarIssue::for_test("AX2", "java:S2076", "CRITICAL", "src/Run.java", 42);
+        let elsewhere = SonarIssue::for_test("AX3", "java:S2076", "CRITICAL", "src/Run.java", 43);
+
+        let first = render(&[issue.clone(), elsewhere]);
+        let second = render(&[issue, rescanned]);
+        assert_eq!(first[0]["fingerprint"], second[0]["fingerprint"]);
+        assert_eq!(second[0]["fingerprint"], second[1]["fingerprint"]);
+        assert_ne!(first[0]["fingerprint"], first[1]["fingerprint"]);
+    }
+
+    #[test]
+    fn prefers_content_fingerprint_and_skips_baseline() {
+        let mut issue = SonarIssue::for_test("AX1", "py:S1", "MINOR", "app.py", 1);
+        issue.fingerprint = Some("content-hash".to_string());
+        let mut known = SonarIssue::for_test("AX2", "py:S2", "MAJOR", "app.py", 2);
+        known.suppressed = true;
+
+        let entries = render(&[issue, known]);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["fingerprint"], "content-hash");
+    }
+}
@@ -0,0 +1,126 @@
+use super::escape_markup;
+use crate::severity::{Severity, SeverityCounts};
+use crate::{AnalyzeResponse, SonarIssue};
+use std::{collections::BTreeMap, fmt::Write};
+
+const STYLE: &str = "\
+body{font-family:system-ui,sans-serif;margin:2rem;color:#222}\
+h1{font-size:1.5rem}h2{font-size:1.15rem;margin-top:2rem}\
+table{border-collapse:collapse;width:100%;font-size:.9rem}\
+th,td{border:1px solid #ddd;padding:.35rem .6rem;text-align:left;vertical-align:top}\
+th{background:#f4f4f4}td.num{text-align:right}\
+.meta{color:#666;font-size:.85rem}\
+.sev{font-weight:600}.BLOCKER,.CRITICAL{color:#b00020}.MAJOR{color:#c75c00}\
+.MINOR{color:#8a6d00}.INFO{color:#555}";
+
+/// Renders a standalone HTML report (inline styles, no external assets) with
+/// a severity summary, a per-file breakdown and the full issue table.
+pub fn html_report(response: &AnalyzeResponse) -> String {
+    let manifest = &response.manifest;
+    let issues = &response.vulnerabilities;
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>SonarQube report: {0}</title>\n<style>{1}</style>\n</head>\n<body>\n\
+         <h1>SonarQube report: {0}</h1>\n",
+        escape_markup(&manifest.project_key),
+        STYLE
+    );
+
+    let mut meta = vec![format!("{} issue(s)", issues.len())];
+    if response.truncated {
+        meta.push("results truncated".to_string());
+    }
+    if let Some(language) = &manifest.language {
+        meta.push(format!("language: {}", language));
+    }
+    if let Some(version) = &manifest.sonarqube_version {
+        meta.push(format!("SonarQube {}", version));
+    }
+    if let Some(git) = &manifest.git {
+        meta.push(format!("{} @ {}", git.url, git.commit));
+    }
+    let _ = writeln!(
+        html,
+        "<p class=\"meta\">{}</p>",
+        escape_markup(&meta.join(" · "))
+    );
+
+    html.push_str("<h2>Severity summary</h2>\n<table>\n<tr>");
+    for label in SEVERITY_LABELS {
+        let _ = write!(html, "<th>{}</th>", label);
+    }
+    html.push_str("</tr>\n");
+    write_count_cells(&mut html, &counts(issues.iter()));
+    html.push_str("</table>\n");
+
+    let mut by_file: BTreeMap<&str, Vec<&SonarIssue>> = BTreeMap::new();
+    for issue in issues {
+        by_file.entry(issue.path()).or_default().push(issue);
+    }
+
+    html.push_str("<h2>Files</h2>\n<table>\n<tr><th>File</th><th>Total</th>");
+    for label in SEVERITY_LABELS {
+        let _ = write!(html, "<th>{}</th>", label);
+    }
+    html.push_str("</tr>\n");
+    for (path, file_issues) in &by_file {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td class=\"num\">{}</td>",
+            escape_markup(path),
+            file_issues.len()
+        );
+        write_count_cells(&mut html, &counts(file_issues.iter().copied()));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str(
+        "<h2>Issues</h2>\n<table>\n<tr><th>Severity</th><th>Type</th><th>Rule</th>\
+         <th>File</th><th>Line</th><th>Message</th></tr>\n",
+    );
+    for (path, file_issues) in &by_file {
+        for issue in file_issues {
+            let severity = escape_markup(&issue.severity);
+            let _ = writeln!(
+                html,
+                "<tr><td class=\"sev {0}\">{0}</td><td>{1}</td><td>{2}</td><td>{3}</td>\
+                 <td class=\"num\">{4}</td><td>{5}</td></tr>",
+                severity,
+                escape_markup(&issue.issue_type),
+                escape_markup(&issue.rule),
+                escape_markup(path),
+                issue.line.map(|line| line.to_string()).unwrap_or_default(),
+                escape_markup(&issue.message)
+            );
+        }
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+const SEVERITY_LABELS: [&str; 5] = ["Blocker", "Critical", "Major", "Minor", "Info"];
+
+fn counts<'a>(issues: impl Iterator<Item = &'a SonarIssue>) -> SeverityCounts {
+    let mut counts = SeverityCounts::default();
+    for severity in issues.filter_map(|issue| Severity::parse(&issue.severity)) {
+        counts.record(severity);
+    }
+    counts
+}
+
+/// Writes the counts in [`SEVERITY_LABELS`] order and closes the row.
+fn write_count_cells(html: &mut String, counts: &SeverityCounts) {
+    for count in [
+        counts.blocker,
+        counts.critical,
+        counts.major,
+        counts.minor,
+        counts.info,
+    ] {
+        let _ = write!(html, "<td class=\"num\">{}</td>", count);
+    }
+    html.push_str("</tr>\n");
+}
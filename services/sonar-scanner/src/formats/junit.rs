@@ -0,0 +1,59 @@
+use super::escape_markup;
+use crate::SonarIssue;
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Renders a JUnit XML report: one `<testsuite>` per file and one failing
+/// `<testcase>` per issue, so CI test report views list findings by file.
+/// Issues in the request's baseline are left out.
+pub fn junit_report(issues: &[SonarIssue]) -> String {
+    let issues: Vec<&SonarIssue> = issues.iter().filter(|issue| !issue.suppressed).collect();
+    let mut by_file: BTreeMap<&str, Vec<&SonarIssue>> = BTreeMap::new();
+    for &issue in &issues {
+        by_file.entry(issue.path()).or_default().push(issue);
+    }
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"sonarqube\" tests=\"{0}\" failures=\"{0}\">",
+        issues.len()
+    );
+
+    for (path, issues) in by_file {
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{0}\" tests=\"{1}\" failures=\"{1}\">",
+            escape_markup(path),
+            issues.len()
+        );
+        for issue in issues {
+            let line = issue
+                .line
+                .map(|line| line.to_string())
+                .unwrap_or_else(|| "-".to_string());
+            let _ = writeln!(
+                xml,
+                "    <testcase name=\"{} at line {}\" classname=\"{}\">",
+                escape_markup(&issue.rule),
+                line,
+                escape_markup(path)
+            );
+            let _ = writeln!(
+                xml,
+                "      <failure message=\"{}\" type=\"{}\">{} {} {}:{}\n{}</failure>",
+                escape_markup(&issue.message),
+                escape_markup(&issue.severity),
+                escape_markup(&issue.severity),
+                escape_markup(&issue.issue_type),
+                escape_markup(path),
+                line,
+                escape_markup(&issue.message)
+            );
+            xml.push_str("    </testcase>\n");
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+
+    xml.push_str("</testsuites>\n");
+    xml
+}
@@ -0,0 +1,181 @@
+use crate::{AnalyzeResponse, AppError, SonarIssue};
+use axum::{
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Json, Response},
+};
+use sha2::{Digest, Sha256};
+
+mod csv;
+mod gitlab;
+mod html;
+mod junit;
+mod sarif;
+
+const SARIF_CONTENT_TYPE: &str = "application/sarif+json";
+
+/// Key of [`fingerprint`] in SARIF `partialFingerprints`.
+pub const SARIF_FINGERPRINT_KEY: &str = "sonarFingerprint/v1";
+
+/// Key of the issue's content-based fingerprint in SARIF `partialFingerprints`.
+pub const CONTENT_FINGERPRINT_KEY: &str = "sonarContentFingerprint/v1";
+
+/// Output formats selectable with `?format=` or `Accept` on `/analyze`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    GitlabCodeQuality,
+    Sarif,
+    Junit,
+    Html,
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn parse(value: Option<&str>) -> Result<Self, AppError> {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("json") => Ok(OutputFormat::Json),
+            Some("gl-codequality") => Ok(OutputFormat::GitlabCodeQuality),
+            Some("sarif") => Ok(OutputFormat::Sarif),
+            Some("junit") => Ok(OutputFormat::Junit),
+            Some("html") => Ok(OutputFormat::Html),
+            Some("csv") => Ok(OutputFormat::Csv),
+            Some(other) => Err(AppError::InvalidRequest(format!(
+                "Unsupported format '{}'",
+                other
+            ))),
+        }
+    }
+
+    /// The `format` parameter if given, otherwise a format named in `Accept`.
+    pub fn negotiate(value: Option<&str>, headers: &HeaderMap) -> Result<Self, AppError> {
+        if value.is_some_and(|v| !v.trim().is_empty()) {
+            return Self::parse(value);
+        }
+
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        let accepts = |media_type: &str| {
+            accept
+                .split(',')
+                .any(|item| item.split(';').next().unwrap_or("").trim() == media_type)
+        };
+
+        if accepts(SARIF_CONTENT_TYPE) {
+            Ok(OutputFormat::Sarif)
+        } else if accepts("text/csv") {
+            Ok(OutputFormat::Csv)
+        } else {
+            Ok(OutputFormat::Json)
+        }
+    }
+}
+
+pub fn render(format: OutputFormat, response: &AnalyzeResponse) -> Response {
+    match format {
+        OutputFormat::Json => Json(response).into_response(),
+        OutputFormat::GitlabCodeQuality => {
+            Json(gitlab::code_quality_report(&response.vulnerabilities)).into_response()
+        }
+        OutputFormat::Sarif => {
+            let mut rendered = Json(sarif::sarif_log(response)).into_response();
+            rendered.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(SARIF_CONTENT_TYPE),
+            );
+            rendered
+        }
+        OutputFormat::Junit => (
+            [(header::CONTENT_TYPE, "application/xml")],
+            junit::junit_report(&response.vulnerabilities),
+        )
+            .into_response(),
+        OutputFormat::Html => (
+            [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+            html::html_report(response),
+        )
+            .into_response(),
+        OutputFormat::Csv => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"issues.csv\"",
+                ),
+            ],
+            csv::csv_report(&response.vulnerabilities),
+        )
+            .into_response(),
+    }
+}
+
+/// The report as a file, with its name and content type, for archiving.
+pub fn render_file(
+    format: OutputFormat,
+    response: &AnalyzeResponse,
+) -> serde_json::Result<(&'static str, &'static str, Vec<u8>)> {
+    Ok(match format {
+        OutputFormat::Json => (
+            "result.json",
+            "application/json",
+            serde_json::to_vec(response)?,
+        ),
+        OutputFormat::GitlabCodeQuality => (
+            "gl-code-quality-report.json",
+            "application/json",
+            serde_json::to_vec(&gitlab::code_quality_report(&response.vulnerabilities))?,
+        ),
+        OutputFormat::Sarif => (
+            "report.sarif",
+            SARIF_CONTENT_TYPE,
+            serde_json::to_vec(&sarif::sarif_log(response))?,
+        ),
+        OutputFormat::Junit => (
+            "junit.xml",
+            "application/xml",
+            junit::junit_report(&response.vulnerabilities).into_bytes(),
+        ),
+        OutputFormat::Html => (
+            "report.html",
+            "text/html; charset=utf-8",
+            html::html_report(response).into_bytes(),
+        ),
+        OutputFormat::Csv => (
+            "issues.csv",
+            "text/csv; charset=utf-8",
+            csv::csv_report(&response.vulnerabilities).into_bytes(),
+        ),
+    })
+}
+
+/// Derived from rule, file path and line only. The SonarQube project key is
+/// left out because it changes on every scan.
+pub fn fingerprint(issue: &SonarIssue) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(issue.rule.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(issue.path().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(issue.line.unwrap_or(0).to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Escapes text for XML and HTML content and attribute values.
+fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            // Control characters other than tab and newlines are invalid in XML 1.0
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,237 @@
+use super::{fingerprint, CONTENT_FINGERPRINT_KEY, SARIF_FINGERPRINT_KEY};
+use crate::{AnalyzeResponse, SonarIssue};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+const TOOL_NAME: &str = "SonarQube";
+const TOOL_URI: &str = "https://www.sonarsource.com/products/sonarqube/";
+
+/// Lets code scanning tools resolve result paths against the checkout root.
+const SRC_ROOT: &str = "%SRCROOT%";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run {
+    tool: Tool,
+    results: Vec<SarifResult>,
+    properties: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    information_uri: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: String,
+    short_description: Message,
+    default_configuration: Configuration,
+    properties: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Configuration {
+    level: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: String,
+    rule_index: usize,
+    level: &'static str,
+    message: Message,
+    locations: Vec<Location>,
+    partial_fingerprints: BTreeMap<&'static str, String>,
+    /// Set for issues in the request's baseline.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    suppressions: Vec<Suppression>,
+    properties: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Suppression {
+    kind: &'static str,
+    justification: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<Region>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactLocation {
+    uri: String,
+    uri_base_id: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: u32,
+}
+
+/// Renders a SARIF 2.1.0 log with one run. Each distinct SonarQube rule
+/// becomes a `reportingDescriptor`; results reference it by index.
+pub fn sarif_log(response: &AnalyzeResponse) -> SarifLog {
+    let mut rules: Vec<Rule> = Vec::new();
+    let mut rule_indexes: BTreeMap<&str, usize> = BTreeMap::new();
+
+    let results = response
+        .vulnerabilities
+        .iter()
+        .map(|issue| {
+            let rule_index = *rule_indexes.entry(&issue.rule).or_insert_with(|| {
+                rules.push(rule(issue));
+                rules.len() - 1
+            });
+            result(issue, rule_index)
+        })
+        .collect();
+
+    SarifLog {
+        schema: SARIF_SCHEMA,
+        version: SARIF_VERSION,
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: TOOL_NAME,
+                    information_uri: TOOL_URI,
+                    version: response.manifest.sonarqube_version.clone(),
+                    rules,
+                },
+            },
+            results,
+            properties: json!({
+                "projectKey": response.manifest.project_key,
+                "truncated": response.truncated,
+            }),
+        }],
+    }
+}
+
+/// Rule metadata is not fetched from SonarQube, so the first message seen
+/// for a rule stands in for its description.
+fn rule(issue: &SonarIssue) -> Rule {
+    let mut properties = json!({ "tags": [issue.issue_type.to_ascii_lowercase()] });
+    if let Some(score) = security_severity(issue) {
+        // Read by GitHub code scanning to rank security alerts
+        properties["security-severity"] = json!(score);
+    }
+
+    Rule {
+        id: issue.rule.clone(),
+        short_description: Message {
+            text: issue.message.clone(),
+        },
+        default_configuration: Configuration {
+            level: level(&issue.severity),
+        },
+        properties,
+    }
+}
+
+fn result(issue: &SonarIssue, rule_index: usize) -> SarifResult {
+    let mut partial_fingerprints = BTreeMap::from([(SARIF_FINGERPRINT_KEY, fingerprint(issue))]);
+    if let Some(content) = &issue.fingerprint {
+        partial_fingerprints.insert(CONTENT_FINGERPRINT_KEY, content.clone());
+    }
+
+    SarifResult {
+        rule_id: issue.rule.clone(),
+        rule_index,
+        level: level(&issue.severity),
+        message: Message {
+            text: issue.message.clone(),
+        },
+        locations: vec![Location {
+            physical_location: PhysicalLocation {
+                artifact_location: ArtifactLocation {
+                    uri: issue.path().to_string(),
+                    uri_base_id: SRC_ROOT,
+                },
+                region: issue.line.map(|start_line| Region { start_line }),
+            },
+        }],
+        partial_fingerprints,
+        suppressions: if issue.suppressed {
+            vec![Suppression {
+                kind: "external",
+                justification: "Present in the baseline",
+            }]
+        } else {
+            Vec::new()
+        },
+        properties: json!({
+            "sonarKey": issue.key,
+            "severity": issue.severity,
+            "type": issue.issue_type,
+        }),
+    }
+}
+
+/// Maps SonarQube severities onto SARIF levels.
+fn level(severity: &str) -> &'static str {
+    match severity.to_ascii_uppercase().as_str() {
+        "BLOCKER" | "CRITICAL" => "error",
+        "MAJOR" => "warning",
+        _ => "note",
+    }
+}
+
+/// CVSS-style score for security findings, `None` for other issue types.
+fn security_severity(issue: &SonarIssue) -> Option<&'static str> {
+    if !matches!(
+        issue.issue_type.as_str(),
+        "VULNERABILITY" | "SECURITY_HOTSPOT"
+    ) {
+        return None;
+    }
+    Some(match issue.severity.to_ascii_uppercase().as_str() {
+        "BLOCKER" => "9.5",
+        "CRITICAL" => "8.0",
+        "MAJOR" => "5.5",
+        "MINOR" => "3.0",
+        _ => "1.0",
+    })
+}
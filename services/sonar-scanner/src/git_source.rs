@@ -0,0 +1,288 @@
+use crate::shutdown::{self, ChildProcesses};
+use crate::source_hosts::check_source_url;
+use crate::AppError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::{io::AsyncReadExt, process::Command, time::Instant};
+use tracing::{error, info};
+
+/// URL schemes accepted for `git_url`. Local paths, `file://` and `ext::`
+/// transports would let a caller read or execute things on this host.
+const ALLOWED_SCHEMES: &[&str] = &["https", "http"];
+
+/// Settings every git command runs with. Symlinks are checked out as plain
+/// files holding their target, so a link to a file on this host is never
+/// read by the scanner, as archives' links are skipped; and redirects are
+/// not followed, since only the URL's own host was checked.
+const GIT_CONFIG: &[&str] = &[
+    "-c",
+    "core.symlinks=false",
+    "-c",
+    "http.followRedirects=false",
+];
+
+/// Ref fetched when the request does not name one.
+const DEFAULT_REF: &str = "HEAD";
+
+/// How often the clone's size is checked while git runs.
+const SIZE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A repository to analyse in place of an uploaded archive.
+#[derive(Debug, Clone)]
+pub struct GitSource {
+    pub url: reqwest::Url,
+    pub reference: Option<String>,
+}
+
+/// The revision that was actually scanned, recorded in the manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitRevision {
+    /// Repository URL with any credentials removed.
+    pub url: String,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    pub commit: String,
+}
+
+/// Caps on a checkout, so a slow or huge repository can neither hold a scan
+/// slot forever nor fill the disk.
+#[derive(Debug, Clone, Copy)]
+pub struct CheckoutLimits {
+    /// How long the whole checkout may take.
+    pub timeout: Duration,
+    /// Most bytes the clone may take on disk, history included.
+    pub max_bytes: u64,
+}
+
+impl GitSource {
+    pub fn parse(url: &str, reference: Option<&str>) -> Result<Self, AppError> {
+        let url = reqwest::Url::parse(url.trim())
+            .map_err(|e| AppError::InvalidRequest(format!("Invalid git_url: {}", e)))?;
+        if !ALLOWED_SCHEMES.contains(&url.scheme()) {
+            return Err(AppError::InvalidRequest(format!(
+                "git_url scheme '{}' is not supported (expected {})",
+                url.scheme(),
+                ALLOWED_SCHEMES.join(" or ")
+            )));
+        }
+
+        let reference = reference.map(str::trim).filter(|r| !r.is_empty());
+        if let Some(reference) = reference {
+            let is_safe = !reference.starts_with('-')
+                && !reference
+                    .chars()
+                    .any(|c| c.is_whitespace() || c.is_control());
+            if !is_safe {
+                return Err(AppError::InvalidRequest(format!(
+                    "Invalid ref '{}'",
+                    reference
+                )));
+            }
+        }
+
+        Ok(Self {
+            url,
+            reference: reference.map(str::to_string),
+        })
+    }
+
+    /// Shallow-fetches the ref (branch, tag or commit) into `dest` and checks
+    /// it out, returning the revision that was checked out. The URL's host
+    /// must be one of `allowed_hosts`, when any, and public. git runs in its
+    /// own process group registered with `children`, and is killed when the
+    /// job is canceled or `limits` are exceeded.
+    pub async fn checkout(
+        &self,
+        dest: &Path,
+        limits: CheckoutLimits,
+        allowed_hosts: &[String],
+        children: &ChildProcesses,
+    ) -> Result<GitRevision, AppError> {
+        check_source_url("git_url", &self.url, allowed_hosts).await?;
+        let reference = self.reference.as_deref().unwrap_or(DEFAULT_REF);
+        info!("Cloning {} at {}", self.redacted_url(), reference);
+
+        fs::create_dir_all(dest).map_err(|e| {
+            AppError::InternalError(format!("Failed to create clone directory: {}", e))
+        })?;
+
+        let git = Git {
+            dir: dest,
+            limits,
+            deadline: Instant::now() + limits.timeout,
+            children,
+        };
+        git.run(&["init", "--quiet"]).await?;
+        git.run(&[
+            "fetch",
+            "--quiet",
+            "--depth",
+            "1",
+            "--",
+            self.url.as_str(),
+            reference,
+        ])
+        .await?;
+        git.run(&["checkout", "--quiet", "FETCH_HEAD"]).await?;
+        let commit = git.run(&["rev-parse", "HEAD"]).await?;
+        // Catches growth between the size checks of the last command
+        git.check_size().await?;
+
+        Ok(GitRevision {
+            url: self.redacted_url(),
+            reference: reference.to_string(),
+            commit,
+        })
+    }
+
+    pub fn redacted_url(&self) -> String {
+        let mut url = self.url.clone();
+        let _ = url.set_username("");
+        let _ = url.set_password(None);
+        url.to_string()
+    }
+}
+
+/// git commands run for one checkout.
+struct Git<'a> {
+    dir: &'a Path,
+    limits: CheckoutLimits,
+    deadline: Instant,
+    children: &'a ChildProcesses,
+}
+
+impl Git<'_> {
+    /// Runs git in the clone without ever prompting for credentials,
+    /// returning trimmed stdout.
+    async fn run(&self, args: &[&str]) -> Result<String, AppError> {
+        let mut child = Command::new("git")
+            .args(GIT_CONFIG)
+            .args(args)
+            .current_dir(self.dir)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| AppError::GitError(format!("Failed to execute git: {}", e)))?;
+        let pid = child.id();
+        let mut registered = pid.map(|pid| self.children.register(pid));
+
+        let mut stdout = child.stdout.take();
+        let mut stderr = child.stderr.take();
+        let finished = tokio::time::timeout_at(self.deadline, async {
+            tokio::select! {
+                output = async {
+                    let (stdout, stderr) =
+                        tokio::join!(read_all(stdout.as_mut()), read_all(stderr.as_mut()));
+                    (stdout, stderr, child.wait().await)
+                } => Ok(output),
+                error = self.watch_size() => Err(error),
+            }
+        })
+        .await;
+
+        let stopped = match finished {
+            Ok(Ok(output)) => Ok(output),
+            Ok(Err(error)) => Err(error),
+            Err(_) => {
+                error!("git {} timed out", args[0]);
+                Err(AppError::GitError(format!(
+                    "Checkout did not finish within {} seconds",
+                    self.limits.timeout.as_secs()
+                )))
+            }
+        };
+        let (stdout, stderr, status) = match stopped {
+            Ok(output) => output,
+            Err(error) => {
+                if let Some(pid) = pid {
+                    shutdown::kill_group(pid);
+                }
+                let _ = child.wait().await;
+                if let Some(guard) = registered.as_mut() {
+                    guard.exited();
+                }
+                return Err(error);
+            }
+        };
+        if let Some(guard) = registered.as_mut() {
+            guard.exited();
+        }
+        let status =
+            status.map_err(|e| AppError::GitError(format!("Failed to wait for git: {}", e)))?;
+
+        if !status.success() {
+            return Err(AppError::GitError(format!(
+                "git {} exited with status {}: {}",
+                args[0],
+                status,
+                stderr.trim()
+            )));
+        }
+
+        Ok(stdout.trim().to_string())
+    }
+
+    /// Resolves once the clone outgrows `max_bytes`, with the error to fail
+    /// the checkout with.
+    async fn watch_size(&self) -> AppError {
+        loop {
+            tokio::time::sleep(SIZE_CHECK_INTERVAL).await;
+            if let Err(error) = self.check_size().await {
+                return error;
+            }
+        }
+    }
+
+    async fn check_size(&self) -> Result<(), AppError> {
+        let dir = self.dir.to_path_buf();
+        let size = tokio::task::spawn_blocking(move || dir_size(&dir))
+            .await
+            .unwrap_or(0);
+        if size > self.limits.max_bytes {
+            return Err(AppError::ArchiveLimitExceeded(format!(
+                "repository exceeds the {} byte limit",
+                self.limits.max_bytes
+            )));
+        }
+        Ok(())
+    }
+}
+
+async fn read_all(reader: Option<&mut (impl tokio::io::AsyncRead + Unpin)>) -> String {
+    let mut output = Vec::new();
+    if let Some(reader) = reader {
+        let _ = reader.read_to_end(&mut output).await;
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+/// Bytes taken by the files under `dir`; symlinks are not followed.
+fn dir_size(dir: &Path) -> u64 {
+    let mut size = 0;
+    let mut pending: Vec<PathBuf> = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                size += metadata.len();
+            }
+        }
+    }
+    size
+}
@@ -0,0 +1,253 @@
+//! Pull request comments on GitHub. A job sent with `github_repo` and
+//! `pr_number` summarises its outcome in a comment on that pull request:
+//! severity counts, the most severe findings and a link to the full result.
+//! Later scans of the same pull request edit that comment instead of adding
+//! another.
+
+use crate::config::GitHubConfig;
+use crate::proxy::OutboundProxy;
+use crate::review_summary;
+use crate::{AnalyzeResponse, AppError};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// GitHub rejects API requests without a user agent.
+const USER_AGENT: &str = "sonar-backend";
+
+const API_VERSION: &str = "2022-11-28";
+
+/// Comments fetched per page while looking for an earlier summary.
+const COMMENTS_PER_PAGE: usize = 100;
+
+/// Pages searched for an earlier summary before posting a new one.
+const MAX_COMMENT_PAGES: usize = 10;
+
+/// Parses `github_repo`: `owner/name`, as in the repository's URL.
+pub fn parse_repo(name: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && part.len() <= 100
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    match value.split_once('/') {
+        Some((owner, repo)) if valid_part(owner) && valid_part(repo) => Ok(value.to_string()),
+        _ => Err(AppError::InvalidRequest(format!(
+            "Field '{}' must be a repository as owner/name",
+            name
+        ))),
+    }
+}
+
+/// The value of `X-GitHub-Token`, which must not be blank when sent.
+pub fn parse_token(value: Option<&str>) -> Result<Option<String>, AppError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let token = value.trim();
+    if token.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Header 'X-GitHub-Token' must hold a GitHub token".to_string(),
+        ));
+    }
+    Ok(Some(token.to_string()))
+}
+
+/// The pull request a job comments on.
+#[derive(Clone)]
+pub struct PullRequest {
+    repo: String,
+    number: u64,
+    token: String,
+    /// Tells the summaries of several projects scanned for one pull request
+    /// apart.
+    project_name: Option<String>,
+}
+
+impl PullRequest {
+    /// The pull request named by `github_repo` and `pr_number`, if any,
+    /// commented on with the request's token or else the configured one.
+    pub fn new(
+        repo: Option<&str>,
+        number: Option<u64>,
+        token: Option<&str>,
+        project_name: Option<&str>,
+        config: &GitHubConfig,
+    ) -> Result<Option<Self>, AppError> {
+        let (repo, number) = match (repo, number) {
+            (None, None) => return Ok(None),
+            (Some(repo), Some(number)) => (repo, number),
+            (Some(_), None) => {
+                return Err(AppError::InvalidRequest(
+                    "Field 'pr_number' is required with 'github_repo'".to_string(),
+                ))
+            }
+            (None, Some(_)) => {
+                return Err(AppError::InvalidRequest(
+                    "Field 'github_repo' is required with 'pr_number'".to_string(),
+                ))
+            }
+        };
+        if number == 0 {
+            return Err(AppError::InvalidRequest(
+                "Field 'pr_number' must be a positive integer".to_string(),
+            ));
+        }
+        let token = token.or(config.token.as_deref()).ok_or_else(|| {
+            AppError::InvalidRequest(
+                "Commenting on 'pr_number' needs an X-GitHub-Token header or GITHUB_TOKEN"
+                    .to_string(),
+            )
+        })?;
+        Ok(Some(Self {
+            repo: repo.to_string(),
+            number,
+            token: token.to_string(),
+            project_name: project_name.map(str::to_string),
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct IssueComment {
+    id: u64,
+    #[serde(default)]
+    body: String,
+}
+
+/// Posts and updates pull request summaries.
+#[derive(Clone)]
+pub struct GitHubCommenter {
+    http: reqwest::Client,
+    config: GitHubConfig,
+}
+
+impl GitHubCommenter {
+    pub fn new(config: &GitHubConfig, proxy: &OutboundProxy) -> Self {
+        let http = proxy
+            .client_builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    /// Summarises a finished job on `pull_request` in a task tracked by
+    /// `tasks`. Failures are logged, never returned: the job has already
+    /// finished and its result stays available from the API.
+    pub fn comment(
+        &self,
+        tasks: &TaskTracker,
+        pull_request: PullRequest,
+        job_id: &str,
+        outcome: Result<&AnalyzeResponse, &AppError>,
+    ) {
+        let body = review_summary::render(
+            pull_request.project_name.as_deref(),
+            job_id,
+            outcome,
+            self.config.top_findings,
+            self.config.public_url.as_deref(),
+        );
+        let commenter = self.clone();
+        let job_id = job_id.to_string();
+        tasks.spawn(async move {
+            match commenter.upsert(&pull_request, &body).await {
+                Ok(()) => info!(
+                    "Commented on {}#{} for job {}",
+                    pull_request.repo, pull_request.number, job_id
+                ),
+                Err(e) => warn!(
+                    "Failed to comment on {}#{} for job {}: {}",
+                    pull_request.repo, pull_request.number, job_id, e
+                ),
+            }
+        });
+    }
+
+    /// Edits the earlier summary on the pull request, or posts one.
+    async fn upsert(&self, pull_request: &PullRequest, body: &str) -> Result<(), String> {
+        let payload = serde_json::json!({ "body": body });
+        let request = match self.find_summary(pull_request).await? {
+            Some(id) => self.http.patch(format!(
+                "{}/repos/{}/issues/comments/{}",
+                self.api_url(),
+                pull_request.repo,
+                id
+            )),
+            None => self.http.post(format!(
+                "{}/repos/{}/issues/{}/comments",
+                self.api_url(),
+                pull_request.repo,
+                pull_request.number
+            )),
+        };
+        self.send(request.json(&payload), pull_request).await?;
+        Ok(())
+    }
+
+    /// ID of the comment holding the pull request's earlier summary.
+    async fn find_summary(&self, pull_request: &PullRequest) -> Result<Option<u64>, String> {
+        let marker = review_summary::marker(pull_request.project_name.as_deref());
+        for page in 1..=MAX_COMMENT_PAGES {
+            let request = self
+                .http
+                .get(format!(
+                    "{}/repos/{}/issues/{}/comments",
+                    self.api_url(),
+                    pull_request.repo,
+                    pull_request.number
+                ))
+                .query(&[("per_page", COMMENTS_PER_PAGE), ("page", page)]);
+            let comments: Vec<IssueComment> = self
+                .send(request, pull_request)
+                .await?
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse comments: {}", e))?;
+            if let Some(comment) = comments
+                .iter()
+                .find(|comment| comment.body.starts_with(&marker))
+            {
+                return Ok(Some(comment.id));
+            }
+            if comments.len() < COMMENTS_PER_PAGE {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        pull_request: &PullRequest,
+    ) -> Result<reqwest::Response, String> {
+        let response = request
+            .bearer_auth(&pull_request.token)
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .header("x-github-api-version", API_VERSION)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            Err(format!("HTTP {}", status))
+        }
+    }
+
+    fn api_url(&self) -> &str {
+        self.config.api_url.trim_end_matches('/')
+    }
+}
@@ -0,0 +1,304 @@
+//! Merge request notes and commit statuses on GitLab. A job sent with
+//! `gitlab_project` and `mr_iid` summarises its outcome in a note on that
+//! merge request, edited rather than posted again on later scans; with
+//! `commit_sha` it also sets a commit status that passes or fails by
+//! `GITLAB_FAIL_ON`, so merge request pipelines can be gated on the scan.
+
+use crate::config::GitLabConfig;
+use crate::proxy::OutboundProxy;
+use crate::review_summary;
+use crate::severity::Severity;
+use crate::{AnalyzeResponse, AppError};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// Notes fetched per page while looking for an earlier summary.
+const NOTES_PER_PAGE: usize = 100;
+
+/// Pages searched for an earlier summary before posting a new one.
+const MAX_NOTE_PAGES: usize = 10;
+
+/// Commit status name, shown in the merge request's pipeline widget.
+const STATUS_NAME: &str = "sonar-backend";
+
+/// Parses `gitlab_project`: a numeric project ID or the project's path,
+/// such as `group/subgroup/project`.
+pub fn parse_project(name: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    let valid_segment = |segment: &str| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    if value.len() > 255 || !value.split('/').all(valid_segment) {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' must be a project ID or path such as group/project",
+            name
+        )));
+    }
+    Ok(value.to_string())
+}
+
+/// Where on GitLab a job reports its outcome.
+#[derive(Clone)]
+pub struct MergeRequestTarget {
+    project: String,
+    mr_iid: Option<u64>,
+    commit_sha: Option<String>,
+    /// Tells the notes and statuses of several projects scanned for one
+    /// merge request apart.
+    project_name: Option<String>,
+}
+
+impl MergeRequestTarget {
+    /// The target named by `gitlab_project`, `mr_iid` and `commit_sha`, if
+    /// any, once checked for consistency.
+    pub fn new(
+        project: Option<&str>,
+        mr_iid: Option<u64>,
+        commit_sha: Option<&str>,
+        project_name: Option<&str>,
+        config: &GitLabConfig,
+    ) -> Result<Option<Self>, AppError> {
+        let Some(project) = project else {
+            return match (mr_iid, commit_sha) {
+                (None, None) => Ok(None),
+                _ => Err(AppError::InvalidRequest(
+                    "Field 'gitlab_project' is required with 'mr_iid' and 'commit_sha'".to_string(),
+                )),
+            };
+        };
+        if mr_iid.is_none() && commit_sha.is_none() {
+            return Err(AppError::InvalidRequest(
+                "Field 'gitlab_project' needs 'mr_iid', 'commit_sha' or both".to_string(),
+            ));
+        }
+        if mr_iid == Some(0) {
+            return Err(AppError::InvalidRequest(
+                "Field 'mr_iid' must be a positive integer".to_string(),
+            ));
+        }
+        if config.token.is_none() {
+            return Err(AppError::InvalidRequest(
+                "Field 'gitlab_project' needs GITLAB_TOKEN to be configured".to_string(),
+            ));
+        }
+        Ok(Some(Self {
+            project: project.to_string(),
+            mr_iid,
+            commit_sha: commit_sha.map(str::to_string),
+            project_name: project_name.map(str::to_string),
+        }))
+    }
+
+    /// The project as a path segment of an API URL.
+    fn project_id(&self) -> String {
+        self.project.replace('/', "%2F")
+    }
+
+    fn status_name(&self) -> String {
+        match &self.project_name {
+            Some(name) => format!("{}: {}", STATUS_NAME, name),
+            None => STATUS_NAME.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Note {
+    id: u64,
+    #[serde(default)]
+    body: String,
+}
+
+/// Posts merge request notes and commit statuses.
+#[derive(Clone)]
+pub struct GitLabReporter {
+    http: reqwest::Client,
+    config: GitLabConfig,
+}
+
+impl GitLabReporter {
+    pub fn new(config: &GitLabConfig, proxy: &OutboundProxy) -> Self {
+        let http = proxy
+            .client_builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    /// Reports a finished job to `target` in a task tracked by `tasks`.
+    /// Failures are logged, never returned: the job has already finished
+    /// and its result stays available from the API.
+    pub fn report(
+        &self,
+        tasks: &TaskTracker,
+        target: MergeRequestTarget,
+        job_id: &str,
+        outcome: Result<&AnalyzeResponse, &AppError>,
+    ) {
+        let note = target.mr_iid.map(|iid| {
+            let body = review_summary::render(
+                target.project_name.as_deref(),
+                job_id,
+                outcome,
+                self.config.top_findings,
+                self.config.public_url.as_deref(),
+            );
+            (iid, body)
+        });
+        let status = target.commit_sha.clone().map(|sha| {
+            let (state, description) = commit_state(outcome, self.config.fail_on);
+            (sha, state, description)
+        });
+        let reporter = self.clone();
+        let job_id = job_id.to_string();
+        tasks.spawn(async move {
+            if let Some((iid, body)) = note {
+                match reporter.upsert_note(&target, iid, &body).await {
+                    Ok(()) => info!("Noted job {} on {}!{}", job_id, target.project, iid),
+                    Err(e) => warn!(
+                        "Failed to note job {} on {}!{}: {}",
+                        job_id, target.project, iid, e
+                    ),
+                }
+            }
+            if let Some((sha, state, description)) = status {
+                match reporter
+                    .set_status(&target, &sha, state, &description, &job_id)
+                    .await
+                {
+                    Ok(()) => info!(
+                        "Set commit status {} on {}@{} for job {}",
+                        state, target.project, sha, job_id
+                    ),
+                    Err(e) => warn!(
+                        "Failed to set commit status on {}@{} for job {}: {}",
+                        target.project, sha, job_id, e
+                    ),
+                }
+            }
+        });
+    }
+
+    /// Edits the earlier summary on the merge request, or posts one.
+    async fn upsert_note(
+        &self,
+        target: &MergeRequestTarget,
+        iid: u64,
+        body: &str,
+    ) -> Result<(), String> {
+        let notes_url = format!(
+            "{}/projects/{}/merge_requests/{}/notes",
+            self.api_url(),
+            target.project_id(),
+            iid
+        );
+        let payload = serde_json::json!({ "body": body });
+        let request = match self.find_summary(target, &notes_url).await? {
+            Some(id) => self.http.put(format!("{}/{}", notes_url, id)),
+            None => self.http.post(&notes_url),
+        };
+        self.send(request.json(&payload)).await?;
+        Ok(())
+    }
+
+    /// ID of the note holding the merge request's earlier summary.
+    async fn find_summary(
+        &self,
+        target: &MergeRequestTarget,
+        notes_url: &str,
+    ) -> Result<Option<u64>, String> {
+        let marker = review_summary::marker(target.project_name.as_deref());
+        for page in 1..=MAX_NOTE_PAGES {
+            let request = self
+                .http
+                .get(notes_url)
+                .query(&[("per_page", NOTES_PER_PAGE), ("page", page)]);
+            let notes: Vec<Note> = self
+                .send(request)
+                .await?
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse notes: {}", e))?;
+            if let Some(note) = notes.iter().find(|note| note.body.starts_with(&marker)) {
+                return Ok(Some(note.id));
+            }
+            if notes.len() < NOTES_PER_PAGE {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    async fn set_status(
+        &self,
+        target: &MergeRequestTarget,
+        sha: &str,
+        state: &str,
+        description: &str,
+        job_id: &str,
+    ) -> Result<(), String> {
+        let mut payload = serde_json::json!({
+            "state": state,
+            "name": target.status_name(),
+            "description": description,
+        });
+        if let Some(url) = &self.config.public_url {
+            payload["target_url"] = review_summary::result_url(url, job_id).into();
+        }
+        let request = self
+            .http
+            .post(format!(
+                "{}/projects/{}/statuses/{}",
+                self.api_url(),
+                target.project_id(),
+                sha
+            ))
+            .json(&payload);
+        self.send(request).await?;
+        Ok(())
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let token = self.config.token.as_deref().unwrap_or_default();
+        let response = request
+            .header("private-token", token)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            Err(format!("HTTP {}", status))
+        }
+    }
+
+    fn api_url(&self) -> &str {
+        self.config.api_url.trim_end_matches('/')
+    }
+}
+
+/// GitLab commit status state and description for a job's outcome.
+fn commit_state(
+    outcome: Result<&AnalyzeResponse, &AppError>,
+    fail_on: Severity,
+) -> (&'static str, String) {
+    match outcome {
+        Ok(response) => match review_summary::verdict(response, fail_on) {
+            (true, reason) => ("success", reason),
+            (false, reason) => ("failed", reason),
+        },
+        Err(AppError::Canceled(_)) => ("canceled", "Scan canceled".to_string()),
+        Err(e) => ("failed", format!("Scan failed: {}", e.parts().1)),
+    }
+}
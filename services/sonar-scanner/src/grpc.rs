@@ -0,0 +1,362 @@
+//! The `sonar.v1.Analyzer` gRPC service (`proto/analyzer.proto`): the
+//! `/analyze` pipeline for clients that speak gRPC rather than multipart
+//! HTTP. Requests are validated, cached, queued and run exactly like REST
+//! ones, and each job is also served by the `/jobs` endpoints.
+
+use crate::audit::{self, Caller};
+use crate::auth::{ApiKeys, Tenant, API_KEY_HEADER, SONAR_TOKEN_HEADER};
+use crate::git_source::GitSource;
+use crate::jobs::{self, JobEvent};
+use crate::rate_limit::{self, RateLimiter};
+use crate::{
+    apply_option, ids, job_temp_dir, parse_sonar_token, prepare_analysis, run_job, Analysis,
+    AnalyzeOptions, AnalyzeResponse, AppError, AppState, UploadSource, OPTION_FIELDS,
+};
+use axum::http::StatusCode;
+use futures::{stream, Stream, StreamExt};
+use proto::{
+    analyze_request::Payload,
+    analyze_update::Update,
+    analyzer_server::{Analyzer, AnalyzerServer},
+    AnalyzeRequest, AnalyzeResult, AnalyzeUpdate, Progress,
+};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+};
+use time::format_description::well_known::Rfc3339;
+use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
+use tonic::{
+    metadata::MetadataValue, transport::Server, Code, Request, Response, Status, Streaming,
+};
+use tracing::{error, info, info_span, instrument, Instrument};
+
+pub mod proto {
+    tonic::include_proto!("sonar.v1");
+}
+
+/// Metadata key carrying the REST error code on failed calls.
+const ERROR_CODE_KEY: &str = "x-error-code";
+
+type UpdateStream = Pin<Box<dyn Stream<Item = Result<AnalyzeUpdate, Status>> + Send>>;
+
+/// Serves the `Analyzer` service on `addr` until `stopping` is cancelled,
+/// behind the same API keys and rate limit as `/analyze`.
+pub async fn serve(
+    state: AppState,
+    addr: SocketAddr,
+    api_keys: ApiKeys,
+    limiter: Option<Arc<RateLimiter>>,
+    stopping: CancellationToken,
+) {
+    let service =
+        AnalyzerServer::with_interceptor(AnalyzerService { state }, move |request: Request<()>| {
+            admit(&api_keys, limiter.as_deref(), request)
+        });
+
+    info!("gRPC listening on {}", addr);
+    if let Err(e) = Server::builder()
+        .add_service(service)
+        .serve_with_shutdown(addr, stopping.cancelled_owned())
+        .await
+    {
+        error!("gRPC server on {} failed: {}", addr, e);
+    }
+}
+
+/// Checks the `x-api-key` metadata and the client's rate, identifying
+/// clients the way the HTTP rate limit does so both share one budget. The
+/// key's [`Tenant`] and the [`Caller`] are recorded on the request.
+fn admit(
+    api_keys: &ApiKeys,
+    limiter: Option<&RateLimiter>,
+    mut request: Request<()>,
+) -> Result<Request<()>, Status> {
+    let key = request
+        .metadata()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let Some(tenant) = api_keys.authenticate(key) else {
+        return Err(status(&AppError::Unauthorized(
+            "missing or invalid x-api-key metadata".to_string(),
+        )));
+    };
+
+    if let Some(limiter) = limiter {
+        let client = match (key, request.remote_addr()) {
+            (Some(key), _) => format!("key:{}", key.trim()),
+            (None, Some(addr)) => format!("ip:{}", addr.ip()),
+            (None, None) => "ip:unknown".to_string(),
+        };
+        if let Err(wait) = limiter.check(&client) {
+            let retry_after = rate_limit::retry_after_secs(wait);
+            let mut rejection = status(&AppError::RateLimited(format!(
+                "too many requests, retry in {} second(s)",
+                retry_after
+            )));
+            rejection
+                .metadata_mut()
+                .insert("retry-after", MetadataValue::from(retry_after));
+            return Err(rejection);
+        }
+    }
+    let caller = Caller {
+        api_key: key
+            .filter(|_| api_keys.is_enabled() || tenant.is_admin())
+            .map(audit::key_fingerprint),
+        client_certificate: None,
+        source_ip: request.remote_addr().map(|addr| addr.ip()),
+    };
+    request.extensions_mut().insert(tenant);
+    request.extensions_mut().insert(caller);
+    Ok(request)
+}
+
+struct AnalyzerService {
+    state: AppState,
+}
+
+#[tonic::async_trait]
+impl Analyzer for AnalyzerService {
+    type AnalyzeStream = UpdateStream;
+
+    async fn analyze(
+        &self,
+        request: Request<Streaming<AnalyzeRequest>>,
+    ) -> Result<Response<Self::AnalyzeStream>, Status> {
+        let tenant = request
+            .extensions()
+            .get::<Tenant>()
+            .cloned()
+            .unwrap_or_default();
+        let caller = request
+            .extensions()
+            .get::<Caller>()
+            .cloned()
+            .unwrap_or_default();
+        let sonar_token = parse_sonar_token(
+            request
+                .metadata()
+                .get(SONAR_TOKEN_HEADER)
+                .map(|value| value.to_str().unwrap_or_default()),
+        )
+        .map_err(|e| status(&e))?;
+        analyze(
+            self.state.clone(),
+            tenant,
+            caller,
+            sonar_token,
+            request.into_inner(),
+        )
+        .await
+        .map(Response::new)
+        .map_err(|e| status(&e))
+    }
+}
+
+#[instrument(skip_all, fields(job_id))]
+async fn analyze(
+    state: AppState,
+    tenant: Tenant,
+    caller: Caller,
+    sonar_token: Option<String>,
+    mut requests: Streaming<AnalyzeRequest>,
+) -> Result<UpdateStream, AppError> {
+    info!("Received gRPC analyze request");
+
+    let job_id = ids::job_id();
+    tracing::Span::current().record("job_id", &job_id);
+
+    let temp_dir = job_temp_dir()?;
+    let (source, mut options, force) = read_request(
+        &mut requests,
+        temp_dir.path(),
+        state.config.limits.max_upload_bytes,
+    )
+    .instrument(info_span!("read_upload"))
+    .await?;
+    options.apply_tenant(&tenant)?;
+    options.sonar_token = sonar_token;
+    options.caller = caller;
+
+    match prepare_analysis(&state, &job_id, temp_dir, source, options, force)? {
+        Analysis::Cached(response) => {
+            let update = result_update(&job_id, &response);
+            Ok(stream::once(async move { update }).boxed())
+        }
+        Analysis::Scan(upload, reserved_slot) => {
+            // Subscribed before the job starts so no stage is missed
+            let (history, receiver) = state
+                .jobs
+                .subscribe(&job_id)
+                .await?
+                .ok_or_else(|| AppError::InternalError(format!("Job {} vanished", job_id)))?;
+
+            // Runs to completion even if the client goes away
+            let outcome = state.tasks.spawn(run_job(
+                state.clone(),
+                job_id.clone(),
+                upload,
+                reserved_slot,
+            ));
+
+            let progress = jobs::event_stream(history, receiver).map({
+                let job_id = job_id.clone();
+                move |event| Ok(progress_update(&job_id, event))
+            });
+            let result = stream::once(async move {
+                match outcome.await {
+                    Ok(Ok(response)) => result_update(&job_id, &response),
+                    Ok(Err(e)) => Err(status(&e)),
+                    Err(e) => Err(Status::internal(format!("Analysis task failed: {}", e))),
+                }
+            });
+            Ok(progress.chain(result).boxed())
+        }
+    }
+}
+
+/// Reads the settings message and, for archive uploads, streams the
+/// following chunks to disk.
+async fn read_request(
+    requests: &mut Streaming<AnalyzeRequest>,
+    temp_path: &Path,
+    max_upload_bytes: u64,
+) -> Result<(UploadSource, AnalyzeOptions, bool), AppError> {
+    let settings = match next_payload(requests).await? {
+        Some(Payload::Settings(settings)) => settings,
+        _ => {
+            return Err(AppError::MissingField(
+                "The first message must carry settings".to_string(),
+            ))
+        }
+    };
+
+    let mut options = AnalyzeOptions::default();
+    for (name, value) in &settings.options {
+        if OPTION_FIELDS.contains(&name.as_str()) {
+            apply_option(&mut options, name, value)?;
+        }
+    }
+
+    let source = match settings.git {
+        Some(git) => UploadSource::Git(GitSource::parse(&git.url, Some(git.r#ref.as_str()))?),
+        None => UploadSource::Archive(save_archive(requests, temp_path, max_upload_bytes).await?),
+    };
+    Ok((source, options, settings.force))
+}
+
+/// Writes the archive chunks to the temp dir as they arrive, so the upload
+/// is never held in memory.
+async fn save_archive(
+    requests: &mut Streaming<AnalyzeRequest>,
+    temp_path: &Path,
+    max_upload_bytes: u64,
+) -> Result<PathBuf, AppError> {
+    let path = temp_path.join("upload.archive");
+    let write_error =
+        |e: std::io::Error| AppError::ZipError(format!("Failed to write zip file: {}", e));
+    let mut file = tokio::fs::File::create(&path).await.map_err(write_error)?;
+    let mut size = 0u64;
+
+    while let Some(payload) = next_payload(requests).await? {
+        let Payload::ArchiveChunk(chunk) = payload else {
+            return Err(AppError::InvalidRequest(
+                "Only the first message may carry settings".to_string(),
+            ));
+        };
+        size += chunk.len() as u64;
+        if size > max_upload_bytes {
+            return Err(AppError::ArchiveLimitExceeded(
+                "upload exceeds the request size limit".to_string(),
+            ));
+        }
+        file.write_all(&chunk).await.map_err(write_error)?;
+    }
+
+    if size == 0 {
+        return Err(AppError::MissingField(
+            "No archive chunks or git repository in the request".to_string(),
+        ));
+    }
+    file.flush().await.map_err(write_error)?;
+    info!("Saved {} byte upload to {:?}", size, path);
+    Ok(path)
+}
+
+/// The next request's payload; `None` once the client closes its side.
+async fn next_payload(
+    requests: &mut Streaming<AnalyzeRequest>,
+) -> Result<Option<Payload>, AppError> {
+    loop {
+        match requests.message().await {
+            Ok(Some(AnalyzeRequest {
+                payload: Some(payload),
+            })) => return Ok(Some(payload)),
+            Ok(Some(AnalyzeRequest { payload: None })) => continue,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                return Err(AppError::InvalidRequest(format!(
+                    "Failed to read request stream: {}",
+                    e.message()
+                )))
+            }
+        }
+    }
+}
+
+fn progress_update(job_id: &str, event: JobEvent) -> AnalyzeUpdate {
+    AnalyzeUpdate {
+        job_id: job_id.to_string(),
+        update: Some(Update::Progress(Progress {
+            stage: event.stage.as_str().to_string(),
+            at: event.at.format(&Rfc3339).unwrap_or_default(),
+            detail: event.detail.unwrap_or_default(),
+        })),
+    }
+}
+
+fn result_update(job_id: &str, response: &AnalyzeResponse) -> Result<AnalyzeUpdate, Status> {
+    let json = serde_json::to_string(response)
+        .map_err(|e| Status::internal(format!("Failed to serialize result: {}", e)))?;
+    Ok(AnalyzeUpdate {
+        job_id: job_id.to_string(),
+        update: Some(Update::Result(AnalyzeResult {
+            total_count: response.total_count as u64,
+            cached: response.cached,
+            truncated: response.truncated,
+            quality_gate_status: response
+                .quality_gate
+                .as_ref()
+                .map(|gate| gate.status.clone())
+                .unwrap_or_default(),
+            json,
+        })),
+    })
+}
+
+/// The gRPC status for `error`, with its REST error code attached.
+fn status(error: &AppError) -> Status {
+    let (http_status, code, message) = error.parts();
+    let grpc_code = match http_status {
+        // Shares 409 with other conflicts
+        _ if matches!(error, AppError::Canceled(_)) => Code::Cancelled,
+        StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::UNPROCESSABLE_ENTITY => Code::FailedPrecondition,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    };
+    let mut status = Status::new(grpc_code, message);
+    status
+        .metadata_mut()
+        .insert(ERROR_CODE_KEY, MetadataValue::from_static(code));
+    status
+}
@@ -0,0 +1,233 @@
+use crate::config::{Config, ScannerIsolation};
+use crate::sonar_client::SonarClient;
+use crate::AppError;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use std::{
+    ffi::CString,
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// Delay between startup checks while SonarQube is unreachable or rejects
+/// the credentials.
+const WARM_UP_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Where the service is in its lifecycle, as reported by `/readyz`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Waiting for SonarQube to accept the configured credentials.
+    Starting,
+    Ready,
+    /// Shutting down; no new work should be routed here.
+    Draining,
+}
+
+#[derive(Debug, Serialize)]
+struct GateState {
+    phase: Phase,
+    /// Why the last startup check failed, while starting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// Readiness for load balancers and Kubernetes: not ready until startup
+/// checks pass, and not ready again once shutdown begins.
+#[derive(Clone)]
+pub struct ReadinessGate(Arc<Mutex<GateState>>);
+
+impl Default for ReadinessGate {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(GateState {
+            phase: Phase::Starting,
+            detail: None,
+        })))
+    }
+}
+
+impl ReadinessGate {
+    /// Checks SonarQube until it is up and accepts the credentials, which
+    /// also warms the HTTP client's connection pool, then opens the gate.
+    pub async fn warm_up(&self, sonar: &SonarClient) {
+        loop {
+            let check = match sonar.system_status().await {
+                Ok(status) if status == "UP" => sonar.validate_auth().await,
+                Ok(status) => Err(AppError::SonarQubeUnavailable(format!(
+                    "SonarQube status is {}",
+                    status
+                ))),
+                Err(e) => Err(e),
+            };
+
+            {
+                let mut state = self.0.lock().unwrap();
+                if state.phase != Phase::Starting {
+                    return;
+                }
+                match check {
+                    Ok(()) => {
+                        info!("SonarQube is up and accepts the credentials; ready");
+                        state.phase = Phase::Ready;
+                        state.detail = None;
+                        return;
+                    }
+                    Err(e) => {
+                        warn!("Not ready yet: {}", e);
+                        state.detail = Some(e.to_string());
+                    }
+                }
+            }
+            tokio::time::sleep(WARM_UP_RETRY_DELAY).await;
+        }
+    }
+
+    /// Reports not ready from now on, for the rest of the shutdown.
+    pub fn drain(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.phase = Phase::Draining;
+        state.detail = None;
+    }
+
+    /// `200` when ready, `503` otherwise, with the phase as JSON.
+    pub fn response(&self) -> Response {
+        let state = self.0.lock().unwrap();
+        let status = if state.phase == Phase::Ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        (status, Json(&*state)).into_response()
+    }
+}
+
+/// Outcome of one readiness check.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn pass(detail: impl Into<String>) -> Self {
+        Self {
+            ok: true,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(detail: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Body of `GET /health/ready`; served with `503` unless every check passed.
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub scanner: Check,
+    pub sonarqube: Check,
+    pub disk: Check,
+}
+
+impl IntoResponse for Readiness {
+    fn into_response(self) -> Response {
+        let status = if self.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+/// Checks that scans can run: the scanner (or the container runtime that
+/// provides it) is installed, SonarQube reports itself `UP`, and the temp
+/// directory has room for uploads.
+pub async fn readiness(config: &Config, sonar: &SonarClient) -> Readiness {
+    let scanner = match config.sandbox.isolation {
+        ScannerIsolation::Host => check_executable("sonar-scanner"),
+        ScannerIsolation::Container => check_executable(&config.sandbox.runtime),
+    };
+    let sonarqube = match sonar.system_status().await {
+        Ok(status) if status == "UP" => Check::pass(status),
+        Ok(status) => Check::fail(format!("SonarQube status is {}", status)),
+        Err(e) => Check::fail(e.to_string()),
+    };
+    let disk = check_disk(
+        &std::env::temp_dir(),
+        config.server.min_free_disk_mb * 1024 * 1024,
+    );
+
+    Readiness {
+        ready: scanner.ok && sonarqube.ok && disk.ok,
+        scanner,
+        sonarqube,
+        disk,
+    }
+}
+
+fn check_executable(name: &str) -> Check {
+    match find_executable(name) {
+        Some(path) => Check::pass(path.display().to_string()),
+        None => Check::fail(format!("{} not found on PATH", name)),
+    }
+}
+
+/// `name` itself if it is a path, else its first executable match on `PATH`.
+fn find_executable(name: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        path.metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return is_executable(&path).then_some(path);
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+fn check_disk(dir: &Path, min_free_bytes: u64) -> Check {
+    match free_bytes(dir) {
+        Ok(free) if free >= min_free_bytes => Check::pass(format!(
+            "{} MiB free in {}",
+            free / (1024 * 1024),
+            dir.display()
+        )),
+        Ok(free) => Check::fail(format!(
+            "{} MiB free in {}, below the {} MiB minimum",
+            free / (1024 * 1024),
+            dir.display(),
+            min_free_bytes / (1024 * 1024)
+        )),
+        Err(e) => Check::fail(format!(
+            "Failed to read free space of {}: {}",
+            dir.display(),
+            e
+        )),
+    }
+}
+
+/// Space available to unprivileged users on the file system holding `dir`.
+fn free_bytes(dir: &Path) -> std::io::Result<u64> {
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
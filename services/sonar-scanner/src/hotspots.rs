@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A security-sensitive piece of code that needs a manual review, from
+/// `/api/hotspots/search`. Unlike a vulnerability it is not necessarily a
+/// problem until reviewed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityHotspot {
+    pub key: String,
+    pub rule: String,
+    pub component: String,
+    pub line: Option<u32>,
+    pub message: String,
+    /// OWASP-style category, e.g. `sql-injection` or `weak-cryptography`.
+    pub security_category: Option<String>,
+    /// `HIGH`, `MEDIUM` or `LOW`.
+    pub vulnerability_probability: Option<String>,
+    /// `TO_REVIEW` or `REVIEWED`.
+    pub status: String,
+    /// Review outcome once reviewed: `FIXED`, `SAFE` or `ACKNOWLEDGED`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    /// Quality profile that reported the hotspot, for multi-profile scans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+impl SecurityHotspot {
+    /// File path of the hotspot with the `projectKey:` prefix stripped.
+    pub fn path(&self) -> &str {
+        self.component
+            .split_once(':')
+            .map(|(_, path)| path)
+            .unwrap_or(&self.component)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct HotspotsResponse {
+    pub hotspots: Vec<ApiHotspot>,
+    pub paging: HotspotsPaging,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct HotspotsPaging {
+    pub total: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ApiHotspot {
+    key: String,
+    #[serde(default)]
+    rule_key: String,
+    component: String,
+    line: Option<u32>,
+    #[serde(default)]
+    message: String,
+    security_category: Option<String>,
+    vulnerability_probability: Option<String>,
+    status: String,
+    resolution: Option<String>,
+}
+
+impl From<ApiHotspot> for SecurityHotspot {
+    fn from(hotspot: ApiHotspot) -> Self {
+        Self {
+            key: hotspot.key,
+            rule: hotspot.rule_key,
+            component: hotspot.component,
+            line: hotspot.line,
+            message: hotspot.message,
+            security_category: hotspot.security_category,
+            vulnerability_probability: hotspot.vulnerability_probability,
+            status: hotspot.status,
+            resolution: hotspot.resolution,
+            profile: None,
+        }
+    }
+}
+
+/// Merges per-profile hotspot sets the way
+/// [`profiles::merge`](crate::profiles::merge) merges issues: a hotspot
+/// reported under several profiles (same rule, file and line) is kept once,
+/// tagged with the first profile that reported it.
+pub fn merge_profiles(results: Vec<(String, Vec<SecurityHotspot>)>) -> Vec<SecurityHotspot> {
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+
+    for (profile, hotspots) in results {
+        for mut hotspot in hotspots {
+            let identity = (
+                hotspot.rule.clone(),
+                hotspot.path().to_string(),
+                hotspot.line,
+            );
+            if seen.insert(identity) {
+                hotspot.profile = Some(profile.clone());
+                merged.push(hotspot);
+            }
+        }
+    }
+    merged
+}
@@ -0,0 +1,61 @@
+//! Identifiers drawn from the operating system's random number generator,
+//! so concurrent requests cannot collide and IDs cannot be guessed from the
+//! time they were issued.
+
+/// New job ID, also the base of the job's SonarQube project keys:
+/// `job_` followed by 128 random bits in hex.
+pub fn job_id() -> String {
+    format!("job_{}", random_hex())
+}
+
+/// ID of an archive a client uploads to object storage itself: `upl_`
+/// followed by 128 random bits in hex.
+pub fn upload_id() -> String {
+    format!("upl_{}", random_hex())
+}
+
+/// Whether `id` has the form [`upload_id`] returns, so it is safe in an
+/// object key.
+pub fn is_upload_id(id: &str) -> bool {
+    id.strip_prefix("upl_")
+        .is_some_and(|hex| hex.len() == 32 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// ID for one HTTP request, reported in response envelopes.
+pub fn request_id() -> String {
+    uuid_v4()
+}
+
+/// ID for one webhook delivery, the same across its retries.
+pub fn delivery_id() -> String {
+    uuid_v4()
+}
+
+/// A random (version 4) UUID in its hyphenated form.
+fn uuid_v4() -> String {
+    let mut bytes: [u8; 16] = random_bytes();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        hex[0..4].concat(),
+        hex[4..6].concat(),
+        hex[6..8].concat(),
+        hex[8..10].concat(),
+        hex[10..16].concat()
+    )
+}
+
+/// 128 random bits in lowercase hex.
+fn random_hex() -> String {
+    let bytes: [u8; 16] = random_bytes();
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).expect("operating system random number generator failed");
+    bytes
+}
@@ -0,0 +1,43 @@
+use crate::{AppError, SonarIssue};
+use std::collections::BTreeMap;
+
+/// Issue types `/api/issues/search` can filter on.
+const KNOWN_TYPES: &[&str] = &["BUG", "CODE_SMELL", "VULNERABILITY", "SECURITY_HOTSPOT"];
+
+/// Reported separately under `hotspots`, from `/api/hotspots/search`.
+pub const HOTSPOT_TYPE: &str = "SECURITY_HOTSPOT";
+
+/// Types fetched when the request does not name any.
+pub const DEFAULT_TYPES: &[&str] = &["VULNERABILITY"];
+
+/// Normalizes requested issue types to SonarQube's upper-case names,
+/// rejecting unknown ones.
+pub fn parse(values: &[String]) -> Result<Vec<String>, AppError> {
+    let mut types = Vec::new();
+    for value in values {
+        let normalized = value.trim().to_ascii_uppercase().replace('-', "_");
+        if !KNOWN_TYPES.contains(&normalized.as_str()) {
+            return Err(AppError::InvalidRequest(format!(
+                "Unknown issue type '{}' (expected one of {})",
+                value,
+                KNOWN_TYPES.join(", ")
+            )));
+        }
+        if !types.contains(&normalized) {
+            types.push(normalized);
+        }
+    }
+    Ok(types)
+}
+
+/// Groups issues by their SonarQube type, preserving order within each type.
+pub fn group(issues: &[SonarIssue]) -> BTreeMap<String, Vec<SonarIssue>> {
+    let mut grouped: BTreeMap<String, Vec<SonarIssue>> = BTreeMap::new();
+    for issue in issues {
+        grouped
+            .entry(issue.issue_type.clone())
+            .or_default()
+            .push(issue.clone());
+    }
+    grouped
+}
@@ -0,0 +1,316 @@
+//! Jira tickets for new severe vulnerabilities. With `JIRA_URL` set, every
+//! successful scan files a ticket in `JIRA_PROJECT` for each vulnerability
+//! at least as severe as `JIRA_MIN_SEVERITY` that the project's earlier
+//! scans did not report. Each ticket carries the finding's fingerprint as a
+//! label, so a finding already ticketed is never filed twice, even when it
+//! disappears for a while and comes back.
+
+use crate::config::{JiraConfig, JiraEdition};
+use crate::jobs::{JobStatus, JobStore};
+use crate::notify::{self, Notice, Notifier};
+use crate::proxy::OutboundProxy;
+use crate::review_summary;
+use crate::severity::Severity;
+use crate::storage::JobFilter;
+use crate::SonarIssue;
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// Prefix of the label holding a ticket's finding fingerprint.
+const FINGERPRINT_LABEL: &str = "sonar-fp-";
+
+/// Fingerprints looked up in Jira per search.
+const SEARCH_BATCH: usize = 50;
+
+/// Jira rejects summaries longer than this, in characters.
+const MAX_SUMMARY_LEN: usize = 255;
+
+#[derive(Debug, Deserialize)]
+struct SearchResults {
+    #[serde(default)]
+    issues: Vec<SearchedIssue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchedIssue {
+    fields: SearchedFields,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchedFields {
+    #[serde(default)]
+    labels: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatedIssue {
+    key: String,
+}
+
+/// A finding a ticket may be filed for, taken from the scan's result.
+struct Finding {
+    fingerprint: String,
+    issue: SonarIssue,
+}
+
+/// Files Jira tickets for new findings.
+#[derive(Clone)]
+pub struct JiraFiler {
+    http: reqwest::Client,
+    jobs: JobStore,
+    base_url: String,
+    project: String,
+    config: JiraConfig,
+}
+
+impl JiraFiler {
+    /// `None` when no Jira site is configured.
+    pub fn new(
+        config: &JiraConfig,
+        proxy: &OutboundProxy,
+        jobs: &JobStore,
+    ) -> Result<Option<Self>> {
+        let Some(url) = &config.url else {
+            return Ok(None);
+        };
+        if config.token.is_none() {
+            bail!("JIRA_TOKEN is required with JIRA_URL");
+        }
+        let Some(project) = &config.project else {
+            bail!("JIRA_PROJECT is required with JIRA_URL");
+        };
+        let http = proxy
+            .client_builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+
+        Ok(Some(Self {
+            http,
+            jobs: jobs.clone(),
+            base_url: url.trim_end_matches('/').to_string(),
+            project: project.clone(),
+            config: config.clone(),
+        }))
+    }
+
+    /// Fingerprints of the findings reported by the project's scans before
+    /// job `job_id`.
+    async fn seen_fingerprints(
+        &self,
+        job_id: &str,
+        project_name: &str,
+        tenant: Option<&str>,
+    ) -> Result<HashSet<String>, String> {
+        let filter = JobFilter {
+            statuses: vec![JobStatus::Succeeded],
+            project_name: Some(project_name.to_string()),
+            tenant: tenant.map(str::to_string),
+            ..JobFilter::default()
+        };
+        // One more, since the listing holds this job too
+        let (_, jobs) = self
+            .jobs
+            .list(&filter, self.config.history_scans + 1, 0)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(jobs
+            .iter()
+            .filter(|job| job.id != job_id)
+            .take(self.config.history_scans)
+            .filter_map(|job| job.result.as_deref())
+            .flat_map(|result| &result.vulnerabilities)
+            .filter_map(|issue| issue.fingerprint.clone())
+            .collect())
+    }
+
+    /// Fingerprints among `fingerprints` that already have a ticket.
+    async fn ticketed(&self, fingerprints: &[&str]) -> Result<HashSet<String>, String> {
+        let mut ticketed = HashSet::new();
+        for batch in fingerprints.chunks(SEARCH_BATCH) {
+            let labels: Vec<String> = batch
+                .iter()
+                .map(|fingerprint| format!("\"{}{}\"", FINGERPRINT_LABEL, fingerprint))
+                .collect();
+            let jql = format!(
+                "project = \"{}\" AND labels in ({})",
+                self.project.replace('"', "\\\""),
+                labels.join(", ")
+            );
+            let path = match self.config.edition {
+                JiraEdition::Cloud => "/rest/api/3/search/jql",
+                JiraEdition::Server => "/rest/api/2/search",
+            };
+            let request = self
+                .http
+                .get(format!("{}{}", self.base_url, path))
+                .query(&[("jql", jql.as_str()), ("fields", "labels")])
+                .query(&[("maxResults", SEARCH_BATCH * 2)]);
+            let results: SearchResults = self
+                .send(request)
+                .await?
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse search results: {}", e))?;
+            ticketed.extend(
+                results
+                    .issues
+                    .into_iter()
+                    .flat_map(|issue| issue.fields.labels)
+                    .filter_map(|label| label.strip_prefix(FINGERPRINT_LABEL).map(str::to_string)),
+            );
+        }
+        Ok(ticketed)
+    }
+
+    /// Files the ticket for `finding` and returns its key.
+    async fn file(&self, finding: &Finding, project: &str, job_id: &str) -> Result<String, String> {
+        let issue = &finding.issue;
+        let location = match issue.line {
+            Some(line) => format!("{}:{}", issue.path(), line),
+            None => issue.path().to_string(),
+        };
+        let summary: String = format!(
+            "[{}] {} in {}",
+            issue.severity,
+            review_summary::one_line(&issue.message),
+            issue.path()
+        )
+        .chars()
+        .take(MAX_SUMMARY_LEN)
+        .collect();
+        let job = match &self.config.public_url {
+            Some(url) => format!("[{}|{}]", job_id, notify::report_url(url, job_id)),
+            None => job_id.to_string(),
+        };
+        // Jira wiki markup; the message is quoted verbatim
+        let description = format!(
+            "*Project:* {}\n*Rule:* {}\n*Severity:* {}\n*Location:* {{{{{}}}}}\n\
+             *Fingerprint:* {{{{{}}}}}\n*Found by job:* {}\n\n{{noformat}}\n{}\n{{noformat}}",
+            project,
+            issue.rule,
+            issue.severity,
+            location,
+            finding.fingerprint,
+            job,
+            issue.message.replace("{noformat}", "")
+        );
+        let mut labels = self.config.labels.clone();
+        labels.push(format!("{}{}", FINGERPRINT_LABEL, finding.fingerprint));
+        let payload = json!({
+            "fields": {
+                "project": { "key": self.project },
+                "issuetype": { "name": self.config.issue_type },
+                "summary": summary,
+                "description": description,
+                "labels": labels,
+            }
+        });
+        let request = self
+            .http
+            .post(format!("{}/rest/api/2/issue", self.base_url))
+            .json(&payload);
+        let created: CreatedIssue = self
+            .send(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse the created issue: {}", e))?;
+        Ok(created.key)
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let token = self.config.token.as_deref().unwrap_or_default();
+        let request = match &self.config.username {
+            Some(username) => request.basic_auth(username, Some(token)),
+            None => request.bearer_auth(token),
+        };
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            Err(format!("HTTP {}", status))
+        }
+    }
+}
+
+impl Notifier for JiraFiler {
+    /// Files tickets for a successful scan's new findings; failed scans
+    /// have none.
+    fn notify(&self, tasks: &TaskTracker, notice: &Notice<'_>) {
+        let Ok(response) = notice.outcome else {
+            return;
+        };
+        let mut findings: Vec<Finding> = response
+            .vulnerabilities
+            .iter()
+            .filter(|issue| !issue.suppressed && issue.issue_type == "VULNERABILITY")
+            .filter(|issue| {
+                Severity::parse(&issue.severity).is_some_and(|s| s >= self.config.min_severity)
+            })
+            .filter_map(|issue| {
+                Some(Finding {
+                    fingerprint: issue.fingerprint.clone()?,
+                    issue: issue.clone(),
+                })
+            })
+            .collect();
+        if findings.is_empty() {
+            return;
+        }
+        // Stable, so findings of one severity keep SonarQube's order
+        findings.sort_by_key(|f| std::cmp::Reverse(Severity::parse(&f.issue.severity)));
+
+        let filer = self.clone();
+        let job_id = notice.job_id.to_string();
+        let project = notice.project().to_string();
+        let project_name = notice.options.project_name.clone();
+        let tenant = notice.options.tenant.clone();
+        tasks.spawn(async move {
+            // Without a project name there is no history; the fingerprint
+            // labels alone keep tickets from being filed twice
+            if let Some(project_name) = &project_name {
+                match filer
+                    .seen_fingerprints(&job_id, project_name, tenant.as_deref())
+                    .await
+                {
+                    Ok(seen) => findings.retain(|f| !seen.contains(&f.fingerprint)),
+                    Err(e) => warn!("Failed to read the history of job {}: {}", job_id, e),
+                }
+            }
+            let mut unique = HashSet::new();
+            findings.retain(|f| unique.insert(f.fingerprint.clone()));
+            findings.truncate(filer.config.max_tickets_per_scan);
+            if findings.is_empty() {
+                return;
+            }
+
+            let fingerprints: Vec<&str> = findings.iter().map(|f| f.fingerprint.as_str()).collect();
+            let ticketed = match filer.ticketed(&fingerprints).await {
+                Ok(ticketed) => ticketed,
+                Err(e) => {
+                    // Filing blindly could duplicate tickets
+                    warn!("Failed to search Jira for job {}: {}", job_id, e);
+                    return;
+                }
+            };
+            for finding in findings
+                .iter()
+                .filter(|f| !ticketed.contains(&f.fingerprint))
+            {
+                match filer.file(finding, &project, &job_id).await {
+                    Ok(key) => info!("Filed {} for {} in job {}", key, finding.issue.rule, job_id),
+                    Err(e) => warn!(
+                        "Failed to file a Jira ticket for {} in job {}: {}",
+                        finding.issue.rule, job_id, e
+                    ),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,179 @@
+//! `GET /jobs`: a paged, filterable listing of jobs with a summary of each,
+//! for dashboards and for finding the ID of an earlier scan.
+
+use crate::auth::Tenant;
+use crate::jobs::{JobRecord, JobStatus};
+use crate::severity::{Severity, SeverityCounts};
+use crate::storage::JobFilter;
+use crate::{parse_tenant_field, parse_timestamp_field, AnalyzeResponse, AppError, AppState};
+use axum::{
+    extract::{Query, State},
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Jobs per page unless `limit` says otherwise.
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Most jobs a single page may hold.
+const MAX_PAGE_SIZE: usize = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    /// Statuses to include (comma-separated); all when absent.
+    status: Option<String>,
+    /// Only jobs created at or after this RFC 3339 timestamp or Unix time.
+    since: Option<String>,
+    /// Part of the project name, ignoring case.
+    project: Option<String>,
+    /// Only this tenant's jobs, for API keys without a tenant of their own.
+    tenant: Option<String>,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobList {
+    /// Jobs matching the filters, across all pages.
+    total: usize,
+    offset: usize,
+    limit: usize,
+    jobs: Vec<JobSummary>,
+}
+
+/// A job without its events, logs or result.
+#[derive(Debug, Serialize)]
+struct JobSummary {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+    status: JobStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project_name: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    started_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    finished_at: Option<OffsetDateTime>,
+    /// Time from leaving the queue to finishing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issue_count: Option<usize>,
+    /// Issues per severity, less those suppressed by a baseline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity_counts: Option<SeverityCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality_gate: Option<String>,
+    /// Error code of a failed or canceled job.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_code: Option<String>,
+}
+
+impl JobSummary {
+    fn new(job: &JobRecord) -> Self {
+        Self {
+            id: job.id.clone(),
+            tenant: job.tenant.clone(),
+            status: job.status,
+            project: job.project().map(str::to_string),
+            project_name: job.project_name.clone(),
+            created_at: job.created_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            duration_ms: job
+                .started_at
+                .zip(job.finished_at)
+                .map(|(started, finished)| (finished - started).whole_milliseconds() as i64),
+            issue_count: job.issue_count,
+            severity_counts: job.result.as_deref().map(severity_counts),
+            quality_gate: job
+                .result
+                .as_ref()
+                .and_then(|result| result.quality_gate.as_ref())
+                .map(|gate| gate.status.clone()),
+            error_code: job.error.as_ref().map(|error| error.code.clone()),
+        }
+    }
+}
+
+pub async fn list_jobs_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<JobList>, AppError> {
+    let requested = query
+        .tenant
+        .as_deref()
+        .map(|tenant| parse_tenant_field("tenant", tenant))
+        .transpose()?;
+    let filter = JobFilter {
+        statuses: parse_statuses(query.status.as_deref())?,
+        since: query
+            .since
+            .as_deref()
+            .map(|since| parse_timestamp_field("since", since).map(OffsetDateTime::from))
+            .transpose()?,
+        project: query
+            .project
+            .map(|project| project.trim().to_string())
+            .filter(|project| !project.is_empty()),
+        tenant: tenant.resolve(requested.as_deref())?,
+        project_name: None,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(AppError::InvalidRequest(format!(
+            "Parameter 'limit' must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    let (total, jobs) = state.jobs.list(&filter, limit, query.offset).await?;
+    Ok(Json(JobList {
+        total,
+        offset: query.offset,
+        limit,
+        jobs: jobs.iter().map(JobSummary::new).collect(),
+    }))
+}
+
+/// Issues per severity, less those suppressed by a baseline.
+pub fn severity_counts(result: &AnalyzeResponse) -> SeverityCounts {
+    let mut counts = SeverityCounts::default();
+    for issue in result
+        .vulnerabilities
+        .iter()
+        .filter(|issue| !issue.suppressed)
+    {
+        if let Some(severity) = Severity::parse(&issue.severity) {
+            counts.record(severity);
+        }
+    }
+    counts
+}
+
+fn parse_statuses(value: Option<&str>) -> Result<Vec<JobStatus>, AppError> {
+    let Some(value) = value else {
+        return Ok(Vec::new());
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|status| !status.is_empty())
+        .map(|status| {
+            JobStatus::parse(&status.to_ascii_uppercase()).ok_or_else(|| {
+                AppError::InvalidRequest(format!(
+                    "Unknown job status '{}' (expected QUEUED, RUNNING, SUCCEEDED, FAILED or CANCELED)",
+                    status
+                ))
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,723 @@
+use crate::artifacts::Artifact;
+use crate::logs::{JobLogs, LogLine, LogStream};
+use crate::resource_limits::ProcessResources;
+use crate::storage::{JobFilter, JobRepository, StoredJob};
+use crate::{AnalyzeResponse, AppError};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex, RwLock},
+};
+use time::OffsetDateTime;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Buffered progress events per job for slow SSE subscribers.
+const EVENT_CHANNEL_CAPACITY: usize = 32;
+
+/// Buffered scanner output lines per job for slow WebSocket subscribers.
+const LOG_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Canceled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobStatus::Succeeded | JobStatus::Failed | JobStatus::Canceled
+        )
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "QUEUED",
+            JobStatus::Running => "RUNNING",
+            JobStatus::Succeeded => "SUCCEEDED",
+            JobStatus::Failed => "FAILED",
+            JobStatus::Canceled => "CANCELED",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            JobStatus::Queued,
+            JobStatus::Running,
+            JobStatus::Succeeded,
+            JobStatus::Failed,
+            JobStatus::Canceled,
+        ]
+        .into_iter()
+        .find(|status| status.as_str() == value)
+    }
+}
+
+/// Pipeline stages reported on `GET /jobs/{id}/events`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStage {
+    UploadReceived,
+    Extracted,
+    ScannerStarted,
+    ScannerFinished,
+    CePending,
+    CeSuccess,
+    IssuesFetched,
+    Completed,
+    Failed,
+    Canceled,
+}
+
+impl JobStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStage::UploadReceived => "upload_received",
+            JobStage::Extracted => "extracted",
+            JobStage::ScannerStarted => "scanner_started",
+            JobStage::ScannerFinished => "scanner_finished",
+            JobStage::CePending => "ce_pending",
+            JobStage::CeSuccess => "ce_success",
+            JobStage::IssuesFetched => "issues_fetched",
+            JobStage::Completed => "completed",
+            JobStage::Failed => "failed",
+            JobStage::Canceled => "canceled",
+        }
+    }
+
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            JobStage::Completed | JobStage::Failed | JobStage::Canceled
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobEvent {
+    pub stage: JobStage,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Failure recorded for a job, replayed by `GET /jobs/{id}/result`.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub code: String,
+    pub message: String,
+}
+
+impl From<&AppError> for JobError {
+    fn from(error: &AppError) -> Self {
+        let (status, code, message) = error.parts();
+        Self {
+            status,
+            code: code.to_string(),
+            message,
+        }
+    }
+}
+
+impl IntoResponse for JobError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({
+            "error": self.message,
+            "code": self.code
+        }));
+        (self.status, body).into_response()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobRecord {
+    pub id: String,
+    /// Tenant that submitted the job; only its API keys can see the job.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Logical project the caller filed the job under, for its history.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<String>,
+    pub status: JobStatus,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub started_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub finished_at: Option<OffsetDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issue_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JobError>,
+    pub events: Vec<JobEvent>,
+    /// Limits and usage of each scanner run.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub resources: Vec<ProcessResources>,
+    /// Objects archived to artifact storage once the job finished.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+    #[serde(skip)]
+    pub result: Option<Arc<AnalyzeResponse>>,
+    #[serde(skip)]
+    events_tx: broadcast::Sender<JobEvent>,
+    #[serde(skip)]
+    logs: VecDeque<LogLine>,
+    /// Lines dropped from the front of `logs` to stay within the limit.
+    #[serde(skip)]
+    dropped_log_lines: usize,
+    #[serde(skip)]
+    logs_tx: broadcast::Sender<LogLine>,
+}
+
+impl JobRecord {
+    fn new(id: &str, tenant: Option<&str>, project_name: Option<&str>) -> Self {
+        let (events_tx, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let (logs_tx, _) = broadcast::channel(LOG_CHANNEL_CAPACITY);
+        Self {
+            id: id.to_string(),
+            tenant: tenant.map(str::to_string),
+            project_name: project_name.map(str::to_string),
+            status: JobStatus::Queued,
+            created_at: OffsetDateTime::now_utc(),
+            started_at: None,
+            finished_at: None,
+            issue_count: None,
+            error: None,
+            events: Vec::new(),
+            resources: Vec::new(),
+            artifacts: Vec::new(),
+            result: None,
+            events_tx,
+            logs: VecDeque::new(),
+            dropped_log_lines: 0,
+            logs_tx,
+        }
+    }
+
+    /// Rebuilds a record from storage. Its channels have no senders left, so
+    /// subscribers see only the stored history.
+    fn restore(stored: StoredJob) -> Self {
+        Self {
+            status: stored.status,
+            created_at: stored.created_at,
+            started_at: stored.started_at,
+            finished_at: stored.finished_at,
+            issue_count: stored.issue_count,
+            error: stored.error,
+            events: stored.events,
+            resources: stored.resources,
+            artifacts: stored.artifacts,
+            result: stored.result,
+            logs: stored.logs.lines.into(),
+            dropped_log_lines: stored.logs.dropped,
+            ..Self::new(
+                &stored.id,
+                stored.tenant.as_deref(),
+                stored.project_name.as_deref(),
+            )
+        }
+    }
+
+    fn snapshot(&self) -> StoredJob {
+        StoredJob {
+            id: self.id.clone(),
+            tenant: self.tenant.clone(),
+            project_name: self.project_name.clone(),
+            status: self.status,
+            created_at: self.created_at,
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            issue_count: self.issue_count,
+            error: self.error.clone(),
+            events: self.events.clone(),
+            result: self.result.clone(),
+            logs: self.logs(),
+            resources: self.resources.clone(),
+            artifacts: self.artifacts.clone(),
+        }
+    }
+
+    /// The scanned project's name, once the job has succeeded.
+    pub fn project(&self) -> Option<&str> {
+        self.result
+            .as_ref()
+            .and_then(|result| result.manifest.project_name())
+    }
+
+    pub fn logs(&self) -> JobLogs {
+        JobLogs {
+            lines: self.logs.iter().cloned().collect(),
+            dropped: self.dropped_log_lines,
+        }
+    }
+
+    fn mark_failed(&mut self, error: &AppError) {
+        self.status = JobStatus::Failed;
+        self.finished_at = Some(OffsetDateTime::now_utc());
+        self.error = Some(JobError::from(error));
+        self.push_event(JobStage::Failed, Some(error.to_string()));
+    }
+
+    fn push_event(&mut self, stage: JobStage, detail: Option<String>) {
+        let event = JobEvent {
+            stage,
+            at: OffsetDateTime::now_utc(),
+            detail,
+        };
+        self.events.push(event.clone());
+        // No receivers simply means nobody is watching
+        let _ = self.events_tx.send(event);
+    }
+
+    fn push_log(&mut self, line: LogLine, max_lines: usize) {
+        if self.logs.len() >= max_lines {
+            self.logs.pop_front();
+            self.dropped_log_lines += 1;
+        }
+        self.logs.push_back(line.clone());
+        let _ = self.logs_tx.send(line);
+    }
+}
+
+/// Buffered scanner output plus receivers for what follows. `events` is
+/// `None` when the job has already finished.
+pub struct LogSubscription {
+    pub history: Vec<LogLine>,
+    pub lines: broadcast::Receiver<LogLine>,
+    pub events: Option<broadcast::Receiver<JobEvent>>,
+}
+
+/// Reports pipeline stages for a single job.
+#[derive(Clone)]
+pub struct JobProgress {
+    store: JobStore,
+    job_id: String,
+}
+
+impl JobProgress {
+    pub fn stage(&self, stage: JobStage, detail: Option<String>) {
+        self.store.emit(&self.job_id, stage, detail);
+    }
+
+    pub fn log(&self, stream: LogStream, line: String) {
+        if let Some(job) = self.store.jobs.write().unwrap().get_mut(&self.job_id) {
+            job.push_log(LogLine { stream, line }, self.store.max_log_lines);
+        }
+    }
+
+    /// Records the limits and usage of a finished scanner run.
+    pub fn resources(&self, resources: ProcessResources) {
+        if let Some(job) = self.store.jobs.write().unwrap().get_mut(&self.job_id) {
+            job.resources.push(resources);
+        }
+    }
+}
+
+/// Registry of analysis jobs, shared by the analyze and job routes. Live jobs
+/// are held in memory; with a repository configured, every status change is
+/// also written through so jobs survive restarts and in-memory eviction.
+#[derive(Clone)]
+pub struct JobStore {
+    jobs: Arc<RwLock<HashMap<String, JobRecord>>>,
+    max_stored_jobs: usize,
+    /// Scanner output lines kept per job, in memory and in storage.
+    max_log_lines: usize,
+    repository: Option<Arc<dyn JobRepository>>,
+    writes: Option<mpsc::UnboundedSender<StoredJob>>,
+    /// Triggered by `DELETE /jobs/{id}`, for jobs still queued or running.
+    cancellations: Arc<Mutex<HashMap<String, CancellationToken>>>,
+}
+
+impl JobStore {
+    pub fn new(
+        max_stored_jobs: usize,
+        max_log_lines: usize,
+        repository: Option<Arc<dyn JobRepository>>,
+    ) -> Self {
+        let writes = repository.clone().map(spawn_writer);
+        Self {
+            jobs: Arc::new(RwLock::new(HashMap::new())),
+            max_stored_jobs,
+            max_log_lines: max_log_lines.max(1),
+            repository,
+            writes,
+            cancellations: Arc::default(),
+        }
+    }
+
+    /// Fails jobs a previous process left queued or running; nothing will
+    /// ever finish them.
+    pub async fn recover_interrupted(&self) -> anyhow::Result<()> {
+        let Some(repository) = &self.repository else {
+            return Ok(());
+        };
+
+        let interrupted = repository.unfinished().await?;
+        let error = AppError::InternalError("Job interrupted by a service restart".to_string());
+        for stored in &interrupted {
+            let mut job = JobRecord::restore(stored.clone());
+            job.mark_failed(&error);
+            repository.save(&job.snapshot()).await?;
+        }
+
+        if !interrupted.is_empty() {
+            info!("Marked {} interrupted job(s) as failed", interrupted.len());
+        }
+        Ok(())
+    }
+
+    pub fn create(&self, id: &str, tenant: Option<&str>, project_name: Option<&str>) {
+        let mut job = JobRecord::new(id, tenant, project_name);
+        job.push_event(JobStage::UploadReceived, None);
+        self.persist(&job);
+        self.cancellations
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), CancellationToken::new());
+
+        let mut jobs = self.jobs.write().unwrap();
+        jobs.insert(id.to_string(), job);
+        Self::evict(&mut jobs, self.max_stored_jobs);
+    }
+
+    pub fn progress(&self, id: &str) -> JobProgress {
+        JobProgress {
+            store: self.clone(),
+            job_id: id.to_string(),
+        }
+    }
+
+    pub fn emit(&self, id: &str, stage: JobStage, detail: Option<String>) {
+        if let Some(job) = self.jobs.write().unwrap().get_mut(id) {
+            job.push_event(stage, detail);
+        }
+    }
+
+    /// Events so far plus a receiver for the ones still to come. Taken under
+    /// one lock so no event is missed or delivered twice.
+    pub async fn subscribe(
+        &self,
+        id: &str,
+    ) -> Result<Option<(Vec<JobEvent>, broadcast::Receiver<JobEvent>)>, AppError> {
+        let live = self
+            .jobs
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|job| (job.events.clone(), job.events_tx.subscribe()));
+        if live.is_some() {
+            return Ok(live);
+        }
+
+        let stored = self.load(id).await?;
+        Ok(stored.map(|job| {
+            let receiver = job.events_tx.subscribe();
+            (job.events, receiver)
+        }))
+    }
+
+    /// Scanner output so far plus receivers for the lines still to come and
+    /// for job completion, taken under one lock.
+    pub async fn subscribe_logs(&self, id: &str) -> Result<Option<LogSubscription>, AppError> {
+        let live = self
+            .jobs
+            .read()
+            .unwrap()
+            .get(id)
+            .map(Self::log_subscription);
+        if live.is_some() {
+            return Ok(live);
+        }
+        Ok(self.load(id).await?.as_ref().map(Self::log_subscription))
+    }
+
+    fn log_subscription(job: &JobRecord) -> LogSubscription {
+        LogSubscription {
+            history: job.logs.iter().cloned().collect(),
+            lines: job.logs_tx.subscribe(),
+            events: (!job.status.is_finished()).then(|| job.events_tx.subscribe()),
+        }
+    }
+
+    pub fn mark_running(&self, id: &str) {
+        if let Some(job) = self.jobs.write().unwrap().get_mut(id) {
+            job.status = JobStatus::Running;
+            job.started_at = Some(OffsetDateTime::now_utc());
+            self.persist(job);
+        }
+    }
+
+    /// Fires once the job is canceled; never for a job that has finished.
+    pub fn cancellation(&self, id: &str) -> CancellationToken {
+        self.cancellations
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Marks a queued or running job `CANCELED` and stops its pipeline.
+    /// Returns the job's previous status, or `None` if it is not live.
+    pub fn cancel(&self, id: &str) -> Option<JobStatus> {
+        let mut jobs = self.jobs.write().unwrap();
+        let job = jobs.get_mut(id)?;
+        let previous = job.status;
+        if previous.is_finished() {
+            return Some(previous);
+        }
+
+        let error = AppError::Canceled(format!("Job {} was canceled", id));
+        job.status = JobStatus::Canceled;
+        job.finished_at = Some(OffsetDateTime::now_utc());
+        job.error = Some(JobError::from(&error));
+        job.push_event(JobStage::Canceled, None);
+        self.persist(job);
+        if let Some(token) = self.cancellations.lock().unwrap().remove(id) {
+            token.cancel();
+        }
+        Some(previous)
+    }
+
+    pub fn succeed(&self, id: &str, result: Arc<AnalyzeResponse>) {
+        self.cancellations.lock().unwrap().remove(id);
+        // A job canceled as it finished stays canceled
+        let mut jobs = self.jobs.write().unwrap();
+        if let Some(job) = jobs.get_mut(id).filter(|job| !job.status.is_finished()) {
+            job.status = JobStatus::Succeeded;
+            job.finished_at = Some(OffsetDateTime::now_utc());
+            job.issue_count = Some(result.total_count);
+            job.result = Some(result);
+            job.push_event(JobStage::Completed, None);
+            self.persist(job);
+        }
+    }
+
+    pub fn fail(&self, id: &str, error: &AppError) {
+        self.cancellations.lock().unwrap().remove(id);
+        let mut jobs = self.jobs.write().unwrap();
+        if let Some(job) = jobs.get_mut(id).filter(|job| !job.status.is_finished()) {
+            job.mark_failed(error);
+            self.persist(job);
+        }
+    }
+
+    /// Records objects archived for a finished job, wherever it is held.
+    pub async fn add_artifacts(&self, id: &str, artifacts: Vec<Artifact>) {
+        let evicted = match self.jobs.write().unwrap().get_mut(id) {
+            Some(job) => {
+                job.artifacts.extend(artifacts);
+                self.persist(job);
+                None
+            }
+            None => Some(artifacts),
+        };
+        let Some(artifacts) = evicted else {
+            return;
+        };
+        match self.load(id).await {
+            Ok(Some(mut job)) => {
+                job.artifacts.extend(artifacts);
+                self.persist(&job);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Could not record artifacts of job {}: {}", id, e),
+        }
+    }
+
+    /// Looks the job up in memory, then in the repository.
+    pub async fn get(&self, id: &str) -> Result<Option<JobRecord>, AppError> {
+        let live = self.jobs.read().unwrap().get(id).cloned();
+        match live {
+            Some(job) => Ok(Some(job)),
+            None => self.load(id).await,
+        }
+    }
+
+    /// Jobs matching `filter`, newest first, and how many match in total.
+    /// With a repository the listing comes from there, since evicted jobs
+    /// live only in storage, with live jobs shown as they are in memory.
+    pub async fn list(
+        &self,
+        filter: &JobFilter,
+        limit: usize,
+        offset: usize,
+    ) -> Result<(usize, Vec<JobRecord>), AppError> {
+        let Some(repository) = &self.repository else {
+            let jobs = self.jobs.read().unwrap();
+            let mut matching: Vec<&JobRecord> = jobs
+                .values()
+                .filter(|job| {
+                    filter.matches(
+                        job.status,
+                        job.created_at,
+                        job.project(),
+                        job.tenant.as_deref(),
+                        job.project_name.as_deref(),
+                    )
+                })
+                .collect();
+            matching.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            let total = matching.len();
+            let page = matching
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .cloned()
+                .collect();
+            return Ok((total, page));
+        };
+
+        let page = repository
+            .list(filter, limit, offset)
+            .await
+            .map_err(|e| AppError::InternalError(format!("Failed to list jobs: {:#}", e)))?;
+        let jobs = self.jobs.read().unwrap();
+        let records = page
+            .jobs
+            .into_iter()
+            .map(|stored| match jobs.get(&stored.id) {
+                Some(live) => live.clone(),
+                None => JobRecord::restore(stored),
+            })
+            .collect();
+        Ok((page.total, records))
+    }
+
+    /// Removes finished jobs that finished before `cutoff` from memory and
+    /// from the repository, and returns them.
+    pub async fn purge_finished_before(
+        &self,
+        cutoff: OffsetDateTime,
+    ) -> Result<Vec<StoredJob>, AppError> {
+        let mut purged: Vec<StoredJob> = {
+            let mut jobs = self.jobs.write().unwrap();
+            let aged: Vec<String> = jobs
+                .values()
+                .filter(|job| {
+                    job.status.is_finished() && job.finished_at.unwrap_or(job.created_at) < cutoff
+                })
+                .map(|job| job.id.clone())
+                .collect();
+            aged.iter()
+                .filter_map(|id| jobs.remove(id))
+                .map(|job| job.snapshot())
+                .collect()
+        };
+
+        if let Some(repository) = &self.repository {
+            let stored = repository
+                .delete_finished_before(cutoff)
+                .await
+                .map_err(|e| AppError::InternalError(format!("Failed to purge jobs: {:#}", e)))?;
+            let in_memory: HashSet<String> = purged.iter().map(|job| job.id.clone()).collect();
+            purged.extend(
+                stored
+                    .into_iter()
+                    .filter(|job| !in_memory.contains(&job.id)),
+            );
+        }
+        Ok(purged)
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<JobRecord>, AppError> {
+        let Some(repository) = &self.repository else {
+            return Ok(None);
+        };
+        let stored = repository
+            .load(id)
+            .await
+            .map_err(|e| AppError::InternalError(format!("Failed to load job {}: {:#}", id, e)))?;
+        Ok(stored.map(JobRecord::restore))
+    }
+
+    /// Queues a snapshot for the repository writer, which applies them in order.
+    fn persist(&self, job: &JobRecord) {
+        if let Some(writes) = &self.writes {
+            let _ = writes.send(job.snapshot());
+        }
+    }
+
+    /// Drops the oldest finished jobs once the store exceeds its capacity.
+    /// Jobs still in progress are never evicted.
+    fn evict(jobs: &mut HashMap<String, JobRecord>, max_stored_jobs: usize) {
+        while jobs.len() > max_stored_jobs {
+            let oldest = jobs
+                .values()
+                .filter(|job| job.status.is_finished())
+                .min_by_key(|job| job.created_at)
+                .map(|job| job.id.clone());
+
+            match oldest {
+                Some(id) => {
+                    jobs.remove(&id);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// Writes snapshots one at a time so a job's rows are updated in the order
+/// its status changed. Failures are logged; the in-memory copy stays current.
+fn spawn_writer(repository: Arc<dyn JobRepository>) -> mpsc::UnboundedSender<StoredJob> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<StoredJob>();
+    tokio::spawn(async move {
+        while let Some(job) = receiver.recv().await {
+            if let Err(e) = repository.save(&job).await {
+                warn!("Failed to persist job {}: {:#}", job.id, e);
+            }
+        }
+    });
+    sender
+}
+
+/// Replays `history`, then yields live events until the job reaches a terminal stage.
+pub fn event_stream(
+    history: Vec<JobEvent>,
+    receiver: broadcast::Receiver<JobEvent>,
+) -> impl Stream<Item = JobEvent> {
+    let finished = history
+        .last()
+        .is_some_and(|event| event.stage.is_terminal());
+    let receiver = if finished { None } else { Some(receiver) };
+
+    let live = stream::unfold(receiver, |receiver| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let next = if event.stage.is_terminal() {
+                        None
+                    } else {
+                        Some(receiver)
+                    };
+                    return Some((event, next));
+                }
+                // Skipped events are still in the job history
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    stream::iter(history).chain(live)
+}
@@ -0,0 +1,131 @@
+//! `LOG_FORMAT=json`: one JSON object per line, for Loki or ELK to ingest
+//! without parsing text. Each line has `timestamp`, `level`, `target` and
+//! `message`, the event's own fields, the fields of every span it happened
+//! in (such as `request_id` and `job_id`) and `stage`, the innermost span's
+//! name, e.g. `scanner` or `ce_wait`.
+
+use serde_json::{Map, Value};
+use std::{fmt, io::Write};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::{writer::BoxMakeWriter, MakeWriter},
+    layer::Context,
+    registry::LookupSpan,
+    Layer,
+};
+
+/// Writes events as JSON lines to `writer`.
+pub struct JsonLayer {
+    writer: BoxMakeWriter,
+}
+
+impl JsonLayer {
+    pub fn new(writer: BoxMakeWriter) -> Self {
+        Self { writer }
+    }
+}
+
+/// A span's fields, kept in its extensions for the events inside it.
+struct SpanFields(Map<String, Value>);
+
+impl<S> Layer<S> for JsonLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = Map::new();
+        attrs.record(&mut JsonVisitor(&mut fields));
+        span.extensions_mut().insert(SpanFields(fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            values.record(&mut JsonVisitor(fields));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        let timestamp = OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .unwrap_or_default();
+        line.insert("timestamp".to_string(), Value::String(timestamp));
+        line.insert(
+            "level".to_string(),
+            Value::String(metadata.level().to_string()),
+        );
+        line.insert(
+            "target".to_string(),
+            Value::String(metadata.target().to_string()),
+        );
+
+        // Outer spans first, so an inner span's field wins over an outer one
+        // of the same name
+        if let Some(scope) = ctx.event_scope(event) {
+            let mut stage = None;
+            for span in scope.from_root() {
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    line.extend(fields.clone());
+                }
+                stage = Some(span.name());
+            }
+            if let Some(stage) = stage {
+                line.insert("stage".to_string(), Value::String(stage.to_string()));
+            }
+        }
+        event.record(&mut JsonVisitor(&mut line));
+
+        let mut text = Value::Object(line).to_string();
+        text.push('\n');
+        // Logging has nowhere to report its own failure
+        let _ = self.writer.make_writer().write_all(text.as_bytes());
+    }
+}
+
+/// Records fields as JSON values, keeping numbers and booleans typed.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl JsonVisitor<'_> {
+    fn insert(&mut self, field: &Field, value: Value) {
+        self.0.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::String(format!("{:?}", value)));
+    }
+}
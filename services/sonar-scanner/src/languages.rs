@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+/// Directories that never hold first-party sources.
+const SKIPPED_DIRS: &[&str] = &[".git", "node_modules"];
+
+/// Maps a file extension to the SonarQube language that analyses it.
+fn language_for_extension(extension: &str) -> Option<&'static str> {
+    let language = match extension.to_ascii_lowercase().as_str() {
+        "java" => "java",
+        "js" | "jsx" | "mjs" | "cjs" => "js",
+        "ts" | "tsx" => "ts",
+        "py" => "py",
+        "go" => "go",
+        "cs" => "cs",
+        "vb" => "vbnet",
+        "php" => "php",
+        "rb" => "ruby",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "m" => "objc",
+        "html" | "htm" => "web",
+        "css" | "scss" | "less" => "css",
+        "tf" => "terraform",
+        "sql" => "plsql",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// How much source code of one language a project holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanguageStats {
+    pub files: usize,
+    pub lines: usize,
+}
+
+/// Counts source files and lines per language under `project_dir`.
+pub fn count_sources(project_dir: &Path) -> BTreeMap<&'static str, LanguageStats> {
+    let mut counts = BTreeMap::new();
+    walk(project_dir, &mut counts);
+    counts
+}
+
+/// The language with the most files, if any sources were recognized.
+pub fn primary_language(counts: &BTreeMap<&'static str, LanguageStats>) -> Option<String> {
+    counts
+        .iter()
+        .max_by_key(|(_, stats)| stats.files)
+        .map(|(language, _)| language.to_string())
+}
+
+/// Lines in the file, counting a last line without a newline; `0` if it
+/// cannot be read.
+fn count_lines(path: &Path) -> usize {
+    let Ok(file) = File::open(path) else {
+        return 0;
+    };
+    let mut reader = BufReader::new(file);
+    let mut lines = 0;
+    let mut ends_with_newline = true;
+    loop {
+        let chunk = match reader.fill_buf() {
+            Ok([]) | Err(_) => break,
+            Ok(chunk) => chunk,
+        };
+        lines += chunk.iter().filter(|&&b| b == b'\n').count();
+        ends_with_newline = chunk.ends_with(b"\n");
+        let len = chunk.len();
+        reader.consume(len);
+    }
+    if ends_with_newline {
+        lines
+    } else {
+        lines + 1
+    }
+}
+
+fn walk(dir: &Path, counts: &mut BTreeMap<&'static str, LanguageStats>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            let name = entry.file_name();
+            if !SKIPPED_DIRS.iter().any(|skipped| name == *skipped) {
+                walk(&path, counts);
+            }
+        } else if let Some(language) = path
+            .extension()
+            .and_then(|extension| language_for_extension(&extension.to_string_lossy()))
+        {
+            let stats = counts.entry(language).or_default();
+            stats.files += 1;
+            stats.lines += count_lines(&path);
+        }
+    }
+}
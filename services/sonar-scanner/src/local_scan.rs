@@ -0,0 +1,115 @@
+//! `sonar-backend scan`: runs one analysis through the same pipeline as
+//! `POST /analyze` and prints the result, for local runs and debugging
+//! without a server or HTTP client.
+
+use crate::cli::ScanArgs;
+use crate::config::Config;
+use crate::formats::{self, OutputFormat};
+use crate::jobs::JobStore;
+use crate::{
+    apply_option, build_state, ids, job_temp_dir, prepare_analysis, run_job, Analysis,
+    AnalyzeOptions, AnalyzeResponse, AppError, AppState, UploadSource, OPTION_FIELDS,
+};
+use std::{io::Write, sync::Arc};
+
+/// Scans `args.path` and writes the result to stdout. Errors, and on failure
+/// the analyzers' output, go to stderr. Returns whether the scan succeeded.
+pub async fn run(config: Arc<Config>, args: &ScanArgs) -> bool {
+    // Jobs stay in memory: a local run has no business in the server's store
+    let jobs = JobStore::new(config.jobs.max_stored, config.jobs.max_log_lines, None);
+    let state = build_state(config, jobs);
+    let job_id = ids::job_id();
+
+    let outcome = match scan(&state, &job_id, args).await {
+        Ok((format, response)) => render(format, &response).await,
+        Err(e) => Err(e),
+    };
+    match outcome {
+        Ok(body) => {
+            let mut stdout = std::io::stdout().lock();
+            if let Err(e) = stdout.write_all(&body).and_then(|()| stdout.flush()) {
+                eprintln!("error: Failed to write the result: {}", e);
+                return false;
+            }
+            true
+        }
+        Err(e) => {
+            print_logs(&state, &job_id).await;
+            eprintln!("error: {}", e);
+            false
+        }
+    }
+}
+
+async fn scan(
+    state: &AppState,
+    job_id: &str,
+    args: &ScanArgs,
+) -> Result<(OutputFormat, Arc<AnalyzeResponse>), AppError> {
+    let format = OutputFormat::parse(Some(args.format.as_str()))?;
+    let options = parse_options(&args.options)?;
+
+    let source = if args.path.is_dir() {
+        UploadSource::Directory(args.path.clone())
+    } else if args.path.is_file() {
+        UploadSource::Archive(args.path.clone())
+    } else {
+        return Err(AppError::InvalidRequest(format!(
+            "{} is neither a directory nor a file",
+            args.path.display()
+        )));
+    };
+    let temp_dir = job_temp_dir()?;
+
+    // The result cache starts empty, so nothing can be reused
+    let response = match prepare_analysis(state, job_id, temp_dir, source, options, true)? {
+        Analysis::Cached(response) => response,
+        Analysis::Scan(upload, reserved_slot) => {
+            run_job(state.clone(), job_id.to_string(), upload, reserved_slot).await?
+        }
+    };
+    Ok((format, response))
+}
+
+/// Applies `NAME=VALUE` pairs the way `/analyze` applies its fields.
+fn parse_options(pairs: &[String]) -> Result<AnalyzeOptions, AppError> {
+    let mut options = AnalyzeOptions::default();
+    for pair in pairs {
+        let Some((name, value)) = pair.split_once('=') else {
+            return Err(AppError::InvalidRequest(format!(
+                "Option '{}' must be NAME=VALUE",
+                pair
+            )));
+        };
+        let name = name.trim();
+        if !OPTION_FIELDS.contains(&name) {
+            return Err(AppError::InvalidRequest(format!(
+                "Unknown option '{}' (expected one of {})",
+                name,
+                OPTION_FIELDS.join(", ")
+            )));
+        }
+        apply_option(&mut options, name, value)?;
+    }
+    Ok(options)
+}
+
+/// The result as `/analyze` would return it in `format`.
+async fn render(format: OutputFormat, response: &AnalyzeResponse) -> Result<Vec<u8>, AppError> {
+    let body = formats::render(format, response).into_body();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| AppError::InternalError(format!("Failed to render the result: {}", e)))?;
+    Ok(bytes.to_vec())
+}
+
+/// Writes the job's captured analyzer output to stderr.
+async fn print_logs(state: &AppState, job_id: &str) {
+    let Ok(Some(job)) = state.jobs.get(job_id).await else {
+        return;
+    };
+    let mut stderr = std::io::stderr().lock();
+    for line in job.logs().lines {
+        let _ = writeln!(stderr, "{}", line.line);
+    }
+}
@@ -0,0 +1,83 @@
+use axum::extract::ws::{Message, WebSocket};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::jobs::JobEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// A single line of scanner output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub line: String,
+}
+
+/// The scanner output kept for a job: its most recent lines, up to
+/// `MAX_JOB_LOG_LINES`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobLogs {
+    pub lines: Vec<LogLine>,
+    /// Older lines dropped to stay within the limit.
+    pub dropped: usize,
+}
+
+/// Sends buffered `history` over the socket, then live lines until the job
+/// reaches a terminal stage or the client disconnects. Each line is a JSON
+/// text frame: `{"stream": "stdout" | "stderr", "line": "..."}`.
+pub async fn forward_to_socket(
+    mut socket: WebSocket,
+    history: Vec<LogLine>,
+    mut lines: broadcast::Receiver<LogLine>,
+    mut events: Option<broadcast::Receiver<JobEvent>>,
+) {
+    for line in &history {
+        if send_line(&mut socket, line).await.is_err() {
+            return;
+        }
+    }
+
+    while let Some(receiver) = events.as_mut() {
+        tokio::select! {
+            line = lines.recv() => match line {
+                Ok(line) => {
+                    if send_line(&mut socket, &line).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    let notice = format!("{} log line(s) dropped", skipped);
+                    let _ = socket.send(Message::Text(notice)).await;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            event = receiver.recv() => match event {
+                Ok(event) if event.stage.is_terminal() => events = None,
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => events = None,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    // Lines written just before the job finished may still be queued
+    while let Ok(line) = lines.try_recv() {
+        if send_line(&mut socket, &line).await.is_err() {
+            return;
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+async fn send_line(socket: &mut WebSocket, line: &LogLine) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(line).unwrap_or_default();
+    socket.send(Message::Text(text)).await
+}
@@ -1,15 +1,147 @@
 use axum::{
-    extract::Multipart,
-    http::StatusCode,
-    response::{IntoResponse, Json, Response},
+    extract::{
+        multipart::{Field, MultipartError},
+        ws::WebSocketUpgrade,
+        DefaultBodyLimit, FromRequest, Multipart, Path as UrlPath, Query, Request, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
     routing::{get, post},
-    Router,
+    Extension, Router,
 };
+use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf, process::Command, time::Duration};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use tempfile::TempDir;
 use thiserror::Error;
-use tracing::{error, info, warn};
+use tokio::{io::AsyncWriteExt, sync::OwnedSemaphorePermit};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{error, info, info_span, instrument, warn, Instrument};
+
+mod archive;
+mod archive_url;
+mod artifacts;
+mod audit;
+mod auth;
+mod backends;
+mod baseline;
+mod batch;
+mod bitbucket;
+mod circuit_breaker;
+mod cli;
+mod config;
+mod coverage;
+mod diff;
+mod email;
+mod envelope;
+mod fingerprints;
+mod formats;
+mod git_source;
+mod github;
+mod gitlab;
+mod grpc;
+mod health;
+mod ids;
+mod hotspots;
+mod issue_types;
+mod jira;
+mod job_list;
+mod jobs;
+mod json_log;
+mod languages;
+mod local_scan;
+mod logs;
+mod manifest;
+mod measures;
+mod metrics;
+mod modules;
+mod notify;
+mod openapi;
+mod policy;
+mod profiles;
+mod project_history;
+mod quality_gate;
+mod rate_limit;
+mod request_id;
+mod resource_limits;
+mod result_cache;
+mod retry;
+mod review_summary;
+mod rules;
+mod prune;
+mod proxy;
+mod purge;
+mod sandbox;
+mod scan_slots;
+mod scanner_validation;
+mod severity;
+mod shutdown;
+mod slack;
+mod snippets;
+mod sonar_client;
+mod sonar_properties;
+mod sonar_webhook;
+mod source_hosts;
+mod storage;
+mod teams;
+mod telemetry;
+mod tls;
+mod uploads;
+mod version;
+mod webhook;
+
+use archive::{ArchiveFormat, ExtractionBudget, ExtractionLimits};
+use archive_url::ArchiveDownloader;
+use artifacts::ArtifactStore;
+use audit::{AuditEntry, AuditLog, AuditOutcome, Caller};
+use auth::{ApiKeys, Tenant};
+use backends::{
+    Backends, DependencyVulnerability, DetectedSecret, Engine, GitleaksScanner, ScanContext,
+    SemgrepBackend, SonarQubeBackend, TrivyScanner,
+};
+use baseline::Baseline;
+use bitbucket::{InsightsPublisher, InsightsTarget};
+use clap::Parser;
+use cli::{Cli, Command};
+use config::Config;
+use coverage::{CoverageFile, UploadedReport};
+use formats::OutputFormat;
+use git_source::{CheckoutLimits, GitSource};
+use github::{GitHubCommenter, PullRequest};
+use gitlab::{GitLabReporter, MergeRequestTarget};
+use hotspots::SecurityHotspot;
+use health::ReadinessGate;
+use jobs::{JobProgress, JobStage, JobStatus, JobStore};
+use languages::LanguageStats;
+use manifest::ScanManifest;
+use measures::ProjectMetrics;
+use metrics::{Metrics, Stage};
+use notify::{Notice, Notifier};
+use proxy::OutboundProxy;
+use rate_limit::RateLimiter;
+use request_id::RequestId;
+use resource_limits::ResourceLimits;
+use result_cache::ResultCache;
+use rules::{RuleCache, RuleDetails};
+use scan_slots::ScanSlots;
+use severity::Severity;
+use shutdown::ChildProcesses;
+use snippets::CodeSnippet;
+use modules::ModuleSummary;
+use quality_gate::QualityGate;
+use sonar_client::{AnalysisRef, IssueFilter, SonarClient};
+use webhook::WebhookSender;
 
 // ============================================================================
 // Error Types
@@ -23,6 +155,9 @@ enum AppError {
     #[error("Scanner execution failed: {0}")]
     ScannerError(String),
 
+    #[error("Scanner timed out: {0}")]
+    ScannerTimeout(String),
+
     #[error("SonarQube API error: {0}")]
     ApiError(String),
 
@@ -31,32 +166,149 @@ enum AppError {
 
     #[error("Internal server error: {0}")]
     InternalError(String),
+
+    #[error("SonarQube unavailable: {0}")]
+    SonarQubeUnavailable(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Git error: {0}")]
+    GitError(String),
+
+    #[error("Download error: {0}")]
+    DownloadError(String),
+
+    #[error("Archive limit exceeded: {0}")]
+    ArchiveLimitExceeded(String),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
+    #[error("Scanner busy: {0}")]
+    ScannerBusy(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Canceled: {0}")]
+    Canceled(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, message) = match self {
-            AppError::ZipError(msg) => (StatusCode::BAD_REQUEST, format!("Zip Error: {}", msg)),
+impl AppError {
+    /// HTTP status, machine-readable code and message for this error.
+    fn parts(&self) -> (StatusCode, &'static str, String) {
+        match self {
+            AppError::ZipError(msg) => (
+                StatusCode::BAD_REQUEST,
+                "ZIP_ERROR",
+                format!("Zip Error: {}", msg),
+            ),
             AppError::ScannerError(msg) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
+                "SCANNER_ERROR",
                 format!("Scanner Error: {}", msg),
             ),
+            AppError::ScannerTimeout(msg) => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "SCANNER_TIMEOUT",
+                format!("Scanner Timeout: {}", msg),
+            ),
             AppError::ApiError(msg) => (
                 StatusCode::BAD_GATEWAY,
+                "SONARQUBE_API_ERROR",
                 format!("SonarQube API Error: {}", msg),
             ),
             AppError::MissingField(msg) => (
                 StatusCode::BAD_REQUEST,
+                "MISSING_FIELD",
                 format!("Missing Field: {}", msg),
             ),
             AppError::InternalError(msg) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL_ERROR",
                 format!("Internal Error: {}", msg),
             ),
-        };
+            AppError::SonarQubeUnavailable(msg) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "SONARQUBE_UNAVAILABLE",
+                format!("SonarQube Unavailable: {}", msg),
+            ),
+            AppError::InvalidRequest(msg) => (
+                StatusCode::BAD_REQUEST,
+                "INVALID_REQUEST",
+                format!("Invalid Request: {}", msg),
+            ),
+            AppError::NotFound(msg) => (
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                format!("Not Found: {}", msg),
+            ),
+            AppError::GitError(msg) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "GIT_ERROR",
+                format!("Git Error: {}", msg),
+            ),
+            AppError::DownloadError(msg) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "DOWNLOAD_ERROR",
+                format!("Download Error: {}", msg),
+            ),
+            AppError::ArchiveLimitExceeded(msg) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "ARCHIVE_LIMIT_EXCEEDED",
+                format!("Archive Limit Exceeded: {}", msg),
+            ),
+            AppError::Unauthorized(msg) => (
+                StatusCode::UNAUTHORIZED,
+                "UNAUTHORIZED",
+                format!("Unauthorized: {}", msg),
+            ),
+            AppError::Forbidden(msg) => (
+                StatusCode::FORBIDDEN,
+                "FORBIDDEN",
+                format!("Forbidden: {}", msg),
+            ),
+            AppError::RateLimited(msg) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "RATE_LIMITED",
+                format!("Rate Limited: {}", msg),
+            ),
+            AppError::ScannerBusy(msg) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "SCANNER_BUSY",
+                format!("Scanner Busy: {}", msg),
+            ),
+            AppError::Conflict(msg) => (
+                StatusCode::CONFLICT,
+                "CONFLICT",
+                format!("Conflict: {}", msg),
+            ),
+            AppError::Canceled(msg) => (
+                StatusCode::CONFLICT,
+                "CANCELED",
+                format!("Canceled: {}", msg),
+            ),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, code, message) = self.parts();
 
         let body = Json(serde_json::json!({
-            "error": message
+            "error": message,
+            "code": code
         }));
 
         (status, body).into_response()
@@ -67,7 +319,7 @@ impl IntoResponse for AppError {
 // Response Types
 // ============================================================================
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct SonarIssue {
     key: String,
     rule: String,
@@ -77,66 +329,936 @@ struct SonarIssue {
     message: String,
     #[serde(rename = "type")]
     issue_type: String,
+    /// Quality profile that reported the issue, for multi-profile scans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+    /// Analyzer that reported the issue.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    engine: Option<Engine>,
+    /// Other analyzers that reported the same finding, merged into this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    also_reported_by: Vec<Engine>,
+    /// What the rule checks and how to fix it, when `enrich_rules` was set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rule_details: Option<RuleDetails>,
+    /// Source lines around `line`, when `snippet_lines` was set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    snippet: Option<CodeSnippet>,
+    /// Content-based fingerprint that survives line shifts and rescans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+    /// In the request's baseline: returned, but not counted in `total_count`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    suppressed: bool,
+}
+
+impl SonarIssue {
+    /// File path of the issue with the `projectKey:` prefix stripped.
+    fn path(&self) -> &str {
+        self.component
+            .split_once(':')
+            .map(|(_, path)| path)
+            .unwrap_or(&self.component)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct SonarIssuesResponse {
-    issues: Vec<SonarIssue>,
+#[cfg(test)]
+impl SonarIssue {
+    /// A SonarQube vulnerability in project `test`, for unit tests.
+    fn for_test(key: &str, rule: &str, severity: &str, path: &str, line: u32) -> Self {
+        serde_json::from_value(serde_json::json!({
+            "key": key,
+            "rule": rule,
+            "severity": severity,
+            "component": format!("test:{}", path),
+            "line": line,
+            "message": format!("{} violated", rule),
+            "type": "VULNERABILITY",
+        }))
+        .unwrap()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AnalyzeResponse {
     vulnerabilities: Vec<SonarIssue>,
+    /// Issues returned, less those suppressed by the baseline.
     total_count: usize,
+    /// Issues matching the request's baseline.
+    #[serde(default)]
+    suppressed_count: usize,
+    /// Reused from an earlier scan of an identical archive with the same
+    /// options instead of scanning again.
+    #[serde(default)]
+    cached: bool,
+    /// More issues matched than were returned (`max_issues` or SonarQube's
+    /// 10,000 result window).
+    #[serde(default)]
+    truncated: bool,
+    /// The returned issues keyed by type, when `issue_types` was requested.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    issues_by_type: BTreeMap<String, Vec<SonarIssue>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    modules: Vec<ModuleSummary>,
+    /// Source files and lines per detected language, keyed by SonarQube
+    /// language key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    languages: BTreeMap<String, LanguageStats>,
+    /// Quality gate of the analyzed project; absent if it could not be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quality_gate: Option<QualityGate>,
+    /// `pass` or `fail` by the request's `max_<severity>` thresholds and
+    /// policy; absent when it has neither.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verdict: Option<policy::Verdict>,
+    /// Severities with more issues than their threshold, most severe first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    policy_violations: Vec<policy::PolicyViolation>,
+    /// Outcome under the request's `policy`, else `DEFAULT_POLICY`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    policy: Option<policy::PolicyReport>,
+    /// Size, duplication, complexity and security rating from SonarQube;
+    /// absent when SonarQube did not run or the measures could not be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metrics: Option<ProjectMetrics>,
+    /// Security hotspots awaiting or past review, kept apart from
+    /// `vulnerabilities` since they are triaged differently.
+    #[serde(default)]
+    hotspots: Vec<SecurityHotspot>,
+    /// Vulnerable dependencies found by Trivy, when `scan_dependencies` was set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dependencies: Option<Vec<DependencyVulnerability>>,
+    /// Hard-coded credentials found by gitleaks, when `scan_secrets` was set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secrets: Option<Vec<DetectedSecret>>,
+    /// Milliseconds spent waiting for SonarQube to process the analysis
+    /// reports, across the job's scans; absent when SonarQube did not run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ce_wait_ms: Option<u64>,
+    scanner_report: ScannerReport,
+    manifest: ScanManifest,
+}
+
+/// Inputs handed to sonar-scanner beyond the default properties.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ScannerReport {
+    coverage_files: Vec<CoverageFile>,
+    /// Files removed by `modified_since`, when pruning ran.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pruned_files: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ComputeEngineTask {
-    status: String,
+#[derive(Debug, Deserialize)]
+struct AnalyzeQuery {
+    format: Option<String>,
+    /// Return `202 Accepted` with a job ID instead of waiting for the scan.
+    #[serde(rename = "async", default)]
+    run_async: bool,
+    /// Issue types to fetch (comma-separated); the multipart field takes precedence.
+    issue_types: Option<String>,
+    /// Least severe issues to return; the multipart field takes precedence.
+    min_severity: Option<String>,
+    /// Scan even if an identical archive's result is cached.
+    #[serde(default)]
+    force: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ComputeEngineResponse {
-    tasks: Vec<ComputeEngineTask>,
+#[derive(Debug, Deserialize)]
+struct ResultQuery {
+    format: Option<String>,
+}
+
+/// Optional per-request settings sent as extra multipart fields.
+#[derive(Debug, Default)]
+struct AnalyzeOptions {
+    /// Module directories to group issues by, relative to the project root.
+    modules: Option<Vec<String>>,
+    /// Coverage report paths, relative to the project root. Replaces auto-detection.
+    coverage_paths: Option<Vec<String>>,
+    /// Coverage report uploaded next to the archive, added to those in it.
+    coverage_report: Option<UploadedReport>,
+    /// Dry-run the scanner first to flag unknown or ignored properties.
+    validate_properties: bool,
+    /// Only scan files modified at or after this time.
+    modified_since: Option<SystemTime>,
+    /// Quality profiles to scan under, one scan each; results are merged.
+    quality_profiles: Vec<String>,
+    /// Quality profile the job's own project is provisioned with before its
+    /// single scan.
+    quality_profile: Option<String>,
+    /// Cap on the number of issues returned.
+    max_issues: Option<usize>,
+    /// Issue types to fetch; `VULNERABILITY` only when unset.
+    issue_types: Option<Vec<String>>,
+    /// Drop issues less severe than this.
+    min_severity: Option<Severity>,
+    /// Most issues allowed per severity (`max_<severity>`); the result
+    /// fails when any is exceeded.
+    thresholds: policy::SeverityPolicy,
+    /// Configured policy to judge the result by instead of `DEFAULT_POLICY`.
+    policy: Option<String>,
+    /// Extra `-D` properties for sonar-scanner, checked against the allowlist.
+    sonar_properties: Vec<(String, String)>,
+    /// Branch to analyse as (`sonar.branch.name`); needs Developer Edition.
+    branch: Option<String>,
+    /// Pull request to analyse as (`sonar.pullrequest.*`); needs Developer Edition.
+    pr_key: Option<String>,
+    pr_branch: Option<String>,
+    pr_base: Option<String>,
+    /// Where to POST the result once the job finishes.
+    callback_url: Option<reqwest::Url>,
+    /// Repository (`owner/name`) of the pull request to comment on.
+    github_repo: Option<String>,
+    /// Pull request to summarise the outcome on, replacing the job's
+    /// earlier summary there.
+    pr_number: Option<u64>,
+    /// GitHub token from `X-GitHub-Token`, used for the comment instead of
+    /// `GITHUB_TOKEN`.
+    github_token: Option<String>,
+    /// GitLab project (ID or path) of the merge request and commit to
+    /// report to.
+    gitlab_project: Option<String>,
+    /// Merge request to summarise the outcome on, replacing the job's
+    /// earlier note there.
+    mr_iid: Option<u64>,
+    /// Commit to set a passing or failing status on.
+    commit_sha: Option<String>,
+    /// Bitbucket repository (`workspace/repo_slug` or `PROJECT/repo_slug`)
+    /// of the commit to publish a Code Insights report on.
+    bitbucket_repo: Option<String>,
+    bitbucket_commit: Option<String>,
+    /// Slack incoming webhook to post the outcome to, instead of the one
+    /// configured for the API key or by default.
+    slack_webhook_url: Option<reqwest::Url>,
+    /// Teams webhook to post the outcome to, instead of the one configured
+    /// for the API key or by default.
+    teams_webhook_url: Option<reqwest::Url>,
+    /// Source globs to scan (`sonar.inclusions`).
+    include: Option<Vec<String>>,
+    /// Source globs to skip (`sonar.exclusions`).
+    exclude: Option<Vec<String>>,
+    /// Scan vendored and generated code too, leaving out `DEFAULT_EXCLUSIONS`.
+    skip_default_exclusions: bool,
+    /// Analyzers to run; SonarQube alone when none are named.
+    engines: Vec<Engine>,
+    /// Also scan dependencies for known vulnerabilities with Trivy.
+    scan_dependencies: bool,
+    /// Also look for hard-coded credentials with gitleaks.
+    scan_secrets: bool,
+    /// Attach rule names, descriptions and remediation guidance to issues.
+    enrich_rules: bool,
+    /// Lines of source to attach before and after each issue's line.
+    snippet_lines: Option<usize>,
+    /// Seconds each analyzer may run; `SCANNER_TIMEOUT_SECS` when unset.
+    scanner_timeout_secs: Option<u64>,
+    /// Seconds between SonarQube task status checks;
+    /// `SONARQUBE_POLL_INTERVAL_SECS` when unset.
+    poll_interval_secs: Option<u64>,
+    /// Seconds to wait for SonarQube to process each report; the configured
+    /// poll timeout when unset.
+    poll_timeout_secs: Option<u64>,
+    /// Issues of an earlier scan to mark `suppressed`.
+    baseline: Option<Baseline>,
+    /// Tenant the job belongs to, which prefixes its project keys.
+    tenant: Option<String>,
+    /// Stable name of the logical project, under which the job's result is
+    /// kept in the project's scan history.
+    project_name: Option<String>,
+    /// SonarQube token from `X-Sonar-Token`, used for the job's scans and
+    /// Web API calls instead of `SONARQUBE_TOKEN`.
+    sonar_token: Option<String>,
+    /// ID of the request that submitted the job, sent with its Web API calls.
+    request_id: Option<String>,
+    /// Who submitted the job, for the audit log.
+    caller: Caller,
+}
+
+impl AnalyzeOptions {
+    /// Fills `issue_types` and `min_severity` from the query string where
+    /// the request body left them unset.
+    fn apply_query_filters(
+        &mut self,
+        issue_types: Option<&str>,
+        min_severity: Option<&str>,
+    ) -> Result<(), AppError> {
+        if self.issue_types.is_none() {
+            self.issue_types = issue_types.map(parse_list_field);
+        }
+        if let (None, Some(value)) = (self.min_severity, min_severity) {
+            self.min_severity = Some(parse_severity_field("min_severity", value)?);
+        }
+        Ok(())
+    }
+
+    /// The SonarQube client the job's scans and Web API calls go through.
+    fn sonar_client(&self, sonar: &SonarClient) -> SonarClient {
+        let sonar = match &self.sonar_token {
+            Some(token) => sonar.with_token(token),
+            None => sonar.clone(),
+        };
+        let sonar = sonar.with_polling(
+            self.poll_interval_secs.map(Duration::from_secs),
+            self.poll_timeout_secs.map(Duration::from_secs),
+        );
+        match &self.request_id {
+            Some(id) => sonar.with_request_id(id),
+            None => sonar,
+        }
+    }
+
+    /// The pull request to summarise the job on, once checked for
+    /// consistency.
+    fn pull_request(&self, config: &config::GitHubConfig) -> Result<Option<PullRequest>, AppError> {
+        PullRequest::new(
+            self.github_repo.as_deref(),
+            self.pr_number,
+            self.github_token.as_deref(),
+            self.project_name.as_deref(),
+            config,
+        )
+    }
+
+    /// Where on GitLab to report the job, once checked for consistency.
+    fn merge_request_target(
+        &self,
+        config: &config::GitLabConfig,
+    ) -> Result<Option<MergeRequestTarget>, AppError> {
+        MergeRequestTarget::new(
+            self.gitlab_project.as_deref(),
+            self.mr_iid,
+            self.commit_sha.as_deref(),
+            self.project_name.as_deref(),
+            config,
+        )
+    }
+
+    /// The Bitbucket commit to publish the job's report on, once checked
+    /// for consistency.
+    fn insights_target(
+        &self,
+        config: &config::BitbucketConfig,
+    ) -> Result<Option<InsightsTarget>, AppError> {
+        InsightsTarget::new(
+            self.bitbucket_repo.as_deref(),
+            self.bitbucket_commit.as_deref(),
+            self.project_name.as_deref(),
+            config,
+        )
+    }
+
+    /// Settles the tenant the job runs as: the caller's own, or the
+    /// `tenant` field for API keys without one.
+    fn apply_tenant(&mut self, tenant: &Tenant) -> Result<(), AppError> {
+        self.tenant = tenant.resolve(self.tenant.as_deref())?;
+        Ok(())
+    }
+
+    /// Branch or pull request named by the request, once checked for consistency.
+    fn analysis_ref(&self) -> Result<Option<AnalysisRef>, AppError> {
+        let pr_fields = self.pr_branch.is_some() || self.pr_base.is_some();
+        match (&self.branch, &self.pr_key) {
+            (Some(_), Some(_)) => Err(AppError::InvalidRequest(
+                "Fields 'branch' and 'pr_key' cannot be combined".to_string(),
+            )),
+            (_, Some(_)) if self.pr_branch.is_none() => Err(AppError::InvalidRequest(
+                "Field 'pr_branch' is required with 'pr_key'".to_string(),
+            )),
+            (_, None) if pr_fields => Err(AppError::InvalidRequest(
+                "Field 'pr_key' is required with 'pr_branch' and 'pr_base'".to_string(),
+            )),
+            (Some(branch), None) => Ok(Some(AnalysisRef::Branch(branch.clone()))),
+            (None, Some(key)) => Ok(Some(AnalysisRef::PullRequest(key.clone()))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Scanner properties for the branch or pull request.
+    fn analysis_ref_properties(&self) -> Vec<(String, String)> {
+        [
+            ("sonar.branch.name", &self.branch),
+            ("sonar.pullrequest.key", &self.pr_key),
+            ("sonar.pullrequest.branch", &self.pr_branch),
+            ("sonar.pullrequest.base", &self.pr_base),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value.clone()?)))
+        .collect()
+    }
+
+    /// Identifies the result of scanning the archive with SHA-256
+    /// `input_sha256` under these options. `callback_url`, the pull request,
+    /// merge request or commit to report to, the chat webhooks, the severity
+    /// thresholds and policy, which are applied to each request's copy of
+    /// the result,
+    /// the timeouts and poll interval, `project_name`,
+    /// `request_id` and `caller` do not change a successful result, so they
+    /// are left out.
+    fn cache_key(&self, input_sha256: &str) -> String {
+        let Self {
+            modules,
+            coverage_paths,
+            coverage_report,
+            validate_properties,
+            modified_since,
+            quality_profiles,
+            quality_profile,
+            max_issues,
+            issue_types,
+            min_severity,
+            thresholds: _,
+            policy: _,
+            sonar_properties,
+            branch,
+            pr_key,
+            pr_branch,
+            pr_base,
+            callback_url: _,
+            github_repo: _,
+            pr_number: _,
+            github_token: _,
+            gitlab_project: _,
+            mr_iid: _,
+            commit_sha: _,
+            bitbucket_repo: _,
+            bitbucket_commit: _,
+            slack_webhook_url: _,
+            teams_webhook_url: _,
+            include,
+            exclude,
+            skip_default_exclusions,
+            engines,
+            scan_dependencies,
+            scan_secrets,
+            enrich_rules,
+            snippet_lines,
+            scanner_timeout_secs: _,
+            poll_interval_secs: _,
+            poll_timeout_secs: _,
+            baseline,
+            tenant,
+            project_name: _,
+            sonar_token,
+            request_id: _,
+            caller: _,
+        } = self;
+        let parts = [
+            input_sha256.to_string(),
+            format!("{:?}", modules),
+            format!("{:?}", coverage_paths),
+            format!(
+                "{:?}",
+                coverage_report
+                    .as_ref()
+                    .map(|report| (&report.file_name, &report.sha256))
+            ),
+            format!("{:?}", validate_properties),
+            format!("{:?}", modified_since),
+            format!("{:?}", quality_profiles),
+            format!("{:?}", quality_profile),
+            format!("{:?}", max_issues),
+            format!("{:?}", issue_types),
+            format!("{:?}", min_severity),
+            format!("{:?}", sonar_properties),
+            format!("{:?}", branch),
+            format!("{:?}", pr_key),
+            format!("{:?}", pr_branch),
+            format!("{:?}", pr_base),
+            format!("{:?}", include),
+            format!("{:?}", exclude),
+            format!("{:?}", skip_default_exclusions),
+            format!("{:?}", engines),
+            format!("{:?}", scan_dependencies),
+            format!("{:?}", scan_secrets),
+            format!("{:?}", enrich_rules),
+            format!("{:?}", snippet_lines),
+            format!("{:?}", baseline),
+            format!("{:?}", tenant),
+            // Results seen with one token are not served to another
+            format!("{:?}", sonar_token),
+        ];
+
+        let mut hasher = Sha256::new();
+        hasher.update(parts.join("\0"));
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Rejects SonarQube-only fields when SonarQube is not among the engines.
+    fn check_engines(&self) -> Result<(), AppError> {
+        if self.engines.contains(&Engine::SonarQube) {
+            return Ok(());
+        }
+        let sonarqube_only = [
+            ("quality_profiles", !self.quality_profiles.is_empty()),
+            ("quality_profile", self.quality_profile.is_some()),
+            ("coverage", self.coverage_report.is_some()),
+            ("validate_properties", self.validate_properties),
+            ("branch", self.branch.is_some()),
+            ("pr_key", self.pr_key.is_some()),
+            ("enrich_rules", self.enrich_rules),
+        ];
+        match sonarqube_only.into_iter().find(|(_, set)| *set) {
+            Some((field, _)) => Err(AppError::InvalidRequest(format!(
+                "Field '{}' requires the sonarqube engine",
+                field
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// `sonar.inclusions`/`sonar.exclusions` for the `include` and `exclude`
+    /// globs, which cannot also be set through `sonar_properties`. The
+    /// `exclude` globs are added to `default_exclusions`.
+    fn glob_properties(
+        &self,
+        default_exclusions: &[String],
+    ) -> Result<Vec<(String, String)>, AppError> {
+        let mut properties = Vec::new();
+        for (field, key, globs) in [
+            ("include", "sonar.inclusions", &self.include),
+            ("exclude", "sonar.exclusions", &self.exclude),
+        ] {
+            let Some(globs) = globs else {
+                continue;
+            };
+            if self.sonar_properties.iter().any(|(k, _)| k == key) {
+                return Err(AppError::InvalidRequest(format!(
+                    "Field '{}' cannot be combined with '{}' in 'sonar_properties'",
+                    field, key
+                )));
+            }
+            let defaults = match field {
+                "exclude" => default_exclusions,
+                _ => &[],
+            };
+            let globs: Vec<&str> = defaults
+                .iter()
+                .filter(|glob| !globs.contains(glob))
+                .chain(globs)
+                .map(String::as_str)
+                .collect();
+            properties.push((key.to_string(), globs.join(",")));
+        }
+        Ok(properties)
+    }
+
+    /// The configured default exclusions as they apply to this job: none
+    /// when the caller opted out or set `sonar.exclusions` outright.
+    fn default_exclusions<'a>(&self, configured: &'a [String]) -> &'a [String] {
+        let overridden = self
+            .sonar_properties
+            .iter()
+            .any(|(key, _)| key == "sonar.exclusions");
+        if self.skip_default_exclusions || overridden {
+            &[]
+        } else {
+            configured
+        }
+    }
+}
+
+/// Where the code to analyse comes from.
+enum UploadSource {
+    /// An uploaded zip, tar or tar.gz archive saved in the temp dir.
+    Archive(PathBuf),
+    /// A repository cloned into the temp dir when the job runs.
+    Git(GitSource),
+    /// A local project directory, copied into the temp dir (`scan` only).
+    Directory(PathBuf),
+}
+
+/// An upload read from the request, ready to be analysed.
+struct PreparedUpload {
+    /// Removed (with everything extracted into it) when the upload is dropped.
+    temp_dir: TempDir,
+    source: UploadSource,
+    options: AnalyzeOptions,
+    /// SHA-256 of the uploaded archive; `None` for git sources.
+    input_sha256: Option<String>,
+    /// Where the result is cached, for archive uploads.
+    cache_key: Option<String>,
+}
+
+/// Shared state handed to every handler.
+#[derive(Clone)]
+struct AppState {
+    config: Arc<Config>,
+    sonar: SonarClient,
+    jobs: JobStore,
+    scan_slots: ScanSlots,
+    metrics: Arc<Metrics>,
+    /// Background analyses, awaited on shutdown.
+    tasks: TaskTracker,
+    children: ChildProcesses,
+    webhooks: WebhookSender,
+    github: GitHubCommenter,
+    gitlab: GitLabReporter,
+    bitbucket: InsightsPublisher,
+    /// Chat and email sinks told about every finished job.
+    notifiers: Arc<Vec<Box<dyn Notifier>>>,
+    /// Configured policies results are judged by.
+    policies: Arc<policy::PolicySet>,
+    /// Analyzers a job's scans run through.
+    backends: Backends,
+    trivy: TrivyScanner,
+    gitleaks: GitleaksScanner,
+    /// Rule details fetched for `enrich_rules`, shared across jobs.
+    rules: RuleCache,
+    /// Recent archive results, reused for identical uploads.
+    results: ResultCache,
+    /// CPU, memory and process limits for sonar-scanner.
+    limits: ResourceLimits,
+    /// Outbound proxy for sonar-scanner; the HTTP clients already use it.
+    proxy: OutboundProxy,
+    /// Served by `/readyz`.
+    readiness: ReadinessGate,
+    /// Where finished jobs' results, reports and logs are archived, if anywhere.
+    artifacts: Option<ArtifactStore>,
+    /// Fetches `archive_url` archives.
+    archives: ArchiveDownloader,
+    /// Where finished scans are recorded, if anywhere.
+    audit: Option<AuditLog>,
+    /// When the service started; job temp dirs older than this belong to an
+    /// earlier process.
+    started_at: SystemTime,
 }
 
 // ============================================================================
 // Main Application Logic
 // ============================================================================
 
+/// How long killed scans get to record their failure before the process exits.
+const SHUTDOWN_CLEANUP_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[tokio::main]
 async fn main() {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "sonar_backend=info,tower_http=info".into()),
-        )
-        .init();
+    let cli = Cli::parse();
+
+    let mut config = Config::load(cli.config.as_deref())
+        .unwrap_or_else(|e| panic!("Failed to load configuration: {:#}", e));
+    cli.apply(&mut config);
+    let config = Arc::new(config);
+
+    // Initialize tracing; a local scan prints its result on stdout, so its
+    // logs go to stderr
+    let scanning = matches!(cli.command, Some(Command::Scan(_)));
+    let tracer_provider = telemetry::init(&config.telemetry, scanning)
+        .unwrap_or_else(|e| panic!("Failed to initialize tracing: {:#}", e));
+
+    if let Some(Command::Scan(args)) = &cli.command {
+        let succeeded = local_scan::run(config, args).await;
+        telemetry::shutdown(tracer_provider);
+        std::process::exit(if succeeded { 0 } else { 1 });
+    }
 
     info!("Starting Sonar Backend Service (Rust)");
+    if let Some(endpoint) = &config.telemetry.otlp_endpoint {
+        info!("Exporting traces to {}", endpoint);
+    }
+
+    let repository = storage::open(&config.storage)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to open job storage: {:#}", e));
+    let jobs = JobStore::new(config.jobs.max_stored, config.jobs.max_log_lines, repository);
+    if config.storage.recover_interrupted {
+        jobs.recover_interrupted()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to recover interrupted jobs: {:#}", e));
+    }
+
+    let state = build_state(config.clone(), jobs);
+    let tasks = state.tasks.clone();
+    let readiness = state.readiness.clone();
+
+    // Run `sonar-scanner --version` now rather than on the first request
+    tokio::task::spawn_blocking(backends::scanner_version);
+
+    // Configuration is valid by now; /readyz waits for SonarQube as well.
+    // The webhook can only be registered once SonarQube is up
+    tokio::spawn({
+        let readiness = readiness.clone();
+        let sonar = state.sonar.clone();
+        let sonar_config = config.sonar.clone();
+        async move {
+            readiness.warm_up(&sonar).await;
+            if let Some(url) = &sonar_config.webhook_url {
+                if let Err(e) = sonar
+                    .register_webhook(url, &sonar_config.webhook_secret)
+                    .await
+                {
+                    warn!("SonarQube webhook not registered, polling instead: {}", e);
+                }
+            }
+        }
+    });
+
+    let api_keys = ApiKeys::new(&config.auth)
+        .unwrap_or_else(|e| panic!("Invalid authentication settings: {:#}", e));
+    if !config.auth.tenants.is_empty() {
+        info!(
+            "Namespacing jobs for {} tenant(s)",
+            config.auth.tenants.len()
+        );
+    }
+    if !config.auth.client_certs.is_empty() && config.tls.client_ca_path.is_none() {
+        warn!("Client certificate tenants are ignored without TLS_CLIENT_CA_PATH");
+    }
+    if !api_keys.is_enabled() {
+        warn!("No API keys configured; /analyze and /jobs are unauthenticated");
+    }
+
+    let upload_limit = usize::try_from(config.limits.max_upload_bytes).unwrap_or(usize::MAX);
+    let mut analyze = post(analyze_handler).layer(DefaultBodyLimit::max(upload_limit));
+    let mut analyze_batch =
+        post(batch::analyze_batch_handler).layer(DefaultBodyLimit::max(upload_limit));
+    let mut analyze_diff =
+        post(diff::analyze_diff_handler).layer(DefaultBodyLimit::max(upload_limit));
+    let limiter = RateLimiter::new(
+        config.rate_limit.requests_per_minute,
+        config.rate_limit.burst,
+    )
+    .map(Arc::new);
+    if let Some(limiter) = &limiter {
+        info!(
+            "Rate limiting /analyze to {} request(s) per minute per client",
+            config.rate_limit.requests_per_minute
+        );
+        analyze = analyze.route_layer(axum::middleware::from_fn_with_state(
+            limiter.clone(),
+            rate_limit::limit_requests,
+        ));
+        analyze_batch = analyze_batch.route_layer(axum::middleware::from_fn_with_state(
+            limiter.clone(),
+            rate_limit::limit_requests,
+        ));
+        analyze_diff = analyze_diff.route_layer(axum::middleware::from_fn_with_state(
+            limiter.clone(),
+            rate_limit::limit_requests,
+        ));
+    }
+
+    // gRPC clients share the pipeline, the API keys and the rate limit; the
+    // server stops with the HTTP listener and its jobs drain the same way
+    let stopping = CancellationToken::new();
+    if config.server.grpc_port != 0 {
+        let addr = std::net::SocketAddr::new(config.server.bind_addr, config.server.grpc_port);
+        tasks.spawn(grpc::serve(
+            state.clone(),
+            addr,
+            api_keys.clone(),
+            limiter.clone(),
+            stopping.clone(),
+        ));
+    }
+
+    // Everything except the health checks requires an API key
+    let protected = Router::new()
+        .route("/analyze", analyze)
+        .route("/analyze/batch", analyze_batch)
+        .route("/analyze/diff", analyze_diff)
+        .route("/uploads", post(uploads::create_upload_handler))
+        .route("/jobs", get(job_list::list_jobs_handler))
+        .route(
+            "/jobs/:id",
+            get(job_status_handler).delete(cancel_job_handler),
+        )
+        .route("/jobs/:id/result", get(job_result_handler))
+        .route("/jobs/:id/report.html", get(job_report_handler))
+        .route("/jobs/:id/events", get(job_events_handler))
+        .route("/jobs/:id/logs", get(job_logs_handler))
+        .route("/jobs/:id/logs/ws", get(job_logs_ws_handler))
+        .route(
+            "/projects/:name/history",
+            get(project_history::project_history_handler),
+        )
+        .route("/admin/audit", get(audit::audit_log_handler))
+        .route("/admin/purge", post(purge::purge_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            api_keys,
+            auth::require_api_key,
+        ));
 
     // Build our application with routes
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/", get(root_handler))
         .route("/health", get(health_handler))
-        .route("/analyze", post(analyze_handler))
+        .route("/health/ready", get(ready_handler))
+        .route("/livez", get(health_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/version", get(version::version_handler))
+        // Authenticated by its signature; SonarQube cannot send an API key
+        .route("/sonarqube/webhook", post(sonar_webhook::webhook_handler))
+        .route(
+            "/metrics",
+            get(metrics::metrics_handler).with_state(state.metrics.clone()),
+        )
+        .merge(protected)
         .layer(
             tower_http::cors::CorsLayer::permissive()
-        );
+        )
+        .with_state(state);
+
+    if config.server.envelope_responses {
+        info!("Response envelopes enabled");
+        app = app.layer(axum::middleware::from_fn(envelope::envelope_responses));
+    }
+    let app = app
+        .merge(openapi::router())
+        .layer(axum::middleware::from_fn(request_id::assign_request_id));
 
     // Run the server
-    let port = std::env::var("PORT").unwrap_or_else(|_| "8000".to_string());
-    let addr = format!("0.0.0.0:{}", port);
+    let certificates = tls::Certificates::load(&config.tls)
+        .unwrap_or_else(|e| panic!("Invalid TLS settings: {:#}", e));
+    let addr = std::net::SocketAddr::new(config.server.bind_addr, config.server.port);
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
-        .expect(&format!("Failed to bind to {}", addr));
+        .unwrap_or_else(|e| panic!("Failed to bind to {}: {}", addr, e));
 
-    info!("Server listening on {}", addr);
+    match &certificates {
+        Some(certificates) => {
+            info!("Server listening on {} (HTTPS)", addr);
+            if let Some(path) = &config.tls.client_ca_path {
+                info!("Requiring client certificates issued by {}", path.display());
+            }
+            if config.tls.reload_interval_secs != 0 {
+                let interval = Duration::from_secs(config.tls.reload_interval_secs);
+                tokio::spawn(certificates.clone().watch(interval));
+            }
+            if config.tls.redirect_port != 0 {
+                let redirect_addr =
+                    std::net::SocketAddr::new(config.server.bind_addr, config.tls.redirect_port);
+                tokio::spawn(tls::serve_redirects(
+                    redirect_addr,
+                    config.server.port,
+                    stopping.clone(),
+                ));
+            }
+        }
+        None => info!("Server listening on {}", addr),
+    }
 
-    axum::serve(listener, app)
-        .await
-        .expect("Server failed to start");
+    // On SIGTERM report not ready, give load balancers the drain delay to
+    // notice, then stop accepting connections and wait for in-flight
+    // requests and background scans for up to the grace period
+    let drain_delay = Duration::from_secs(config.server.drain_delay_secs);
+    tokio::spawn({
+        let stopping = stopping.clone();
+        async move {
+            shutdown::signal_received().await;
+            readiness.drain();
+            if !drain_delay.is_zero() {
+                info!("Shutdown requested; reporting not ready for {:?}", drain_delay);
+                tokio::time::sleep(drain_delay).await;
+            }
+            info!("Shutdown requested; draining in-flight scans");
+            stopping.cancel();
+        }
+    });
+
+    let drain = async {
+        match &certificates {
+            Some(certificates) => {
+                tls::serve(listener, app, certificates.acceptor(), stopping.clone()).await
+            }
+            None => axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+            )
+            .with_graceful_shutdown(stopping.clone().cancelled_owned())
+            .await
+            .expect("Server failed to start"),
+        }
+        tasks.close();
+        tasks.wait().await;
+    };
+    tokio::pin!(drain);
+
+    let grace = Duration::from_secs(config.server.shutdown_grace_secs);
+    tokio::select! {
+        _ = &mut drain => {}
+        _ = async {
+            stopping.cancelled().await;
+            tokio::time::sleep(grace).await;
+        } => {
+            let killed = children.kill_all();
+            warn!(
+                "Scans still running after {}s; killed {} sonar-scanner process group(s)",
+                grace.as_secs(),
+                killed
+            );
+            // Let the killed jobs record their failure and remove their
+            // temp dirs; anything left is dropped with the runtime
+            let _ = tokio::time::timeout(SHUTDOWN_CLEANUP_TIMEOUT, drain).await;
+        }
+    }
+    info!("Shutdown complete");
+
+    telemetry::shutdown(tracer_provider);
+}
+
+/// The analysers, clients and bookkeeping shared by every request.
+fn build_state(config: Arc<Config>, jobs: JobStore) -> AppState {
+    let proxy = OutboundProxy::new(&config.proxy)
+        .unwrap_or_else(|e| panic!("Invalid proxy settings: {:#}", e));
+    if let Some(url) = proxy.redacted_url() {
+        info!("Sending outbound requests through proxy {}", url);
+    }
+    if let Some(path) = &config.audit.path {
+        info!("Recording finished scans in audit log {}", path.display());
+    }
+    let sonar = SonarClient::new(&config.sonarqube, &proxy);
+    let metrics = Arc::new(Metrics::new());
+    let children = ChildProcesses::default();
+    let limits = ResourceLimits::new(&config.resource_limits);
+    let notifiers = notify::notifiers(&config, &proxy, &jobs)
+        .unwrap_or_else(|e| panic!("Failed to configure notifications: {:#}", e));
+    let policies = policy::PolicySet::new(&config.policies, config.scans.default_policy.as_deref())
+        .unwrap_or_else(|e| panic!("Invalid policies: {:#}", e));
+    AppState {
+        backends: Backends {
+            sonarqube: Arc::new(SonarQubeBackend::new(
+                config.sandbox.clone(),
+                limits.clone(),
+                proxy.clone(),
+                metrics.clone(),
+                children.clone(),
+            )),
+            semgrep: Arc::new(SemgrepBackend::new(
+                &config.semgrep,
+                metrics.clone(),
+                children.clone(),
+            )),
+        },
+        trivy: TrivyScanner::new(&config.trivy, metrics.clone(), children.clone()),
+        gitleaks: GitleaksScanner::new(&config.gitleaks, metrics.clone(), children.clone()),
+        rules: RuleCache::default(),
+        results: ResultCache::new(&config.result_cache),
+        limits,
+        readiness: ReadinessGate::default(),
+        sonar,
+        jobs,
+        scan_slots: ScanSlots::new(
+            config.scans.max_concurrent,
+            config.scans.reject_when_full,
+        ),
+        metrics,
+        tasks: TaskTracker::new(),
+        children,
+        webhooks: WebhookSender::new(&config.webhooks, &proxy),
+        github: GitHubCommenter::new(&config.github, &proxy),
+        gitlab: GitLabReporter::new(&config.gitlab, &proxy),
+        bitbucket: InsightsPublisher::new(&config.bitbucket, &proxy),
+        notifiers: Arc::new(notifiers),
+        policies: Arc::new(policies),
+        artifacts: ArtifactStore::new(&config.artifacts, &proxy)
+            .unwrap_or_else(|e| panic!("Failed to configure artifact storage: {:#}", e)),
+        archives: ArchiveDownloader::new(&config.limits, &config.sources, &proxy),
+        audit: AuditLog::new(&config.audit),
+        started_at: SystemTime::now(),
+        proxy,
+        config,
+    }
 }
 
 async fn root_handler() -> &'static str {
@@ -147,104 +1269,1484 @@ async fn health_handler() -> &'static str {
     "ok"
 }
 
-async fn analyze_handler(mut multipart: Multipart) -> Result<Json<AnalyzeResponse>, AppError> {
+async fn ready_handler(State(state): State<AppState>) -> health::Readiness {
+    health::readiness(&state.config, &state.sonar).await
+}
+
+async fn readyz_handler(State(state): State<AppState>) -> Response {
+    state.readiness.response()
+}
+
+#[instrument(skip_all, fields(job_id))]
+async fn analyze_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    Extension(request_id): Extension<RequestId>,
+    Extension(caller): Extension<Caller>,
+    Query(query): Query<AnalyzeQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
     info!("Received analyze request");
 
+    // Generate unique job ID
+    let job_id = ids::job_id();
+    tracing::Span::current().record("job_id", &job_id);
+
+    let format = OutputFormat::negotiate(query.format.as_deref(), request.headers())?;
+    let sonar_token = parse_sonar_token(
+        request
+            .headers()
+            .get(auth::SONAR_TOKEN_HEADER)
+            .map(|value| value.to_str().unwrap_or_default()),
+    )?;
+    let github_token = github::parse_token(
+        request
+            .headers()
+            .get(auth::GITHUB_TOKEN_HEADER)
+            .map(|value| value.to_str().unwrap_or_default()),
+    )?;
+
     // Create temporary directory for this job
-    let temp_dir = TempDir::new()
-        .map_err(|e| AppError::InternalError(format!("Failed to create temp dir: {}", e)))?;
+    let temp_dir = job_temp_dir()?;
 
     let temp_path = temp_dir.path().to_path_buf();
     info!("Created temp directory: {:?}", temp_path);
 
-    // Extract zip file from multipart
-    let zip_path = extract_zip_from_multipart(&mut multipart, &temp_path).await?;
+    // Read the zip upload, the repository to clone or the archive to
+    // download, and the options
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    let (source, mut options) = if content_type == "application/json" {
+        let Json(body) =
+            Json::<serde_json::Map<String, serde_json::Value>>::from_request(request, &state)
+                .await
+                .map_err(|e| AppError::InvalidRequest(format!("Invalid JSON body: {}", e)))?;
+        if body.contains_key("upload_id") {
+            uploads::read_upload_request(&state, body, &temp_path)
+                .instrument(info_span!("read_upload"))
+                .await?
+        } else if body.contains_key("archive_url") {
+            archive_url::read_archive_url_request(&state, body, &temp_path)
+                .instrument(info_span!("read_upload"))
+                .await?
+        } else {
+            read_git_request(body)?
+        }
+    } else if RAW_ARCHIVE_TYPES.contains(&content_type.as_str()) {
+        read_raw_archive(request, &temp_path, state.config.limits.max_upload_bytes)
+            .instrument(info_span!("read_upload"))
+            .await?
+    } else {
+        let mut multipart = Multipart::from_request(request, &state)
+            .await
+            .map_err(|e| AppError::MissingField(format!("Invalid multipart body: {}", e)))?;
+        let (zip_path, options) = extract_zip_from_multipart(&mut multipart, &temp_path)
+            .instrument(info_span!("read_upload"))
+            .await?;
+        (UploadSource::Archive(zip_path), options)
+    };
+    options.apply_query_filters(query.issue_types.as_deref(), query.min_severity.as_deref())?;
+    options.apply_tenant(&tenant)?;
+    options.sonar_token = sonar_token;
+    options.github_token = github_token;
+    options.request_id = Some(request_id.0);
+    options.caller = caller;
+
+    match prepare_analysis(&state, &job_id, temp_dir, source, options, query.force)? {
+        Analysis::Cached(response) => {
+            if query.run_async {
+                return Ok(accepted_response(&job_id, JobStatus::Succeeded));
+            }
+            Ok(render_waited_result(format, &response))
+        }
+        Analysis::Scan(upload, reserved_slot) => {
+            if query.run_async {
+                info!("Queued job {} for asynchronous analysis", job_id);
+                let response = accepted_response(&job_id, JobStatus::Queued);
+                let tasks = state.tasks.clone();
+                tasks.spawn(run_job(state, job_id, upload, reserved_slot));
+                return Ok(response);
+            }
 
-    // Unzip the file
-    let project_dir = unzip_file(&zip_path, &temp_path)?;
+            let response = run_job(state, job_id, upload, reserved_slot).await?;
+            Ok(render_waited_result(format, &response))
+        }
+    }
+}
 
-    // Generate unique job ID
-    let job_id = format!("job_{}", uuid::Uuid::new_v4().to_string().replace("-", ""));
+/// Renders a result the request waited for, as `422 Unprocessable Entity`
+/// when it fails the request's severity thresholds.
+fn render_waited_result(format: OutputFormat, response: &AnalyzeResponse) -> Response {
+    let mut rendered = formats::render(format, response);
+    if response.verdict == Some(policy::Verdict::Fail) {
+        *rendered.status_mut() = StatusCode::UNPROCESSABLE_ENTITY;
+    }
+    rendered
+}
 
-    // Get SonarQube configuration from environment
-    let sonarqube_url = std::env::var("SONARQUBE_URL")
-        .unwrap_or_else(|_| "http://sonarqube:9000".to_string());
-    let sonarqube_token = std::env::var("SONARQUBE_TOKEN").unwrap_or_default();
-    let sonarqube_password = std::env::var("SONARQUBE_PASSWORD").unwrap_or_else(|_| "admin".to_string());
-    let use_token = !sonarqube_token.trim().is_empty();
+/// What to do with a request once its source and options are read.
+enum Analysis {
+    /// An identical archive was scanned recently; its result is already
+    /// recorded as the job's.
+    Cached(Arc<AnalyzeResponse>),
+    /// A registered job, to be handed to [`run_job`] with its scan slot.
+    Scan(PreparedUpload, Option<OwnedSemaphorePermit>),
+}
 
-    // Run sonar-scanner
-    run_sonar_scanner(
-        &project_dir,
-        &job_id,
-        &sonarqube_url,
-        &sonarqube_token,
-        &sonarqube_password,
-        use_token,
+/// Validates the options and registers the job, either with a cached result
+/// or queued for a scan. Shared by the HTTP and gRPC front ends.
+fn prepare_analysis(
+    state: &AppState,
+    job_id: &str,
+    temp_dir: TempDir,
+    source: UploadSource,
+    mut options: AnalyzeOptions,
+    force: bool,
+) -> Result<Analysis, AppError> {
+    profiles::check_limit(
+        &options.quality_profiles,
+        state.config.limits.max_quality_profiles,
+    )?;
+    if options.quality_profile.is_some() && !options.quality_profiles.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Fields 'quality_profile' and 'quality_profiles' cannot be combined".to_string(),
+        ));
+    }
+    sonar_properties::check_allowed(
+        &options.sonar_properties,
+        &state.config.scans.allowed_properties,
     )?;
+    options.analysis_ref()?;
+    options.pull_request(&state.config.github)?;
+    options.merge_request_target(&state.config.gitlab)?;
+    options.insights_target(&state.config.bitbucket)?;
+    if let Some(url) = &options.slack_webhook_url {
+        let allowed_hosts = &state.config.slack.allowed_hosts;
+        notify::check_allowed_host("slack_webhook_url", url, allowed_hosts)?;
+    }
+    if let Some(url) = &options.teams_webhook_url {
+        let allowed_hosts = &state.config.teams.allowed_hosts;
+        notify::check_allowed_host("teams_webhook_url", url, allowed_hosts)?;
+    }
+    options.glob_properties(&[])?;
+    if let Some(name) = &options.policy {
+        state.policies.check(name)?;
+    }
+    if let Some(secs) = options.scanner_timeout_secs {
+        let max = state.config.scans.max_scanner_timeout_secs;
+        if secs == 0 || secs > max {
+            return Err(AppError::InvalidRequest(format!(
+                "Field 'scanner_timeout_secs' must be between 1 and {}",
+                max
+            )));
+        }
+    }
+    for (name, secs) in [
+        ("poll_interval_secs", options.poll_interval_secs),
+        ("poll_timeout_secs", options.poll_timeout_secs),
+    ] {
+        let max = state.config.sonarqube.max_poll_timeout_secs;
+        if secs.is_some_and(|secs| secs == 0 || secs > max) {
+            return Err(AppError::InvalidRequest(format!(
+                "Field '{}' must be between 1 and {}",
+                name, max
+            )));
+        }
+    }
+    if options.engines.is_empty() {
+        options.engines = vec![Engine::SonarQube];
+    }
+    options.check_engines()?;
 
-    // Poll for task completion
-    poll_for_completion(
-        &job_id,
-        &sonarqube_url,
-        &sonarqube_token,
-        &sonarqube_password,
-        use_token,
-    )
-    .await?;
+    // Fail fast before extraction if SonarQube is needed and known to be down
+    if options.engines.contains(&Engine::SonarQube) {
+        state.sonar.ensure_available()?;
+    }
+    if let Some(requested) = &options.issue_types {
+        options.issue_types = Some(issue_types::parse(requested)?);
+    }
+
+    // An identical archive scanned recently with the same options is not
+    // scanned again unless the caller forces it
+    let input_sha256 = match &source {
+        UploadSource::Archive(zip_path) => Some(sha256_file(zip_path)?),
+        UploadSource::Git(_) | UploadSource::Directory(_) => None,
+    };
+    let cache_key = input_sha256
+        .as_deref()
+        .map(|input_sha256| options.cache_key(input_sha256));
+    let cached = cache_key
+        .as_deref()
+        .filter(|_| !force)
+        .and_then(|key| state.results.get(key));
+    if let Some(cached) = cached {
+        info!("Reusing cached result for job {}", job_id);
+        let mut response = AnalyzeResponse::clone(&cached);
+        response.cached = true;
+        state.policies.apply(&options, &mut response);
+        let response = Arc::new(response);
+        state.jobs.create(
+            job_id,
+            options.tenant.as_deref(),
+            options.project_name.as_deref(),
+        );
+        state.jobs.succeed(job_id, response.clone());
+        if let Some(url) = &options.callback_url {
+            state
+                .webhooks
+                .notify(&state.tasks, url.clone(), job_id, Ok(response.as_ref()));
+        }
+        report_to_code_review(state, job_id, &options, Ok(response.as_ref()));
+        notify_subscribers(state, job_id, &options, Ok(response.as_ref()), None);
+        archive_artifacts(state, job_id);
+        record_audit(
+            state,
+            job_id,
+            &options,
+            input_sha256.as_deref(),
+            Ok(AuditOutcome::Cached),
+        );
+        return Ok(Analysis::Cached(response));
+    }
 
-    // Fetch vulnerabilities
-    let vulnerabilities = fetch_vulnerabilities(
+    // Claim a scan slot now in reject mode; queued requests wait in run_job
+    let reserved_slot = state.scan_slots.reserve()?;
+    state.jobs.create(
+        job_id,
+        options.tenant.as_deref(),
+        options.project_name.as_deref(),
+    );
+
+    let upload = PreparedUpload {
+        temp_dir,
+        source,
+        options,
+        input_sha256,
+        cache_key,
+    };
+    Ok(Analysis::Scan(upload, reserved_slot))
+}
+
+/// `202 Accepted` pointing at the job's status and result.
+fn accepted_response(job_id: &str, status: JobStatus) -> Response {
+    let location = format!("/jobs/{}", job_id);
+    let body = Json(serde_json::json!({
+        "job_id": job_id,
+        "status": status,
+        "status_url": location,
+        "result_url": format!("{}/result", location),
+    }));
+    (StatusCode::ACCEPTED, [(header::LOCATION, location)], body).into_response()
+}
+
+/// Prefix of every job's temp directory, by which `POST /admin/purge`
+/// finds the ones a previous process left behind.
+const TEMP_DIR_PREFIX: &str = "sonar-job-";
+
+/// A new temp directory for a job, removed with its contents when dropped.
+fn job_temp_dir() -> Result<TempDir, AppError> {
+    tempfile::Builder::new()
+        .prefix(TEMP_DIR_PREFIX)
+        .tempdir()
+        .map_err(|e| AppError::InternalError(format!("Failed to create temp dir: {}", e)))
+}
+
+/// Reports a finished job to the pull request, merge request or commit it
+/// names, if any.
+fn report_to_code_review(
+    state: &AppState,
+    job_id: &str,
+    options: &AnalyzeOptions,
+    outcome: Result<&AnalyzeResponse, &AppError>,
+) {
+    // Checked in prepare_analysis
+    if let Ok(Some(pull_request)) = options.pull_request(&state.config.github) {
+        state
+            .github
+            .comment(&state.tasks, pull_request, job_id, outcome);
+    }
+    if let Ok(Some(target)) = options.merge_request_target(&state.config.gitlab) {
+        state.gitlab.report(&state.tasks, target, job_id, outcome);
+    }
+    if let Ok(Some(target)) = options.insights_target(&state.config.bitbucket) {
+        state
+            .bitbucket
+            .publish(&state.tasks, target, job_id, outcome);
+    }
+}
+
+/// Tells every notification sink about a finished job.
+fn notify_subscribers(
+    state: &AppState,
+    job_id: &str,
+    options: &AnalyzeOptions,
+    outcome: Result<&AnalyzeResponse, &AppError>,
+    duration: Option<Duration>,
+) {
+    let notice = Notice {
+        job_id,
+        options,
+        outcome,
+        duration,
+    };
+    for notifier in state.notifiers.iter() {
+        notifier.notify(&state.tasks, &notice);
+    }
+}
+
+/// Runs the analysis for a registered job and records the outcome in the job store.
+#[instrument(skip_all, fields(job_id = %job_id))]
+async fn run_job(
+    state: AppState,
+    job_id: String,
+    upload: PreparedUpload,
+    reserved_slot: Option<OwnedSemaphorePermit>,
+) -> Result<Arc<AnalyzeResponse>, AppError> {
+    let outcome = run_scan(&state, &job_id, &upload, reserved_slot)
+        .await
+        .map(|mut response| {
+            state.policies.apply(&upload.options, &mut response);
+            response
+        });
+    if let Some(url) = &upload.options.callback_url {
+        state
+            .webhooks
+            .notify(&state.tasks, url.clone(), &job_id, outcome.as_ref());
+    }
+    report_to_code_review(&state, &job_id, &upload.options, outcome.as_ref());
+    let result = match outcome {
+        Ok(response) => {
+            let response = Arc::new(response);
+            state.jobs.succeed(&job_id, response.clone());
+            // Results with warnings are missing parts and are worth retrying
+            if let Some(key) = &upload.cache_key {
+                if response.scanner_report.warnings.is_empty() {
+                    state.results.insert(key.clone(), response.clone());
+                }
+            }
+            Ok(response)
+        }
+        // Already recorded by the cancel request
+        Err(e @ AppError::Canceled(_)) => Err(e),
+        Err(e) => {
+            error!("Job {} failed: {}", job_id, e);
+            state.jobs.fail(&job_id, &e);
+            Err(e)
+        }
+    };
+    // Read back once finished, so queueing is not counted
+    let duration = match state.jobs.get(&job_id).await {
+        Ok(Some(job)) => job
+            .started_at
+            .zip(job.finished_at)
+            .and_then(|(started, finished)| Duration::try_from(finished - started).ok()),
+        _ => None,
+    };
+    notify_subscribers(
+        &state,
         &job_id,
-        &sonarqube_url,
-        &sonarqube_token,
-        &sonarqube_password,
-        use_token,
-    )
-    .await?;
+        &upload.options,
+        result.as_ref().map(Arc::as_ref),
+        duration,
+    );
+    archive_artifacts(&state, &job_id);
+    record_audit(
+        &state,
+        &job_id,
+        &upload.options,
+        upload.input_sha256.as_deref(),
+        result.as_ref().map(|_| AuditOutcome::Succeeded),
+    );
+    result
+}
+
+/// Uploads a finished job's artifacts in the background when artifact
+/// storage is configured.
+fn archive_artifacts(state: &AppState, job_id: &str) {
+    if let Some(artifacts) = &state.artifacts {
+        artifacts.archive_in_background(&state.tasks, state.jobs.clone(), job_id);
+    }
+}
+
+/// Appends a finished scan to the audit log when one is configured.
+fn record_audit(
+    state: &AppState,
+    job_id: &str,
+    options: &AnalyzeOptions,
+    input_sha256: Option<&str>,
+    outcome: Result<AuditOutcome, &AppError>,
+) {
+    if let Some(audit) = &state.audit {
+        let entry = AuditEntry::new(job_id, options, input_sha256, outcome);
+        audit.record(&state.tasks, entry);
+    }
+}
+
+/// Waits for a scan slot and runs the pipeline, unless the job is canceled
+/// first. Dropping the pipeline kills its scanner processes.
+async fn run_scan(
+    state: &AppState,
+    job_id: &str,
+    upload: &PreparedUpload,
+    reserved_slot: Option<OwnedSemaphorePermit>,
+) -> Result<AnalyzeResponse, AppError> {
+    let canceled = state.jobs.cancellation(job_id);
+    let was_canceled = || AppError::Canceled(format!("Job {} was canceled", job_id));
+
+    // Stays QUEUED until a scan slot frees up; released when this returns
+    state.metrics.scan_queued();
+    let _slot = tokio::select! {
+        slot = state.scan_slots.acquire(reserved_slot) => slot,
+        _ = canceled.cancelled() => {
+            state.metrics.scan_dequeued();
+            info!("Job {} canceled while queued", job_id);
+            return Err(was_canceled());
+        }
+    };
+    state.jobs.mark_running(job_id);
+    let started = state.metrics.scan_started();
+
+    let progress = state.jobs.progress(job_id);
+    let outcome = tokio::select! {
+        outcome = run_analysis(state, job_id, upload, &progress) => Some(outcome),
+        _ = canceled.cancelled() => None,
+    };
+    let outcome = match outcome {
+        Some(outcome) => outcome,
+        None => {
+            info!("Job {} canceled while running", job_id);
+            if upload.options.engines.contains(&Engine::SonarQube) {
+                let project_keys = project_keys(job_id, &upload.options);
+                let sonar = upload.options.sonar_client(&state.sonar);
+                state
+                    .backends
+                    .get(Engine::SonarQube)
+                    .cancel(&sonar, &project_keys)
+                    .await;
+            }
+            Err(was_canceled())
+        }
+    };
+    state.metrics.scan_finished(started, outcome.is_ok());
+    outcome
+}
+
+/// SonarQube project key of a job: its ID, prefixed by its tenant if any so
+/// tenants sharing a server never collide.
+pub(crate) fn project_key(job_id: &str, tenant: Option<&str>) -> String {
+    match tenant {
+        Some(tenant) => format!("{}_{}", tenant, job_id),
+        None => job_id.to_string(),
+    }
+}
+
+/// SonarQube project key of the `n`th quality profile a job with key
+/// `project_key` is scanned against, counting from 1.
+pub(crate) fn profile_project_key(project_key: &str, n: usize) -> String {
+    format!("{}_{}", project_key, n)
+}
+
+/// SonarQube project keys of a job: one per requested quality profile, or
+/// the job's own.
+pub(crate) fn project_keys(job_id: &str, options: &AnalyzeOptions) -> Vec<String> {
+    let project_key = project_key(job_id, options.tenant.as_deref());
+    if options.quality_profiles.is_empty() {
+        vec![project_key]
+    } else {
+        (1..=options.quality_profiles.len())
+            .map(|n| profile_project_key(&project_key, n))
+            .collect()
+    }
+}
+
+/// The analysis pipeline: extract, scan, poll, fetch and summarise.
+async fn run_analysis(
+    state: &AppState,
+    job_id: &str,
+    upload: &PreparedUpload,
+    progress: &JobProgress,
+) -> Result<AnalyzeResponse, AppError> {
+    let sonar = &upload.options.sonar_client(&state.sonar);
+    let temp_path = upload.temp_dir.path();
+    let options = &upload.options;
+    let project_key = project_key(job_id, options.tenant.as_deref());
+    let scanner_timeout = Duration::from_secs(
+        options
+            .scanner_timeout_secs
+            .unwrap_or(state.config.scans.scanner_timeout_secs),
+    );
+
+    // Extract the upload or clone the repository
+    let extracted_at = SystemTime::now();
+    let started = Instant::now();
+    let (project_dir, input_sha256, git) = match &upload.source {
+        UploadSource::Archive(zip_path) => {
+            if let Ok(metadata) = fs::metadata(zip_path) {
+                state.metrics.observe_upload(metadata.len());
+            }
+            let input_sha256 = upload.input_sha256.clone();
+            let limits = ExtractionLimits::new(&state.config.limits);
+            let project_dir =
+                info_span!("extract").in_scope(|| extract_archive(zip_path, temp_path, limits))?;
+            (project_dir, input_sha256, None)
+        }
+        UploadSource::Git(source) => {
+            let project_dir = temp_path.join("project");
+            let limits = CheckoutLimits {
+                timeout: scanner_timeout,
+                max_bytes: state.config.limits.max_extracted_bytes,
+            };
+            let allowed_hosts = &state.config.sources.allowed_hosts;
+            let revision = source
+                .checkout(&project_dir, limits, allowed_hosts, &state.children)
+                .instrument(info_span!("git_checkout"))
+                .await?;
+            (project_dir, None, Some(revision))
+        }
+        UploadSource::Directory(source) => {
+            let project_dir = temp_path.join("project");
+            info_span!("copy").in_scope(|| archive::copy_directory(source, &project_dir))?;
+            (project_dir, None, None)
+        }
+    };
+    state.metrics.observe_stage(Stage::Extract, started);
+    progress.stage(JobStage::Extracted, None);
+
+    // Pick up coverage reports shipped in the archive, and one uploaded next
+    // to it
+    let mut coverage_files =
+        coverage::find_coverage_reports(&project_dir, options.coverage_paths.as_deref());
+    if let Some(report) = &options.coverage_report {
+        coverage_files.push(report.place(&project_dir)?);
+    }
+    info!("Found {} coverage report(s)", coverage_files.len());
+
+    // Defaults give way to the project's sonar-project.properties unless the
+    // service takes precedence; explicitly requested coverage always applies
+    let mut defaults = vec![("sonar.sources".to_string(), ".".to_string())];
+    let default_exclusions = options.default_exclusions(&state.config.scans.default_exclusions);
+    if options.exclude.is_none() && !default_exclusions.is_empty() {
+        defaults.push(("sonar.exclusions".to_string(), default_exclusions.join(",")));
+    }
+    let mut requested = Vec::new();
+    let coverage_properties = coverage::scanner_properties(&coverage_files);
+    if options.coverage_paths.is_some() || options.coverage_report.is_some() {
+        requested.extend(coverage_properties);
+    } else {
+        defaults.extend(coverage_properties);
+    }
+    let mut scanner_properties = sonar_properties::merge_project_file(
+        &project_dir,
+        defaults,
+        state.config.scans.properties_precedence,
+    );
+    scanner_properties.extend(requested);
+    scanner_properties.extend(options.sonar_properties.iter().cloned());
+    scanner_properties.extend(options.glob_properties(default_exclusions)?);
+    scanner_properties.extend(options.analysis_ref_properties());
+
+    // Drop files older than modified_since, keeping the coverage reports
+    let mut warnings = Vec::new();
+    let mut pruned_files = None;
+    if let Some(since) = options.modified_since {
+        let keep: Vec<String> = coverage_files.iter().map(|f| f.path.clone()).collect();
+        let outcome = prune::prune_older_than(&project_dir, since, extracted_at, &keep);
+        match outcome.warning {
+            Some(warning) => warnings.push(warning),
+            None => pruned_files = Some(outcome.removed),
+        }
+    }
+
+    // Optionally check the property set with a dry scanner run
+    let uses_sonarqube = options.engines.contains(&Engine::SonarQube);
+    if options.validate_properties && uses_sonarqube {
+        let sandbox_config = &state.config.sandbox;
+        let (dump_path, scanner_dump_path) =
+            sandbox::output_file(sandbox_config, &project_dir, "scanner-properties.dump");
+        let command = backends::scanner_command(
+            &project_dir,
+            &project_key,
+            sonar,
+            sandbox_config,
+            &state.limits,
+            &state.proxy,
+            &scanner_properties,
+        )?;
+        warnings.extend(scanner_validation::validate_properties(
+            command,
+            &dump_path,
+            &scanner_dump_path,
+            &project_dir,
+            &scanner_properties,
+        ));
+    }
+
+    // Record how the scan is run so it can be reproduced
+    let language_counts = languages::count_sources(&project_dir);
+    let mut cli_properties = backends::base_scanner_properties(&project_key, sonar);
+    cli_properties.extend(scanner_properties.iter().cloned());
+    let effective_properties = manifest::effective_properties(&project_dir, &cli_properties);
+
+    let filter = IssueFilter {
+        types: match &options.issue_types {
+            Some(types) if !types.is_empty() => types.clone(),
+            _ => issue_types::DEFAULT_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+        }
+        .into_iter()
+        .filter(|t| t != issue_types::HOTSPOT_TYPE)
+        .collect(),
+        max_issues: options.max_issues,
+        min_severity: options.min_severity,
+        analysis_ref: options.analysis_ref()?,
+    };
+
+    // Look for secrets before the scanners write their working files into
+    // the project; a failure here should not cost the caller the issues
+    let secrets = if options.scan_secrets {
+        match state.gitleaks.scan(&project_dir, scanner_timeout, progress).await {
+            Ok(found) => Some(found),
+            Err(e) => {
+                warnings.push(format!("Secret scan failed: {}", e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Scan once, or once per requested quality profile
+    let project_keys = project_keys(job_id, options);
+    let languages: Vec<&str> = language_counts.keys().copied().collect();
+    let single_scan = ScanContext {
+        project_dir: &project_dir,
+        project_key: &project_key,
+        properties: &scanner_properties,
+        filter: &filter,
+        profile: options.quality_profile.as_deref(),
+        languages: &languages,
+        timeout: scanner_timeout,
+        progress,
+        sonar,
+    };
+    let mut engine_results = Vec::new();
+    let mut quality_gate = None;
+    let mut hotspots = Vec::new();
+    let mut metrics = None;
+    let mut ce_wait = None;
+    if uses_sonarqube {
+        let sonarqube = state.backends.get(Engine::SonarQube);
+        let scanned = async {
+            if options.quality_profiles.is_empty() {
+                let output = sonarqube.scan(&single_scan).await?;
+                return Ok((
+                    output.fetched,
+                    output.quality_gate,
+                    output.hotspots,
+                    output.metrics,
+                    output.ce_wait.unwrap_or_default(),
+                ));
+            }
+
+            let mut results = Vec::new();
+            let mut gates = Vec::new();
+            let mut profile_hotspots = Vec::new();
+            let mut profile_metrics = Vec::new();
+            let mut waited = Duration::ZERO;
+            for (profile, project_key) in options.quality_profiles.iter().zip(&project_keys) {
+                let scan = ScanContext {
+                    project_key,
+                    profile: Some(profile.as_str()),
+                    ..single_scan
+                };
+                let output = sonarqube.scan(&scan).await?;
+                results.push((profile.clone(), output.fetched));
+                gates.extend(output.quality_gate);
+                profile_hotspots.push((profile.clone(), output.hotspots));
+                profile_metrics.extend(output.metrics);
+                waited += output.ce_wait.unwrap_or_default();
+            }
+            Ok((
+                profiles::merge(results, options.max_issues),
+                quality_gate::combine(gates),
+                hotspots::merge_profiles(profile_hotspots),
+                measures::combine(profile_metrics),
+                waited,
+            ))
+        }
+        .await;
+        sonarqube
+            .clean_up(sonar, &project_keys, scanned.is_ok())
+            .await;
+        let (fetched, gate, found, measured, waited) = scanned?;
+        engine_results.push((Engine::SonarQube, fetched));
+        quality_gate = gate;
+        hotspots = found;
+        metrics = measured;
+        ce_wait = Some(waited);
+    }
 
-    let total_count = vulnerabilities.len();
+    // The other engines scan the project once, without a server
+    for &engine in &options.engines {
+        if engine != Engine::SonarQube {
+            let output = state.backends.get(engine).scan(&single_scan).await?;
+            engine_results.push((engine, output.fetched));
+        }
+    }
+    let fetched = backends::merge(engine_results, options.max_issues);
+
+    // A failed dependency scan should not cost the caller the issues
+    let dependencies = if options.scan_dependencies {
+        match state.trivy.scan(&project_dir, scanner_timeout, progress).await {
+            Ok(found) => Some(found),
+            Err(e) => {
+                warnings.push(format!("Dependency scan failed: {}", e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut vulnerabilities = fetched.issues;
+    if options.enrich_rules {
+        warnings.extend(rules::enrich(sonar, &state.rules, &mut vulnerabilities).await);
+    }
+    if let Some(context) = options.snippet_lines {
+        snippets::attach(&project_dir, &mut vulnerabilities, context);
+    }
+    fingerprints::assign(&project_dir, &mut vulnerabilities);
+    let truncated = fetched.truncated;
+
+    let suppressed_count = options
+        .baseline
+        .as_ref()
+        .map_or(0, |baseline| baseline.suppress(&mut vulnerabilities));
+    let total_count = vulnerabilities.len() - suppressed_count;
+    let issues_by_type = if options.issue_types.is_some() {
+        issue_types::group(&vulnerabilities)
+    } else {
+        BTreeMap::new()
+    };
     info!("Analysis complete. Found {} vulnerabilities", total_count);
 
-    // Cleanup happens automatically when temp_dir is dropped
-    Ok(Json(AnalyzeResponse {
+    // Group by module for monorepo uploads
+    let module_specs = modules::resolve_modules(&project_dir, options.modules.as_deref());
+    let modules = if module_specs.is_empty() {
+        Vec::new()
+    } else {
+        modules::group_issues(&vulnerabilities, &module_specs)
+    };
+
+    let manifest = ScanManifest {
+        project_key,
+        input_sha256,
+        git,
+        engines: options.engines.clone(),
+        quality_profile: options.quality_profile.clone(),
+        scanner_version: if uses_sonarqube {
+            backends::scanner_version()
+        } else {
+            None
+        },
+        sonarqube_version: if uses_sonarqube {
+            sonar.server_version().await
+        } else {
+            None
+        },
+        language: languages::primary_language(&language_counts),
+        exclusions: manifest::exclusions(&effective_properties),
+        properties: effective_properties,
+    };
+
+    // Cleanup happens automatically when the upload's temp_dir is dropped
+    Ok(AnalyzeResponse {
         vulnerabilities,
         total_count,
+        suppressed_count,
+        cached: false,
+        truncated,
+        issues_by_type,
+        modules,
+        languages: language_counts
+            .iter()
+            .map(|(language, stats)| (language.to_string(), *stats))
+            .collect(),
+        quality_gate,
+        verdict: None,
+        policy_violations: Vec::new(),
+        policy: None,
+        metrics,
+        hotspots,
+        dependencies,
+        secrets,
+        ce_wait_ms: ce_wait.map(|waited| waited.as_millis() as u64),
+        scanner_report: ScannerReport {
+            coverage_files,
+            pruned_files,
+            warnings,
+        },
+        manifest,
+    })
+}
+
+/// Looks up a job the caller's tenant may see. Other tenants' jobs are
+/// reported as not found, so their IDs cannot be probed.
+async fn visible_job(
+    state: &AppState,
+    tenant: &Tenant,
+    id: &str,
+) -> Result<jobs::JobRecord, AppError> {
+    state
+        .jobs
+        .get(id)
+        .await?
+        .filter(|job| tenant.can_see(job.tenant.as_deref()))
+        .ok_or_else(|| AppError::NotFound(format!("Job {}", id)))
+}
+
+async fn job_status_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Json<jobs::JobRecord>, AppError> {
+    visible_job(&state, &tenant, &id).await.map(Json)
+}
+
+/// Cancels a queued or running job: its scanner processes are killed, its
+/// pending SonarQube task withdrawn and the job marked `CANCELED`.
+async fn cancel_job_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Json<jobs::JobRecord>, AppError> {
+    // Jobs only in storage have finished, or were failed on startup
+    let job = visible_job(&state, &tenant, &id).await?;
+    let previous = state.jobs.cancel(&id).unwrap_or(job.status);
+    if previous.is_finished() {
+        return Err(AppError::Conflict(format!(
+            "Job {} has already finished with status {}",
+            id,
+            previous.as_str()
+        )));
+    }
+    info!("Canceled job {} ({})", id, previous.as_str());
+
+    state
+        .jobs
+        .get(&id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Job {}", id)))
+}
+
+/// Streams a job's pipeline stages as Server-Sent Events, starting with
+/// those already reached and ending after `completed`, `failed` or `canceled`.
+async fn job_events_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    visible_job(&state, &tenant, &id).await?;
+    let (history, receiver) = state
+        .jobs
+        .subscribe(&id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Job {}", id)))?;
+
+    let events = jobs::event_stream(history, receiver).map(|event| {
+        Ok(Event::default()
+            .event(event.stage.as_str())
+            .json_data(&event)
+            .unwrap_or_else(|_| Event::default().event(event.stage.as_str())))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// The scanner output kept for a job, live or finished.
+async fn job_logs_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let job = visible_job(&state, &tenant, &id).await?;
+    let logs = job.logs();
+
+    Ok(Json(serde_json::json!({
+        "job_id": job.id,
+        "status": job.status,
+        "lines": logs.lines,
+        "dropped": logs.dropped,
+    })))
+}
+
+/// Upgrades to a WebSocket that streams the job's sonar-scanner output,
+/// replaying buffered lines first and closing once the job finishes.
+async fn job_logs_ws_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    UrlPath(id): UrlPath<String>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    visible_job(&state, &tenant, &id).await?;
+    let subscription = state
+        .jobs
+        .subscribe_logs(&id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Job {}", id)))?;
+
+    Ok(ws.on_upgrade(move |socket| {
+        logs::forward_to_socket(
+            socket,
+            subscription.history,
+            subscription.lines,
+            subscription.events,
+        )
     }))
 }
 
+/// Returns the result of a finished job, `202` with the job status while it
+/// is still running, or the job's original error if it failed.
+async fn job_result_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    UrlPath(id): UrlPath<String>,
+    Query(query): Query<ResultQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let format = OutputFormat::negotiate(query.format.as_deref(), &headers)?;
+    job_result(&state, &tenant, &id, format).await
+}
+
+/// Standalone HTML report for a finished job.
+async fn job_report_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Response, AppError> {
+    job_result(&state, &tenant, &id, OutputFormat::Html).await
+}
+
+/// Renders a job's result in `format`, its recorded error, or `202 Accepted`
+/// while it is still running.
+async fn job_result(
+    state: &AppState,
+    tenant: &Tenant,
+    id: &str,
+    format: OutputFormat,
+) -> Result<Response, AppError> {
+    let job = visible_job(state, tenant, id).await?;
+
+    if let Some(error) = job.error.clone() {
+        return Ok(error.into_response());
+    }
+
+    match job.result {
+        Some(result) => Ok(formats::render(format, &result)),
+        None => Ok((StatusCode::ACCEPTED, [(header::RETRY_AFTER, "5")], Json(job)).into_response()),
+    }
+}
+
 async fn extract_zip_from_multipart(
     multipart: &mut Multipart,
-    temp_path: &PathBuf,
-) -> Result<PathBuf, AppError> {
-    while let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|e| AppError::MissingField(format!("Failed to read multipart field: {}", e)))?
-    {
+    temp_path: &Path,
+) -> Result<(PathBuf, AnalyzeOptions), AppError> {
+    let mut zip_path = None;
+    let mut options = AnalyzeOptions::default();
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        upload_error(e, |e| {
+            AppError::MissingField(format!("Failed to read multipart field: {}", e))
+        })
+    })? {
         let name = field.name().unwrap_or("").to_string();
 
-        if name == "file" || name == "zip" {
-            let data = field
-                .bytes()
-                .await
-                .map_err(|e| AppError::ZipError(format!("Failed to read file data: {}", e)))?;
+        match name.as_str() {
+            "file" | "zip" => {
+                let path = temp_path.join("upload.archive");
+                let size = save_field(field, &path).await?;
+                info!("Saved {} byte upload to {:?}", size, path);
+                zip_path = Some(path);
+            }
+            "coverage" => {
+                let file_name = field.file_name().map(str::to_string);
+                let path = temp_path.join("upload.coverage");
+                let size = save_field(field, &path).await?;
+                info!("Saved {} byte coverage report to {:?}", size, path);
+                let sha256 = sha256_file(&path)?;
+                options.coverage_report =
+                    Some(UploadedReport::new(path, file_name.as_deref(), sha256)?);
+            }
+            name if OPTION_FIELDS.contains(&name) => {
+                let value = read_text_field(field, name).await?;
+                apply_option(&mut options, name, &value)?;
+            }
+            _ => {}
+        }
+    }
+
+    let zip_path = zip_path.ok_or_else(|| {
+        AppError::MissingField("No zip file found in multipart request".to_string())
+    })?;
+
+    Ok((zip_path, options))
+}
+
+/// Streams a multipart file field to `path` chunk by chunk, so the upload is
+/// never held in memory. Returns the number of bytes written.
+async fn save_field(mut field: Field<'_>, path: &Path) -> Result<u64, AppError> {
+    let write_error =
+        |e: std::io::Error| AppError::ZipError(format!("Failed to write zip file: {}", e));
+    let mut file = tokio::fs::File::create(path).await.map_err(write_error)?;
+    let mut size = 0u64;
+
+    while let Some(chunk) = field.chunk().await.map_err(|e| {
+        upload_error(e, |e| {
+            AppError::ZipError(format!("Failed to read file data: {}", e))
+        })
+    })? {
+        file.write_all(&chunk).await.map_err(write_error)?;
+        size += chunk.len() as u64;
+    }
 
-            let zip_path = temp_path.join("upload.zip");
-            fs::write(&zip_path, data)
-                .map_err(|e| AppError::ZipError(format!("Failed to write zip file: {}", e)))?;
+    file.flush().await.map_err(write_error)?;
+    Ok(size)
+}
 
-            info!("Saved zip file to {:?}", zip_path);
-            return Ok(zip_path);
+/// Content types of an `/analyze` body that is the archive itself rather
+/// than multipart form data. The format is still detected from the bytes.
+const RAW_ARCHIVE_TYPES: &[&str] = &[
+    "application/zip",
+    "application/x-zip-compressed",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-tar",
+    "application/octet-stream",
+];
+
+/// Reads an `/analyze` request whose body is the archive, streaming it to
+/// the temp dir, with the option fields taken from the query string.
+async fn read_raw_archive(
+    request: Request,
+    temp_path: &Path,
+    max_upload_bytes: u64,
+) -> Result<(UploadSource, AnalyzeOptions), AppError> {
+    let Query(params) = Query::<Vec<(String, String)>>::try_from_uri(request.uri())
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid query string: {}", e)))?;
+    let mut options = AnalyzeOptions::default();
+    for (name, value) in params
+        .iter()
+        .filter(|(name, _)| OPTION_FIELDS.contains(&name.as_str()))
+    {
+        apply_option(&mut options, name, value)?;
+    }
+
+    let path = temp_path.join("upload.archive");
+    let write_error =
+        |e: std::io::Error| AppError::ZipError(format!("Failed to write zip file: {}", e));
+    let mut file = tokio::fs::File::create(&path).await.map_err(write_error)?;
+    let mut size = 0u64;
+    let mut body = request.into_body().into_data_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk
+            .map_err(|e| AppError::ZipError(format!("Failed to read request body: {}", e)))?;
+        size += chunk.len() as u64;
+        if size > max_upload_bytes {
+            return Err(AppError::ArchiveLimitExceeded(
+                "upload exceeds the request size limit".to_string(),
+            ));
         }
+        file.write_all(&chunk).await.map_err(write_error)?;
     }
 
-    Err(AppError::MissingField(
-        "No zip file found in multipart request".to_string(),
-    ))
+    if size == 0 {
+        return Err(AppError::MissingField(
+            "The request body is empty; expected an archive".to_string(),
+        ));
+    }
+    file.flush().await.map_err(write_error)?;
+    info!("Saved {} byte upload to {:?}", size, path);
+    Ok((UploadSource::Archive(path), options))
 }
 
-fn unzip_file(zip_path: &PathBuf, temp_path: &PathBuf) -> Result<PathBuf, AppError> {
+/// Reports a request body over `MAX_UPLOAD_BYTES` as `413`; any other
+/// multipart failure is mapped by `otherwise`.
+fn upload_error(
+    error: MultipartError,
+    otherwise: impl FnOnce(MultipartError) -> AppError,
+) -> AppError {
+    if error.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::ArchiveLimitExceeded("upload exceeds the request size limit".to_string())
+    } else {
+        otherwise(error)
+    }
+}
+
+/// Reads a JSON `/analyze` body: `git_url`, an optional `ref` and any of the
+/// multipart option fields (lists as arrays or strings).
+fn read_git_request(
+    mut body: serde_json::Map<String, serde_json::Value>,
+) -> Result<(UploadSource, AnalyzeOptions), AppError> {
+    let git_url = match body.remove("git_url") {
+        Some(serde_json::Value::String(url)) => url,
+        _ => return Err(AppError::MissingField("git_url".to_string())),
+    };
+    let reference = match body.remove("ref") {
+        Some(serde_json::Value::String(reference)) => Some(reference),
+        None | Some(serde_json::Value::Null) => None,
+        Some(_) => {
+            return Err(AppError::InvalidRequest(
+                "Field 'ref' must be a string".to_string(),
+            ))
+        }
+    };
+    let source = GitSource::parse(&git_url, reference.as_deref())?;
+
+    let mut options = AnalyzeOptions::default();
+    for (name, value) in json_option_fields(body) {
+        apply_option(&mut options, &name, &value)?;
+    }
+
+    Ok((UploadSource::Git(source), options))
+}
+
+/// The option fields of a JSON body as name/value pairs, with arrays and
+/// objects passed on as JSON text the way multipart clients send them.
+fn json_option_fields(body: serde_json::Map<String, serde_json::Value>) -> Vec<(String, String)> {
+    body.into_iter()
+        .filter(|(name, _)| OPTION_FIELDS.contains(&name.as_str()))
+        .filter_map(|(name, value)| match value {
+            serde_json::Value::String(text) => Some((name, text)),
+            serde_json::Value::Null => None,
+            other => Some((name, other.to_string())),
+        })
+        .collect()
+}
+
+/// Request fields that map onto [`AnalyzeOptions`].
+const OPTION_FIELDS: &[&str] = &[
+    "modules",
+    "coverage_paths",
+    "validate_properties",
+    "quality_profiles",
+    "quality_profile",
+    "modified_since",
+    "issue_types",
+    "min_severity",
+    "max_issues",
+    "max_blocker",
+    "max_critical",
+    "max_major",
+    "max_minor",
+    "max_info",
+    "policy",
+    "sonar_properties",
+    "branch",
+    "pr_key",
+    "pr_branch",
+    "pr_base",
+    "callback_url",
+    "github_repo",
+    "pr_number",
+    "gitlab_project",
+    "mr_iid",
+    "commit_sha",
+    "bitbucket_repo",
+    "bitbucket_commit",
+    "slack_webhook_url",
+    "teams_webhook_url",
+    "include",
+    "exclude",
+    "default_exclusions",
+    "engine",
+    "scan_dependencies",
+    "scan_secrets",
+    "enrich_rules",
+    "snippet_lines",
+    "scanner_timeout_secs",
+    "poll_interval_secs",
+    "poll_timeout_secs",
+    "baseline",
+    "tenant",
+    "project_name",
+];
+
+/// Options from fields read once for several jobs, since each job takes its
+/// own [`AnalyzeOptions`].
+fn options_from_fields(fields: &[(String, String)]) -> Result<AnalyzeOptions, AppError> {
+    let mut options = AnalyzeOptions::default();
+    for (name, value) in fields {
+        apply_option(&mut options, name, value)?;
+    }
+    Ok(options)
+}
+
+fn apply_option(options: &mut AnalyzeOptions, name: &str, value: &str) -> Result<(), AppError> {
+    match name {
+        "modules" => options.modules = Some(parse_list_field(value)),
+        "coverage_paths" => options.coverage_paths = Some(parse_list_field(value)),
+        "validate_properties" => options.validate_properties = parse_bool_field(value),
+        "quality_profiles" => options.quality_profiles = parse_list_field(value),
+        "quality_profile" => options.quality_profile = Some(parse_name_field(name, value)?),
+        "modified_since" => options.modified_since = Some(parse_timestamp_field(name, value)?),
+        "issue_types" => options.issue_types = Some(parse_list_field(value)),
+        "min_severity" => options.min_severity = Some(parse_severity_field(name, value)?),
+        "max_issues" => options.max_issues = Some(parse_count_field(name, value)?),
+        "max_blocker" | "max_critical" | "max_major" | "max_minor" | "max_info" => options
+            .thresholds
+            .set(name, parse_count_field(name, value)?),
+        "policy" => options.policy = Some(parse_name_field(name, value)?),
+        "sonar_properties" => options.sonar_properties = sonar_properties::parse(value)?,
+        "branch" => options.branch = Some(parse_name_field(name, value)?),
+        "pr_key" => options.pr_key = Some(parse_name_field(name, value)?),
+        "pr_branch" => options.pr_branch = Some(parse_name_field(name, value)?),
+        "pr_base" => options.pr_base = Some(parse_name_field(name, value)?),
+        "callback_url" => options.callback_url = Some(webhook::parse_callback_url(value)?),
+        "github_repo" => options.github_repo = Some(github::parse_repo(name, value)?),
+        "pr_number" => options.pr_number = Some(parse_count_field(name, value)? as u64),
+        "gitlab_project" => options.gitlab_project = Some(gitlab::parse_project(name, value)?),
+        "mr_iid" => options.mr_iid = Some(parse_count_field(name, value)? as u64),
+        "commit_sha" => options.commit_sha = Some(parse_commit_sha(name, value)?),
+        "bitbucket_repo" => options.bitbucket_repo = Some(bitbucket::parse_repo(name, value)?),
+        "bitbucket_commit" => options.bitbucket_commit = Some(parse_commit_sha(name, value)?),
+        "slack_webhook_url" => {
+            options.slack_webhook_url = Some(notify::parse_webhook_url(name, value)?)
+        }
+        "teams_webhook_url" => {
+            options.teams_webhook_url = Some(notify::parse_webhook_url(name, value)?)
+        }
+        "include" => options.include = Some(parse_glob_field(name, value)?),
+        "exclude" => options.exclude = Some(parse_glob_field(name, value)?),
+        "default_exclusions" => options.skip_default_exclusions = !parse_bool_field(value),
+        "engine" => options.engines = Engine::parse(&parse_list_field(value))?,
+        "scan_dependencies" => options.scan_dependencies = parse_bool_field(value),
+        "scan_secrets" => options.scan_secrets = parse_bool_field(value),
+        "enrich_rules" => options.enrich_rules = parse_bool_field(value),
+        "snippet_lines" => options.snippet_lines = Some(parse_snippet_lines(name, value)?),
+        "scanner_timeout_secs" => {
+            options.scanner_timeout_secs = Some(parse_count_field(name, value)? as u64)
+        }
+        "poll_interval_secs" => {
+            options.poll_interval_secs = Some(parse_count_field(name, value)? as u64)
+        }
+        "poll_timeout_secs" => {
+            options.poll_timeout_secs = Some(parse_count_field(name, value)? as u64)
+        }
+        "baseline" => options.baseline = Some(Baseline::parse(value)?),
+        "tenant" => options.tenant = Some(parse_tenant_field(name, value)?),
+        "project_name" => options.project_name = Some(parse_project_name(name, value)?),
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn read_text_field(
+    field: axum::extract::multipart::Field<'_>,
+    name: &str,
+) -> Result<String, AppError> {
+    field
+        .text()
+        .await
+        .map_err(|e| AppError::MissingField(format!("Failed to read field '{}': {}", name, e)))
+}
+
+/// Parses a list field given either as a JSON array or comma-separated.
+fn parse_list_field(value: &str) -> Vec<String> {
+    let value = value.trim();
+    if value.starts_with('[') {
+        if let Ok(items) = serde_json::from_str::<Vec<String>>(value) {
+            return items;
+        }
+    }
+
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Parses a list of file globs. Patterns are joined with commas for the
+/// scanner, so they may not contain one themselves.
+fn parse_glob_field(name: &str, value: &str) -> Result<Vec<String>, AppError> {
+    let globs = parse_list_field(value);
+    if globs
+        .iter()
+        .any(|glob| glob.contains(',') || glob.chars().any(char::is_control))
+    {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' patterns may not contain commas or control characters",
+            name
+        )));
+    }
+    Ok(globs)
+}
+
+fn parse_bool_field(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+/// Parses an RFC 3339 timestamp or Unix epoch seconds.
+fn parse_timestamp_field(name: &str, value: &str) -> Result<SystemTime, AppError> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Ok(UNIX_EPOCH + Duration::from_secs(seconds));
+    }
+
+    time::OffsetDateTime::parse(value, &time::format_description::well_known::Rfc3339)
+        .map(SystemTime::from)
+        .map_err(|e| {
+            AppError::InvalidRequest(format!(
+                "Field '{}' must be an RFC 3339 timestamp or Unix seconds: {}",
+                name, e
+            ))
+        })
+}
+
+/// Parses a branch or similar name passed on to SonarQube.
+fn parse_name_field(name: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    if value.is_empty() || value.starts_with('-') || value.chars().any(char::is_control) {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' must be a non-empty name not starting with '-'",
+            name
+        )));
+    }
+    Ok(value.to_string())
+}
+
+/// Parses a `project_name`: a stable label of up to 200 characters, which
+/// may contain spaces and slashes but no control characters.
+fn parse_project_name(name: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    if value.is_empty() || value.chars().count() > 200 || value.chars().any(char::is_control) {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' must be 1 to 200 characters without control characters",
+            name
+        )));
+    }
+    Ok(value.to_string())
+}
+
+/// Parses a full SHA-1 or SHA-256 commit hash.
+fn parse_commit_sha(name: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    if !matches!(value.len(), 40 | 64) || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' must be a full commit SHA",
+            name
+        )));
+    }
+    Ok(value.to_ascii_lowercase())
+}
+
+/// The value of `X-Sonar-Token`, which must not be blank when sent.
+fn parse_sonar_token(value: Option<&str>) -> Result<Option<String>, AppError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let token = value.trim();
+    if token.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Header 'X-Sonar-Token' must hold a SonarQube token".to_string(),
+        ));
+    }
+    Ok(Some(token.to_string()))
+}
+
+fn parse_tenant_field(name: &str, value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+    if !auth::is_valid_tenant(value) {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' must be up to 64 letters, digits, '-' or '_'",
+            name
+        )));
+    }
+    Ok(value.to_string())
+}
+
+fn parse_severity_field(name: &str, value: &str) -> Result<Severity, AppError> {
+    Severity::parse(value).ok_or_else(|| {
+        AppError::InvalidRequest(format!(
+            "Field '{}' must be one of INFO, MINOR, MAJOR, CRITICAL or BLOCKER",
+            name
+        ))
+    })
+}
+
+fn parse_count_field(name: &str, value: &str) -> Result<usize, AppError> {
+    value.trim().parse().map_err(|_| {
+        AppError::InvalidRequest(format!(
+            "Field '{}' must be a non-negative integer",
+            name
+        ))
+    })
+}
+
+fn parse_snippet_lines(name: &str, value: &str) -> Result<usize, AppError> {
+    let lines = parse_count_field(name, value)?;
+    if lines > snippets::MAX_CONTEXT_LINES {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' must be at most {}",
+            name,
+            snippets::MAX_CONTEXT_LINES
+        )));
+    }
+    Ok(lines)
+}
+
+fn sha256_file(path: &Path) -> Result<String, AppError> {
+    let mut file = fs::File::open(path)
+        .map_err(|e| AppError::InternalError(format!("Failed to open upload: {}", e)))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .map_err(|e| AppError::InternalError(format!("Failed to hash upload: {}", e)))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Extracts a zip, tar or tar.gz upload into `<temp_path>/project`, within
+/// the given [`ExtractionLimits`].
+fn extract_archive(
+    archive_path: &Path,
+    temp_path: &Path,
+    limits: ExtractionLimits,
+) -> Result<PathBuf, AppError> {
+    let mut budget = ExtractionBudget::new(limits, archive_path);
+
+    match archive::detect(archive_path)? {
+        ArchiveFormat::Zip => unzip_file(archive_path, temp_path, &mut budget),
+        format => {
+            let extract_path = temp_path.join("project");
+            fs::create_dir_all(&extract_path).map_err(|e| {
+                AppError::ZipError(format!("Failed to create extract directory: {}", e))
+            })?;
+            archive::extract_tar(
+                archive_path,
+                &extract_path,
+                format == ArchiveFormat::TarGz,
+                &mut budget,
+            )?;
+            info!("Extracted tar archive to {:?}", extract_path);
+            Ok(extract_path)
+        }
+    }
+}
+
+fn unzip_file(
+    zip_path: &Path,
+    temp_path: &Path,
+    budget: &mut ExtractionBudget,
+) -> Result<PathBuf, AppError> {
     let file = fs::File::open(zip_path)
         .map_err(|e| AppError::ZipError(format!("Failed to open zip file: {}", e)))?;
 
@@ -256,6 +2758,7 @@ fn unzip_file(zip_path: &PathBuf, temp_path: &PathBuf) -> Result<PathBuf, AppErr
         .map_err(|e| AppError::ZipError(format!("Failed to create extract directory: {}", e)))?;
 
     for i in 0..archive.len() {
+        budget.entry()?;
         let mut file = archive
             .by_index(i)
             .map_err(|e| AppError::ZipError(format!("Failed to access zip entry: {}", e)))?;
@@ -279,9 +2782,13 @@ fn unzip_file(zip_path: &PathBuf, temp_path: &PathBuf) -> Result<PathBuf, AppErr
             }
             let mut outfile = fs::File::create(&outpath)
                 .map_err(|e| AppError::ZipError(format!("Failed to create output file: {}", e)))?;
-            std::io::copy(&mut file, &mut outfile).map_err(|e| {
-                AppError::ZipError(format!("Failed to extract file contents: {}", e))
-            })?;
+            let name = file.name().to_string();
+            budget.copy(&name, &mut file, &mut outfile)?;
+
+            // Keep the archived mtime so modified_since has something to compare
+            if let Ok(modified) = file.last_modified().to_time() {
+                let _ = outfile.set_modified(SystemTime::from(modified));
+            }
         }
 
         // Set permissions on Unix
@@ -298,206 +2805,3 @@ fn unzip_file(zip_path: &PathBuf, temp_path: &PathBuf) -> Result<PathBuf, AppErr
     info!("Extracted project to {:?}", extract_path);
     Ok(extract_path)
 }
-
-fn run_sonar_scanner(
-    project_dir: &PathBuf,
-    job_id: &str,
-    sonarqube_url: &str,
-    sonarqube_token: &str,
-    sonarqube_password: &str,
-    use_token: bool,
-) -> Result<(), AppError> {
-    info!("Running sonar-scanner for job: {}", job_id);
-
-    let mut command = Command::new("sonar-scanner");
-    command
-        .arg(format!("-Dsonar.projectKey={}", job_id))
-        .arg(format!("-Dsonar.host.url={}", sonarqube_url))
-        .arg("-Dsonar.sources=.");
-
-    if use_token {
-        command.arg(format!("-Dsonar.login={}", sonarqube_token));
-    } else {
-        command
-            .arg("-Dsonar.login=admin")
-            .arg(format!("-Dsonar.password={}", sonarqube_password));
-    }
-
-    let output = command
-        .current_dir(project_dir)
-        .output()
-        .map_err(|e| AppError::ScannerError(format!("Failed to execute sonar-scanner: {}", e)))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        error!("Scanner stderr: {}", stderr);
-        error!("Scanner stdout: {}", stdout);
-        return Err(AppError::ScannerError(format!(
-            "Scanner exited with status: {}. Stderr: {}",
-            output.status, stderr
-        )));
-    }
-
-    info!("Scanner completed successfully");
-    Ok(())
-}
-
-async fn poll_for_completion(
-    job_id: &str,
-    sonarqube_url: &str,
-    sonarqube_token: &str,
-    sonarqube_password: &str,
-    use_token: bool,
-) -> Result<(), AppError> {
-    info!("Polling for task completion for job: {}", job_id);
-
-    let client = reqwest::Client::new();
-    let poll_url = format!("{}/api/ce/activity", sonarqube_url);
-    let max_attempts = 60; // 5 minutes max (60 * 5 seconds)
-    let poll_interval = Duration::from_secs(5);
-
-    for attempt in 1..=max_attempts {
-        tokio::time::sleep(poll_interval).await;
-
-        let mut request = client
-            .get(&poll_url)
-            .query(&[("component", job_id)]);
-
-        if use_token {
-            request = request.basic_auth(sonarqube_token, Some(""));
-        } else {
-            request = request.basic_auth("admin", Some(sonarqube_password));
-        }
-
-        let response = request
-            .send()
-            .await
-            .map_err(|e| AppError::ApiError(format!("Failed to poll task status: {}", e)))?;
-
-        if !response.status().is_success() {
-            warn!("Poll attempt {} failed with status: {}", attempt, response.status());
-            continue;
-        }
-
-        let ce_response: ComputeEngineResponse = response
-            .json()
-            .await
-            .map_err(|e| AppError::ApiError(format!("Failed to parse CE response: {}", e)))?;
-
-        if let Some(task) = ce_response.tasks.first() {
-            info!("Task status: {}", task.status);
-            match task.status.as_str() {
-                "SUCCESS" => {
-                    info!("Task completed successfully");
-                    return Ok(());
-                }
-                "FAILED" => {
-                    return Err(AppError::ApiError(
-                        "SonarQube analysis task failed".to_string(),
-                    ));
-                }
-                "CANCELED" => {
-                    return Err(AppError::ApiError(
-                        "SonarQube analysis task was canceled".to_string(),
-                    ));
-                }
-                _ => {
-                    // Still processing
-                    info!("Task still processing (attempt {}/{})", attempt, max_attempts);
-                }
-            }
-        }
-    }
-
-    Err(AppError::ApiError(
-        "Task polling timeout - analysis took too long".to_string(),
-    ))
-}
-
-async fn fetch_vulnerabilities(
-    job_id: &str,
-    sonarqube_url: &str,
-    sonarqube_token: &str,
-    sonarqube_password: &str,
-    use_token: bool,
-) -> Result<Vec<SonarIssue>, AppError> {
-    info!("Fetching vulnerabilities for job: {}", job_id);
-
-    let client = reqwest::Client::new();
-    let issues_url = format!("{}/api/issues/search", sonarqube_url);
-
-    let mut request = client.get(&issues_url).query(&[
-        ("componentKeys", job_id),
-        ("types", "VULNERABILITY"),
-        ("ps", "500"), // Page size
-    ]);
-
-    if use_token {
-        request = request.basic_auth(sonarqube_token, Some(""));
-    } else {
-        request = request.basic_auth("admin", Some(sonarqube_password));
-    }
-
-    let response = request
-        .send()
-        .await
-        .map_err(|e| AppError::ApiError(format!("Failed to fetch issues: {}", e)))?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(AppError::ApiError(format!(
-            "Failed to fetch issues. Status: {}, Body: {}",
-            status, body
-        )));
-    }
-
-    let issues_response: SonarIssuesResponse = response
-        .json()
-        .await
-        .map_err(|e| AppError::ApiError(format!("Failed to parse issues response: {}", e)))?;
-
-    info!("Found {} issues", issues_response.issues.len());
-    Ok(issues_response.issues)
-}
-
-// Add uuid dependency
-mod uuid {
-    use std::fmt;
-
-    pub struct Uuid([u8; 16]);
-
-    impl Uuid {
-        pub fn new_v4() -> Self {
-            use std::time::{SystemTime, UNIX_EPOCH};
-            let nanos = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_nanos();
-            let mut bytes = [0u8; 16];
-            bytes[0..8].copy_from_slice(&nanos.to_le_bytes()[0..8]);
-            bytes[8..16].copy_from_slice(&nanos.to_le_bytes()[0..8]);
-            
-            // Set version and variant bits for UUID v4
-            bytes[6] = (bytes[6] & 0x0f) | 0x40;
-            bytes[8] = (bytes[8] & 0x3f) | 0x80;
-            
-            Uuid(bytes)
-        }
-    }
-
-    impl fmt::Display for Uuid {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(
-                f,
-                "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
-                self.0[0], self.0[1], self.0[2], self.0[3],
-                self.0[4], self.0[5],
-                self.0[6], self.0[7],
-                self.0[8], self.0[9],
-                self.0[10], self.0[11], self.0[12], self.0[13], self.0[14], self.0[15]
-            )
-        }
-    }
-}
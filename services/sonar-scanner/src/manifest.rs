@@ -0,0 +1,160 @@
+use crate::backends::Engine;
+use crate::git_source::GitRevision;
+use crate::scanner_validation::{parse_properties, PROJECT_PROPERTIES_FILE};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Properties never written to the manifest.
+const CREDENTIAL_KEYS: &[&str] = &["sonar.login", "sonar.password", "sonar.token"];
+
+/// Everything needed to reproduce a scan: inputs, tool versions and the
+/// effective scanner properties (credentials excluded).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanManifest {
+    pub project_key: String,
+    /// SHA-256 of the uploaded archive; absent for git sources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_sha256: Option<String>,
+    /// The repository revision scanned, for git sources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitRevision>,
+    /// Analyzers that ran; SonarQube alone for jobs recorded before engines
+    /// could be chosen.
+    #[serde(default = "default_engines")]
+    pub engines: Vec<Engine>,
+    /// Quality profile the project was provisioned with, when requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality_profile: Option<String>,
+    pub scanner_version: Option<String>,
+    pub sonarqube_version: Option<String>,
+    pub language: Option<String>,
+    pub properties: BTreeMap<String, String>,
+    pub exclusions: Vec<String>,
+}
+
+impl ScanManifest {
+    /// The name `GET /jobs?project=` matches: `sonar.projectName`, or the
+    /// repository URL of a git scan.
+    pub fn project_name(&self) -> Option<&str> {
+        self.properties
+            .get("sonar.projectName")
+            .map(String::as_str)
+            .or_else(|| self.git.as_ref().map(|git| git.url.as_str()))
+    }
+}
+
+fn default_engines() -> Vec<Engine> {
+    vec![Engine::SonarQube]
+}
+
+/// Merges the project's `sonar-project.properties` with the command-line
+/// properties, which take precedence just as they do in the scanner.
+pub fn effective_properties(
+    project_dir: &Path,
+    cli_properties: &[(String, String)],
+) -> BTreeMap<String, String> {
+    let mut properties: BTreeMap<String, String> =
+        fs::read_to_string(project_dir.join(PROJECT_PROPERTIES_FILE))
+            .map(|text| parse_properties(&text).into_iter().collect())
+            .unwrap_or_default();
+
+    properties.extend(cli_properties.iter().cloned());
+    properties.retain(|key, _| !CREDENTIAL_KEYS.contains(&key.as_str()));
+    properties
+}
+
+/// The `sonar.exclusions` patterns from an effective property set.
+pub fn exclusions(properties: &BTreeMap<String, String>) -> Vec<String> {
+    properties
+        .get("sonar.exclusions")
+        .map(|value| {
+            value
+                .split(',')
+                .map(|pattern| pattern.trim().to_string())
+                .filter(|pattern| !pattern.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(properties: BTreeMap<String, String>) -> ScanManifest {
+        ScanManifest {
+            project_key: "job_123".to_string(),
+            input_sha256: Some("ab".repeat(32)),
+            git: None,
+            engines: default_engines(),
+            quality_profile: None,
+            scanner_version: Some("5.0.1".to_string()),
+            sonarqube_version: Some("10.4".to_string()),
+            language: Some("java".to_string()),
+            exclusions: exclusions(&properties),
+            properties,
+        }
+    }
+
+    #[test]
+    fn records_key_fields_without_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(PROJECT_PROPERTIES_FILE),
+            "sonar.projectName=Shop\nsonar.login=admin\nsonar.exclusions=docs/**\n",
+        )
+        .unwrap();
+        let cli = [
+            ("sonar.projectKey".to_string(), "job_123".to_string()),
+            ("sonar.token".to_string(), "squ_secret".to_string()),
+            ("sonar.password".to_string(), "hunter2".to_string()),
+            (
+                "sonar.exclusions".to_string(),
+                "**/vendor/**, **/*.min.js".to_string(),
+            ),
+        ];
+
+        let manifest = manifest(effective_properties(dir.path(), &cli));
+        assert_eq!(manifest.project_name(), Some("Shop"));
+        assert_eq!(manifest.properties["sonar.projectKey"], "job_123");
+        // Command-line properties win over the project's file
+        assert_eq!(manifest.exclusions, ["**/vendor/**", "**/*.min.js"]);
+        assert_eq!(manifest.engines, [Engine::SonarQube]);
+
+        let json = serde_json::to_string(&manifest).unwrap();
+        for key in ["sonar.login", "sonar.password", "sonar.token"] {
+            assert!(!json.contains(key), "{} leaked into the manifest", key);
+        }
+        assert!(!json.contains("squ_secret"));
+        assert!(!json.contains("hunter2"));
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        for field in [
+            "project_key",
+            "input_sha256",
+            "engines",
+            "scanner_version",
+            "sonarqube_version",
+            "language",
+            "properties",
+            "exclusions",
+        ] {
+            assert!(value.get(field).is_some(), "{} missing", field);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_git_url_for_the_project_name() {
+        let mut manifest = manifest(BTreeMap::new());
+        manifest.input_sha256 = None;
+        manifest.git = Some(GitRevision {
+            url: "https://github.com/acme/shop.git".to_string(),
+            reference: "main".to_string(),
+            commit: "0123abc".to_string(),
+        });
+        assert_eq!(
+            manifest.project_name(),
+            Some("https://github.com/acme/shop.git")
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+/// Measures requested from `/api/measures/component`.
+pub const METRIC_KEYS: &str =
+    "ncloc,coverage,duplicated_lines_density,cognitive_complexity,security_rating";
+
+/// Overall code health of the scanned project, from
+/// `/api/measures/component`. Measures SonarQube did not compute are absent.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectMetrics {
+    /// Lines of code, without blank lines and comments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ncloc: Option<u64>,
+    /// Percentage of lines and conditions covered by tests, when a coverage
+    /// report was imported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<f64>,
+    /// Percentage of lines in duplicated blocks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicated_lines_density: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cognitive_complexity: Option<u64>,
+    /// `A` (no vulnerabilities) to `E` (at least one blocker).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security_rating: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ComponentMeasuresResponse {
+    component: Component,
+}
+
+#[derive(Debug, Deserialize)]
+struct Component {
+    #[serde(default)]
+    measures: Vec<Measure>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Measure {
+    metric: String,
+    value: Option<String>,
+}
+
+impl From<ComponentMeasuresResponse> for ProjectMetrics {
+    fn from(response: ComponentMeasuresResponse) -> Self {
+        let mut metrics = Self::default();
+        for measure in response.component.measures {
+            let Some(value) = measure.value else {
+                continue;
+            };
+            match measure.metric.as_str() {
+                "ncloc" => metrics.ncloc = value.parse().ok(),
+                "coverage" => metrics.coverage = value.parse().ok(),
+                "duplicated_lines_density" => metrics.duplicated_lines_density = value.parse().ok(),
+                "cognitive_complexity" => metrics.cognitive_complexity = value.parse().ok(),
+                "security_rating" => metrics.security_rating = rating_letter(&value),
+                _ => {}
+            }
+        }
+        metrics
+    }
+}
+
+/// SonarQube reports ratings as `1.0` to `5.0`; its UI shows them as letters.
+fn rating_letter(value: &str) -> Option<String> {
+    let letter = match value.parse::<f64>().ok()?.round() as u8 {
+        1 => "A",
+        2 => "B",
+        3 => "C",
+        4 => "D",
+        5 => "E",
+        _ => return None,
+    };
+    Some(letter.to_string())
+}
+
+/// Combines the metrics of a multi-profile scan. Every profile scans the
+/// same code, so size, coverage, duplication and complexity are taken from
+/// the first profile that has them; the worst security rating wins, since it
+/// depends on the profile's rules.
+pub fn combine(metrics: Vec<ProjectMetrics>) -> Option<ProjectMetrics> {
+    metrics.into_iter().reduce(|mut combined, metrics| {
+        combined.ncloc = combined.ncloc.or(metrics.ncloc);
+        combined.coverage = combined.coverage.or(metrics.coverage);
+        combined.duplicated_lines_density = combined
+            .duplicated_lines_density
+            .or(metrics.duplicated_lines_density);
+        combined.cognitive_complexity = combined
+            .cognitive_complexity
+            .or(metrics.cognitive_complexity);
+        if metrics.security_rating > combined.security_rating {
+            combined.security_rating = metrics.security_rating;
+        }
+        combined
+    })
+}
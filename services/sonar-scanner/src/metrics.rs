@@ -0,0 +1,154 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::{sync::Arc, time::Instant};
+
+/// Pipeline stages timed by `sonar_scan_stage_duration_seconds`.
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Extract,
+    Scanner,
+    CeWait,
+    Fetch,
+}
+
+impl Stage {
+    fn as_str(self) -> &'static str {
+        match self {
+            Stage::Extract => "extract",
+            Stage::Scanner => "scanner",
+            Stage::CeWait => "ce_wait",
+            Stage::Fetch => "fetch",
+        }
+    }
+}
+
+/// Prometheus metrics for the analysis pipeline, served on `GET /metrics`.
+pub struct Metrics {
+    registry: Registry,
+    scans_started: IntCounter,
+    scans_finished: IntCounterVec,
+    scan_duration: Histogram,
+    stage_duration: HistogramVec,
+    queued_scans: IntGauge,
+    upload_bytes: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let scans_started =
+            IntCounter::new("sonar_scans_started_total", "Analyses that started running")
+                .expect("valid metric");
+        let scans_finished = IntCounterVec::new(
+            Opts::new(
+                "sonar_scans_finished_total",
+                "Analyses that finished, by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("valid metric");
+        // 1s to ~68 minutes
+        let scan_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "sonar_scan_duration_seconds",
+                "Wall time of an analysis once it left the queue",
+            )
+            .buckets(exponential_buckets(1.0, 2.0, 13).expect("valid buckets")),
+        )
+        .expect("valid metric");
+        let stage_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "sonar_scan_stage_duration_seconds",
+                "Wall time of each pipeline stage",
+            )
+            .buckets(exponential_buckets(0.1, 2.0, 16).expect("valid buckets")),
+            &["stage"],
+        )
+        .expect("valid metric");
+        let queued_scans = IntGauge::new("sonar_scans_queued", "Analyses waiting for a scan slot")
+            .expect("valid metric");
+        // 1 KiB to 4 GiB
+        let upload_bytes = Histogram::with_opts(
+            HistogramOpts::new("sonar_upload_size_bytes", "Size of uploaded archives")
+                .buckets(exponential_buckets(1024.0, 4.0, 12).expect("valid buckets")),
+        )
+        .expect("valid metric");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(scans_started.clone()))
+            .and_then(|_| registry.register(Box::new(scans_finished.clone())))
+            .and_then(|_| registry.register(Box::new(scan_duration.clone())))
+            .and_then(|_| registry.register(Box::new(stage_duration.clone())))
+            .and_then(|_| registry.register(Box::new(queued_scans.clone())))
+            .and_then(|_| registry.register(Box::new(upload_bytes.clone())))
+            .expect("metric names are unique");
+
+        Self {
+            registry,
+            scans_started,
+            scans_finished,
+            scan_duration,
+            stage_duration,
+            queued_scans,
+            upload_bytes,
+        }
+    }
+
+    pub fn scan_queued(&self) {
+        self.queued_scans.inc();
+    }
+
+    /// A queued scan was canceled before it got a slot.
+    pub fn scan_dequeued(&self) {
+        self.queued_scans.dec();
+    }
+
+    /// A queued scan got its slot. The returned instant is passed back to
+    /// [`Metrics::scan_finished`].
+    pub fn scan_started(&self) -> Instant {
+        self.queued_scans.dec();
+        self.scans_started.inc();
+        Instant::now()
+    }
+
+    pub fn scan_finished(&self, started: Instant, succeeded: bool) {
+        let outcome = if succeeded { "succeeded" } else { "failed" };
+        self.scans_finished.with_label_values(&[outcome]).inc();
+        self.scan_duration.observe(started.elapsed().as_secs_f64());
+    }
+
+    pub fn observe_stage(&self, stage: Stage, started: Instant) {
+        self.stage_duration
+            .with_label_values(&[stage.as_str()])
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    pub fn observe_upload(&self, bytes: u64) {
+        self.upload_bytes.observe(bytes as f64);
+    }
+
+    fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+/// Prometheus text exposition of every metric.
+pub async fn metrics_handler(State(metrics): State<Arc<Metrics>>) -> Response {
+    match metrics.render() {
+        Ok(body) => (
+            [(header::CONTENT_TYPE, TextEncoder::new().format_type())],
+            body,
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
@@ -0,0 +1,237 @@
+use crate::severity::{Severity, SeverityCounts};
+use crate::SonarIssue;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Build manifests that mark a top-level directory as a module.
+const MODULE_MARKERS: &[&str] = &[
+    "package.json",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "Cargo.toml",
+    "go.mod",
+    "pyproject.toml",
+    "setup.py",
+    "requirements.txt",
+    "composer.json",
+    "Gemfile",
+];
+
+/// Bucket for issues whose component is outside every module.
+pub const UNASSIGNED_MODULE: &str = "(root)";
+
+/// A module name and its path relative to the extracted project directory.
+#[derive(Debug, Clone)]
+pub struct ModuleSpec {
+    pub name: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleSummary {
+    pub name: String,
+    pub counts: SeverityCounts,
+    pub worst_severity: Option<Severity>,
+}
+
+/// Resolves the modules to group by: the declared list if given, otherwise
+/// every top-level directory containing a build manifest.
+///
+/// Archives that wrap everything in a single folder (`repo-main/...`) are
+/// handled by treating that folder as the source root.
+pub fn resolve_modules(project_dir: &Path, declared: Option<&[String]>) -> Vec<ModuleSpec> {
+    let prefix = wrapper_prefix(project_dir);
+    let source_root = project_dir.join(&prefix);
+
+    let names: Vec<String> = match declared {
+        Some(declared) => declared
+            .iter()
+            .map(|name| name.trim().trim_matches('/').to_string())
+            .filter(|name| !name.is_empty())
+            .collect(),
+        None => detect_module_dirs(&source_root),
+    };
+
+    names
+        .into_iter()
+        .map(|name| ModuleSpec {
+            path: format!("{}{}", prefix, name),
+            name,
+        })
+        .collect()
+}
+
+/// Groups issues by the module their component falls under. Modules with no
+/// issues are still listed; issues outside every module are reported under
+/// [`UNASSIGNED_MODULE`]. Issues suppressed by the baseline are not counted,
+/// as in `total_count`.
+pub fn group_issues(issues: &[SonarIssue], modules: &[ModuleSpec]) -> Vec<ModuleSummary> {
+    let mut summaries: Vec<ModuleSummary> = modules
+        .iter()
+        .map(|module| ModuleSummary {
+            name: module.name.clone(),
+            counts: SeverityCounts::default(),
+            worst_severity: None,
+        })
+        .collect();
+    let mut unassigned = ModuleSummary {
+        name: UNASSIGNED_MODULE.to_string(),
+        counts: SeverityCounts::default(),
+        worst_severity: None,
+    };
+    let mut unassigned_count = 0;
+
+    for issue in issues.iter().filter(|issue| !issue.suppressed) {
+        let path = issue.path();
+
+        // Longest match wins so nested modules take precedence over parents.
+        let index = modules
+            .iter()
+            .enumerate()
+            .filter(|(_, module)| {
+                path == module.path || path.starts_with(&format!("{}/", module.path))
+            })
+            .max_by_key(|(_, module)| module.path.len())
+            .map(|(index, _)| index);
+
+        let summary = match index {
+            Some(index) => &mut summaries[index],
+            None => {
+                unassigned_count += 1;
+                &mut unassigned
+            }
+        };
+
+        if let Some(severity) = Severity::parse(&issue.severity) {
+            summary.counts.record(severity);
+            summary.worst_severity = summary.worst_severity.max(Some(severity));
+        }
+    }
+
+    if unassigned_count > 0 {
+        summaries.push(unassigned);
+    }
+    summaries
+}
+
+/// Returns `"<dir>/"` when the project consists of a single top-level folder.
+fn wrapper_prefix(project_dir: &Path) -> String {
+    let entries: Vec<_> = match fs::read_dir(project_dir) {
+        Ok(entries) => entries.filter_map(Result::ok).collect(),
+        Err(_) => return String::new(),
+    };
+
+    match entries.as_slice() {
+        [entry] if entry.path().is_dir() => format!("{}/", entry.file_name().to_string_lossy()),
+        _ => String::new(),
+    }
+}
+
+fn detect_module_dirs(source_root: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(source_root) else {
+        return Vec::new();
+    };
+
+    let mut modules: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter(|entry| {
+            MODULE_MARKERS
+                .iter()
+                .any(|marker| entry.path().join(marker).is_file())
+        })
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+
+    modules.sort();
+    modules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(name: &str, path: &str) -> ModuleSpec {
+        ModuleSpec {
+            name: name.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn groups_by_longest_module_prefix() {
+        let modules = [
+            module("web", "web"),
+            module("web-admin", "web/admin"),
+            module("api", "api"),
+        ];
+        let issues = [
+            SonarIssue::for_test("1", "js:S1", "CRITICAL", "web/src/app.js", 3),
+            SonarIssue::for_test("2", "js:S2", "MINOR", "web/src/util.js", 8),
+            SonarIssue::for_test("3", "js:S3", "BLOCKER", "web/admin/panel.js", 1),
+            SonarIssue::for_test("4", "java:S4", "MAJOR", "api/src/Main.java", 12),
+            SonarIssue::for_test("5", "java:S5", "MAJOR", "api/src/Db.java", 40),
+            // Shares a prefix with `api` but is not inside it
+            SonarIssue::for_test("6", "py:S6", "INFO", "api-docs/gen.py", 2),
+        ];
+
+        let summaries = group_issues(&issues, &modules);
+        let names: Vec<&str> = summaries.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["web", "web-admin", "api", UNASSIGNED_MODULE]);
+
+        let web = &summaries[0];
+        assert_eq!(web.counts.critical, 1);
+        assert_eq!(web.counts.minor, 1);
+        assert_eq!(web.counts.blocker, 0);
+        assert_eq!(web.worst_severity, Some(Severity::Critical));
+
+        let admin = &summaries[1];
+        assert_eq!(admin.counts.blocker, 1);
+        assert_eq!(admin.worst_severity, Some(Severity::Blocker));
+
+        let api = &summaries[2];
+        assert_eq!(api.counts.major, 2);
+        assert_eq!(api.worst_severity, Some(Severity::Major));
+
+        let unassigned = &summaries[3];
+        assert_eq!(unassigned.counts.info, 1);
+    }
+
+    #[test]
+    fn lists_modules_without_issues() {
+        let modules = [module("web", "web"), module("api", "api")];
+        let issues = [SonarIssue::for_test("1", "js:S1", "MAJOR", "web/a.js", 1)];
+
+        let summaries = group_issues(&issues, &modules);
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[1].name, "api");
+        assert_eq!(summaries[1].counts, SeverityCounts::default());
+        assert_eq!(summaries[1].worst_severity, None);
+    }
+
+    #[test]
+    fn skips_issues_suppressed_by_the_baseline() {
+        let modules = [module("web", "web")];
+        let mut known = SonarIssue::for_test("1", "js:S1", "BLOCKER", "web/a.js", 1);
+        known.suppressed = true;
+        let issues = [
+            known,
+            SonarIssue::for_test("2", "js:S2", "MINOR", "web/b.js", 4),
+            SonarIssue::for_test("3", "js:S3", "MAJOR", "docs/c.js", 2),
+        ];
+        let mut unassigned = SonarIssue::for_test("4", "js:S4", "INFO", "docs/d.js", 1);
+        unassigned.suppressed = true;
+
+        let summaries = group_issues(&issues, &modules);
+        assert_eq!(summaries[0].counts.blocker, 0);
+        assert_eq!(summaries[0].counts.minor, 1);
+        assert_eq!(summaries[0].worst_severity, Some(Severity::Minor));
+        assert_eq!(summaries[1].counts.major, 1);
+
+        // An unassigned bucket holding only suppressed issues is left out
+        let summaries = group_issues(&[unassigned], &modules);
+        assert_eq!(summaries.len(), 1);
+    }
+}
@@ -0,0 +1,193 @@
+//! Notification sinks told about every finished job: chat webhooks, email
+//! and Jira. Each implements [`Notifier`] and decides for itself whether a job
+//! concerns it; [`notifiers`] builds the configured ones.
+
+use crate::config::Config;
+use crate::email::EmailNotifier;
+use crate::jira::JiraFiler;
+use crate::jobs::JobStore;
+use crate::proxy::OutboundProxy;
+use crate::review_summary;
+use crate::severity::Severity;
+use crate::slack::SlackNotifier;
+use crate::teams::TeamsNotifier;
+use crate::{AnalyzeOptions, AnalyzeResponse, AppError};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// A finished job, as notification sinks see it.
+pub struct Notice<'a> {
+    pub job_id: &'a str,
+    pub options: &'a AnalyzeOptions,
+    pub outcome: Result<&'a AnalyzeResponse, &'a AppError>,
+    /// How long the scan ran, when known.
+    pub duration: Option<Duration>,
+}
+
+impl Notice<'_> {
+    /// The project as people know it: `project_name`, else the name in the
+    /// scan's manifest, else the job ID.
+    pub fn project(&self) -> &str {
+        self.options
+            .project_name
+            .as_deref()
+            .or_else(|| self.outcome.ok().and_then(|r| r.manifest.project_name()))
+            .unwrap_or(self.job_id)
+    }
+
+    /// Whether the job passes by `fail_on`, its verdict (`Passed`,
+    /// `Failed`, `Canceled` or `Scan failed`) and the reason.
+    pub fn verdict(&self, fail_on: Severity) -> (bool, &'static str, String) {
+        match self.outcome {
+            Ok(response) => match review_summary::verdict(response, fail_on) {
+                (true, reason) => (true, "Passed", reason),
+                (false, reason) => (false, "Failed", reason),
+            },
+            Err(e @ AppError::Canceled(_)) => {
+                (false, "Canceled", review_summary::one_line(&e.parts().2))
+            }
+            Err(e) => {
+                let (_, code, message) = e.parts();
+                let reason = format!("{}: {}", code, review_summary::one_line(&message));
+                (false, "Scan failed", reason)
+            }
+        }
+    }
+
+    /// How long the scan ran, for people to read.
+    pub fn duration_text(&self) -> String {
+        if self.outcome.is_ok_and(|response| response.cached) {
+            return "Cached result".to_string();
+        }
+        let Some(duration) = self.duration else {
+            return "Unknown".to_string();
+        };
+        let secs = duration.as_secs();
+        match (secs / 3600, secs / 60 % 60, secs % 60) {
+            (0, 0, s) => format!("{}s", s),
+            (0, m, s) => format!("{}m {}s", m, s),
+            (h, m, _) => format!("{}h {}m", h, m),
+        }
+    }
+}
+
+/// A sink told about every finished job.
+pub trait Notifier: Send + Sync {
+    /// Sends `notice` in a task tracked by `tasks`, if it concerns this
+    /// sink. Failures are logged, never returned: the job has already
+    /// finished and its result stays available from the API.
+    fn notify(&self, tasks: &TaskTracker, notice: &Notice<'_>);
+}
+
+/// The configured sinks.
+pub fn notifiers(
+    config: &Config,
+    proxy: &OutboundProxy,
+    jobs: &JobStore,
+) -> Result<Vec<Box<dyn Notifier>>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = vec![
+        Box::new(SlackNotifier::new(&config.slack, proxy)),
+        Box::new(TeamsNotifier::new(&config.teams, proxy)),
+    ];
+    if let Some(email) = EmailNotifier::new(&config.email)? {
+        notifiers.push(Box::new(email));
+    }
+    if let Some(jira) = JiraFiler::new(&config.jira, proxy, jobs)? {
+        notifiers.push(Box::new(jira));
+    }
+    Ok(notifiers)
+}
+
+/// Parses a request's chat webhook field `name`; its host is checked with
+/// [`check_allowed_host`] once the config is at hand.
+pub fn parse_webhook_url(name: &str, value: &str) -> Result<reqwest::Url, AppError> {
+    let url = reqwest::Url::parse(value.trim())
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid {}: {}", name, e)))?;
+    if url.scheme() != "https" {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' must be an https URL",
+            name
+        )));
+    }
+    Ok(url)
+}
+
+/// Rejects a request's URL in field `name` on a host that is not one of
+/// `allowed_hosts` or below one, so callers cannot have the service reach
+/// arbitrary hosts.
+pub fn check_allowed_host(
+    name: &str,
+    url: &reqwest::Url,
+    allowed_hosts: &[String],
+) -> Result<(), AppError> {
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    let allowed = allowed_hosts.iter().any(|allowed| {
+        let allowed = allowed.trim_start_matches('.').to_ascii_lowercase();
+        host == allowed || host.ends_with(&format!(".{}", allowed))
+    });
+    if allowed {
+        Ok(())
+    } else {
+        Err(AppError::InvalidRequest(format!(
+            "Field '{}' must point at one of: {}",
+            name,
+            allowed_hosts.join(", ")
+        )))
+    }
+}
+
+/// The webhook a job is posted to: the request's own, else the one of the
+/// API key it was submitted with, else the default.
+pub fn webhook_for(
+    requested: Option<&reqwest::Url>,
+    api_key: Option<&str>,
+    api_key_webhooks: &BTreeMap<String, String>,
+    default: Option<&str>,
+) -> Option<String> {
+    requested
+        .map(|url| url.to_string())
+        .or_else(|| {
+            api_key
+                .and_then(|fingerprint| api_key_webhooks.get(fingerprint))
+                .cloned()
+        })
+        .or_else(|| default.map(str::to_string))
+}
+
+/// POSTs `message` to a chat webhook in a task tracked by `tasks`, logging
+/// the outcome under the name of the `sink`.
+pub fn post_webhook(
+    tasks: &TaskTracker,
+    http: &reqwest::Client,
+    webhook_url: String,
+    message: serde_json::Value,
+    job_id: &str,
+    sink: &'static str,
+) {
+    let http = http.clone();
+    let job_id = job_id.to_string();
+    tasks.spawn(async move {
+        let result = match http.post(&webhook_url).json(&message).send().await {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => Err(format!("HTTP {}", response.status())),
+            Err(e) => Err(e.to_string()),
+        };
+        // The webhook URL is a secret, so it is not logged
+        match result {
+            Ok(()) => info!("Posted job {} to {}", job_id, sink),
+            Err(e) => warn!("Failed to post job {} to {}: {}", job_id, sink, e),
+        }
+    });
+}
+
+/// URL of job `job_id`'s HTML report under `public_url`.
+pub fn report_url(public_url: &str, job_id: &str) -> String {
+    format!(
+        "{}/jobs/{}/report.html",
+        public_url.trim_end_matches('/'),
+        job_id
+    )
+}
@@ -0,0 +1,122 @@
+//! The OpenAPI description of the HTTP API and a Swagger UI page to browse
+//! it. The document is maintained by hand in `openapi.json`; keep it in step
+//! with the handlers, their multipart fields and the response types. A test
+//! checks that it documents exactly the routes `main.rs` serves.
+
+use axum::{
+    http::header,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::sync::OnceLock;
+
+const SPEC: &str = include_str!("openapi.json");
+
+/// Swagger UI from a CDN, pointed at `/openapi.json`.
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>SonarQube Scanner Service API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// `GET /openapi.json` and `GET /docs`. Merged outside the response
+/// envelope, which would otherwise wrap the document and the page.
+pub fn router() -> Router {
+    Router::new()
+        .route("/openapi.json", get(spec_handler))
+        .route("/docs", get(docs_handler))
+}
+
+async fn spec_handler() -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], spec()).into_response()
+}
+
+async fn docs_handler() -> Html<&'static str> {
+    Html(SWAGGER_UI)
+}
+
+/// The document with `info.version` set to the crate version.
+fn spec() -> &'static str {
+    static SPEC_JSON: OnceLock<String> = OnceLock::new();
+    SPEC_JSON.get_or_init(|| {
+        let mut spec: serde_json::Value =
+            serde_json::from_str(SPEC).expect("openapi.json is valid JSON");
+        spec["info"]["version"] = env!("CARGO_PKG_VERSION").into();
+        spec.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Routed but deliberately left out of the document.
+    const UNDOCUMENTED: &[&str] = &["/"];
+
+    /// Paths routed in `main.rs`, in OpenAPI's `{param}` syntax.
+    fn routed_paths() -> BTreeSet<String> {
+        let source = include_str!("main.rs");
+        source
+            .split(".route(")
+            .skip(1)
+            .filter_map(|call| {
+                let path = call.trim_start().strip_prefix('"')?;
+                let path = &path[..path.find('"')?];
+                let segments: Vec<String> = path
+                    .split('/')
+                    .map(|segment| match segment.strip_prefix(':') {
+                        Some(param) => format!("{{{}}}", param),
+                        None => segment.to_string(),
+                    })
+                    .collect();
+                Some(segments.join("/"))
+            })
+            .filter(|path| !UNDOCUMENTED.contains(&path.as_str()))
+            .collect()
+    }
+
+    fn documented_paths() -> BTreeSet<String> {
+        let spec: serde_json::Value = serde_json::from_str(SPEC).unwrap();
+        spec["paths"].as_object().unwrap().keys().cloned().collect()
+    }
+
+    #[test]
+    fn documents_every_route() {
+        let routed = routed_paths();
+        assert!(routed.contains("/analyze"));
+        assert!(routed.contains("/jobs/{id}"));
+
+        let documented = documented_paths();
+        let missing: Vec<&String> = routed.difference(&documented).collect();
+        assert!(
+            missing.is_empty(),
+            "Routes missing from openapi.json: {:?}",
+            missing
+        );
+        let stale: Vec<&String> = documented.difference(&routed).collect();
+        assert!(
+            stale.is_empty(),
+            "openapi.json documents unrouted paths: {:?}",
+            stale
+        );
+    }
+
+    #[test]
+    fn sets_the_crate_version() {
+        let spec: serde_json::Value = serde_json::from_str(spec()).unwrap();
+        assert_eq!(spec["info"]["version"], env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -0,0 +1,287 @@
+//! Verdicts on scan results. A request may allow at most so many issues of a
+//! severity, such as `max_critical=0&max_major=5`, and may name a policy
+//! from the config, whose rules limit the issues matching a severity, rule
+//! keys, file globs and whether they are new since the request's baseline.
+//! A result over any limit carries `verdict: "fail"` and, when the request
+//! waited for it, is answered with `422 Unprocessable Entity`, so CI
+//! pipelines can break the build on the status code alone.
+
+use crate::config::{IssueAge, PolicyConfig};
+use crate::job_list::severity_counts;
+use crate::severity::Severity;
+use crate::{AnalyzeOptions, AnalyzeResponse, AppError, SonarIssue};
+use anyhow::{bail, Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Fields setting a threshold, and the severity each one limits.
+pub const THRESHOLD_FIELDS: [(&str, Severity); 5] = [
+    ("max_blocker", Severity::Blocker),
+    ("max_critical", Severity::Critical),
+    ("max_major", Severity::Major),
+    ("max_minor", Severity::Minor),
+    ("max_info", Severity::Info),
+];
+
+/// Most issues allowed per severity; severities without a threshold are
+/// not limited.
+#[derive(Debug, Clone, Default)]
+pub struct SeverityPolicy {
+    thresholds: Vec<(Severity, usize)>,
+}
+
+impl SeverityPolicy {
+    /// Sets the threshold of threshold field `name`.
+    pub fn set(&mut self, name: &str, max: usize) {
+        let Some((_, severity)) = THRESHOLD_FIELDS.iter().find(|(field, _)| *field == name) else {
+            return;
+        };
+        self.thresholds.retain(|(limited, _)| limited != severity);
+        self.thresholds.push((*severity, max));
+        self.thresholds
+            .sort_by_key(|(severity, _)| std::cmp::Reverse(*severity));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.thresholds.is_empty()
+    }
+
+    /// The severities of `response` over their threshold.
+    fn violations(&self, response: &AnalyzeResponse) -> Vec<PolicyViolation> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let counts = severity_counts(response);
+        self.thresholds
+            .iter()
+            .filter(|(severity, max)| counts.count(*severity) > *max)
+            .map(|&(severity, max)| PolicyViolation {
+                severity,
+                count: counts.count(severity),
+                max,
+            })
+            .collect()
+    }
+}
+
+/// The policies of the config, with their globs compiled.
+pub struct PolicySet {
+    policies: BTreeMap<String, Policy>,
+    /// Policy applied when a request names none.
+    default: Option<String>,
+}
+
+struct Policy {
+    description: Option<String>,
+    rules: Vec<PolicyRule>,
+}
+
+struct PolicyRule {
+    name: String,
+    min_severity: Option<Severity>,
+    rule_keys: Vec<String>,
+    /// `None` matches every file.
+    paths: Option<GlobSet>,
+    issues: IssueAge,
+    max: usize,
+}
+
+impl PolicySet {
+    /// Compiles `policies`; `default` must name one of them.
+    pub fn new(policies: &BTreeMap<String, PolicyConfig>, default: Option<&str>) -> Result<Self> {
+        let policies = policies
+            .iter()
+            .map(|(name, config)| Ok((name.clone(), Policy::new(name, config)?)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        if let Some(name) = default {
+            if !policies.contains_key(name) {
+                bail!("DEFAULT_POLICY names no configured policy: {:?}", name);
+            }
+        }
+        Ok(Self {
+            policies,
+            default: default.map(str::to_string),
+        })
+    }
+
+    /// Rejects a request's `policy` naming no configured policy.
+    pub fn check(&self, name: &str) -> Result<(), AppError> {
+        if self.policies.contains_key(name) {
+            return Ok(());
+        }
+        let names: Vec<&str> = self.policies.keys().map(String::as_str).collect();
+        Err(AppError::InvalidRequest(if names.is_empty() {
+            format!("Unknown policy '{}'; none is configured", name)
+        } else {
+            format!(
+                "Unknown policy '{}' (expected one of {})",
+                name,
+                names.join(", ")
+            )
+        }))
+    }
+
+    /// Sets `response`'s verdict, threshold violations and policy outcome
+    /// by `options`, replacing those of the request the result was first
+    /// computed for.
+    pub fn apply(&self, options: &AnalyzeOptions, response: &mut AnalyzeResponse) {
+        let named = options
+            .policy
+            .as_deref()
+            .or(self.default.as_deref())
+            .and_then(|name| self.policies.get_key_value(name));
+        response.policy_violations = options.thresholds.violations(response);
+        response.policy = named.map(|(name, policy)| policy.evaluate(name, response));
+
+        if options.thresholds.is_empty() && response.policy.is_none() {
+            response.verdict = None;
+            return;
+        }
+        let failed = !response.policy_violations.is_empty()
+            || response
+                .policy
+                .as_ref()
+                .is_some_and(|report| !report.violations.is_empty());
+        response.verdict = Some(if failed { Verdict::Fail } else { Verdict::Pass });
+    }
+}
+
+impl Policy {
+    fn new(name: &str, config: &PolicyConfig) -> Result<Self> {
+        if config.rules.is_empty() {
+            bail!("Policy {:?} has no rules", name);
+        }
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                if rule.name.trim().is_empty() {
+                    bail!("Policy {:?} has a rule without a name", name);
+                }
+                let paths = if rule.paths.is_empty() {
+                    None
+                } else {
+                    let mut builder = GlobSetBuilder::new();
+                    for glob in &rule.paths {
+                        // `*` stays within a directory, like SonarQube's globs
+                        let glob = GlobBuilder::new(glob)
+                            .literal_separator(true)
+                            .build()
+                            .with_context(|| {
+                                format!("Invalid glob in policy {:?}, rule {:?}", name, rule.name)
+                            })?;
+                        builder.add(glob);
+                    }
+                    Some(builder.build()?)
+                };
+                Ok(PolicyRule {
+                    name: rule.name.clone(),
+                    min_severity: rule.min_severity,
+                    rule_keys: rule.rules.clone(),
+                    paths,
+                    issues: rule.issues,
+                    max: rule.max,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            description: config.description.clone(),
+            rules,
+        })
+    }
+
+    /// The outcome of `response` under this policy, named `name`.
+    fn evaluate(&self, name: &str, response: &AnalyzeResponse) -> PolicyReport {
+        let violations = self
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let findings: Vec<String> = response
+                    .vulnerabilities
+                    .iter()
+                    .filter(|issue| rule.matches(issue))
+                    .map(|issue| issue.key.clone())
+                    .collect();
+                (findings.len() > rule.max).then(|| PolicyRuleViolation {
+                    rule: rule.name.clone(),
+                    count: findings.len(),
+                    max: rule.max,
+                    findings,
+                })
+            })
+            .collect();
+
+        PolicyReport {
+            name: name.to_string(),
+            description: self.description.clone(),
+            violations,
+        }
+    }
+}
+
+impl PolicyRule {
+    fn matches(&self, issue: &SonarIssue) -> bool {
+        let age = match self.issues {
+            IssueAge::New => !issue.suppressed,
+            IssueAge::Existing => issue.suppressed,
+            IssueAge::All => true,
+        };
+        let severity = match self.min_severity {
+            Some(min) => Severity::parse(&issue.severity).is_some_and(|s| s >= min),
+            None => true,
+        };
+        let rule_key = self.rule_keys.is_empty()
+            || self
+                .rule_keys
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => issue.rule.starts_with(prefix),
+                    None => *pattern == issue.rule,
+                });
+        let path = match &self.paths {
+            Some(paths) => paths.is_match(issue.path()),
+            None => true,
+        };
+        age && severity && rule_key && path
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Pass,
+    Fail,
+}
+
+/// A severity with more issues than the request allowed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyViolation {
+    pub severity: Severity,
+    /// Issues of the severity, less those suppressed by the baseline.
+    pub count: usize,
+    pub max: usize,
+}
+
+/// The outcome of a result under a configured policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyReport {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Rules matching more issues than they allow; the result passes the
+    /// policy when empty.
+    #[serde(default)]
+    pub violations: Vec<PolicyRuleViolation>,
+}
+
+/// A policy rule matching more issues than it allows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRuleViolation {
+    pub rule: String,
+    pub count: usize,
+    pub max: usize,
+    /// Keys of the matching issues, as in `vulnerabilities`.
+    pub findings: Vec<String>,
+}
@@ -0,0 +1,161 @@
+use crate::sonar_client::{FetchedIssues, SonarClient};
+use crate::AppError;
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+/// Profile name that leaves the server's default profile in place.
+const DEFAULT_PROFILE: &str = "default";
+
+/// Each profile costs a full scanner run, so the number per request is capped.
+pub fn check_limit(profiles: &[String], max_profiles: usize) -> Result<(), AppError> {
+    if profiles.len() > max_profiles {
+        return Err(AppError::InvalidRequest(format!(
+            "At most {} quality profiles may be requested, got {}",
+            max_profiles,
+            profiles.len()
+        )));
+    }
+    Ok(())
+}
+
+pub fn is_default(profile: &str) -> bool {
+    profile.trim().eq_ignore_ascii_case(DEFAULT_PROFILE)
+}
+
+/// Provisions `project_key` and assigns `profile` for every detected language
+/// that has a profile by that name. Fails if no language accepted it.
+pub async fn apply_profile(
+    sonar: &SonarClient,
+    project_key: &str,
+    profile: &str,
+    languages: &[&str],
+) -> Result<(), AppError> {
+    sonar.create_project(project_key).await?;
+
+    let mut applied = 0;
+    for language in languages {
+        match sonar
+            .add_quality_profile(project_key, language, profile)
+            .await
+        {
+            Ok(()) => applied += 1,
+            Err(e) => warn!(
+                "Quality profile '{}' not applied for {}: {}",
+                profile, language, e
+            ),
+        }
+    }
+
+    if applied == 0 {
+        return Err(AppError::InvalidRequest(format!(
+            "Quality profile '{}' does not exist for any detected language",
+            profile
+        )));
+    }
+
+    info!(
+        "Applied quality profile '{}' to {} ({} language(s))",
+        profile, project_key, applied
+    );
+    Ok(())
+}
+
+/// Merges per-profile issue sets, tagging each issue with its profile. An
+/// issue reported under several profiles (same rule, file and line) is kept
+/// once, tagged with the first profile that reported it. The merged set is
+/// capped at `max_issues` and truncated if any profile's fetch was.
+pub fn merge(results: Vec<(String, FetchedIssues)>, max_issues: Option<usize>) -> FetchedIssues {
+    let mut seen = HashSet::new();
+    let mut merged = FetchedIssues::default();
+
+    for (profile, fetched) in results {
+        merged.truncated |= fetched.truncated;
+        for mut issue in fetched.issues {
+            let identity = (issue.rule.clone(), issue.path().to_string(), issue.line);
+            if seen.insert(identity) {
+                issue.profile = Some(profile.clone());
+                merged.issues.push(issue);
+            }
+        }
+    }
+
+    merged.total = merged.issues.len();
+    if let Some(max_issues) = max_issues {
+        if merged.issues.len() > max_issues {
+            merged.issues.truncate(max_issues);
+            merged.truncated = true;
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SonarIssue;
+
+    fn fetched(issues: Vec<SonarIssue>) -> FetchedIssues {
+        FetchedIssues {
+            total: issues.len(),
+            issues,
+            truncated: false,
+        }
+    }
+
+    fn results() -> Vec<(String, FetchedIssues)> {
+        let strict = fetched(vec![
+            SonarIssue::for_test("A1", "java:S1", "MAJOR", "src/A.java", 10),
+            SonarIssue::for_test("A2", "java:S2", "CRITICAL", "src/B.java", 5),
+        ]);
+        // The second scan reports its issues under new keys
+        let security = fetched(vec![
+            SonarIssue::for_test("B1", "java:S2", "CRITICAL", "src/B.java", 5),
+            SonarIssue::for_test("B2", "java:S3", "BLOCKER", "src/C.java", 7),
+            SonarIssue::for_test("B3", "java:S1", "MAJOR", "src/A.java", 11),
+        ]);
+        vec![
+            ("strict".to_string(), strict),
+            ("security".to_string(), security),
+        ]
+    }
+
+    #[test]
+    fn dedups_and_attributes_profiles() {
+        let merged = merge(results(), None);
+        let keys: Vec<(&str, Option<&str>)> = merged
+            .issues
+            .iter()
+            .map(|issue| (issue.key.as_str(), issue.profile.as_deref()))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("A1", Some("strict")),
+                ("A2", Some("strict")),
+                ("B2", Some("security")),
+                ("B3", Some("security")),
+            ]
+        );
+        assert_eq!(merged.total, 4);
+        assert!(!merged.truncated);
+    }
+
+    #[test]
+    fn truncates_to_max_issues() {
+        let merged = merge(results(), Some(3));
+        assert_eq!(merged.issues.len(), 3);
+        assert_eq!(merged.total, 4);
+        assert!(merged.truncated);
+
+        let merged = merge(results(), Some(4));
+        assert_eq!(merged.issues.len(), 4);
+        assert!(!merged.truncated);
+    }
+
+    #[test]
+    fn keeps_truncation_of_any_profile() {
+        let mut results = results();
+        results[1].1.truncated = true;
+        assert!(merge(results, None).truncated);
+    }
+}
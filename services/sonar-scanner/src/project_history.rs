@@ -0,0 +1,121 @@
+//! `GET /projects/{name}/history`: how a logical project's issues changed
+//! over time. Callers file jobs under a stable `project_name`; every
+//! successful scan of it is a point in the history.
+
+use crate::auth::Tenant;
+use crate::job_list::severity_counts;
+use crate::jobs::{JobRecord, JobStatus};
+use crate::severity::SeverityCounts;
+use crate::storage::JobFilter;
+use crate::{parse_project_name, parse_tenant_field, parse_timestamp_field, AppError, AppState};
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Scans returned unless `limit` says otherwise.
+const DEFAULT_LIMIT: usize = 100;
+
+/// Most scans a single response may hold.
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Only scans started at or after this RFC 3339 timestamp or Unix time.
+    since: Option<String>,
+    /// Only this tenant's scans, for API keys without a tenant of their own.
+    tenant: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectHistory {
+    project_name: String,
+    /// Successful scans of the project, across all of history.
+    total: usize,
+    /// The most recent scans, oldest first.
+    scans: Vec<HistoryPoint>,
+}
+
+/// One successful scan of the project.
+#[derive(Debug, Serialize)]
+struct HistoryPoint {
+    job_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    finished_at: Option<OffsetDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issue_count: Option<usize>,
+    /// Issues per severity, less those suppressed by a baseline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity_counts: Option<SeverityCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality_gate: Option<String>,
+    /// Whether the scan returned fewer issues than matched.
+    truncated: bool,
+}
+
+impl HistoryPoint {
+    fn new(job: &JobRecord) -> Self {
+        Self {
+            job_id: job.id.clone(),
+            tenant: job.tenant.clone(),
+            created_at: job.created_at,
+            finished_at: job.finished_at,
+            issue_count: job.issue_count,
+            severity_counts: job.result.as_deref().map(severity_counts),
+            quality_gate: job
+                .result
+                .as_ref()
+                .and_then(|result| result.quality_gate.as_ref())
+                .map(|gate| gate.status.clone()),
+            truncated: job.result.as_ref().is_some_and(|result| result.truncated),
+        }
+    }
+}
+
+pub async fn project_history_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    Path(name): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<ProjectHistory>, AppError> {
+    let project_name = parse_project_name("name", &name)?;
+    let requested = query
+        .tenant
+        .as_deref()
+        .map(|tenant| parse_tenant_field("tenant", tenant))
+        .transpose()?;
+    let filter = JobFilter {
+        statuses: vec![JobStatus::Succeeded],
+        since: query
+            .since
+            .as_deref()
+            .map(|since| parse_timestamp_field("since", since).map(OffsetDateTime::from))
+            .transpose()?,
+        project: None,
+        tenant: tenant.resolve(requested.as_deref())?,
+        project_name: Some(project_name.clone()),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(AppError::InvalidRequest(format!(
+            "Parameter 'limit' must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+
+    // The listing is newest first; the history reads forward in time
+    let (total, jobs) = state.jobs.list(&filter, limit, 0).await?;
+    Ok(Json(ProjectHistory {
+        project_name,
+        total,
+        scans: jobs.iter().rev().map(HistoryPoint::new).collect(),
+    }))
+}
@@ -0,0 +1,142 @@
+//! Outbound HTTP(S) proxy, for networks that only reach SonarQube, object
+//! storage and callback receivers through one. Every reqwest client is built
+//! from [`OutboundProxy::client_builder`], and sonar-scanner, a Java program
+//! that ignores `HTTPS_PROXY`, gets the same settings as JVM system
+//! properties in `SONAR_SCANNER_OPTS`.
+
+use crate::config::ProxyConfig;
+use anyhow::{bail, Context, Result};
+
+/// The configured proxy, shared by every outbound client. Not `Debug`, since
+/// the URL may carry credentials.
+#[derive(Clone)]
+pub struct OutboundProxy {
+    url: Option<reqwest::Url>,
+    proxy: Option<reqwest::Proxy>,
+    /// Hosts, domains, IPs and CIDR ranges reached directly.
+    no_proxy: Vec<String>,
+}
+
+impl OutboundProxy {
+    pub fn new(config: &ProxyConfig) -> Result<Self> {
+        let url = match config.url.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(url) => {
+                let url = reqwest::Url::parse(url).context("Invalid proxy URL")?;
+                if !matches!(url.scheme(), "http" | "https") {
+                    bail!(
+                        "Unsupported proxy URL scheme {}:// (expected http:// or https://)",
+                        url.scheme()
+                    );
+                }
+                if url.host_str().is_none() {
+                    bail!("Proxy URL has no host");
+                }
+                Some(url)
+            }
+        };
+        // Credentials in the URL are sent as Proxy-Authorization
+        let proxy = match &url {
+            Some(url) => Some(
+                reqwest::Proxy::all(url.clone())
+                    .context("Invalid proxy URL")?
+                    .no_proxy(reqwest::NoProxy::from_string(&config.no_proxy.join(","))),
+            ),
+            None => None,
+        };
+        Ok(Self {
+            url,
+            proxy,
+            no_proxy: config.no_proxy.clone(),
+        })
+    }
+
+    /// The proxy URL without credentials, for logs.
+    pub fn redacted_url(&self) -> Option<String> {
+        self.url.as_ref().map(|url| {
+            let mut url = url.clone();
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.to_string()
+        })
+    }
+
+    /// A client builder sending requests through the proxy, except to
+    /// `no_proxy` hosts. The proxy environment variables were read into the
+    /// config, so reqwest's own lookup of them is turned off.
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        let builder = reqwest::Client::builder().no_proxy();
+        match &self.proxy {
+            Some(proxy) => builder.proxy(proxy.clone()),
+            None => builder,
+        }
+    }
+
+    /// JVM system properties for sonar-scanner, or `None` without a proxy.
+    pub fn java_options(&self) -> Option<String> {
+        let url = self.url.as_ref()?;
+        let host = url.host_str()?;
+        let port = url.port_or_known_default()?;
+        let mut options = Vec::new();
+        for scheme in ["http", "https"] {
+            options.push(format!("-D{}.proxyHost={}", scheme, host));
+            options.push(format!("-D{}.proxyPort={}", scheme, port));
+        }
+        // sonar-scanner reads these for both schemes
+        if !url.username().is_empty() {
+            options.push(format!("-Dhttp.proxyUser={}", decoded(url.username())));
+        }
+        if let Some(password) = url.password() {
+            options.push(format!("-Dhttp.proxyPassword={}", decoded(password)));
+        }
+        let non_proxy_hosts: Vec<String> = self
+            .no_proxy
+            .iter()
+            .map(String::as_str)
+            .flat_map(java_host_patterns)
+            .collect();
+        if !non_proxy_hosts.is_empty() {
+            options.push(format!(
+                "-Dhttp.nonProxyHosts={}",
+                non_proxy_hosts.join("|")
+            ));
+        }
+        Some(options.join(" "))
+    }
+}
+
+/// `nonProxyHosts` patterns for a `NO_PROXY` entry: a domain matches its
+/// subdomains too, as it does for reqwest. CIDR ranges have no equivalent.
+fn java_host_patterns(entry: &str) -> Vec<String> {
+    if entry.contains('/') {
+        return Vec::new();
+    }
+    match entry.trim_start_matches("*.").trim_start_matches('.') {
+        "" | "*" => vec!["*".to_string()],
+        domain => vec![domain.to_string(), format!("*.{}", domain)],
+    }
+}
+
+/// A percent-encoded URL component as plain text.
+fn decoded(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
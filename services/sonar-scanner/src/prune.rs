@@ -0,0 +1,163 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tracing::{info, warn};
+
+/// Files kept regardless of age because they configure the scan.
+const ALWAYS_KEPT: &[&str] = &["sonar-project.properties"];
+
+/// Tolerance when comparing mtimes against the extraction time.
+const MTIME_SLACK: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Default)]
+pub struct PruneOutcome {
+    pub removed: usize,
+    pub warning: Option<String>,
+}
+
+/// Deletes files last modified before `since` so only recent changes get
+/// scanned. `keep` lists project-relative paths that must survive (e.g.
+/// coverage reports).
+///
+/// Pruning is skipped with a warning when the mtimes carry no information:
+/// all files share one timestamp, or every file was stamped at extraction.
+pub fn prune_older_than(
+    project_dir: &Path,
+    since: SystemTime,
+    extracted_at: SystemTime,
+    keep: &[String],
+) -> PruneOutcome {
+    let mut files = Vec::new();
+    collect_files(project_dir, &mut files);
+
+    let mtimes: Vec<SystemTime> = files.iter().map(|(_, mtime)| *mtime).collect();
+    if let Some(warning) = mtime_warning(&mtimes, extracted_at) {
+        warn!("{}", warning);
+        return PruneOutcome {
+            removed: 0,
+            warning: Some(warning),
+        };
+    }
+
+    let keep: HashSet<PathBuf> = keep.iter().map(|path| project_dir.join(path)).collect();
+    let mut removed = 0;
+
+    for (path, mtime) in files {
+        let is_config = path
+            .file_name()
+            .is_some_and(|name| ALWAYS_KEPT.iter().any(|kept| name == *kept));
+        if mtime >= since || is_config || keep.contains(&path) {
+            continue;
+        }
+
+        match fs::remove_file(&path) {
+            Ok(()) => removed += 1,
+            Err(e) => warn!("Failed to prune {:?}: {}", path, e),
+        }
+    }
+
+    info!("Pruned {} file(s) older than modified_since", removed);
+    PruneOutcome {
+        removed,
+        warning: None,
+    }
+}
+
+fn mtime_warning(mtimes: &[SystemTime], extracted_at: SystemTime) -> Option<String> {
+    let (Some(oldest), Some(newest)) = (mtimes.iter().min(), mtimes.iter().max()) else {
+        return None;
+    };
+
+    if mtimes.len() > 1 && newest.duration_since(*oldest).unwrap_or_default() <= MTIME_SLACK {
+        return Some(
+            "modified_since ignored: all files share the same modification time".to_string(),
+        );
+    }
+
+    if *oldest + MTIME_SLACK >= extracted_at {
+        return Some(
+            "modified_since ignored: file modification times reflect extraction, not the source"
+                .to_string(),
+        );
+    }
+
+    None
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<(PathBuf, SystemTime)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+
+        if metadata.is_dir() {
+            collect_files(&path, files);
+        } else if let Ok(mtime) = metadata.modified() {
+            files.push((path, mtime));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn write(dir: &Path, path: &str, mtime: SystemTime) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "x").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    #[test]
+    fn removes_only_files_older_than_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        write(dir.path(), "src/old.js", now - 30 * DAY);
+        write(dir.path(), "src/nested/old.py", now - 20 * DAY);
+        write(dir.path(), "src/new.js", now - DAY);
+        write(dir.path(), "lib/new.py", now - 2 * DAY);
+        write(dir.path(), "sonar-project.properties", now - 30 * DAY);
+        write(dir.path(), "coverage/lcov.info", now - 30 * DAY);
+
+        let keep = ["coverage/lcov.info".to_string()];
+        let outcome = prune_older_than(dir.path(), now - 7 * DAY, now, &keep);
+
+        assert_eq!(outcome.removed, 2);
+        assert!(outcome.warning.is_none());
+        assert!(!dir.path().join("src/old.js").exists());
+        assert!(!dir.path().join("src/nested/old.py").exists());
+        assert!(dir.path().join("src/new.js").exists());
+        assert!(dir.path().join("lib/new.py").exists());
+        assert!(dir.path().join("sonar-project.properties").exists());
+        assert!(dir.path().join("coverage/lcov.info").exists());
+    }
+
+    #[test]
+    fn skips_when_mtimes_are_uniform() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        write(dir.path(), "a.js", now - 30 * DAY);
+        write(dir.path(), "b.js", now - 30 * DAY);
+
+        let outcome = prune_older_than(dir.path(), now - 7 * DAY, now, &[]);
+        assert_eq!(outcome.removed, 0);
+        assert!(outcome.warning.is_some());
+        assert!(dir.path().join("a.js").exists());
+    }
+}
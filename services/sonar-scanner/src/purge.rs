@@ -0,0 +1,197 @@
+//! `POST /admin/purge`: reclaims what aged jobs leave behind in one sweep.
+//! Finished jobs older than `older_than` are deleted from memory and from
+//! the job database along with their stored artifacts; job temp
+//! directories a crashed process failed to remove are deleted too and, with
+//! `delete_projects=true`, so are the jobs' SonarQube projects.
+
+use crate::auth::Tenant;
+use crate::sonar_client::SonarClient;
+use crate::storage::StoredJob;
+use crate::{profile_project_key, project_key, AppError, AppState, TEMP_DIR_PREFIX};
+use axum::{
+    extract::{Query, State},
+    response::Json,
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, time::SystemTime};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+#[derive(Debug, Deserialize)]
+pub struct PurgeQuery {
+    /// Age of the newest jobs purged, e.g. `30d`, `12h`, `90m` or `3600s`.
+    older_than: Option<String>,
+    /// Also delete the purged jobs' SonarQube projects.
+    #[serde(default)]
+    delete_projects: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PurgeReport {
+    /// Jobs finished before this were purged.
+    #[serde(with = "time::serde::rfc3339")]
+    cutoff: OffsetDateTime,
+    jobs_deleted: usize,
+    artifacts_deleted: usize,
+    artifacts_failed: usize,
+    temp_dirs_deleted: usize,
+    sonar_projects_deleted: usize,
+    sonar_projects_failed: usize,
+}
+
+/// Parses an age such as `30d`, `12h`, `90m`, `45s` or a bare number of
+/// seconds into the time that long ago.
+fn parse_cutoff(value: &str) -> Result<OffsetDateTime, AppError> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => value.split_at(split),
+        None => (value, "s"),
+    };
+    let unit_seconds: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => 0,
+    };
+    let seconds = number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit_seconds))
+        .filter(|&seconds| seconds > 0)
+        .and_then(|seconds| i64::try_from(seconds).ok());
+    seconds
+        .and_then(|seconds| OffsetDateTime::now_utc().checked_sub(time::Duration::seconds(seconds)))
+        .ok_or_else(|| {
+            AppError::InvalidRequest(format!(
+                "Parameter 'older_than' must be a positive age such as 30d, 12h, 90m or 3600s, not '{}'",
+                value
+            ))
+        })
+}
+
+/// `POST /admin/purge`: deletes finished jobs older than `older_than` and
+/// everything kept for them. Only admin keys may call it.
+pub async fn purge_handler(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Tenant>,
+    Query(query): Query<PurgeQuery>,
+) -> Result<Json<PurgeReport>, AppError> {
+    tenant.require_admin()?;
+    let older_than = query.older_than.as_deref().ok_or_else(|| {
+        AppError::InvalidRequest("Parameter 'older_than' is required".to_string())
+    })?;
+    let cutoff = parse_cutoff(older_than)?;
+
+    let jobs = state.jobs.purge_finished_before(cutoff).await?;
+    let mut report = PurgeReport {
+        cutoff,
+        jobs_deleted: jobs.len(),
+        artifacts_deleted: 0,
+        artifacts_failed: 0,
+        temp_dirs_deleted: 0,
+        sonar_projects_deleted: 0,
+        sonar_projects_failed: 0,
+    };
+
+    if let Some(artifacts) = &state.artifacts {
+        for artifact in jobs.iter().flat_map(|job| &job.artifacts) {
+            match artifacts.delete(artifact).await {
+                Ok(()) => report.artifacts_deleted += 1,
+                Err(e) => {
+                    warn!("Failed to delete artifact {}: {:#}", artifact.key, e);
+                    report.artifacts_failed += 1;
+                }
+            }
+        }
+    }
+
+    if query.delete_projects {
+        for job in &jobs {
+            delete_projects(
+                &state.sonar,
+                job,
+                state.config.limits.max_quality_profiles,
+                &mut report,
+            )
+            .await;
+        }
+    }
+
+    // Directories of jobs this process is running are never old enough
+    let before = SystemTime::from(cutoff).min(state.started_at);
+    report.temp_dirs_deleted = tokio::task::spawn_blocking(move || remove_temp_dirs(before))
+        .await
+        .map_err(|e| AppError::InternalError(format!("Temp directory sweep failed: {}", e)))?;
+
+    info!(
+        "Purged {} jobs finished before {}: {} artifacts, {} temp directories, {} SonarQube projects deleted",
+        report.jobs_deleted,
+        cutoff,
+        report.artifacts_deleted,
+        report.temp_dirs_deleted,
+        report.sonar_projects_deleted
+    );
+    Ok(Json(report))
+}
+
+/// Deletes a job's SonarQube projects: its own key and, for scans against
+/// several quality profiles, the numbered keys after it until one is missing.
+async fn delete_projects(
+    sonar: &SonarClient,
+    job: &StoredJob,
+    max_profiles: usize,
+    report: &mut PurgeReport,
+) {
+    let project_key = project_key(&job.id, job.tenant.as_deref());
+    let keys = std::iter::once(project_key.clone())
+        .chain((1..=max_profiles).map(|n| profile_project_key(&project_key, n)));
+    for (index, key) in keys.enumerate() {
+        match sonar.delete_project_if_exists(&key).await {
+            Ok(true) => report.sonar_projects_deleted += 1,
+            Ok(false) if index > 0 => break,
+            Ok(false) => {}
+            Err(e) => {
+                warn!("Failed to delete SonarQube project {}: {}", key, e);
+                report.sonar_projects_failed += 1;
+            }
+        }
+    }
+}
+
+/// Removes job temp directories last modified before `before`, returning
+/// how many were removed.
+fn remove_temp_dirs(before: SystemTime) -> usize {
+    let temp_dir = std::env::temp_dir();
+    let entries = match fs::read_dir(&temp_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to list {}: {}", temp_dir.display(), e);
+            return 0;
+        }
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let is_job_dir = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(TEMP_DIR_PREFIX));
+        // Not following symlinks, so only real directories are removed
+        let is_aged = entry
+            .metadata()
+            .ok()
+            .filter(|metadata| metadata.is_dir())
+            .and_then(|metadata| metadata.modified().ok())
+            .is_some_and(|modified| modified < before);
+        if !is_job_dir || !is_aged {
+            continue;
+        }
+        match fs::remove_dir_all(entry.path()) {
+            Ok(()) => removed += 1,
+            Err(e) => warn!("Failed to remove {}: {}", entry.path().display(), e),
+        }
+    }
+    removed
+}
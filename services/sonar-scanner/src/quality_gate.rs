@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+/// Quality gate outcome for the scanned project, from
+/// `/api/qualitygates/project_status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualityGate {
+    /// `OK`, `WARN`, `ERROR` or `NONE` (no gate assigned).
+    pub status: String,
+    /// Conditions that did not pass.
+    #[serde(default)]
+    pub failed_conditions: Vec<GateCondition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GateCondition {
+    pub metric: String,
+    pub status: String,
+    pub comparator: Option<String>,
+    pub threshold: Option<String>,
+    pub actual_value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ProjectStatusResponse {
+    project_status: ProjectStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectStatus {
+    status: String,
+    #[serde(default)]
+    conditions: Vec<ApiCondition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiCondition {
+    status: String,
+    metric_key: String,
+    comparator: Option<String>,
+    error_threshold: Option<String>,
+    actual_value: Option<String>,
+}
+
+impl From<ProjectStatusResponse> for QualityGate {
+    fn from(response: ProjectStatusResponse) -> Self {
+        let status = response.project_status;
+        Self {
+            status: status.status,
+            failed_conditions: status
+                .conditions
+                .into_iter()
+                .filter(|condition| condition.status != "OK")
+                .map(|condition| GateCondition {
+                    metric: condition.metric_key,
+                    status: condition.status,
+                    comparator: condition.comparator,
+                    threshold: condition.error_threshold,
+                    actual_value: condition.actual_value,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Combines the gates of a multi-profile scan: the worst status wins and
+/// every failed condition is kept.
+pub fn combine(gates: Vec<QualityGate>) -> Option<QualityGate> {
+    gates.into_iter().reduce(|mut combined, gate| {
+        if rank(&gate.status) > rank(&combined.status) {
+            combined.status = gate.status;
+        }
+        combined.failed_conditions.extend(gate.failed_conditions);
+        combined
+    })
+}
+
+fn rank(status: &str) -> u8 {
+    match status {
+        "ERROR" => 3,
+        "WARN" => 2,
+        "OK" => 1,
+        _ => 0,
+    }
+}
@@ -0,0 +1,121 @@
+use crate::auth::API_KEY_HEADER;
+use crate::AppError;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Idle, full buckets are dropped once this many clients are tracked.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token bucket per client: `burst` requests at once, refilled at
+/// `per_minute` requests per minute.
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    refill_per_sec: f64,
+    burst: f64,
+}
+
+impl RateLimiter {
+    /// Returns `None` when `per_minute` is zero, which disables limiting.
+    pub fn new(per_minute: u32, burst: u32) -> Option<Self> {
+        if per_minute == 0 {
+            return None;
+        }
+        Some(Self {
+            buckets: Mutex::new(HashMap::new()),
+            refill_per_sec: f64::from(per_minute) / 60.0,
+            burst: f64::from(burst.max(1)),
+        })
+    }
+
+    /// Takes a token for `client`, or returns how long until one is available.
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| self.refill(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        bucket.tokens = self.refill(bucket, now);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_per_sec).min(self.burst)
+    }
+}
+
+/// Rejects requests over the client's rate with `429 Too Many Requests` and a
+/// `Retry-After` header. Clients are identified by API key, else source IP.
+pub async fn limit_requests(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = client_id(&request);
+
+    match limiter.check(&client) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = retry_after_secs(wait);
+            let mut response = AppError::RateLimited(format!(
+                "too many requests, retry in {} second(s)",
+                retry_after
+            ))
+            .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
+
+/// Whole seconds to wait, as reported to clients; never zero.
+pub fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+fn client_id(request: &Request) -> String {
+    if let Some(key) = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        return format!("key:{}", key.trim());
+    }
+
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_string(),
+    }
+}
@@ -0,0 +1,88 @@
+//! `X-Request-Id`: every request gets an ID, the client's own when it sends
+//! a usable one. It is recorded on the request's tracing span, so every
+//! event logged while handling it, including by the jobs it starts, carries
+//! it; it is echoed in the response header and in error bodies, and sent to
+//! SonarQube with the Web API calls of those jobs.
+
+use crate::ids;
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+use tracing::{error, info_span, Instrument};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied ID accepted; longer ones are replaced.
+const MAX_LEN: usize = 128;
+
+/// The request's ID, added to every request.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Assigns the request its ID and runs it inside a span carrying the ID.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(ids::request_id);
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let span = info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path()
+    );
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    if response.status().is_client_error() || response.status().is_server_error() {
+        return with_request_id(response, &id).await;
+    }
+    response
+}
+
+/// Printable ASCII without spaces, so it is safe in headers and logs.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Adds `request_id` to an `{error, code}` body. Other bodies, including
+/// response envelopes, which report the ID in `meta`, are left alone.
+async fn with_request_id(response: Response, id: &str) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Failed to buffer error body: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(mut body)) if body.get("error").is_some_and(Value::is_string) => {
+            body.insert("request_id".to_string(), Value::String(id.to_string()));
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(Value::Object(body).to_string()))
+        }
+        _ => Response::from_parts(parts, Body::from(bytes)),
+    }
+}
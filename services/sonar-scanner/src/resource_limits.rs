@@ -0,0 +1,331 @@
+//! CPU, memory and process limits for sonar-scanner, so one pathological
+//! project cannot starve the scans running beside it.
+//!
+//! On the host each run gets its own cgroup (v2) under a delegated parent
+//! directory, which enforces all three limits on the whole process tree and
+//! records peak usage. Without a usable cgroup parent, memory and process
+//! counts fall back to rlimits, which apply per process (per user for
+//! processes) and report no usage. In container mode the limits are passed to
+//! the container runtime instead.
+
+use crate::config::ResourceLimitsConfig;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::{CStr, CString},
+    fs, io,
+    os::unix::{ffi::OsStrExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
+};
+use tracing::warn;
+
+/// `cpu.max` period; the quota is a share of it.
+const CPU_PERIOD_USEC: u64 = 100_000;
+
+/// How a run's limits were enforced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Enforcement {
+    Cgroup,
+    Rlimit,
+    Container,
+    /// No limits are configured.
+    Unlimited,
+}
+
+/// Limits one analyzer run was held to and what it used, reported on the job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessResources {
+    pub process: String,
+    pub enforcement: Enforcement,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_bytes: Option<u64>,
+    /// CPU cores.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids_limit: Option<u64>,
+    /// Usage is only known when the run had its own cgroup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_memory_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_time_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_pids: Option<u64>,
+    /// The kernel killed a process for exceeding the memory limit.
+    #[serde(default)]
+    pub oom_killed: bool,
+}
+
+/// The configured limits, shared by every scan.
+#[derive(Debug, Clone)]
+pub struct ResourceLimits {
+    memory_bytes: Option<u64>,
+    cpus: Option<f64>,
+    pids: Option<u64>,
+    /// Delegated cgroup v2 directory runs get a child cgroup of; rlimits
+    /// are used when unset or unusable.
+    cgroup_parent: Option<PathBuf>,
+}
+
+impl ResourceLimits {
+    /// Reads the limits and checks the cgroup parent, enabling the
+    /// controllers the limits need in its children.
+    pub fn new(config: &ResourceLimitsConfig) -> Self {
+        let mut limits = Self {
+            memory_bytes: (config.memory_mb > 0).then(|| config.memory_mb * 1024 * 1024),
+            cpus: (config.cpus > 0.0).then_some(config.cpus),
+            pids: (config.pids > 0).then_some(config.pids),
+            cgroup_parent: None,
+        };
+        if let Some(parent) = &config.cgroup_parent {
+            match limits.enable_controllers(parent) {
+                Ok(()) => limits.cgroup_parent = Some(parent.clone()),
+                Err(e) => warn!(
+                    "Cgroup {} is unusable, falling back to rlimits: {}",
+                    parent.display(),
+                    e
+                ),
+            }
+        }
+        limits
+    }
+
+    fn is_unlimited(&self) -> bool {
+        self.memory_bytes.is_none() && self.cpus.is_none() && self.pids.is_none()
+    }
+
+    fn controllers(&self) -> Vec<&'static str> {
+        let mut controllers = Vec::new();
+        if self.memory_bytes.is_some() {
+            controllers.push("memory");
+        }
+        if self.cpus.is_some() {
+            controllers.push("cpu");
+        }
+        if self.pids.is_some() {
+            controllers.push("pids");
+        }
+        controllers
+    }
+
+    fn enable_controllers(&self, parent: &Path) -> io::Result<()> {
+        let available = fs::read_to_string(parent.join("cgroup.controllers"))?;
+        let enabled = fs::read_to_string(parent.join("cgroup.subtree_control"))?;
+        for controller in self.controllers() {
+            if !available.split_whitespace().any(|name| name == controller) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("the {} controller is not available", controller),
+                ));
+            }
+            if !enabled.split_whitespace().any(|name| name == controller) {
+                fs::write(
+                    parent.join("cgroup.subtree_control"),
+                    format!("+{}", controller),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flags for `<runtime> run` that apply the limits to a container.
+    pub fn container_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(bytes) = self.memory_bytes {
+            args.extend(["--memory".to_string(), bytes.to_string()]);
+        }
+        if let Some(cpus) = self.cpus {
+            args.extend(["--cpus".to_string(), cpus.to_string()]);
+        }
+        if let Some(pids) = self.pids {
+            args.extend(["--pids-limit".to_string(), pids.to_string()]);
+        }
+        args
+    }
+
+    /// The limits of a run of `process`, without usage.
+    pub fn report(&self, process: &str, enforcement: Enforcement) -> ProcessResources {
+        let enforcement = if self.is_unlimited() {
+            Enforcement::Unlimited
+        } else {
+            enforcement
+        };
+        let limited = enforcement != Enforcement::Unlimited;
+        ProcessResources {
+            process: process.to_string(),
+            enforcement,
+            memory_limit_bytes: self.memory_bytes.filter(|_| limited),
+            cpu_limit: self
+                .cpus
+                .filter(|_| limited && enforcement != Enforcement::Rlimit),
+            pids_limit: self.pids.filter(|_| limited),
+            peak_memory_bytes: None,
+            cpu_time_ms: None,
+            peak_pids: None,
+            oom_killed: false,
+        }
+    }
+
+    /// Makes `command` start under the limits: in a new cgroup named after
+    /// `run` when possible, with rlimits otherwise. The returned guard
+    /// reads the run's usage and removes its cgroup.
+    pub fn apply(&self, command: &mut Command, process: &str, run: &str) -> LimitedRun {
+        if self.is_unlimited() {
+            return LimitedRun {
+                cgroup: None,
+                report: self.report(process, Enforcement::Unlimited),
+            };
+        }
+
+        if let Some(parent) = &self.cgroup_parent {
+            let path = parent.join(format!("{}-{}", process, run));
+            match self.create_cgroup(&path) {
+                Ok(procs) => {
+                    // SAFETY: join_cgroup only makes async-signal-safe calls
+                    unsafe {
+                        command.pre_exec(move || join_cgroup(&procs));
+                    }
+                    return LimitedRun {
+                        cgroup: Some(path),
+                        report: self.report(process, Enforcement::Cgroup),
+                    };
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to create cgroup {}, using rlimits: {}",
+                        path.display(),
+                        e
+                    );
+                    remove_cgroup(&path);
+                }
+            }
+        }
+
+        let (memory, pids) = (self.memory_bytes, self.pids);
+        // SAFETY: set_rlimits only makes async-signal-safe calls
+        unsafe {
+            command.pre_exec(move || set_rlimits(memory, pids));
+        }
+        LimitedRun {
+            cgroup: None,
+            report: self.report(process, Enforcement::Rlimit),
+        }
+    }
+
+    /// Creates the cgroup with its limits; returns its `cgroup.procs` path.
+    fn create_cgroup(&self, path: &Path) -> io::Result<CString> {
+        fs::create_dir(path)?;
+        if let Some(bytes) = self.memory_bytes {
+            fs::write(path.join("memory.max"), bytes.to_string())?;
+        }
+        if let Some(cpus) = self.cpus {
+            let quota = ((cpus * CPU_PERIOD_USEC as f64) as u64).max(1000);
+            fs::write(
+                path.join("cpu.max"),
+                format!("{} {}", quota, CPU_PERIOD_USEC),
+            )?;
+        }
+        if let Some(pids) = self.pids {
+            fs::write(path.join("pids.max"), pids.to_string())?;
+        }
+        CString::new(path.join("cgroup.procs").as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+}
+
+/// One run under the limits. Its cgroup is removed on `finish` or drop.
+pub struct LimitedRun {
+    cgroup: Option<PathBuf>,
+    report: ProcessResources,
+}
+
+impl LimitedRun {
+    /// The limits and, under a cgroup, the usage of the finished run.
+    pub fn finish(mut self) -> ProcessResources {
+        let mut report = self.report.clone();
+        if let Some(path) = self.cgroup.take() {
+            let read = |file: &str| fs::read_to_string(path.join(file)).ok();
+            report.peak_memory_bytes =
+                read("memory.peak").and_then(|text| text.trim().parse().ok());
+            report.peak_pids = read("pids.peak").and_then(|text| text.trim().parse().ok());
+            report.cpu_time_ms = read("cpu.stat")
+                .and_then(|text| stat_value(&text, "usage_usec"))
+                .map(|usec| usec / 1000);
+            report.oom_killed = read("memory.events")
+                .and_then(|text| stat_value(&text, "oom_kill"))
+                .is_some_and(|kills| kills > 0);
+            remove_cgroup(&path);
+        }
+        report
+    }
+}
+
+impl Drop for LimitedRun {
+    fn drop(&mut self) {
+        if let Some(path) = self.cgroup.take() {
+            remove_cgroup(&path);
+        }
+    }
+}
+
+/// The value of `key` in a flat-keyed cgroup file such as `cpu.stat`.
+fn stat_value(text: &str, key: &str) -> Option<u64> {
+    text.lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(name, _)| *name == key)
+        .and_then(|(_, value)| value.trim().parse().ok())
+}
+
+/// Kills whatever is left in the cgroup and removes it. The directory can
+/// only go once its processes have exited, which takes a moment.
+fn remove_cgroup(path: &Path) {
+    if !path.exists() {
+        return;
+    }
+    let _ = fs::write(path.join("cgroup.kill"), "1");
+    for _ in 0..50 {
+        match fs::remove_dir(path) {
+            Ok(()) => return,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    warn!("Failed to remove cgroup {}", path.display());
+}
+
+/// Moves the calling process into the cgroup whose `cgroup.procs` is
+/// `procs`; writing `0` names the writer. Runs between fork and exec.
+fn join_cgroup(procs: &CStr) -> io::Result<()> {
+    let fd = unsafe { libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let written = unsafe { libc::write(fd, b"0".as_ptr().cast(), 1) };
+    let error = io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+    if written != 1 {
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Caps the address space and the user's process count. Runs between fork
+/// and exec.
+fn set_rlimits(memory_bytes: Option<u64>, pids: Option<u64>) -> io::Result<()> {
+    for (resource, limit) in [(libc::RLIMIT_AS, memory_bytes), (libc::RLIMIT_NPROC, pids)] {
+        let Some(limit) = limit else {
+            continue;
+        };
+        let rlimit = libc::rlimit {
+            rlim_cur: limit as libc::rlim_t,
+            rlim_max: limit as libc::rlim_t,
+        };
+        if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
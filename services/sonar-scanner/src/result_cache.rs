@@ -0,0 +1,79 @@
+use crate::config::ResultCacheConfig;
+use crate::AnalyzeResponse;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+struct CachedResult {
+    stored_at: Instant,
+    response: Arc<AnalyzeResponse>,
+}
+
+/// Results of recent archive scans, keyed by the archive's SHA-256 and the
+/// options that shape the result, so CI re-runs of unchanged code skip the
+/// scan. Held in memory only; every instance keeps its own.
+#[derive(Clone)]
+pub struct ResultCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Arc<Mutex<HashMap<String, CachedResult>>>,
+}
+
+impl ResultCache {
+    pub fn new(config: &ResultCacheConfig) -> Self {
+        Self {
+            ttl: Duration::from_secs(config.ttl_secs),
+            max_entries: config.max_entries,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        !self.ttl.is_zero() && self.max_entries > 0
+    }
+
+    /// The result stored under `key`, unless it has expired.
+    pub fn get(&self, key: &str) -> Option<Arc<AnalyzeResponse>> {
+        if !self.enabled() {
+            return None;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(cached) if cached.stored_at.elapsed() < self.ttl => Some(cached.response.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Stores `response` under `key`, dropping expired entries and then the
+    /// oldest ones to stay within `max_entries`.
+    pub fn insert(&self, key: String, response: Arc<AnalyzeResponse>) {
+        if !self.enabled() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, cached| cached.stored_at.elapsed() < self.ttl);
+        while entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.stored_at)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => entries.remove(&oldest),
+                None => break,
+            };
+        }
+        entries.insert(
+            key,
+            CachedResult {
+                stored_at: Instant::now(),
+                response,
+            },
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// How often, and how far apart, a failed call is tried again. The delay
+/// doubles after each attempt up to `max_delay`, and a random half of it is
+/// skipped so that clients failing together do not retry together.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first included; `1` disables retries.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Whether another attempt may follow attempt number `attempt` (1-based).
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts
+    }
+
+    /// Delay before the attempt after attempt number `attempt` (1-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+        let half = delay / 2;
+        half + half.mul_f64(jitter())
+    }
+}
+
+/// A value in `[0, 1)`; `RandomState` is seeded randomly for every instance.
+fn jitter() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,174 @@
+//! The Markdown summary of a finished job that code review integrations post
+//! on pull and merge requests: the quality gate, issue counts per severity,
+//! the most severe findings and a link to the full result. It opens with a
+//! hidden marker, by which a later scan finds the summary to edit it.
+
+use crate::job_list::severity_counts;
+use crate::severity::Severity;
+use crate::{AnalyzeResponse, AppError, SonarIssue};
+use std::fmt::Write;
+
+/// Longest finding message quoted in a summary, in characters.
+const MAX_MESSAGE_LEN: usize = 200;
+
+/// Hidden first line of the summary of `project_name`, so several projects
+/// scanned for one review each keep their own.
+pub fn marker(project_name: Option<&str>) -> String {
+    match project_name {
+        // `--` may not appear inside an HTML comment
+        Some(name) => format!(
+            "<!-- sonar-backend:summary:{} -->",
+            name.replace("--", "- -")
+        ),
+        None => "<!-- sonar-backend:summary -->".to_string(),
+    }
+}
+
+/// The summary of job `job_id`: its marker, then the outcome, listing at
+/// most `top_findings` issues and linking to the result under `public_url`.
+pub fn render(
+    project_name: Option<&str>,
+    job_id: &str,
+    outcome: Result<&AnalyzeResponse, &AppError>,
+    top_findings: usize,
+    public_url: Option<&str>,
+) -> String {
+    let mut body = marker(project_name);
+    body.push('\n');
+    let title = match project_name {
+        Some(name) => format!("Scan results for `{}`", name),
+        None => "Scan results".to_string(),
+    };
+    let response = match outcome {
+        Ok(response) => response,
+        Err(e) => {
+            let (_, code, message) = e.parts();
+            let _ = writeln!(body, "### {}: scan failed\n", title);
+            let _ = writeln!(body, "`{}`: {}\n", code, one_line(&message));
+            write_link(&mut body, job_id, public_url);
+            return body;
+        }
+    };
+
+    let _ = writeln!(body, "### {}\n", title);
+    if let Some(gate) = &response.quality_gate {
+        let _ = writeln!(body, "Quality gate: **{}**\n", gate.status);
+    }
+    let counts = severity_counts(response);
+    body.push_str("| Blocker | Critical | Major | Minor | Info |\n");
+    body.push_str("|---:|---:|---:|---:|---:|\n");
+    let _ = writeln!(
+        body,
+        "| {} | {} | {} | {} | {} |\n",
+        counts.blocker, counts.critical, counts.major, counts.minor, counts.info
+    );
+
+    let mut findings: Vec<&SonarIssue> = response
+        .vulnerabilities
+        .iter()
+        .filter(|issue| !issue.suppressed)
+        .collect();
+    // Stable, so issues of one severity keep SonarQube's order
+    findings.sort_by_key(|issue| std::cmp::Reverse(Severity::parse(&issue.severity)));
+    if findings.is_empty() {
+        body.push_str("No findings.\n\n");
+    } else if top_findings > 0 {
+        body.push_str("| Severity | Rule | Location | Message |\n");
+        body.push_str("|---|---|---|---|\n");
+        for issue in findings.iter().take(top_findings) {
+            let location = match issue.line {
+                Some(line) => format!("{}:{}", issue.path(), line),
+                None => issue.path().to_string(),
+            };
+            let _ = writeln!(
+                body,
+                "| {} | `{}` | `{}` | {} |",
+                issue.severity,
+                table_cell(&issue.rule),
+                table_cell(&location),
+                table_cell(&one_line(&issue.message))
+            );
+        }
+        body.push('\n');
+        let more = findings.len().saturating_sub(top_findings);
+        if more > 0 {
+            let _ = writeln!(body, "_and {} more_\n", more);
+        }
+    }
+    if response.truncated {
+        body.push_str("More issues matched than the scan returned.\n\n");
+    }
+    write_link(&mut body, job_id, public_url);
+    body
+}
+
+/// Whether a scan passes review, with the reason: it fails on a failed
+/// quality gate or an issue at least as severe as `fail_on`.
+pub fn verdict(response: &AnalyzeResponse, fail_on: Severity) -> (bool, String) {
+    if response
+        .quality_gate
+        .as_ref()
+        .is_some_and(|gate| gate.status == "ERROR")
+    {
+        return (false, "Quality gate failed".to_string());
+    }
+    let counts = severity_counts(response);
+    let failing: usize = Severity::ALL
+        .into_iter()
+        .filter(|severity| *severity >= fail_on)
+        .map(|severity| match severity {
+            Severity::Blocker => counts.blocker,
+            Severity::Critical => counts.critical,
+            Severity::Major => counts.major,
+            Severity::Minor => counts.minor,
+            Severity::Info => counts.info,
+        })
+        .sum();
+    match failing {
+        0 => (true, format!("No issues at {} or above", fail_on.as_str())),
+        1 => (false, format!("1 issue at {} or above", fail_on.as_str())),
+        n => (
+            false,
+            format!("{} issues at {} or above", n, fail_on.as_str()),
+        ),
+    }
+}
+
+/// URL of job `job_id`'s result under `public_url`.
+pub fn result_url(public_url: &str, job_id: &str) -> String {
+    format!(
+        "{}/jobs/{}/result",
+        public_url.trim_end_matches('/'),
+        job_id
+    )
+}
+
+fn write_link(body: &mut String, job_id: &str, public_url: Option<&str>) {
+    match public_url {
+        Some(url) => {
+            let _ = writeln!(
+                body,
+                "[Full result]({}) (job `{}`)",
+                result_url(url, job_id),
+                job_id
+            );
+        }
+        None => {
+            let _ = writeln!(body, "Job `{}`", job_id);
+        }
+    }
+}
+
+/// `text` on one line, shortened to `MAX_MESSAGE_LEN` characters.
+pub fn one_line(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(MAX_MESSAGE_LEN) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line,
+    }
+}
+
+/// Keeps `text` from ending its Markdown table cell or code span.
+fn table_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('`', "'")
+}
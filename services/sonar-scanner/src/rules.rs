@@ -0,0 +1,162 @@
+use crate::backends::Engine;
+use crate::sonar_client::SonarClient;
+use crate::SonarIssue;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+};
+
+/// Longest summary or remediation text returned, in characters.
+const MAX_TEXT_CHARS: usize = 500;
+
+/// What a rule checks and how to fix what it reports, from `/api/rules/show`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleDetails {
+    pub name: String,
+    /// First paragraph of the rule description, as plain text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// First paragraph of the rule's "How to fix" section, as plain text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remediation: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RuleShowResponse {
+    rule: ApiRule,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiRule {
+    name: String,
+    /// Whole description; SonarQube before 9.6 has no sections.
+    html_desc: Option<String>,
+    #[serde(default)]
+    description_sections: Vec<DescriptionSection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DescriptionSection {
+    /// `introduction`, `root_cause`, `how_to_fix`, `resources`, ...
+    key: String,
+    content: String,
+}
+
+impl From<RuleShowResponse> for RuleDetails {
+    fn from(response: RuleShowResponse) -> Self {
+        let rule = response.rule;
+        let section = |keys: &[&str]| {
+            keys.iter().find_map(|key| {
+                rule.description_sections
+                    .iter()
+                    .find(|section| section.key == *key)
+                    .and_then(|section| first_paragraph(&section.content))
+            })
+        };
+
+        Self {
+            summary: section(&["introduction", "root_cause"])
+                .or_else(|| rule.html_desc.as_deref().and_then(first_paragraph)),
+            remediation: section(&["how_to_fix"]),
+            name: rule.name,
+        }
+    }
+}
+
+/// Rule details looked up so far, shared by every job. Rules only change
+/// when the server is upgraded, so entries are kept for the life of the
+/// process; failed lookups are not cached and are retried by the next job.
+#[derive(Clone, Default)]
+pub struct RuleCache(Arc<Mutex<HashMap<String, RuleDetails>>>);
+
+impl RuleCache {
+    fn get(&self, rule: &str) -> Option<RuleDetails> {
+        self.0.lock().unwrap().get(rule).cloned()
+    }
+
+    fn insert(&self, rule: &str, details: RuleDetails) {
+        self.0.lock().unwrap().insert(rule.to_string(), details);
+    }
+}
+
+/// Attaches rule details to every SonarQube issue. Each rule is looked up
+/// once, from the cache when possible. Returns a warning for each rule whose
+/// details could not be fetched; its issues are left without them.
+pub async fn enrich(
+    sonar: &SonarClient,
+    cache: &RuleCache,
+    issues: &mut [SonarIssue],
+) -> Vec<String> {
+    let rules: BTreeSet<String> = issues
+        .iter()
+        .filter(|issue| issue.engine == Some(Engine::SonarQube))
+        .map(|issue| issue.rule.clone())
+        .collect();
+
+    let mut details = HashMap::new();
+    let mut warnings = Vec::new();
+    for rule in rules {
+        if let Some(cached) = cache.get(&rule) {
+            details.insert(rule, cached);
+            continue;
+        }
+        match sonar.rule_details(&rule).await {
+            Ok(fetched) => {
+                cache.insert(&rule, fetched.clone());
+                details.insert(rule, fetched);
+            }
+            Err(e) => warnings.push(format!("Rule details for {} unavailable: {}", rule, e)),
+        }
+    }
+
+    for issue in issues
+        .iter_mut()
+        .filter(|issue| issue.engine == Some(Engine::SonarQube))
+    {
+        issue.rule_details = details.get(&issue.rule).cloned();
+    }
+    warnings
+}
+
+/// Text of the first non-empty `<p>` (or the whole fragment when it has
+/// none), with tags removed, entities decoded and whitespace collapsed.
+fn first_paragraph(html: &str) -> Option<String> {
+    let paragraph = html
+        .split("</p>")
+        .filter_map(|chunk| chunk.split_once("<p>").map(|(_, text)| text))
+        .map(plain_text)
+        .find(|text| !text.is_empty())
+        .unwrap_or_else(|| plain_text(html));
+    if paragraph.is_empty() {
+        return None;
+    }
+    if paragraph.chars().count() <= MAX_TEXT_CHARS {
+        return Some(paragraph);
+    }
+    let mut truncated: String = paragraph.chars().take(MAX_TEXT_CHARS - 3).collect();
+    truncated.push_str("...");
+    Some(truncated)
+}
+
+fn plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    let words: Vec<&str> = text.split_whitespace().collect();
+    words.join(" ")
+}
@@ -0,0 +1,145 @@
+//! Runs sonar-scanner in a throwaway container rather than as a child of the
+//! service. Analyzers and their build-tool integrations execute code from
+//! the uploaded project, so in container mode the scanner sees the project
+//! read-only, joins a network that should reach only SonarQube, and inherits
+//! none of the service's environment or credentials beyond the scan's own
+//! `sonar.*` properties and the outbound proxy settings.
+
+use crate::config::{SandboxConfig, ScannerIsolation};
+use crate::proxy::OutboundProxy;
+use crate::resource_limits::ResourceLimits;
+use crate::AppError;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tracing::warn;
+
+/// Where the project is mounted inside the container.
+const PROJECT_MOUNT: &str = "/usr/src";
+/// The writable directory mounted next to the project.
+const WORK_MOUNT: &str = "/scanner-work";
+/// Host directory behind [`WORK_MOUNT`], a sibling of the project directory.
+const WORK_DIR: &str = "scanner-work";
+
+/// Container name for the scan of `project_key`, so a timed-out run can be
+/// removed by name.
+pub fn container_name(project_key: &str) -> String {
+    format!("sonar-scanner-{}", project_key)
+}
+
+/// Environment variable sonar-scanner reads JVM options from.
+const SCANNER_OPTS: &str = "SONAR_SCANNER_OPTS";
+
+/// The sonar-scanner invocation for `args`, wrapped in `<runtime> run` when
+/// scans are isolated.
+pub fn scanner_command(
+    config: &SandboxConfig,
+    limits: &ResourceLimits,
+    proxy: &OutboundProxy,
+    project_dir: &Path,
+    project_key: &str,
+    args: Vec<String>,
+) -> Result<Command, AppError> {
+    if config.isolation == ScannerIsolation::Host {
+        let mut command = Command::new("sonar-scanner");
+        command.args(args).current_dir(project_dir);
+        if let Some(options) = proxy.java_options() {
+            // Added to any options the service was started with
+            let options = match std::env::var(SCANNER_OPTS) {
+                Ok(existing) if !existing.trim().is_empty() => {
+                    format!("{} {}", existing.trim(), options)
+                }
+                _ => options,
+            };
+            command.env(SCANNER_OPTS, options);
+        }
+        return Ok(command);
+    }
+
+    // The scanner runs as the service's user so it can write the work
+    // directory, and needs no write access anywhere else
+    let work_dir = project_dir.with_file_name(WORK_DIR);
+    fs::create_dir_all(&work_dir).map_err(|e| {
+        AppError::InternalError(format!("Failed to create {}: {}", work_dir.display(), e))
+    })?;
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+
+    let scanner_opts = proxy.java_options();
+    let mut command = Command::new(&config.runtime);
+    command
+        .args(["run", "--rm", "--name"])
+        .arg(container_name(project_key))
+        .args(["--network", &config.network])
+        .args(["--user", &format!("{}:{}", uid, gid)])
+        .args(["--read-only", "--tmpfs", "/tmp"])
+        .args(["--cap-drop", "ALL", "--security-opt", "no-new-privileges"])
+        .args(limits.container_args())
+        // Plugins are cached under the user home, which must be writable
+        .args(["--env", "SONAR_USER_HOME=/tmp/.sonar"])
+        // Passed by name, so proxy credentials stay off the runtime's
+        // command line
+        .args(scanner_opts.iter().flat_map(|_| ["--env", SCANNER_OPTS]))
+        .arg("--volume")
+        .arg(format!("{}:{}:ro", project_dir.display(), PROJECT_MOUNT))
+        .arg("--volume")
+        .arg(format!("{}:{}", work_dir.display(), WORK_MOUNT))
+        .args(["--workdir", PROJECT_MOUNT])
+        .arg(&config.image)
+        .arg("sonar-scanner")
+        .args(args)
+        .arg(format!(
+            "-Dsonar.working.directory={}/.scannerwork",
+            WORK_MOUNT
+        ));
+    if let Some(options) = scanner_opts {
+        command.env(SCANNER_OPTS, options);
+    }
+    Ok(command)
+}
+
+/// SonarQube URL to hand the scanner: the container's view of the server
+/// when one is configured.
+pub fn sonar_url<'a>(config: &'a SandboxConfig, service_url: &'a str) -> &'a str {
+    match (config.isolation, &config.sonar_url) {
+        (ScannerIsolation::Container, Some(url)) => url,
+        _ => service_url,
+    }
+}
+
+/// A file the scanner writes beside the project: its path on the host and
+/// the path to pass to the scanner, which differ inside a container.
+pub fn output_file(config: &SandboxConfig, project_dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+    match config.isolation {
+        ScannerIsolation::Host => {
+            let path = project_dir.with_file_name(name);
+            (path.clone(), path)
+        }
+        ScannerIsolation::Container => (
+            project_dir.with_file_name(WORK_DIR).join(name),
+            Path::new(WORK_MOUNT).join(name),
+        ),
+    }
+}
+
+/// Force-removes the container of `project_key`. Killing the runtime CLI
+/// on a timeout leaves the container itself running.
+pub async fn remove_container(config: &SandboxConfig, project_key: &str) {
+    let name = container_name(project_key);
+    let output = tokio::process::Command::new(&config.runtime)
+        .args(["rm", "--force", &name])
+        .output()
+        .await;
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => {
+            // Already gone if the scanner exited on its own
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !stderr.contains("No such container") {
+                warn!("Failed to remove container {}: {}", name, stderr.trim());
+            }
+        }
+        Err(e) => warn!("Failed to run {} rm: {}", config.runtime, e),
+    }
+}
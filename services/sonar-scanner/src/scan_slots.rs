@@ -0,0 +1,51 @@
+use crate::AppError;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Caps how many analyses run sonar-scanner at once. When every slot is
+/// taken, new requests either wait for one or are rejected with `429`.
+#[derive(Clone)]
+pub struct ScanSlots {
+    semaphore: Option<Arc<Semaphore>>,
+    reject_when_full: bool,
+}
+
+impl ScanSlots {
+    /// `max_concurrent` of zero means unlimited.
+    pub fn new(max_concurrent: usize, reject_when_full: bool) -> Self {
+        Self {
+            semaphore: (max_concurrent > 0).then(|| Arc::new(Semaphore::new(max_concurrent))),
+            reject_when_full,
+        }
+    }
+
+    /// Called while handling the request. In reject mode this claims a slot
+    /// immediately or fails; in queue mode the slot is claimed later by
+    /// [`ScanSlots::acquire`].
+    pub fn reserve(&self) -> Result<Option<OwnedSemaphorePermit>, AppError> {
+        match &self.semaphore {
+            Some(semaphore) if self.reject_when_full => semaphore
+                .clone()
+                .try_acquire_owned()
+                .map(Some)
+                .map_err(|_| {
+                    AppError::ScannerBusy("all scan slots are in use, try again later".to_string())
+                }),
+            _ => Ok(None),
+        }
+    }
+
+    /// Waits for a slot unless one was already reserved. The slot is released
+    /// when the returned permit is dropped.
+    pub async fn acquire(
+        &self,
+        reserved: Option<OwnedSemaphorePermit>,
+    ) -> Option<OwnedSemaphorePermit> {
+        if reserved.is_some() {
+            return reserved;
+        }
+        let semaphore = self.semaphore.clone()?;
+        // The semaphore is never closed
+        semaphore.acquire_owned().await.ok()
+    }
+}
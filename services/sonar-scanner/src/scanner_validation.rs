@@ -0,0 +1,305 @@
+use std::{collections::HashSet, fs, path::Path, process::Command};
+use tracing::{info, warn};
+
+/// Analysis parameters documented by SonarQube that are not tied to a plugin.
+const KNOWN_KEYS: &[&str] = &[
+    "sonar.projectKey",
+    "sonar.projectName",
+    "sonar.projectVersion",
+    "sonar.projectDescription",
+    "sonar.projectBaseDir",
+    "sonar.sources",
+    "sonar.tests",
+    "sonar.inclusions",
+    "sonar.exclusions",
+    "sonar.test.inclusions",
+    "sonar.test.exclusions",
+    "sonar.cpd.exclusions",
+    "sonar.sourceEncoding",
+    "sonar.host.url",
+    "sonar.login",
+    "sonar.password",
+    "sonar.token",
+    "sonar.modules",
+    "sonar.language",
+    "sonar.log.level",
+    "sonar.verbose",
+    "sonar.working.directory",
+    "sonar.coverageReportPaths",
+    "sonar.testExecutionReportPaths",
+    "sonar.externalIssuesReportPaths",
+    "sonar.sarifReportPaths",
+    "sonar.buildString",
+    "sonar.analysis.mode",
+];
+
+/// Key prefixes owned by core features or language plugins.
+const KNOWN_PREFIXES: &[&str] = &[
+    "sonar.analysis.",
+    "sonar.branch.",
+    "sonar.pullrequest.",
+    "sonar.qualitygate.",
+    "sonar.scanner.",
+    "sonar.scm.",
+    "sonar.links.",
+    "sonar.issue.",
+    "sonar.coverage.",
+    "sonar.cpd.",
+    "sonar.java.",
+    "sonar.javascript.",
+    "sonar.typescript.",
+    "sonar.css.",
+    "sonar.html.",
+    "sonar.python.",
+    "sonar.go.",
+    "sonar.cs.",
+    "sonar.vbnet.",
+    "sonar.dotnet.",
+    "sonar.php.",
+    "sonar.ruby.",
+    "sonar.kotlin.",
+    "sonar.scala.",
+    "sonar.swift.",
+    "sonar.c.",
+    "sonar.cpp.",
+    "sonar.cfamily.",
+    "sonar.objc.",
+    "sonar.xml.",
+    "sonar.yaml.",
+    "sonar.json.",
+    "sonar.terraform.",
+    "sonar.cloudformation.",
+    "sonar.docker.",
+    "sonar.kubernetes.",
+    "sonar.flex.",
+    "sonar.plsql.",
+];
+
+/// Project-level properties file the scanner reads from the base directory.
+pub const PROJECT_PROPERTIES_FILE: &str = "sonar-project.properties";
+
+/// Runs `command` (a fully configured sonar-scanner invocation) with
+/// `sonar.scanner.dumpToFile`, which makes the scanner write its effective
+/// property set and exit without analysing. Properties supplied by the request
+/// or the project's `sonar-project.properties` are then checked: unknown keys
+/// and keys missing from the effective set are returned as warnings.
+///
+/// The scanner is told to write `scanner_dump_path`, which is `dump_path` as
+/// the scanner sees it and differs only when it runs in a container.
+pub fn validate_properties(
+    mut command: Command,
+    dump_path: &Path,
+    scanner_dump_path: &Path,
+    project_dir: &Path,
+    supplied: &[(String, String)],
+) -> Vec<String> {
+    info!("Validating scanner properties via dry run");
+
+    let output = command
+        .arg(format!(
+            "-Dsonar.scanner.dumpToFile={}",
+            scanner_dump_path.display()
+        ))
+        .output();
+
+    let effective = match output {
+        Ok(output) if output.status.success() => match fs::read_to_string(dump_path) {
+            Ok(text) => parse_properties(&text),
+            Err(e) => {
+                return vec![format!(
+                    "Property validation skipped: scanner did not write a property dump ({})",
+                    e
+                )]
+            }
+        },
+        Ok(output) => {
+            return vec![format!(
+                "Property validation skipped: dry run exited with status {}",
+                output.status
+            )]
+        }
+        Err(e) => {
+            return vec![format!(
+                "Property validation skipped: failed to execute sonar-scanner ({})",
+                e
+            )]
+        }
+    };
+    let effective_keys: HashSet<&str> = effective.iter().map(|(key, _)| key.as_str()).collect();
+
+    let mut keys: Vec<String> = supplied.iter().map(|(key, _)| key.clone()).collect();
+    if let Ok(text) = fs::read_to_string(project_dir.join(PROJECT_PROPERTIES_FILE)) {
+        keys.extend(parse_properties(&text).into_iter().map(|(key, _)| key));
+    }
+    keys.sort();
+    keys.dedup();
+
+    let warnings = key_warnings(&keys, &effective_keys);
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    warnings
+}
+
+/// Warnings for the supplied `keys` that are unknown or missing from the
+/// scanner's effective property set.
+fn key_warnings(keys: &[String], effective_keys: &HashSet<&str>) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for key in keys {
+        if !is_known_key(key) {
+            warnings.push(format!("Unknown scanner property '{}'", key));
+        } else if !effective_keys.contains(key.as_str()) {
+            warnings.push(format!("Scanner property '{}' was ignored", key));
+        }
+    }
+    warnings
+}
+
+fn is_known_key(key: &str) -> bool {
+    KNOWN_KEYS.contains(&key) || KNOWN_PREFIXES.iter().any(|prefix| key.starts_with(prefix))
+}
+
+/// Parses a Java `.properties` file: `key=value`, `key:value` or `key value`
+/// lines, `#`/`!` comments, backslash escapes and line continuations.
+pub fn parse_properties(text: &str) -> Vec<(String, String)> {
+    let mut properties = Vec::new();
+    let mut logical = String::new();
+
+    for line in text.lines() {
+        let line = line.trim_start();
+        if logical.is_empty() && (line.is_empty() || line.starts_with('#') || line.starts_with('!'))
+        {
+            continue;
+        }
+
+        // An odd number of trailing backslashes continues the line
+        let trailing = line.chars().rev().take_while(|c| *c == '\\').count();
+        if trailing % 2 == 1 {
+            logical.push_str(&line[..line.len() - 1]);
+            continue;
+        }
+        logical.push_str(line);
+
+        if let Some(property) = split_property(&logical) {
+            properties.push(property);
+        }
+        logical.clear();
+    }
+
+    if let Some(property) = split_property(&logical) {
+        properties.push(property);
+    }
+    properties
+}
+
+fn split_property(line: &str) -> Option<(String, String)> {
+    let mut key = String::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    key.push(unescape(escaped));
+                }
+            }
+            '=' | ':' => break,
+            c if c.is_whitespace() => {
+                // Whitespace may be followed by an explicit separator
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+                if matches!(chars.peek(), Some('=') | Some(':')) {
+                    chars.next();
+                }
+                break;
+            }
+            c => key.push(c),
+        }
+    }
+
+    if key.is_empty() {
+        return None;
+    }
+
+    let rest: String = chars.collect();
+    let mut value = String::new();
+    let mut rest = rest.trim_start().chars();
+    while let Some(c) = rest.next() {
+        if c == '\\' {
+            if let Some(escaped) = rest.next() {
+                value.push(unescape(escaped));
+            }
+        } else {
+            value.push(c);
+        }
+    }
+
+    Some((key, value))
+}
+
+fn unescape(c: char) -> char {
+    match c {
+        't' => '\t',
+        'n' => '\n',
+        'r' => '\r',
+        'f' => '\x0c',
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warns_about_unknown_and_ignored_properties() {
+        let keys = [
+            "sonar.exclusions".to_string(),
+            "sonar.java.binaries".to_string(),
+            "sonar.sourcesEncoding".to_string(),
+            "sonar.typo.key".to_string(),
+        ];
+        let effective: HashSet<&str> = ["sonar.exclusions", "sonar.sources"].into();
+
+        let warnings = key_warnings(&keys, &effective);
+        assert_eq!(
+            warnings,
+            [
+                "Scanner property 'sonar.java.binaries' was ignored",
+                "Unknown scanner property 'sonar.sourcesEncoding'",
+                "Unknown scanner property 'sonar.typo.key'",
+            ]
+        );
+    }
+
+    #[test]
+    fn no_warnings_for_known_effective_properties() {
+        let keys = [
+            "sonar.sources".to_string(),
+            "sonar.python.version".to_string(),
+        ];
+        let effective: HashSet<&str> = ["sonar.sources", "sonar.python.version"].into();
+        assert!(key_warnings(&keys, &effective).is_empty());
+    }
+
+    #[test]
+    fn parses_properties_files() {
+        let text = concat!(
+            "# comment\n",
+            "sonar.sources = src\n",
+            "sonar.exclusions: a/**,\\\n",
+            "  b/**\n",
+            "! other\n",
+            "sonar.key\\=x value\n",
+        );
+        assert_eq!(
+            parse_properties(text),
+            [
+                ("sonar.sources".to_string(), "src".to_string()),
+                ("sonar.exclusions".to_string(), "a/**,b/**".to_string()),
+                ("sonar.key=x".to_string(), "value".to_string()),
+            ]
+        );
+    }
+}
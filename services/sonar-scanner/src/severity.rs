@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// SonarQube issue severities, ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Severity {
+    Info,
+    Minor,
+    Major,
+    Critical,
+    Blocker,
+}
+
+impl Severity {
+    /// Every severity, from least to most severe.
+    pub const ALL: [Severity; 5] = [
+        Severity::Info,
+        Severity::Minor,
+        Severity::Major,
+        Severity::Critical,
+        Severity::Blocker,
+    ];
+
+    /// Parses a SonarQube severity string, ignoring case.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "INFO" => Some(Severity::Info),
+            "MINOR" => Some(Severity::Minor),
+            "MAJOR" => Some(Severity::Major),
+            "CRITICAL" => Some(Severity::Critical),
+            "BLOCKER" => Some(Severity::Blocker),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "INFO",
+            Severity::Minor => "MINOR",
+            Severity::Major => "MAJOR",
+            Severity::Critical => "CRITICAL",
+            Severity::Blocker => "BLOCKER",
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = serde::de::value::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::parse(value).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "unknown severity '{}' (expected INFO, MINOR, MAJOR, CRITICAL or BLOCKER)",
+                value
+            ))
+        })
+    }
+}
+
+/// Number of issues per severity.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeverityCounts {
+    pub blocker: usize,
+    pub critical: usize,
+    pub major: usize,
+    pub minor: usize,
+    pub info: usize,
+}
+
+impl SeverityCounts {
+    pub fn record(&mut self, severity: Severity) {
+        match severity {
+            Severity::Blocker => self.blocker += 1,
+            Severity::Critical => self.critical += 1,
+            Severity::Major => self.major += 1,
+            Severity::Minor => self.minor += 1,
+            Severity::Info => self.info += 1,
+        }
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        match severity {
+            Severity::Blocker => self.blocker,
+            Severity::Critical => self.critical,
+            Severity::Major => self.major,
+            Severity::Minor => self.minor,
+            Severity::Info => self.info,
+        }
+    }
+}
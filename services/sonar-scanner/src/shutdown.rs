@@ -0,0 +1,81 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::warn;
+
+/// Resolves on the first SIGTERM or Ctrl-C.
+pub async fn signal_received() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+/// Process groups of running sonar-scanner children. The scanner script
+/// starts a JVM, so killing only the direct child would leave it running.
+#[derive(Clone, Default)]
+pub struct ChildProcesses(Arc<Mutex<HashSet<u32>>>);
+
+impl ChildProcesses {
+    /// Tracks a child started with `process_group(0)` until the guard drops.
+    pub fn register(&self, pid: u32) -> ChildGuard {
+        self.0.lock().unwrap().insert(pid);
+        ChildGuard {
+            processes: self.clone(),
+            pid,
+            exited: false,
+        }
+    }
+
+    /// Sends SIGKILL to every tracked process group; returns how many there were.
+    pub fn kill_all(&self) -> usize {
+        let pids = self.0.lock().unwrap();
+        for &pid in pids.iter() {
+            kill_group(pid);
+        }
+        pids.len()
+    }
+}
+
+/// Kills the child's process group when dropped before [`ChildGuard::exited`]
+/// is called, e.g. when its job is canceled; `kill_on_drop` alone would
+/// only reach the direct child.
+pub struct ChildGuard {
+    processes: ChildProcesses,
+    pid: u32,
+    exited: bool,
+}
+
+impl ChildGuard {
+    /// The child has been waited for; there is nothing left to kill.
+    pub fn exited(&mut self) {
+        self.exited = true;
+    }
+}
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        self.processes.0.lock().unwrap().remove(&self.pid);
+        if !self.exited {
+            kill_group(self.pid);
+        }
+    }
+}
+
+/// Sends SIGKILL to the process group led by `pid`.
+pub fn kill_group(pid: u32) {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return;
+    };
+    // A negative pid addresses the whole process group
+    if unsafe { libc::kill(-pid, libc::SIGKILL) } != 0 {
+        warn!(
+            "Failed to kill process group {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        );
+    }
+}
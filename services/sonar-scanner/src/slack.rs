@@ -0,0 +1,122 @@
+//! Slack notifications. Every finished job, failed ones included, can post a
+//! summary to a Slack incoming webhook: the project, whether the scan passes
+//! by `SLACK_FAIL_ON`, issue counts per severity, how long the scan took and
+//! a link to the job's HTML report. The webhook is the request's
+//! `slack_webhook_url`, else the one configured for its API key, else
+//! `SLACK_WEBHOOK_URL`.
+
+use crate::config::SlackConfig;
+use crate::job_list::severity_counts;
+use crate::notify::{self, Notice, Notifier};
+use crate::proxy::OutboundProxy;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio_util::task::TaskTracker;
+
+/// Posts job summaries to Slack incoming webhooks.
+#[derive(Clone)]
+pub struct SlackNotifier {
+    http: reqwest::Client,
+    config: SlackConfig,
+}
+
+impl SlackNotifier {
+    pub fn new(config: &SlackConfig, proxy: &OutboundProxy) -> Self {
+        let http = proxy
+            .client_builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    /// The Block Kit message: the project and its verdict, the summary as
+    /// fields, and a footer linking to the report.
+    fn message(&self, notice: &Notice<'_>) -> Value {
+        let project = escape(notice.project());
+        let (passed, verdict, reason) = notice.verdict(self.config.fail_on);
+        let icon = if passed { ":white_check_mark:" } else { ":x:" };
+
+        let mut fields = vec![
+            field("Verdict", &format!("{}: {}", verdict, escape(&reason))),
+            field("Duration", &notice.duration_text()),
+        ];
+        if let Ok(response) = notice.outcome {
+            if let Some(gate) = &response.quality_gate {
+                fields.push(field("Quality gate", &escape(&gate.status)));
+            }
+            let counts = severity_counts(response);
+            fields.push(field(
+                "Issues",
+                &format!(
+                    "Blocker {} · Critical {} · Major {} · Minor {} · Info {}",
+                    counts.blocker, counts.critical, counts.major, counts.minor, counts.info
+                ),
+            ));
+        }
+
+        let footer = match &self.config.public_url {
+            Some(url) => format!(
+                "Job `{}` · <{}|HTML report>",
+                notice.job_id,
+                notify::report_url(url, notice.job_id)
+            ),
+            None => format!("Job `{}`", notice.job_id),
+        };
+        json!({
+            "text": format!("{} {}: {}", icon, project, verdict),
+            "blocks": [
+                {
+                    "type": "section",
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": format!("{} *{}*: {}", icon, project, verdict),
+                    },
+                },
+                { "type": "section", "fields": fields },
+                {
+                    "type": "context",
+                    "elements": [{ "type": "mrkdwn", "text": footer }],
+                },
+            ],
+        })
+    }
+}
+
+impl Notifier for SlackNotifier {
+    fn notify(&self, tasks: &TaskTracker, notice: &Notice<'_>) {
+        let webhook = notify::webhook_for(
+            notice.options.slack_webhook_url.as_ref(),
+            notice.options.caller.api_key.as_deref(),
+            &self.config.api_key_webhooks,
+            self.config.webhook_url.as_deref(),
+        );
+        let Some(webhook_url) = webhook else {
+            return;
+        };
+        let message = self.message(notice);
+        notify::post_webhook(
+            tasks,
+            &self.http,
+            webhook_url,
+            message,
+            notice.job_id,
+            "Slack",
+        );
+    }
+}
+
+fn field(title: &str, value: &str) -> Value {
+    json!({ "type": "mrkdwn", "text": format!("*{}*\n{}", title, value) })
+}
+
+/// Escapes the characters Slack's mrkdwn reads as markup.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,77 @@
+use crate::SonarIssue;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path},
+};
+
+/// Largest `snippet_lines` a request may ask for.
+pub const MAX_CONTEXT_LINES: usize = 20;
+
+/// Files larger than this are not read for snippets.
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Longer source lines are cut to this many characters.
+const MAX_LINE_CHARS: usize = 400;
+
+/// Source lines around an issue, for rendering without the archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeSnippet {
+    /// 1-based line number of the first entry in `lines`.
+    pub start_line: u32,
+    pub lines: Vec<String>,
+}
+
+/// Attaches up to `context` lines before and after each issue's line, read
+/// from the extracted project. Issues without a line, or whose file is
+/// missing, too large or not UTF-8, are left without a snippet.
+pub fn attach(project_dir: &Path, issues: &mut [SonarIssue], context: usize) {
+    let mut files: HashMap<String, Option<Vec<String>>> = HashMap::new();
+
+    for issue in issues.iter_mut() {
+        let Some(line) = issue.line.filter(|line| *line > 0) else {
+            continue;
+        };
+        let lines = files
+            .entry(issue.path().to_string())
+            .or_insert_with(|| read_lines(project_dir, issue.path()));
+        let Some(lines) = lines else {
+            continue;
+        };
+
+        let index = line as usize - 1;
+        if index >= lines.len() {
+            continue;
+        }
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+        issue.snippet = Some(CodeSnippet {
+            start_line: start as u32 + 1,
+            lines: lines[start..end].to_vec(),
+        });
+    }
+}
+
+/// Lines of `path`, which must stay inside `project_dir`.
+pub fn read_lines(project_dir: &Path, path: &str) -> Option<Vec<String>> {
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let path = project_dir.join(relative);
+    let metadata = fs::symlink_metadata(&path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let text = fs::read_to_string(&path).ok()?;
+    Some(
+        text.lines()
+            .map(|line| line.chars().take(MAX_LINE_CHARS).collect())
+            .collect(),
+    )
+}
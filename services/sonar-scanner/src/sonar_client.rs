@@ -0,0 +1,1024 @@
+use crate::circuit_breaker::CircuitBreaker;
+use crate::config::{ProjectRetention, SonarConfig};
+use crate::hotspots::{HotspotsResponse, SecurityHotspot};
+use crate::measures::{self, ComponentMeasuresResponse, ProjectMetrics};
+use crate::proxy::OutboundProxy;
+use crate::quality_gate::{ProjectStatusResponse, QualityGate};
+use crate::request_id::REQUEST_ID_HEADER;
+use crate::retry::RetryPolicy;
+use crate::rules::{RuleDetails, RuleShowResponse};
+use crate::severity::Severity;
+use crate::sonar_webhook::{CeNotifications, CompletionWatch};
+use crate::{AppError, SonarIssue};
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// Largest page size `/api/issues/search` accepts.
+const ISSUES_PAGE_SIZE: usize = 500;
+
+/// SonarQube refuses to page past this many results (`p * ps`).
+const MAX_SEARCHABLE_ISSUES: usize = 10_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SonarIssuesResponse {
+    issues: Vec<SonarIssue>,
+    paging: Paging,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Paging {
+    total: usize,
+}
+
+/// Which issues to read from `/api/issues/search`.
+#[derive(Debug, Clone)]
+pub struct IssueFilter {
+    /// SonarQube issue types, e.g. `VULNERABILITY` or `BUG`.
+    pub types: Vec<String>,
+    /// Cap on the number of issues read.
+    pub max_issues: Option<usize>,
+    /// Only issues at least this severe are read.
+    pub min_severity: Option<Severity>,
+    /// Branch or pull request the issues were reported on; the main branch
+    /// when unset.
+    pub analysis_ref: Option<AnalysisRef>,
+}
+
+impl IssueFilter {
+    /// `min_severity` and everything above it, comma-separated as
+    /// `/api/issues/search` expects.
+    fn severities(&self) -> Option<String> {
+        let min_severity = self.min_severity?;
+        let severities: Vec<&str> = Severity::ALL
+            .into_iter()
+            .filter(|severity| *severity >= min_severity)
+            .map(Severity::as_str)
+            .collect();
+        Some(severities.join(","))
+    }
+
+    /// Whether an issue reported with `severity` passes `min_severity`.
+    /// Unknown severities only pass when no minimum is set.
+    pub fn accepts_severity(&self, severity: &str) -> bool {
+        match self.min_severity {
+            Some(min_severity) => Severity::parse(severity).is_some_and(|s| s >= min_severity),
+            None => true,
+        }
+    }
+}
+
+/// A non-main-branch analysis, as named in Web API queries.
+#[derive(Debug, Clone)]
+pub enum AnalysisRef {
+    Branch(String),
+    /// Pull request key. Only issues new in the pull request are reported.
+    PullRequest(String),
+}
+
+impl AnalysisRef {
+    fn query(&self) -> [(&'static str, &str); 1] {
+        match self {
+            AnalysisRef::Branch(name) => [("branch", name)],
+            AnalysisRef::PullRequest(key) => [("pullRequest", key)],
+        }
+    }
+}
+
+/// Issues read from `/api/issues/search`.
+#[derive(Debug, Default)]
+pub struct FetchedIssues {
+    pub issues: Vec<SonarIssue>,
+    /// Number of matching issues reported by SonarQube.
+    pub total: usize,
+    /// Fewer issues were read than SonarQube reported.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ComputeEngineTask {
+    #[serde(default)]
+    id: String,
+    status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ComputeEngineResponse {
+    tasks: Vec<ComputeEngineTask>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhooksResponse {
+    webhooks: Vec<Webhook>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Webhook {
+    key: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SystemStatusResponse {
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthenticationResponse {
+    valid: bool,
+}
+
+/// How long a readiness probe waits for SonarQube.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Name of the global webhook this service registers.
+const WEBHOOK_NAME: &str = "sonar-backend";
+
+/// While waiting for the webhook, the task status is still checked this
+/// often in case the notification went to another replica or was lost.
+const WEBHOOK_FALLBACK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// SonarQube Web API client shared by all requests.
+///
+/// Every call goes through a circuit breaker so that an unreachable server
+/// fails fast instead of costing each scan a full round of timeouts.
+#[derive(Clone)]
+pub struct SonarClient {
+    http: reqwest::Client,
+    pub base_url: String,
+    pub token: String,
+    pub password: String,
+    breaker: Arc<CircuitBreaker>,
+    retry: RetryPolicy,
+    poll_interval: Duration,
+    /// Longest wait for a compute engine task.
+    poll_timeout: Duration,
+    /// Extra issue fetches after CE success while the count keeps changing.
+    stabilization_attempts: u32,
+    stabilization_delay: Duration,
+    project_retention: ProjectRetention,
+    /// Task completions pushed to `/sonarqube/webhook`.
+    notifications: CeNotifications,
+    /// Sent as `X-Request-Id` so SonarQube's access log can be matched to
+    /// the request that started the job.
+    request_id: Option<String>,
+}
+
+impl SonarClient {
+    pub fn new(config: &SonarConfig, proxy: &OutboundProxy) -> Self {
+        let http = proxy
+            .client_builder()
+            .timeout(config.request_timeout())
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            base_url: config.url.clone(),
+            token: config.token.clone(),
+            password: config.password.clone(),
+            breaker: Arc::new(CircuitBreaker::new(
+                config.breaker_threshold,
+                Duration::from_secs(config.breaker_cooldown_secs),
+            )),
+            retry: config.retry_policy(),
+            poll_interval: config.poll_interval(),
+            poll_timeout: config.poll_timeout(),
+            stabilization_attempts: config.stabilization_attempts,
+            stabilization_delay: Duration::from_millis(config.stabilization_delay_ms),
+            project_retention: config.project_retention,
+            notifications: CeNotifications::default(),
+            request_id: None,
+        }
+    }
+
+    /// A client acting with `token` instead of the configured credentials.
+    /// It shares this one's connections, circuit breaker and webhook
+    /// listeners.
+    pub fn with_token(&self, token: &str) -> Self {
+        Self {
+            token: token.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// A client sending `id` as `X-Request-Id` with every call.
+    pub fn with_request_id(&self, id: &str) -> Self {
+        Self {
+            request_id: Some(id.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// A client waiting for compute engine tasks on its own schedule: status
+    /// checks every `interval` and at most `timeout` in all, where given.
+    pub fn with_polling(&self, interval: Option<Duration>, timeout: Option<Duration>) -> Self {
+        Self {
+            poll_interval: interval.unwrap_or(self.poll_interval),
+            poll_timeout: timeout.unwrap_or(self.poll_timeout),
+            ..self.clone()
+        }
+    }
+
+    pub fn notifications(&self) -> &CeNotifications {
+        &self.notifications
+    }
+
+    pub fn use_token(&self) -> bool {
+        !self.token.trim().is_empty()
+    }
+
+    /// Fails fast with [`AppError::SonarQubeUnavailable`] while the breaker is open.
+    pub fn ensure_available(&self) -> Result<(), AppError> {
+        if self.breaker.is_available() {
+            Ok(())
+        } else {
+            Err(AppError::SonarQubeUnavailable(
+                "circuit breaker is open after repeated failures".to_string(),
+            ))
+        }
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.authed(self.http.get(format!("{}{}", self.base_url, path)))
+    }
+
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.authed(self.http.post(format!("{}{}", self.base_url, path)))
+    }
+
+    fn authed(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let request = match &self.request_id {
+            Some(id) => request.header(REQUEST_ID_HEADER, id),
+            None => request,
+        };
+        if self.use_token() {
+            request.basic_auth(&self.token, Some(""))
+        } else {
+            request.basic_auth("admin", Some(&self.password))
+        }
+    }
+
+    /// Sends a request through the circuit breaker, retrying connection
+    /// errors, timeouts and 5xx responses under the retry policy. Each failed
+    /// attempt counts against the breaker; any other response means SonarQube
+    /// is up. The last 5xx response is returned once retries run out.
+    async fn send(
+        &self,
+        mut request: reqwest::RequestBuilder,
+        context: &str,
+    ) -> Result<reqwest::Response, AppError> {
+        let mut attempt = 1;
+        loop {
+            if !self.breaker.try_acquire() {
+                return Err(AppError::SonarQubeUnavailable(format!(
+                    "{}: circuit breaker is open",
+                    context
+                )));
+            }
+
+            // Requests with streaming bodies cannot be cloned and get one attempt
+            let next = if self.retry.should_retry(attempt) {
+                request.try_clone()
+            } else {
+                None
+            };
+
+            let (failure, next) = match (request.send().await, next) {
+                (Ok(response), Some(next)) if response.status().is_server_error() => {
+                    self.breaker.record_failure();
+                    (format!("status {}", response.status()), next)
+                }
+                (Err(e), Some(next)) if e.is_connect() || e.is_timeout() => {
+                    self.breaker.record_failure();
+                    (e.to_string(), next)
+                }
+                (Ok(response), _) => {
+                    if response.status().is_server_error() {
+                        self.breaker.record_failure();
+                    } else {
+                        self.breaker.record_success();
+                    }
+                    return Ok(response);
+                }
+                (Err(e), _) => {
+                    self.breaker.record_failure();
+                    return Err(AppError::ApiError(format!("{}: {}", context, e)));
+                }
+            };
+
+            let delay = self.retry.delay(attempt);
+            warn!(
+                "{} (attempt {}/{}): {}; retrying in {:?}",
+                context, attempt, self.retry.max_attempts, failure, delay
+            );
+            tokio::time::sleep(delay).await;
+            request = next;
+            attempt += 1;
+        }
+    }
+
+    /// Points the global webhook [`WEBHOOK_NAME`] at `url`, creating it or
+    /// updating an earlier registration, and switches scans from polling to
+    /// waiting for it. Needs the global `Administer` permission.
+    pub async fn register_webhook(&self, url: &str, secret: &str) -> Result<(), AppError> {
+        let response = self
+            .send(self.get("/api/webhooks/list"), "Failed to list webhooks")
+            .await?;
+        if !response.status().is_success() {
+            return Err(AppError::ApiError(format!(
+                "Failed to list webhooks. Status: {}",
+                response.status()
+            )));
+        }
+        let existing: WebhooksResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::ApiError(format!("Failed to parse webhooks: {}", e)))?;
+        let existing = existing
+            .webhooks
+            .into_iter()
+            .find(|webhook| webhook.name == WEBHOOK_NAME);
+
+        let mut form = vec![("name", WEBHOOK_NAME), ("url", url)];
+        if !secret.is_empty() {
+            form.push(("secret", secret));
+        }
+        let request = match &existing {
+            Some(webhook) => {
+                form.push(("webhook", webhook.key.as_str()));
+                self.post("/api/webhooks/update").form(&form)
+            }
+            None => self.post("/api/webhooks/create").form(&form),
+        };
+        let response = self.send(request, "Failed to register webhook").await?;
+        Self::expect_success(response, "Failed to register webhook").await?;
+
+        self.notifications.activate();
+        info!("Registered SonarQube webhook {} -> {}", WEBHOOK_NAME, url);
+        Ok(())
+    }
+
+    /// Waits for the project's compute engine task: for the webhook when
+    /// `watch` is set, otherwise by polling. Returns how long it waited.
+    pub async fn wait_for_completion(
+        &self,
+        project_key: &str,
+        watch: Option<CompletionWatch>,
+    ) -> Result<Duration, AppError> {
+        let Some(mut watch) = watch else {
+            return self.poll_for_completion(project_key).await;
+        };
+        info!("Waiting for the SonarQube webhook for {}", project_key);
+
+        let started = Instant::now();
+        let deadline = started + self.poll_timeout;
+        loop {
+            let wait =
+                WEBHOOK_FALLBACK_INTERVAL.min(deadline.saturating_duration_since(Instant::now()));
+            match tokio::time::timeout(wait, &mut watch.receiver).await {
+                Ok(Ok(status)) => {
+                    let outcome = ce_outcome(&status).unwrap_or_else(|| {
+                        Err(AppError::ApiError(format!(
+                            "SonarQube reported unexpected task status {}",
+                            status
+                        )))
+                    });
+                    return outcome.map(|()| started.elapsed());
+                }
+                // The registration was dropped; fall back to polling
+                Ok(Err(_)) => {
+                    self.poll_for_completion(project_key).await?;
+                    return Ok(started.elapsed());
+                }
+                Err(_) => {}
+            }
+
+            if let Some(outcome) = self
+                .ce_status(project_key)
+                .await?
+                .as_deref()
+                .and_then(ce_outcome)
+            {
+                return outcome.map(|()| started.elapsed());
+            }
+            if Instant::now() >= deadline {
+                return Err(self.poll_timeout_error());
+            }
+        }
+    }
+
+    /// Checks the task status every poll interval until it finishes or the
+    /// poll timeout passes. Returns how long it waited.
+    pub async fn poll_for_completion(&self, job_id: &str) -> Result<Duration, AppError> {
+        info!("Polling for task completion for job: {}", job_id);
+
+        let started = Instant::now();
+        let deadline = started + self.poll_timeout;
+        for attempt in 1.. {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            tokio::time::sleep(self.poll_interval.min(remaining)).await;
+
+            if let Some(status) = self.ce_status(job_id).await? {
+                info!("Task status: {}", status);
+                match ce_outcome(&status) {
+                    Some(outcome) => return outcome.map(|()| started.elapsed()),
+                    None => {
+                        // Still processing
+                        info!(
+                            "Task still processing (attempt {}, {}s of {}s)",
+                            attempt,
+                            started.elapsed().as_secs(),
+                            self.poll_timeout.as_secs()
+                        );
+                    }
+                }
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        Err(self.poll_timeout_error())
+    }
+
+    fn poll_timeout_error(&self) -> AppError {
+        AppError::ApiError(format!(
+            "Task polling timeout - analysis took longer than {}s",
+            self.poll_timeout.as_secs()
+        ))
+    }
+
+    /// Status of the project's latest compute engine task, if it has one and
+    /// `/api/ce/activity` answered.
+    async fn ce_status(&self, project_key: &str) -> Result<Option<String>, AppError> {
+        let request = self
+            .get("/api/ce/activity")
+            .query(&[("component", project_key)]);
+
+        let response = self.send(request, "Failed to poll task status").await?;
+
+        if !response.status().is_success() {
+            warn!(
+                "Task status check failed with status: {}",
+                response.status()
+            );
+            return Ok(None);
+        }
+
+        let ce_response: ComputeEngineResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::ApiError(format!("Failed to parse CE response: {}", e)))?;
+        Ok(ce_response.tasks.into_iter().next().map(|task| task.status))
+    }
+
+    /// Cancels the project's compute engine tasks that have not started
+    /// yet; SonarQube cannot stop one already in progress. Returns how many
+    /// were canceled.
+    pub async fn cancel_pending_tasks(&self, project_key: &str) -> Result<usize, AppError> {
+        let request = self
+            .get("/api/ce/activity")
+            .query(&[("component", project_key), ("status", "PENDING")]);
+        let response = self.send(request, "Failed to list pending tasks").await?;
+        // The project only exists once the scanner has submitted its report
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(0);
+        }
+        if !response.status().is_success() {
+            Self::expect_success(response, "Failed to list pending tasks").await?;
+            return Ok(0);
+        }
+        let pending: ComputeEngineResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::ApiError(format!("Failed to parse CE response: {}", e)))?;
+
+        for task in &pending.tasks {
+            let request = self
+                .post("/api/ce/cancel")
+                .form(&[("id", task.id.as_str())]);
+            let response = self.send(request, "Failed to cancel task").await?;
+            Self::expect_success(response, "Failed to cancel task").await?;
+        }
+        Ok(pending.tasks.len())
+    }
+
+    /// Pages through `/api/issues/search` until `paging.total` issues have
+    /// been read, `max_issues` is reached or SonarQube's 10,000 result
+    /// window is exhausted.
+    pub async fn fetch_vulnerabilities(
+        &self,
+        job_id: &str,
+        filter: &IssueFilter,
+    ) -> Result<FetchedIssues, AppError> {
+        // An empty `types` would match every type
+        if filter.types.is_empty() {
+            return Ok(FetchedIssues::default());
+        }
+
+        info!(
+            "Fetching {} issues for job: {}",
+            filter.types.join(","),
+            job_id
+        );
+
+        let types = filter.types.join(",");
+        let limit = filter
+            .max_issues
+            .unwrap_or(MAX_SEARCHABLE_ISSUES)
+            .min(MAX_SEARCHABLE_ISSUES);
+        let mut fetched = FetchedIssues::default();
+
+        for page in 1.. {
+            let page_index = page.to_string();
+            let page_size = ISSUES_PAGE_SIZE.to_string();
+            let mut request = self.get("/api/issues/search").query(&[
+                ("componentKeys", job_id),
+                ("types", types.as_str()),
+                ("p", page_index.as_str()),
+                ("ps", page_size.as_str()),
+            ]);
+            if let Some(analysis_ref) = &filter.analysis_ref {
+                request = request.query(&analysis_ref.query());
+            }
+            if let Some(severities) = filter.severities() {
+                request = request.query(&[("severities", severities)]);
+            }
+
+            let response = self.send(request, "Failed to fetch issues").await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                return Err(AppError::ApiError(format!(
+                    "Failed to fetch issues. Status: {}, Body: {}",
+                    status, body
+                )));
+            }
+
+            let issues_response: SonarIssuesResponse = response.json().await.map_err(|e| {
+                AppError::ApiError(format!("Failed to parse issues response: {}", e))
+            })?;
+
+            let page_len = issues_response.issues.len();
+            fetched.total = issues_response.paging.total;
+            fetched.issues.extend(issues_response.issues);
+
+            if page_len < ISSUES_PAGE_SIZE
+                || fetched.issues.len() >= fetched.total
+                || fetched.issues.len() >= limit
+            {
+                break;
+            }
+        }
+
+        fetched.issues.truncate(limit);
+        fetched.truncated = fetched.issues.len() < fetched.total;
+        if fetched.truncated {
+            warn!(
+                "Returning {} of {} issues for job: {}",
+                fetched.issues.len(),
+                fetched.total,
+                job_id
+            );
+        }
+
+        info!("Found {} issues", fetched.total);
+        Ok(fetched)
+    }
+
+    /// Fetches issues until two consecutive fetches agree on the count.
+    ///
+    /// The issues index can lag behind CE task completion, so the first fetch
+    /// after SUCCESS may be incomplete. Returns the largest result seen.
+    pub async fn fetch_stable_vulnerabilities(
+        &self,
+        job_id: &str,
+        filter: &IssueFilter,
+    ) -> Result<FetchedIssues, AppError> {
+        fetch_until_stable(
+            self.stabilization_attempts,
+            self.stabilization_delay,
+            || self.fetch_vulnerabilities(job_id, filter),
+        )
+        .await
+    }
+
+    /// Quality gate status of `project_key`'s latest analysis, on the given
+    /// branch or pull request.
+    pub async fn quality_gate(
+        &self,
+        project_key: &str,
+        analysis_ref: Option<&AnalysisRef>,
+    ) -> Result<QualityGate, AppError> {
+        let mut request = self
+            .get("/api/qualitygates/project_status")
+            .query(&[("projectKey", project_key)]);
+        if let Some(analysis_ref) = analysis_ref {
+            request = request.query(&analysis_ref.query());
+        }
+        let response = self
+            .send(request, "Failed to fetch quality gate status")
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::ApiError(format!(
+                "Failed to fetch quality gate status. Status: {}, Body: {}",
+                status, body
+            )));
+        }
+
+        response
+            .json::<ProjectStatusResponse>()
+            .await
+            .map(QualityGate::from)
+            .map_err(|e| AppError::ApiError(format!("Failed to parse quality gate status: {}", e)))
+    }
+
+    /// Size, duplication, complexity and security rating of the project's
+    /// latest analysis.
+    pub async fn project_metrics(
+        &self,
+        project_key: &str,
+        analysis_ref: Option<&AnalysisRef>,
+    ) -> Result<ProjectMetrics, AppError> {
+        let mut request = self.get("/api/measures/component").query(&[
+            ("component", project_key),
+            ("metricKeys", measures::METRIC_KEYS),
+        ]);
+        if let Some(analysis_ref) = analysis_ref {
+            request = request.query(&analysis_ref.query());
+        }
+        let response = self.send(request, "Failed to fetch measures").await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::ApiError(format!(
+                "Failed to fetch measures. Status: {}, Body: {}",
+                status, body
+            )));
+        }
+
+        response
+            .json::<ComponentMeasuresResponse>()
+            .await
+            .map(ProjectMetrics::from)
+            .map_err(|e| AppError::ApiError(format!("Failed to parse measures: {}", e)))
+    }
+
+    /// Pages through `/api/hotspots/search` for `project_key`'s security
+    /// hotspots, reviewed or not, up to SonarQube's 10,000 result window.
+    pub async fn fetch_hotspots(
+        &self,
+        project_key: &str,
+        analysis_ref: Option<&AnalysisRef>,
+    ) -> Result<Vec<SecurityHotspot>, AppError> {
+        let mut hotspots: Vec<SecurityHotspot> = Vec::new();
+
+        for page in 1.. {
+            let page_index = page.to_string();
+            let page_size = ISSUES_PAGE_SIZE.to_string();
+            let mut request = self.get("/api/hotspots/search").query(&[
+                ("projectKey", project_key),
+                ("p", page_index.as_str()),
+                ("ps", page_size.as_str()),
+            ]);
+            if let Some(analysis_ref) = analysis_ref {
+                request = request.query(&analysis_ref.query());
+            }
+
+            let response = self.send(request, "Failed to fetch hotspots").await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                return Err(AppError::ApiError(format!(
+                    "Failed to fetch hotspots. Status: {}, Body: {}",
+                    status, body
+                )));
+            }
+
+            let hotspots_response: HotspotsResponse = response.json().await.map_err(|e| {
+                AppError::ApiError(format!("Failed to parse hotspots response: {}", e))
+            })?;
+
+            let page_len = hotspots_response.hotspots.len();
+            hotspots.extend(
+                hotspots_response
+                    .hotspots
+                    .into_iter()
+                    .map(SecurityHotspot::from),
+            );
+
+            if page_len < ISSUES_PAGE_SIZE
+                || hotspots.len() >= hotspots_response.paging.total
+                || hotspots.len() >= MAX_SEARCHABLE_ISSUES
+            {
+                break;
+            }
+        }
+
+        info!("Found {} hotspots", hotspots.len());
+        Ok(hotspots)
+    }
+
+    /// Name, description and remediation guidance of `rule`.
+    pub async fn rule_details(&self, rule: &str) -> Result<RuleDetails, AppError> {
+        let request = self.get("/api/rules/show").query(&[("key", rule)]);
+        let response = self.send(request, "Failed to fetch rule").await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(AppError::ApiError(format!(
+                "Failed to fetch rule. Status: {}, Body: {}",
+                status, body
+            )));
+        }
+
+        response
+            .json::<RuleShowResponse>()
+            .await
+            .map(RuleDetails::from)
+            .map_err(|e| AppError::ApiError(format!("Failed to parse rule: {}", e)))
+    }
+
+    pub async fn create_project(&self, project_key: &str) -> Result<(), AppError> {
+        let request = self
+            .post("/api/projects/create")
+            .form(&[("project", project_key), ("name", project_key)]);
+        let response = self.send(request, "Failed to create project").await?;
+        Self::expect_success(response, "Failed to create project").await
+    }
+
+    pub async fn delete_project(&self, project_key: &str) -> Result<(), AppError> {
+        let request = self
+            .post("/api/projects/delete")
+            .form(&[("project", project_key)]);
+        let response = self.send(request, "Failed to delete project").await?;
+        Self::expect_success(response, "Failed to delete project").await
+    }
+
+    /// Deletes the project if it exists; `false` when there was none.
+    pub async fn delete_project_if_exists(&self, project_key: &str) -> Result<bool, AppError> {
+        let request = self
+            .post("/api/projects/delete")
+            .form(&[("project", project_key)]);
+        let response = self.send(request, "Failed to delete project").await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        Self::expect_success(response, "Failed to delete project").await?;
+        Ok(true)
+    }
+
+    /// Deletes a finished scan's projects as the retention policy asks.
+    /// Failures are only logged; the results have already been fetched.
+    pub async fn clean_up_projects(&self, project_keys: &[String], succeeded: bool) {
+        let delete = match self.project_retention {
+            ProjectRetention::Keep => false,
+            ProjectRetention::Delete => true,
+            ProjectRetention::DeleteOnSuccess => succeeded,
+        };
+        if !delete {
+            return;
+        }
+
+        for project_key in project_keys {
+            match self.delete_project(project_key).await {
+                Ok(()) => info!("Deleted SonarQube project {}", project_key),
+                Err(e) => warn!("Could not delete SonarQube project {}: {}", project_key, e),
+            }
+        }
+    }
+
+    pub async fn add_quality_profile(
+        &self,
+        project_key: &str,
+        language: &str,
+        profile: &str,
+    ) -> Result<(), AppError> {
+        let request = self.post("/api/qualityprofiles/add_project").form(&[
+            ("project", project_key),
+            ("language", language),
+            ("qualityProfile", profile),
+        ]);
+        let response = self
+            .send(request, "Failed to assign quality profile")
+            .await?;
+        Self::expect_success(response, "Failed to assign quality profile").await
+    }
+
+    async fn expect_success(response: reqwest::Response, context: &str) -> Result<(), AppError> {
+        if response.status().is_success() {
+            return Ok(());
+        }
+
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        Err(AppError::ApiError(format!(
+            "{}. Status: {}, Body: {}",
+            context, status, body
+        )))
+    }
+
+    /// The server's `api/system/status`, e.g. `UP` or `STARTING`. Sent once,
+    /// outside the circuit breaker, so probes see the server's actual state.
+    pub async fn system_status(&self) -> Result<String, AppError> {
+        let response = self
+            .get("/api/system/status")
+            .timeout(STATUS_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| AppError::SonarQubeUnavailable(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(AppError::SonarQubeUnavailable(format!(
+                "api/system/status returned {}",
+                response.status()
+            )));
+        }
+        let body: SystemStatusResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::ApiError(format!("Failed to parse system status: {}", e)))?;
+        Ok(body.status)
+    }
+
+    /// Confirms the configured token (or admin password) is accepted.
+    pub async fn validate_auth(&self) -> Result<(), AppError> {
+        let response = self
+            .get("/api/authentication/validate")
+            .timeout(STATUS_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| AppError::SonarQubeUnavailable(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(AppError::SonarQubeUnavailable(format!(
+                "api/authentication/validate returned {}",
+                response.status()
+            )));
+        }
+        let body: AuthenticationResponse = response.json().await.map_err(|e| {
+            AppError::ApiError(format!("Failed to parse authentication status: {}", e))
+        })?;
+        if !body.valid {
+            return Err(AppError::ApiError(
+                "SonarQube rejected the configured credentials".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// SonarQube server version, or `None` if it cannot be determined.
+    pub async fn server_version(&self) -> Option<String> {
+        let request = self.get("/api/server/version");
+        let response = self
+            .send(request, "Failed to fetch server version")
+            .await
+            .ok()?;
+
+        if !response.status().is_success() {
+            warn!(
+                "Server version lookup failed with status: {}",
+                response.status()
+            );
+            return None;
+        }
+
+        response
+            .text()
+            .await
+            .ok()
+            .map(|version| version.trim().to_string())
+    }
+}
+
+/// What a finished task's status means for the scan; `None` while the task
+/// is still pending or running.
+fn ce_outcome(status: &str) -> Option<Result<(), AppError>> {
+    match status {
+        "SUCCESS" => {
+            info!("Task completed successfully");
+            Some(Ok(()))
+        }
+        "FAILED" => Some(Err(AppError::ApiError(
+            "SonarQube analysis task failed".to_string(),
+        ))),
+        "CANCELED" => Some(Err(AppError::ApiError(
+            "SonarQube analysis task was canceled".to_string(),
+        ))),
+        _ => None,
+    }
+}
+
+/// Calls `fetch` until two consecutive results agree on the count, retrying
+/// at most `attempts` times after `delay` each. Returns the largest result
+/// seen.
+async fn fetch_until_stable<F, Fut>(
+    attempts: u32,
+    delay: Duration,
+    mut fetch: F,
+) -> Result<FetchedIssues, AppError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<FetchedIssues, AppError>>,
+{
+    let mut fetched = fetch().await?;
+    let mut previous = fetched.total;
+
+    for attempt in 1..=attempts {
+        tokio::time::sleep(delay).await;
+
+        let next = fetch().await?;
+        if next.total == previous {
+            break;
+        }
+
+        info!(
+            "Issue count changed from {} to {} (stabilization attempt {}/{})",
+            previous, next.total, attempt, attempts
+        );
+        previous = next.total;
+        if next.total > fetched.total {
+            fetched = next;
+        }
+    }
+
+    Ok(fetched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn issues(count: usize) -> FetchedIssues {
+        FetchedIssues {
+            issues: (0..count)
+                .map(|i| SonarIssue::for_test(&i.to_string(), "js:S1", "MAJOR", "a.js", 1))
+                .collect(),
+            total: count,
+            truncated: false,
+        }
+    }
+
+    /// Fetches that return `counts` in turn, repeating the last one.
+    async fn stabilize(attempts: u32, counts: &[usize]) -> (FetchedIssues, usize) {
+        let calls = AtomicUsize::new(0);
+        let fetched = fetch_until_stable(attempts, Duration::ZERO, || {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            let count = counts[call.min(counts.len() - 1)];
+            async move { Ok(issues(count)) }
+        })
+        .await
+        .unwrap();
+        (fetched, calls.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn refetches_until_the_count_stops_growing() {
+        let (fetched, calls) = stabilize(5, &[3, 7, 9, 9]).await;
+        assert_eq!(fetched.total, 9);
+        assert_eq!(fetched.issues.len(), 9);
+        assert_eq!(calls, 4);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_largest_result() {
+        let (fetched, calls) = stabilize(2, &[4, 8, 6]).await;
+        assert_eq!(fetched.total, 8);
+        assert_eq!(calls, 3);
+    }
+
+    #[tokio::test]
+    async fn stops_once_counts_agree() {
+        let (fetched, calls) = stabilize(5, &[5, 5, 9]).await;
+        assert_eq!(fetched.total, 5);
+        assert_eq!(calls, 2);
+    }
+
+    #[tokio::test]
+    async fn compares_with_the_previous_fetch_not_the_largest() {
+        // 11 then 12 differ, though 12 matches the largest count seen
+        let (fetched, calls) = stabilize(5, &[10, 12, 11, 12, 12]).await;
+        assert_eq!(fetched.total, 12);
+        assert_eq!(calls, 5);
+    }
+
+    #[tokio::test]
+    async fn fetches_once_without_attempts() {
+        let (fetched, calls) = stabilize(0, &[2, 9]).await;
+        assert_eq!(fetched.total, 2);
+        assert_eq!(calls, 1);
+    }
+}
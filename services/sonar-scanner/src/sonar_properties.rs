@@ -0,0 +1,152 @@
+use crate::config::PropertiesPrecedence;
+use crate::scanner_validation::{parse_properties, PROJECT_PROPERTIES_FILE};
+use crate::AppError;
+use std::{fs, path::Path};
+
+/// Properties the service sets itself; a request can never override them.
+const RESERVED_KEYS: &[&str] = &[
+    "sonar.projectKey",
+    "sonar.host.url",
+    "sonar.login",
+    "sonar.password",
+    "sonar.token",
+    "sonar.projectBaseDir",
+    "sonar.working.directory",
+];
+
+/// Properties a request may set when no allowlist is configured. Entries
+/// ending in `*` match any key with that prefix.
+pub const DEFAULT_ALLOWED: &[&str] = &[
+    "sonar.sources",
+    "sonar.tests",
+    "sonar.inclusions",
+    "sonar.exclusions",
+    "sonar.test.inclusions",
+    "sonar.test.exclusions",
+    "sonar.coverage.exclusions",
+    "sonar.cpd.exclusions",
+    "sonar.sourceEncoding",
+    "sonar.projectName",
+    "sonar.projectVersion",
+    "sonar.issue.ignore.*",
+    "sonar.java.binaries",
+    "sonar.java.libraries",
+    "sonar.java.source",
+    "sonar.python.version",
+];
+
+/// Vendored, generated and built code left out of scans unless a request
+/// opts out: third-party code the caller cannot fix, which only costs time.
+pub const DEFAULT_EXCLUSIONS: &[&str] = &[
+    "**/node_modules/**",
+    "**/bower_components/**",
+    "**/vendor/**",
+    "**/third_party/**",
+    "**/dist/**",
+    "**/target/**",
+    "**/__pycache__/**",
+    "**/.venv/**",
+    "**/*.min.js",
+    "**/*.min.css",
+    "**/*.bundle.js",
+    "**/*.pb.go",
+    "**/*_pb2.py",
+];
+
+/// Parses the `sonar_properties` field: a JSON object of property names to
+/// values. Numbers and booleans are accepted and passed through as text.
+pub fn parse(value: &str) -> Result<Vec<(String, String)>, AppError> {
+    let invalid =
+        |reason: String| AppError::InvalidRequest(format!("Field 'sonar_properties' {}", reason));
+
+    let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(value)
+        .map_err(|e| invalid(format!("must be a JSON object: {}", e)))?;
+
+    let mut properties = Vec::with_capacity(object.len());
+    for (key, value) in object {
+        if !is_valid_key(&key) {
+            return Err(invalid(format!("has an invalid property name '{}'", key)));
+        }
+        let value = match value {
+            serde_json::Value::String(text) => text,
+            serde_json::Value::Number(number) => number.to_string(),
+            serde_json::Value::Bool(flag) => flag.to_string(),
+            _ => return Err(invalid(format!("value for '{}' must be a string", key))),
+        };
+        if value.chars().any(char::is_control) {
+            return Err(invalid(format!(
+                "value for '{}' contains control characters",
+                key
+            )));
+        }
+        properties.push((key, value));
+    }
+    Ok(properties)
+}
+
+/// Rejects properties that are reserved or not on the allowlist.
+pub fn check_allowed(properties: &[(String, String)], allowed: &[String]) -> Result<(), AppError> {
+    for (key, _) in properties {
+        if RESERVED_KEYS.contains(&key.as_str()) {
+            return Err(AppError::InvalidRequest(format!(
+                "Property '{}' is set by the service and cannot be overridden",
+                key
+            )));
+        }
+        if !allowed.iter().any(|pattern| matches(pattern, key)) {
+            return Err(AppError::InvalidRequest(format!(
+                "Property '{}' is not allowed (allowed: {})",
+                key,
+                allowed.join(", ")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Project file keys that describe the source layout themselves, so the
+/// default `sonar.sources` is dropped when any of them is set.
+const LAYOUT_KEYS: &[&str] = &["sonar.sources", "sonar.modules"];
+
+/// Resolves the service `defaults` against the project's
+/// `sonar-project.properties`. Under `Project` precedence, defaults the file
+/// already sets are dropped so the scanner reads the file's values instead.
+pub fn merge_project_file(
+    project_dir: &Path,
+    defaults: Vec<(String, String)>,
+    precedence: PropertiesPrecedence,
+) -> Vec<(String, String)> {
+    if precedence == PropertiesPrecedence::Service {
+        return defaults;
+    }
+    let Ok(text) = fs::read_to_string(project_dir.join(PROJECT_PROPERTIES_FILE)) else {
+        return defaults;
+    };
+
+    let project = parse_properties(&text);
+    let sets = |key: &str| project.iter().any(|(k, _)| k == key);
+    defaults
+        .into_iter()
+        .filter(|(key, _)| {
+            if key == "sonar.sources" {
+                !LAYOUT_KEYS.iter().any(|layout| sets(layout))
+            } else {
+                !sets(key)
+            }
+        })
+        .collect()
+}
+
+fn matches(pattern: &str, key: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => key.starts_with(prefix),
+        None => pattern == key,
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    key.starts_with("sonar.")
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
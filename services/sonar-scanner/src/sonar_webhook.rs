@@ -0,0 +1,139 @@
+//! `POST /sonarqube/webhook`: SonarQube pushes compute engine completions
+//! here, so scans learn that their analysis is done without polling
+//! `/api/ce/activity` every few seconds.
+
+use crate::auth::constant_time_eq;
+use crate::{AppError, AppState};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::oneshot;
+use tracing::{debug, info};
+
+/// Header carrying the hex HMAC-SHA256 of the body under the webhook secret.
+const SIGNATURE_HEADER: &str = "x-sonar-webhook-hmac-sha256";
+
+/// The part of SonarQube's webhook payload that matters here.
+#[derive(Debug, Deserialize)]
+struct WebhookPayload {
+    /// `SUCCESS`, `FAILED` or `CANCELED`.
+    status: String,
+    project: WebhookProject,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookProject {
+    key: String,
+}
+
+/// Scans waiting for their compute engine task, by project key. Shared by
+/// every clone of the SonarQube client.
+#[derive(Clone, Default)]
+pub struct CeNotifications {
+    /// Set once the webhook is registered; scans poll until then.
+    active: Arc<AtomicBool>,
+    waiting: Arc<Mutex<HashMap<String, oneshot::Sender<String>>>>,
+}
+
+impl CeNotifications {
+    pub fn activate(&self) {
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    /// Starts listening for `project_key`'s task status. Called before the
+    /// scanner submits the report so a fast task cannot be missed; `None`
+    /// while the webhook is not registered.
+    pub fn watch(&self, project_key: &str) -> Option<CompletionWatch> {
+        if !self.active.load(Ordering::Relaxed) {
+            return None;
+        }
+        let (sender, receiver) = oneshot::channel();
+        self.waiting
+            .lock()
+            .unwrap()
+            .insert(project_key.to_string(), sender);
+        Some(CompletionWatch {
+            project_key: project_key.to_string(),
+            receiver,
+            waiting: self.waiting.clone(),
+        })
+    }
+
+    /// Hands `status` to the scan waiting for `project_key`, if any.
+    fn deliver(&self, project_key: &str, status: String) -> bool {
+        match self.waiting.lock().unwrap().remove(project_key) {
+            Some(sender) => sender.send(status).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// A scan's registration for its task status, withdrawn when dropped.
+pub struct CompletionWatch {
+    project_key: String,
+    pub receiver: oneshot::Receiver<String>,
+    waiting: Arc<Mutex<HashMap<String, oneshot::Sender<String>>>>,
+}
+
+impl Drop for CompletionWatch {
+    fn drop(&mut self) {
+        self.waiting.lock().unwrap().remove(&self.project_key);
+    }
+}
+
+/// Accepts a webhook call, checking its signature when a secret is set.
+/// Tasks of projects nobody is waiting for, e.g. other users' projects on a
+/// shared server, are acknowledged and ignored.
+pub async fn webhook_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, AppError> {
+    let secret = &state.config.sonar.webhook_secret;
+    if !secret.is_empty() {
+        let signature = headers
+            .get(SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !constant_time_eq(sign(secret, &body).as_bytes(), signature.as_bytes()) {
+            return Err(AppError::Unauthorized(
+                "missing or invalid webhook signature".to_string(),
+            ));
+        }
+    }
+
+    let payload: WebhookPayload = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid webhook payload: {}", e)))?;
+    if state
+        .sonar
+        .notifications()
+        .deliver(&payload.project.key, payload.status.clone())
+    {
+        info!(
+            "SonarQube reported task {} for {}",
+            payload.status, payload.project.key
+        );
+    } else {
+        debug!("Ignoring webhook for {}", payload.project.key);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("{:x}", mac.finalize().into_bytes())
+}
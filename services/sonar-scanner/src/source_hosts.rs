@@ -0,0 +1,136 @@
+//! Hosts the service fetches sources from on a caller's behalf, for
+//! `archive_url` and `git_url`. Unchecked, a caller could have it reach
+//! what only this host can, such as a cloud metadata endpoint or an internal
+//! API: the host must be one of `SOURCE_ALLOWED_HOSTS` when that is set, and
+//! every address it resolves to must be public.
+
+use crate::notify::check_allowed_host;
+use crate::AppError;
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Rejects the URL in request field `name` unless its host is allowed and
+/// resolves only to public addresses. Run again for every redirect.
+pub async fn check_source_url(
+    name: &str,
+    url: &Url,
+    allowed_hosts: &[String],
+) -> Result<(), AppError> {
+    if !allowed_hosts.is_empty() {
+        check_allowed_host(name, url, allowed_hosts)?;
+    }
+    let Some(host) = url.host_str() else {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' must name a host",
+            name
+        )));
+    };
+    // IPv6 hosts come bracketed
+    let literal = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<IpAddr> = match literal.parse::<IpAddr>() {
+        Ok(ip) => vec![ip],
+        Err(_) => {
+            let port = url.port_or_known_default().unwrap_or(443);
+            tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|e| {
+                    AppError::InvalidRequest(format!(
+                        "Field '{}': cannot resolve {}: {}",
+                        name, host, e
+                    ))
+                })?
+                .map(|address| address.ip())
+                .collect()
+        }
+    };
+    if addresses.is_empty() || !addresses.iter().all(|ip| is_public(*ip)) {
+        return Err(AppError::InvalidRequest(format!(
+            "Field '{}' must point at a public address, not {}",
+            name, host
+        )));
+    }
+    Ok(())
+}
+
+/// Whether `ip` is routable on the internet, so neither this host, its
+/// private networks, link-local metadata services nor reserved ranges.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && (18..20).contains(&b))
+        // Reserved, 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (first == 0x2001 && ip.segments()[1] == 0xdb8)
+        // NAT64, 64:ff9b::/96, whose addresses embed an IPv4 one
+        || (first == 0x64 && ip.segments()[1] == 0xff9b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn check(url: &str, allowed_hosts: &[&str]) -> Result<(), AppError> {
+        let allowed_hosts: Vec<String> = allowed_hosts.iter().map(|h| h.to_string()).collect();
+        check_source_url("archive_url", &Url::parse(url).unwrap(), &allowed_hosts).await
+    }
+
+    #[tokio::test]
+    async fn refuses_internal_addresses() {
+        for url in [
+            "http://127.0.0.1/a.zip",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.0.0.5/a.zip",
+            "http://192.168.1.1/a.zip",
+            "http://100.64.0.1/a.zip",
+            "http://[::1]/a.zip",
+            "http://[fd00::1]/a.zip",
+            "http://[::ffff:127.0.0.1]/a.zip",
+            "http://localhost:8080/a.zip",
+        ] {
+            assert!(check(url, &[]).await.is_err(), "{} was accepted", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_public_addresses_on_the_allowed_hosts() {
+        assert!(check("https://93.184.215.14/a.zip", &[]).await.is_ok());
+        assert!(check("https://[2606:4700::1111]/a.zip", &[]).await.is_ok());
+        assert!(check("https://93.184.215.14/a.zip", &["ci.example.com"])
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::artifacts::Artifact;
+use crate::config::{StorageBackend, StorageConfig};
+use crate::jobs::{JobError, JobEvent, JobStatus};
+use crate::logs::JobLogs;
+use crate::resource_limits::ProcessResources;
+use crate::AnalyzeResponse;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+mod postgres;
+mod sqlite;
+
+pub use postgres::PostgresRepository;
+pub use sqlite::SqliteRepository;
+
+/// Everything about a job that outlives the process: status, timing, events,
+/// the result payload, the tail of its scanner output, the resources its
+/// scanner runs used and its archived artifacts. Live channels are not kept.
+#[derive(Debug, Clone)]
+pub struct StoredJob {
+    pub id: String,
+    pub tenant: Option<String>,
+    pub project_name: Option<String>,
+    pub status: JobStatus,
+    pub created_at: OffsetDateTime,
+    pub started_at: Option<OffsetDateTime>,
+    pub finished_at: Option<OffsetDateTime>,
+    pub issue_count: Option<usize>,
+    pub error: Option<JobError>,
+    pub events: Vec<JobEvent>,
+    pub result: Option<Arc<AnalyzeResponse>>,
+    pub logs: JobLogs,
+    pub resources: Vec<ProcessResources>,
+    pub artifacts: Vec<Artifact>,
+}
+
+/// Durable job storage behind the in-memory [`JobStore`](crate::jobs::JobStore).
+#[async_trait]
+pub trait JobRepository: Send + Sync {
+    /// Inserts the job or replaces the stored copy.
+    async fn save(&self, job: &StoredJob) -> Result<()>;
+
+    async fn load(&self, id: &str) -> Result<Option<StoredJob>>;
+
+    /// Jobs still queued or running, left behind by a previous process.
+    async fn unfinished(&self) -> Result<Vec<StoredJob>>;
+
+    /// Jobs matching `filter`, newest first, skipping `offset` and returning
+    /// at most `limit`.
+    async fn list(&self, filter: &JobFilter, limit: usize, offset: usize) -> Result<JobPage>;
+
+    /// Deletes finished jobs that finished (or, lacking a finish time, were
+    /// created) before `cutoff`, and returns them.
+    async fn delete_finished_before(&self, cutoff: OffsetDateTime) -> Result<Vec<StoredJob>>;
+}
+
+/// Which jobs `GET /jobs` returns.
+#[derive(Debug, Default)]
+pub struct JobFilter {
+    /// Any of these statuses; every status when empty.
+    pub statuses: Vec<JobStatus>,
+    /// Created at or after this time.
+    pub since: Option<OffsetDateTime>,
+    /// Part of the project name, ignoring case.
+    pub project: Option<String>,
+    /// Submitted by this tenant; every tenant's jobs when unset.
+    pub tenant: Option<String>,
+    /// Filed under exactly this `project_name`.
+    pub project_name: Option<String>,
+}
+
+impl JobFilter {
+    pub fn matches(
+        &self,
+        status: JobStatus,
+        created_at: OffsetDateTime,
+        project: Option<&str>,
+        tenant: Option<&str>,
+        project_name: Option<&str>,
+    ) -> bool {
+        (self.statuses.is_empty() || self.statuses.contains(&status))
+            && self
+                .tenant
+                .as_deref()
+                .map_or(true, |wanted| tenant == Some(wanted))
+            && self
+                .project_name
+                .as_deref()
+                .map_or(true, |wanted| project_name == Some(wanted))
+            && self.since.map_or(true, |since| created_at >= since)
+            && self.project.as_ref().map_or(true, |wanted| {
+                project
+                    .is_some_and(|project| project.to_lowercase().contains(&wanted.to_lowercase()))
+            })
+    }
+
+    /// `LIKE` pattern for the project filter, with wildcards in the value
+    /// escaped by a backslash.
+    fn project_pattern(&self) -> Option<String> {
+        self.project.as_ref().map(|project| {
+            let escaped = project
+                .to_lowercase()
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        })
+    }
+}
+
+/// One page of a job listing.
+pub struct JobPage {
+    /// Jobs matching the filter, across all pages.
+    pub total: usize,
+    pub jobs: Vec<StoredJob>,
+}
+
+/// Opens the configured backend; `None` keeps jobs in memory only.
+pub async fn open(config: &StorageConfig) -> Result<Option<Arc<dyn JobRepository>>> {
+    match config.backend {
+        StorageBackend::Memory => Ok(None),
+        StorageBackend::Sqlite => Ok(Some(Arc::new(
+            SqliteRepository::open(&config.sqlite_path).await?,
+        ))),
+        StorageBackend::Postgres => {
+            let url = config
+                .database_url
+                .as_deref()
+                .context("The postgres job store requires DATABASE_URL")?;
+            Ok(Some(Arc::new(PostgresRepository::open(url).await?)))
+        }
+    }
+}
+
+/// A job as stored in a table row: plain integers and strings that every
+/// backend can bind and read. Timestamps are Unix milliseconds so rows sort
+/// by time; events, the result, the logs, resource usage and artifacts are
+/// JSON.
+struct JobRow {
+    id: String,
+    /// `NULL` for jobs submitted without a tenant, and for rows written
+    /// before tenants were stored.
+    tenant: Option<String>,
+    status: String,
+    /// Copied out of the result for `GET /jobs?project=`; `NULL` until the
+    /// job succeeds, and for rows written before it was stored.
+    project: Option<String>,
+    created_at: i64,
+    started_at: Option<i64>,
+    finished_at: Option<i64>,
+    issue_count: Option<i64>,
+    error_status: Option<i64>,
+    error_code: Option<String>,
+    error_message: Option<String>,
+    events: String,
+    result: Option<String>,
+    /// `NULL` for rows written before logs were stored.
+    logs: Option<String>,
+    /// `NULL` for rows written before resource usage was stored.
+    resources: Option<String>,
+    /// `NULL` for rows written before artifacts were stored.
+    artifacts: Option<String>,
+    /// The caller's `project_name`; `NULL` for jobs submitted without one,
+    /// and for rows written before it was stored.
+    project_name: Option<String>,
+}
+
+impl JobRow {
+    fn encode(job: &StoredJob) -> Result<Self> {
+        Ok(Self {
+            id: job.id.clone(),
+            tenant: job.tenant.clone(),
+            status: job.status.as_str().to_string(),
+            project: job
+                .result
+                .as_ref()
+                .and_then(|result| result.manifest.project_name())
+                .map(str::to_string),
+            created_at: to_millis(job.created_at),
+            started_at: job.started_at.map(to_millis),
+            finished_at: job.finished_at.map(to_millis),
+            issue_count: job.issue_count.map(|count| count as i64),
+            error_status: job.error.as_ref().map(|e| i64::from(e.status.as_u16())),
+            error_code: job.error.as_ref().map(|e| e.code.clone()),
+            error_message: job.error.as_ref().map(|e| e.message.clone()),
+            events: serde_json::to_string(&job.events)?,
+            result: job
+                .result
+                .as_ref()
+                .map(|result| serde_json::to_string(result.as_ref()))
+                .transpose()?,
+            logs: Some(serde_json::to_string(&job.logs)?),
+            resources: Some(serde_json::to_string(&job.resources)?),
+            artifacts: Some(serde_json::to_string(&job.artifacts)?),
+            project_name: job.project_name.clone(),
+        })
+    }
+
+    fn decode(self) -> Result<StoredJob> {
+        let status = JobStatus::parse(&self.status)
+            .ok_or_else(|| anyhow!("job {} has status {}", self.id, self.status))?;
+
+        let error = self.error_status.map(|code| JobError {
+            status: u16::try_from(code)
+                .ok()
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            code: self.error_code.unwrap_or_default(),
+            message: self.error_message.unwrap_or_default(),
+        });
+
+        Ok(StoredJob {
+            tenant: self.tenant,
+            project_name: self.project_name,
+            status,
+            created_at: from_millis(self.created_at)?,
+            started_at: self.started_at.map(from_millis).transpose()?,
+            finished_at: self.finished_at.map(from_millis).transpose()?,
+            issue_count: self.issue_count.map(|count| count as usize),
+            error,
+            events: serde_json::from_str(&self.events)?,
+            result: self
+                .result
+                .map(|result| serde_json::from_str(&result).map(Arc::new))
+                .transpose()?,
+            logs: self
+                .logs
+                .map(|logs| serde_json::from_str(&logs))
+                .transpose()?
+                .unwrap_or_default(),
+            resources: self
+                .resources
+                .map(|resources| serde_json::from_str(&resources))
+                .transpose()?
+                .unwrap_or_default(),
+            artifacts: self
+                .artifacts
+                .map(|artifacts| serde_json::from_str(&artifacts))
+                .transpose()?
+                .unwrap_or_default(),
+            id: self.id,
+        })
+    }
+}
+
+fn to_millis(at: OffsetDateTime) -> i64 {
+    (at.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn from_millis(millis: i64) -> Result<OffsetDateTime> {
+    Ok(OffsetDateTime::from_unix_timestamp_nanos(
+        i128::from(millis) * 1_000_000,
+    )?)
+}
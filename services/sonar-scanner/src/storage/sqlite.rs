@@ -0,0 +1,236 @@
+use super::{to_millis, JobFilter, JobPage, JobRepository, JobRow, StoredJob};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sqlx::{
+    sqlite::{
+        Sqlite, SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow,
+    },
+    QueryBuilder, Row,
+};
+use std::path::Path;
+use time::OffsetDateTime;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS jobs (
+    id TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    started_at INTEGER,
+    finished_at INTEGER,
+    issue_count INTEGER,
+    error_status INTEGER,
+    error_code TEXT,
+    error_message TEXT,
+    events TEXT NOT NULL,
+    result TEXT,
+    logs TEXT,
+    resources TEXT,
+    project TEXT,
+    artifacts TEXT,
+    tenant TEXT,
+    project_name TEXT
+);
+CREATE INDEX IF NOT EXISTS jobs_created_at ON jobs (created_at);
+";
+
+/// Job storage in a local SQLite file, created on first use.
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    pub async fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("Failed to open job database {}", path.display()))?;
+
+        sqlx::raw_sql(SCHEMA)
+            .execute(&pool)
+            .await
+            .context("Failed to create the jobs table")?;
+
+        // Databases created before logs, resource usage, the project,
+        // artifacts, the tenant and the project name were stored lack their
+        // columns
+        for column in [
+            "logs",
+            "resources",
+            "project",
+            "artifacts",
+            "tenant",
+            "project_name",
+        ] {
+            let exists: i64 =
+                sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info('jobs') WHERE name = ?")
+                    .bind(column)
+                    .fetch_one(&pool)
+                    .await?;
+            if exists == 0 {
+                sqlx::query(&format!("ALTER TABLE jobs ADD COLUMN {} TEXT", column))
+                    .execute(&pool)
+                    .await
+                    .with_context(|| format!("Failed to add the {} column", column))?;
+            }
+        }
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS jobs_project_name ON jobs (project_name, created_at)",
+        )
+        .execute(&pool)
+        .await
+        .context("Failed to index the project_name column")?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl JobRepository for SqliteRepository {
+    async fn save(&self, job: &StoredJob) -> Result<()> {
+        let row = JobRow::encode(job)?;
+        sqlx::query(
+            "INSERT OR REPLACE INTO jobs (id, status, created_at, started_at, finished_at, \
+             issue_count, error_status, error_code, error_message, events, result, logs, \
+             resources, project, artifacts, tenant, project_name) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(row.id)
+        .bind(row.status)
+        .bind(row.created_at)
+        .bind(row.started_at)
+        .bind(row.finished_at)
+        .bind(row.issue_count)
+        .bind(row.error_status)
+        .bind(row.error_code)
+        .bind(row.error_message)
+        .bind(row.events)
+        .bind(row.result)
+        .bind(row.logs)
+        .bind(row.resources)
+        .bind(row.project)
+        .bind(row.artifacts)
+        .bind(row.tenant)
+        .bind(row.project_name)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<StoredJob>> {
+        sqlx::query("SELECT * FROM jobs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|row| read_row(&row)?.decode())
+            .transpose()
+    }
+
+    async fn unfinished(&self) -> Result<Vec<StoredJob>> {
+        sqlx::query("SELECT * FROM jobs WHERE status IN ('QUEUED', 'RUNNING')")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| read_row(row)?.decode())
+            .collect()
+    }
+
+    async fn list(&self, filter: &JobFilter, limit: usize, offset: usize) -> Result<JobPage> {
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM jobs");
+        push_filter(&mut count, filter);
+        let total: i64 = count.build_query_scalar().fetch_one(&self.pool).await?;
+
+        let mut page = QueryBuilder::new("SELECT * FROM jobs");
+        push_filter(&mut page, filter);
+        page.push(" ORDER BY created_at DESC LIMIT ")
+            .push_bind(limit as i64)
+            .push(" OFFSET ")
+            .push_bind(offset as i64);
+        let jobs = page
+            .build()
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| read_row(row)?.decode())
+            .collect::<Result<_>>()?;
+
+        Ok(JobPage {
+            total: total as usize,
+            jobs,
+        })
+    }
+
+    async fn delete_finished_before(&self, cutoff: OffsetDateTime) -> Result<Vec<StoredJob>> {
+        sqlx::query(
+            "DELETE FROM jobs WHERE status IN ('SUCCEEDED', 'FAILED', 'CANCELED') \
+             AND COALESCE(finished_at, created_at) < ? RETURNING *",
+        )
+        .bind(to_millis(cutoff))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| read_row(row)?.decode())
+        .collect()
+    }
+}
+
+fn push_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &JobFilter) {
+    query.push(" WHERE 1 = 1");
+    if !filter.statuses.is_empty() {
+        query.push(" AND status IN (");
+        let mut statuses = query.separated(", ");
+        for status in &filter.statuses {
+            statuses.push_bind(status.as_str());
+        }
+        statuses.push_unseparated(")");
+    }
+    if let Some(since) = filter.since {
+        query
+            .push(" AND created_at >= ")
+            .push_bind(to_millis(since));
+    }
+    if let Some(pattern) = filter.project_pattern() {
+        query
+            .push(" AND LOWER(project) LIKE ")
+            .push_bind(pattern)
+            .push(" ESCAPE '\\'");
+    }
+    if let Some(tenant) = &filter.tenant {
+        query.push(" AND tenant = ").push_bind(tenant.clone());
+    }
+    if let Some(project_name) = &filter.project_name {
+        query
+            .push(" AND project_name = ")
+            .push_bind(project_name.clone());
+    }
+}
+
+fn read_row(row: &SqliteRow) -> Result<JobRow> {
+    Ok(JobRow {
+        id: row.try_get("id")?,
+        status: row.try_get("status")?,
+        created_at: row.try_get("created_at")?,
+        started_at: row.try_get("started_at")?,
+        finished_at: row.try_get("finished_at")?,
+        issue_count: row.try_get("issue_count")?,
+        error_status: row.try_get("error_status")?,
+        error_code: row.try_get("error_code")?,
+        error_message: row.try_get("error_message")?,
+        events: row.try_get("events")?,
+        result: row.try_get("result")?,
+        logs: row.try_get("logs")?,
+        resources: row.try_get("resources")?,
+        project: row.try_get("project")?,
+        artifacts: row.try_get("artifacts")?,
+        tenant: row.try_get("tenant")?,
+        project_name: row.try_get("project_name")?,
+    })
+}
@@ -0,0 +1,128 @@
+//! Microsoft Teams notifications, the counterpart of the Slack ones: every
+//! finished job can post an Adaptive Card to a Teams webhook, either an
+//! incoming webhook or a Workflows one, giving the project, whether the scan
+//! passes by `TEAMS_FAIL_ON`, issue counts per severity, how long the scan
+//! took and a link to the job's HTML report. The webhook is the request's
+//! `teams_webhook_url`, else the one configured for its API key, else
+//! `TEAMS_WEBHOOK_URL`.
+
+use crate::config::TeamsConfig;
+use crate::job_list::severity_counts;
+use crate::notify::{self, Notice, Notifier};
+use crate::proxy::OutboundProxy;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio_util::task::TaskTracker;
+
+/// Posts job summaries to Teams webhooks.
+#[derive(Clone)]
+pub struct TeamsNotifier {
+    http: reqwest::Client,
+    config: TeamsConfig,
+}
+
+impl TeamsNotifier {
+    pub fn new(config: &TeamsConfig, proxy: &OutboundProxy) -> Self {
+        let http = proxy
+            .client_builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    /// A message holding one Adaptive Card: the project and its verdict,
+    /// the summary as facts, and a button opening the report.
+    fn message(&self, notice: &Notice<'_>) -> Value {
+        let (passed, verdict, reason) = notice.verdict(self.config.fail_on);
+
+        let mut facts = vec![
+            fact("Verdict", &format!("{}: {}", verdict, reason)),
+            fact("Duration", &notice.duration_text()),
+        ];
+        if let Ok(response) = notice.outcome {
+            if let Some(gate) = &response.quality_gate {
+                facts.push(fact("Quality gate", &gate.status));
+            }
+            let counts = severity_counts(response);
+            for (title, count) in [
+                ("Blocker", counts.blocker),
+                ("Critical", counts.critical),
+                ("Major", counts.major),
+                ("Minor", counts.minor),
+                ("Info", counts.info),
+            ] {
+                facts.push(fact(title, &count.to_string()));
+            }
+        }
+
+        let mut card = json!({
+            "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+            "type": "AdaptiveCard",
+            "version": "1.4",
+            "body": [
+                {
+                    "type": "TextBlock",
+                    "text": format!("{}: {}", notice.project(), verdict),
+                    "size": "Medium",
+                    "weight": "Bolder",
+                    "color": if passed { "Good" } else { "Attention" },
+                    "wrap": true,
+                },
+                { "type": "FactSet", "facts": facts },
+                {
+                    "type": "TextBlock",
+                    "text": format!("Job {}", notice.job_id),
+                    "isSubtle": true,
+                    "size": "Small",
+                    "wrap": true,
+                },
+            ],
+        });
+        if let Some(url) = &self.config.public_url {
+            card["actions"] = json!([{
+                "type": "Action.OpenUrl",
+                "title": "HTML report",
+                "url": notify::report_url(url, notice.job_id),
+            }]);
+        }
+        json!({
+            "type": "message",
+            "attachments": [{
+                "contentType": "application/vnd.microsoft.card.adaptive",
+                "content": card,
+            }],
+        })
+    }
+}
+
+impl Notifier for TeamsNotifier {
+    fn notify(&self, tasks: &TaskTracker, notice: &Notice<'_>) {
+        let webhook = notify::webhook_for(
+            notice.options.teams_webhook_url.as_ref(),
+            notice.options.caller.api_key.as_deref(),
+            &self.config.api_key_webhooks,
+            self.config.webhook_url.as_deref(),
+        );
+        let Some(webhook_url) = webhook else {
+            return;
+        };
+        let message = self.message(notice);
+        notify::post_webhook(
+            tasks,
+            &self.http,
+            webhook_url,
+            message,
+            notice.job_id,
+            "Teams",
+        );
+    }
+}
+
+fn fact(title: &str, value: &str) -> Value {
+    json!({ "title": title, "value": value })
+}
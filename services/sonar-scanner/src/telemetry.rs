@@ -0,0 +1,67 @@
+use crate::config::{LogFormat, TelemetryConfig};
+use crate::json_log::JsonLayer;
+use anyhow::{Context, Result};
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing::warn;
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+};
+
+/// Installs the global subscriber: text or JSON logs, on stdout unless
+/// `to_stderr` is set, plus span export over OTLP/gRPC when an endpoint is
+/// configured. The returned provider must be passed to [`shutdown`] before
+/// exit so buffered spans are flushed.
+pub fn init(config: &TelemetryConfig, to_stderr: bool) -> Result<Option<TracerProvider>> {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "sonar_backend=info,tower_http=info".into());
+    let writer = if to_stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let (text, json) = match config.log_format {
+        LogFormat::Text => (
+            Some(tracing_subscriber::fmt::layer().with_writer(writer)),
+            None,
+        ),
+        LogFormat::Json => (None, Some(JsonLayer::new(writer))),
+    };
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json);
+
+    let Some(endpoint) = config.otlp_endpoint.as_deref() else {
+        registry.init();
+        return Ok(None);
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .context("Failed to build the OTLP span exporter")?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )]))
+        .build();
+
+    registry
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("sonar-backend")))
+        .init();
+    Ok(Some(provider))
+}
+
+/// Flushes spans still buffered for export.
+pub fn shutdown(provider: Option<TracerProvider>) {
+    if let Some(provider) = provider {
+        if let Err(e) = provider.shutdown() {
+            warn!("Failed to flush traces: {}", e);
+        }
+    }
+}
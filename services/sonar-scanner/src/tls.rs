@@ -0,0 +1,329 @@
+//! HTTPS termination, so the service can be exposed without a reverse proxy
+//! in front. The certificate and key are PEM files, re-read when either
+//! changes so a renewal (e.g. by certbot or cert-manager) needs no restart.
+//! An optional plain HTTP listener redirects every request to HTTPS.
+//!
+//! With a client CA configured, clients must present a certificate it
+//! issued (mutual TLS). The certificate's names are added to each request as
+//! a [`ClientCertificate`], which authentication maps to a tenant.
+
+use crate::config::TlsConfig;
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{header, uri::Authority, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+};
+use std::{
+    fs,
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{
+        server::{AllowAnyAuthenticatedClient, ClientHello, ResolvesServerCert},
+        sign::{self, CertifiedKey},
+        Certificate, PrivateKey, RootCertStore, ServerConfig,
+    },
+    TlsAcceptor,
+};
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+use tracing::{debug, error, info, warn};
+use x509_parser::extensions::GeneralName;
+
+/// How long a client gets to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The served certificate, swapped in place when the files change.
+pub struct Certificates {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    /// Modification times of the certificate and key last loaded.
+    loaded: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+    /// CAs client certificates must chain to; `None` asks for none.
+    client_roots: Option<RootCertStore>,
+}
+
+/// Names from the client certificate of a mutual TLS connection: its
+/// subject common names and its DNS, email and URI alternative names.
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    pub identities: Vec<String>,
+}
+
+impl Certificates {
+    /// Loads the configured certificate and key; `None` when HTTPS is not
+    /// configured.
+    pub fn load(config: &TlsConfig) -> Result<Option<Arc<Self>>> {
+        let (cert_path, key_path) = match (&config.cert_path, &config.key_path) {
+            (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+            (None, None) if config.redirect_port != 0 => {
+                bail!("TLS_REDIRECT_PORT requires TLS_CERT_PATH and TLS_KEY_PATH")
+            }
+            (None, None) if config.client_ca_path.is_some() => {
+                bail!("TLS_CLIENT_CA_PATH requires TLS_CERT_PATH and TLS_KEY_PATH")
+            }
+            (None, None) => return Ok(None),
+            _ => bail!("TLS_CERT_PATH and TLS_KEY_PATH must be set together"),
+        };
+        let loaded = modified(cert_path, key_path);
+        let key = read_certified_key(cert_path, key_path)?;
+        let client_roots = match &config.client_ca_path {
+            Some(path) => Some(read_roots(path)?),
+            None => None,
+        };
+        Ok(Some(Arc::new(Self {
+            cert_path: cert_path.clone(),
+            key_path: key_path.clone(),
+            current: RwLock::new(Arc::new(key)),
+            loaded: Mutex::new(loaded),
+            client_roots,
+        })))
+    }
+
+    /// Accepts TLS connections with whichever certificate is current at
+    /// the time of each handshake.
+    pub fn acceptor(self: &Arc<Self>) -> TlsAcceptor {
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_roots {
+            Some(roots) => builder.with_client_cert_verifier(Arc::new(
+                AllowAnyAuthenticatedClient::new(roots.clone()),
+            )),
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_cert_resolver(Arc::new(Resolver(self.clone())));
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    /// Checks the files every `interval` and reloads them when either has
+    /// changed. A renewal that fails to load keeps the previous certificate.
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            let modified = modified(&self.cert_path, &self.key_path);
+            if *self.loaded.lock().unwrap() == modified {
+                continue;
+            }
+            match read_certified_key(&self.cert_path, &self.key_path) {
+                Ok(key) => {
+                    *self.current.write().unwrap() = Arc::new(key);
+                    info!("Reloaded TLS certificate {}", self.cert_path.display());
+                }
+                // A certificate and key written one after the other can be
+                // briefly mismatched; writing the second triggers a retry
+                Err(e) => warn!("Keeping the current TLS certificate: {:#}", e),
+            }
+            *self.loaded.lock().unwrap() = modified;
+        }
+    }
+}
+
+struct Resolver(Arc<Certificates>);
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.current.read().unwrap().clone())
+    }
+}
+
+fn modified(cert_path: &Path, key_path: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    (modified(cert_path), modified(key_path))
+}
+
+/// Reads a PEM certificate chain and the PKCS#8, PKCS#1 or SEC1 private key
+/// for it.
+fn read_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let open = |path: &Path| {
+        fs::File::open(path)
+            .map(BufReader::new)
+            .with_context(|| format!("Failed to read {}", path.display()))
+    };
+    let certs: Vec<Certificate> = rustls_pemfile::certs(&mut open(cert_path)?)
+        .with_context(|| format!("Invalid certificate file {}", cert_path.display()))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certs.is_empty() {
+        bail!("No certificate found in {}", cert_path.display());
+    }
+
+    let key = rustls_pemfile::read_all(&mut open(key_path)?)
+        .with_context(|| format!("Invalid key file {}", key_path.display()))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .with_context(|| format!("No private key found in {}", key_path.display()))?;
+    let signing_key = sign::any_supported_type(&key)
+        .map_err(|_| anyhow::anyhow!("Unsupported private key in {}", key_path.display()))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// Reads the PEM CA certificates client certificates must chain to.
+fn read_roots(path: &Path) -> Result<RootCertStore> {
+    let file =
+        fs::File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .with_context(|| format!("Invalid CA file {}", path.display()))?;
+    let mut roots = RootCertStore::empty();
+    for cert in certs {
+        roots
+            .add(&Certificate(cert))
+            .with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+    }
+    if roots.is_empty() {
+        bail!("No CA certificate found in {}", path.display());
+    }
+    Ok(roots)
+}
+
+impl ClientCertificate {
+    /// The names of a DER certificate, or `None` if it cannot be parsed.
+    fn parse(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let mut identities: Vec<String> = cert
+            .subject()
+            .iter_common_name()
+            .filter_map(|name| name.as_str().ok())
+            .map(str::to_string)
+            .collect();
+        if let Ok(Some(names)) = cert.subject_alternative_name() {
+            for name in &names.value.general_names {
+                match name {
+                    GeneralName::DNSName(name)
+                    | GeneralName::RFC822Name(name)
+                    | GeneralName::URI(name) => identities.push(name.to_string()),
+                    _ => {}
+                }
+            }
+        }
+        Some(Self { identities })
+    }
+}
+
+/// Serves `app` over TLS on `listener` until `stopping` is cancelled, then
+/// waits for open connections to finish, as `axum::serve` does for plain
+/// HTTP. Handlers see the client's address as `ConnectInfo<SocketAddr>`,
+/// and a mutual TLS client's certificate as a [`ClientCertificate`].
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    acceptor: TlsAcceptor,
+    stopping: CancellationToken,
+) {
+    let graceful = GracefulShutdown::new();
+    loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors; give connections
+                    // time to close
+                    error!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = stopping.cancelled() => break,
+        };
+
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        debug!("TLS handshake with {} failed: {}", remote, e);
+                        return;
+                    }
+                    Err(_) => {
+                        debug!("TLS handshake with {} timed out", remote);
+                        return;
+                    }
+                };
+            let client_certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|chain| chain.first())
+                .and_then(|cert| ClientCertificate::parse(&cert.0));
+            let service = hyper::service::service_fn(move |mut request: Request<_>| {
+                request.extensions_mut().insert(ConnectInfo(remote));
+                if let Some(certificate) = &client_certificate {
+                    request.extensions_mut().insert(certificate.clone());
+                }
+                app.clone().oneshot(request)
+            });
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            if let Err(e) = watcher.watch(connection).await {
+                debug!("Connection from {} closed with an error: {}", remote, e);
+            }
+        });
+    }
+
+    drop(listener);
+    graceful.shutdown().await;
+}
+
+/// Redirects every plain HTTP request on `addr` to the same host and path on
+/// `https_port`, until `stopping` is cancelled.
+pub async fn serve_redirects(addr: SocketAddr, https_port: u16, stopping: CancellationToken) {
+    let app = Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        redirect(&headers, &uri, https_port)
+    });
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(
+                "Failed to bind the HTTPS redirect listener to {}: {}",
+                addr, e
+            );
+            return;
+        }
+    };
+
+    info!("Redirecting HTTP on {} to HTTPS", addr);
+    if let Err(e) = axum::serve(listener, app)
+        .with_graceful_shutdown(stopping.cancelled_owned())
+        .await
+    {
+        error!("HTTPS redirect listener on {} failed: {}", addr, e);
+    }
+}
+
+fn redirect(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Response {
+    let Some(authority) = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+    else {
+        return (StatusCode::BAD_REQUEST, "missing or invalid Host header").into_response();
+    };
+    let host = match https_port {
+        443 => authority.host().to_string(),
+        port => format!("{}:{}", authority.host(), port),
+    };
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    Redirect::permanent(&format!("https://{}{}", host, path)).into_response()
+}
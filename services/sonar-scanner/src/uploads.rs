@@ -0,0 +1,89 @@
+//! Presigned uploads: a client asks `POST /uploads` for an upload slot,
+//! PUTs its archive straight to object storage and then starts the scan
+//! with `{"upload_id": ...}` on `/analyze`, so multi-GB archives never pass
+//! through this service's request body.
+
+use crate::{
+    apply_option, ids, json_option_fields, AnalyzeOptions, AppError, AppState, UploadSource,
+};
+use axum::{extract::State, http::StatusCode, response::Json};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+use time::OffsetDateTime;
+use tracing::info;
+
+#[derive(Debug, Serialize)]
+pub struct UploadSlot {
+    /// Passed as `upload_id` to `/analyze` once the archive is uploaded.
+    upload_id: String,
+    url: String,
+    method: &'static str,
+    /// Headers the upload must carry.
+    headers: BTreeMap<String, String>,
+    #[serde(with = "time::serde::rfc3339")]
+    expires_at: OffsetDateTime,
+}
+
+pub async fn create_upload_handler(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<UploadSlot>), AppError> {
+    let store = state.artifacts.as_ref().ok_or_else(|| {
+        AppError::NotFound("Presigned uploads require ARTIFACTS_URL to be set".to_string())
+    })?;
+    let upload_id = ids::upload_id();
+    let presigned = store.presign_upload(&upload_id).await?;
+    info!("Issued upload slot {}", upload_id);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(UploadSlot {
+            upload_id,
+            url: presigned.url,
+            method: "PUT",
+            headers: presigned
+                .headers
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            expires_at: presigned.expires_at,
+        }),
+    ))
+}
+
+/// Reads a JSON `/analyze` body naming an `upload_id`: downloads the
+/// archive into `temp_path`, deletes it from storage and reads the option
+/// fields. An upload can be analysed once.
+pub async fn read_upload_request(
+    state: &AppState,
+    mut body: serde_json::Map<String, serde_json::Value>,
+    temp_path: &Path,
+) -> Result<(UploadSource, AnalyzeOptions), AppError> {
+    let upload_id = match body.remove("upload_id") {
+        Some(serde_json::Value::String(id)) if ids::is_upload_id(&id) => id,
+        _ => {
+            return Err(AppError::InvalidRequest(
+                "Field 'upload_id' must be an ID returned by POST /uploads".to_string(),
+            ))
+        }
+    };
+    let store = state.artifacts.as_ref().ok_or_else(|| {
+        AppError::InvalidRequest("Presigned uploads require ARTIFACTS_URL to be set".to_string())
+    })?;
+
+    let mut options = AnalyzeOptions::default();
+    for (name, value) in json_option_fields(body) {
+        apply_option(&mut options, &name, &value)?;
+    }
+
+    let path = temp_path.join("upload.archive");
+    let size = store
+        .download_upload(&upload_id, &path, state.config.limits.max_upload_bytes)
+        .await?;
+    info!(
+        "Downloaded {} byte upload {} to {:?}",
+        size, upload_id, path
+    );
+    store.remove_upload_in_background(&state.tasks, &upload_id);
+
+    Ok((UploadSource::Archive(path), options))
+}
@@ -0,0 +1,42 @@
+use crate::backends;
+use axum::response::Json;
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Embedded by `build.rs`.
+const GIT_COMMIT: &str = env!("GIT_COMMIT");
+const BUILD_UNIX_TIME: &str = env!("BUILD_UNIX_TIME");
+
+/// What is deployed, for `GET /version`.
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    /// Short commit hash, or `unknown` when built outside a git checkout.
+    pub git_commit: &'static str,
+    pub build_timestamp: Option<String>,
+    /// `None` when `sonar-scanner --version` could not be run.
+    pub scanner_version: Option<String>,
+}
+
+pub async fn version_handler() -> Json<VersionInfo> {
+    // Looked up once per process, usually already at startup
+    let scanner_version = tokio::task::spawn_blocking(backends::scanner_version)
+        .await
+        .ok()
+        .flatten();
+
+    Json(VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: GIT_COMMIT,
+        build_timestamp: build_timestamp(),
+        scanner_version,
+    })
+}
+
+fn build_timestamp() -> Option<String> {
+    let seconds = BUILD_UNIX_TIME.parse().ok()?;
+    OffsetDateTime::from_unix_timestamp(seconds)
+        .ok()?
+        .format(&Rfc3339)
+        .ok()
+}
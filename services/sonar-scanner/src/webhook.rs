@@ -0,0 +1,210 @@
+use crate::config::WebhookConfig;
+use crate::ids;
+use crate::jobs::{JobError, JobStatus};
+use crate::proxy::OutboundProxy;
+use crate::{AnalyzeResponse, AppError};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// `sha256=<hex>` HMAC of `<timestamp>.<body>`, keyed by the webhook secret.
+const SIGNATURE_HEADER: &str = "x-sonar-signature";
+
+/// Unix seconds at which the delivery was signed, so receivers can reject replays.
+const TIMESTAMP_HEADER: &str = "x-sonar-timestamp";
+
+const EVENT_HEADER: &str = "x-sonar-event";
+
+/// Job the callback reports on; stays the same across retries.
+const JOB_ID_HEADER: &str = "x-sonar-job-id";
+
+/// Random ID of the delivery; stays the same across retries, so receivers
+/// can drop duplicates.
+const DELIVERY_HEADER: &str = "x-sonar-delivery";
+
+const COMPLETED_EVENT: &str = "scan.completed";
+
+/// Callback targets must be web URLs; the same schemes as `git_url`.
+const ALLOWED_SCHEMES: &[&str] = &["https", "http"];
+
+pub fn parse_callback_url(value: &str) -> Result<reqwest::Url, AppError> {
+    let url = reqwest::Url::parse(value.trim())
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid callback_url: {}", e)))?;
+    if !ALLOWED_SCHEMES.contains(&url.scheme()) {
+        return Err(AppError::InvalidRequest(format!(
+            "callback_url scheme '{}' is not supported (expected {})",
+            url.scheme(),
+            ALLOWED_SCHEMES.join(" or ")
+        )));
+    }
+    Ok(url)
+}
+
+/// Body POSTed to `callback_url` once a job finishes.
+#[derive(Debug, Serialize)]
+struct CompletionEvent<'a> {
+    event: &'static str,
+    job_id: &'a str,
+    status: JobStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'a AnalyzeResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JobError>,
+}
+
+impl<'a> CompletionEvent<'a> {
+    fn new(job_id: &'a str, outcome: Result<&'a AnalyzeResponse, &AppError>) -> Self {
+        let (status, result, error) = match outcome {
+            Ok(response) => (JobStatus::Succeeded, Some(response), None),
+            Err(e @ AppError::Canceled(_)) => (JobStatus::Canceled, None, Some(JobError::from(e))),
+            Err(e) => (JobStatus::Failed, None, Some(JobError::from(e))),
+        };
+        Self {
+            event: COMPLETED_EVENT,
+            job_id,
+            status,
+            result,
+            error,
+        }
+    }
+}
+
+/// Delivers completion callbacks, retrying with exponential backoff.
+#[derive(Clone)]
+pub struct WebhookSender {
+    http: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl WebhookSender {
+    pub fn new(config: &WebhookConfig, proxy: &OutboundProxy) -> Self {
+        let http = proxy
+            .client_builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            config: config.clone(),
+        }
+    }
+
+    /// Reports a finished job to `url` in a task tracked by `tasks`, so
+    /// shutdown waits for pending deliveries.
+    pub fn notify(
+        &self,
+        tasks: &TaskTracker,
+        url: reqwest::Url,
+        job_id: &str,
+        outcome: Result<&AnalyzeResponse, &AppError>,
+    ) {
+        let body = match serde_json::to_vec(&CompletionEvent::new(job_id, outcome)) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to encode callback for job {}: {}", job_id, e);
+                return;
+            }
+        };
+        tasks.spawn(self.clone().deliver(url, job_id.to_string(), body));
+    }
+
+    /// Failures are logged, never returned: the job has already finished and
+    /// its result stays available from the API.
+    async fn deliver(self, url: reqwest::Url, job_id: String, body: Vec<u8>) {
+        let attempts = self.config.max_attempts.max(1);
+        let mut delay = Duration::from_secs(self.config.retry_delay_secs);
+        let delivery_id = ids::delivery_id();
+        for attempt in 1..=attempts {
+            match self.send(&url, &job_id, &delivery_id, &body).await {
+                Ok(()) => {
+                    info!("Delivered callback for job {} to {}", job_id, url);
+                    return;
+                }
+                Err(Failure::Permanent(reason)) => {
+                    warn!(
+                        "Callback for job {} rejected by {}: {}",
+                        job_id, url, reason
+                    );
+                    return;
+                }
+                Err(Failure::Retryable(reason)) if attempt < attempts => {
+                    warn!(
+                        "Callback for job {} failed (attempt {}/{}), retrying in {:?}: {}",
+                        job_id, attempt, attempts, delay, reason
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(Failure::Retryable(reason)) => {
+                    warn!(
+                        "Giving up on callback for job {} after {} attempts: {}",
+                        job_id, attempts, reason
+                    );
+                }
+            }
+        }
+    }
+
+    async fn send(
+        &self,
+        url: &reqwest::Url,
+        job_id: &str,
+        delivery_id: &str,
+        body: &[u8],
+    ) -> Result<(), Failure> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+
+        let mut request = self
+            .http
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, COMPLETED_EVENT)
+            .header(JOB_ID_HEADER, job_id)
+            .header(DELIVERY_HEADER, delivery_id)
+            .header(TIMESTAMP_HEADER, &timestamp);
+        if let Some(secret) = &self.config.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &timestamp, body));
+        }
+
+        let response = request
+            .body(body.to_vec())
+            .send()
+            .await
+            .map_err(|e| Failure::Retryable(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else if status.is_server_error()
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        {
+            Err(Failure::Retryable(format!("HTTP {}", status)))
+        } else {
+            // Other client errors will not change on a retry
+            Err(Failure::Permanent(format!("HTTP {}", status)))
+        }
+    }
+}
+
+enum Failure {
+    Retryable(String),
+    Permanent(String),
+}
+
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}